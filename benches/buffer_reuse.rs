@@ -0,0 +1,125 @@
+//! Allocating [`rgb_to_jpeg`] against [`rgb_to_jpeg_into`] with one output buffer held across
+//! frames, at 1080p RGB888. Run with `cargo bench --bench buffer_reuse`.
+//!
+//! Before the timings, both paths convert a few frames under a counting allocator and the
+//! heap allocations per frame are printed, which is what the reuse path exists to cut. The
+//! frame is the RGB QCIF fixture under `tests/data/input` tiled up to 1080p; without it the
+//! benchmark is skipped.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use make87_messages::image::uncompressed::image_raw_any::Image as RawImageVariant;
+use make87_messages::image::uncompressed::{ImageRawAny, ImageRgb888};
+use raw_to_jpeg::{rgb_to_jpeg, rgb_to_jpeg_into, ConversionConfig};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use turbojpeg::Compressor;
+
+/// Counts heap allocations made through Rust. libjpeg-turbo's own `malloc` calls are not seen,
+/// but they are the same on both paths.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const JPEG_QUALITY: i32 = 90;
+
+const SIZE: (usize, usize) = (1920, 1080);
+
+const FIXTURE: &str = "tulips_rgb444_prog_packed_qcif.yuv";
+
+const FIXTURE_SIZE: (usize, usize) = (176, 144);
+
+/// Frames converted per path when counting allocations.
+const COUNTED_FRAMES: usize = 10;
+
+/// First frame of the RGB fixture repeated across a 1080p frame, or `None` if it is missing.
+fn fixture_frame() -> Option<ImageRawAny> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/input").join(FIXTURE);
+    let fixture = std::fs::read(path).ok()?;
+    let (fixture_width, fixture_height) = FIXTURE_SIZE;
+    let (width, height) = SIZE;
+    let mut data = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        let row = (y % fixture_height) * fixture_width * 3;
+        for x in 0..width {
+            let pixel = row + (x % fixture_width) * 3;
+            data.extend_from_slice(fixture.get(pixel..pixel + 3)?);
+        }
+    }
+    Some(ImageRawAny {
+        header: None,
+        image: Some(RawImageVariant::Rgb888(ImageRgb888 {
+            header: None,
+            width: width as u32,
+            height: height as u32,
+            data,
+        })),
+    })
+}
+
+/// Heap allocations per frame made by `convert` over [`COUNTED_FRAMES`] calls, after one
+/// warm-up call.
+fn allocations_per_frame(mut convert: impl FnMut()) -> f64 {
+    convert();
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for _ in 0..COUNTED_FRAMES {
+        convert();
+    }
+    (ALLOCATIONS.load(Ordering::Relaxed) - before) as f64 / COUNTED_FRAMES as f64
+}
+
+fn buffer_reuse(c: &mut Criterion) {
+    let Some(frame) = fixture_frame() else {
+        eprintln!("Skipping buffer reuse benchmark - file {} not found", FIXTURE);
+        return;
+    };
+    let mut compressor = Compressor::new().expect("compressor");
+    compressor.set_quality(JPEG_QUALITY).expect("quality");
+    let config = ConversionConfig::default();
+    let mut output = Vec::new();
+
+    let allocating = allocations_per_frame(|| {
+        black_box(rgb_to_jpeg(&frame, &mut compressor, &config).expect("conversion"));
+    });
+    let reusing = allocations_per_frame(|| {
+        rgb_to_jpeg_into(&frame, &mut compressor, &config, &mut output).expect("conversion");
+    });
+    eprintln!(
+        "Heap allocations per 1080p RGB888 frame: {allocating:.1} allocating, {reusing:.1} with buffer reuse ({:.1} fewer)",
+        allocating - reusing
+    );
+
+    let (width, height) = SIZE;
+    let mut group = c.benchmark_group("RGB888_1080p");
+    group.throughput(Throughput::Bytes((width * height * 3) as u64));
+    group.bench_function("allocating", |b| {
+        b.iter(|| rgb_to_jpeg(black_box(&frame), &mut compressor, &config).expect("conversion"))
+    });
+    group.bench_function("buffer_reuse", |b| {
+        b.iter(|| rgb_to_jpeg_into(black_box(&frame), &mut compressor, &config, &mut output).expect("conversion"))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, buffer_reuse);
+criterion_main!(benches);
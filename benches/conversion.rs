@@ -0,0 +1,83 @@
+//! Conversion throughput per input format. Run with `cargo bench`; criterion writes its
+//! reports to `target/criterion`.
+//!
+//! The synthetic frames always run. The QCIF fixtures under `tests/data/input` are benchmarked
+//! too when present and skipped otherwise.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use raw_to_jpeg::{compress_raw_into, ConversionConfig, RawPixelFormat};
+use std::hint::black_box;
+use std::path::Path;
+use turbojpeg::Compressor;
+
+const JPEG_QUALITY: i32 = 90;
+
+const RESOLUTIONS: [(usize, usize); 2] = [(640, 480), (1920, 1080)];
+
+const FIXTURE_SIZE: (usize, usize) = (176, 144);
+
+const FIXTURES: [(RawPixelFormat, &str); 5] = [
+    (RawPixelFormat::Rgb888, "tulips_rgb444_prog_packed_qcif.yuv"),
+    (RawPixelFormat::Yuv420, "tulips_yuv420_prog_planar_qcif.yuv"),
+    (RawPixelFormat::Yuv422, "tulips_yuv422_prog_planar_qcif.yuv"),
+    (RawPixelFormat::Yuv444, "tulips_yuv444_prog_planar_qcif.yuv"),
+    (RawPixelFormat::Nv12, "tulips_nv12_prog_qcif.yuv"),
+];
+
+/// Deterministic frame with enough variation that the encoder does real work, unlike a flat
+/// buffer.
+fn synthetic_frame(format: RawPixelFormat, width: usize, height: usize) -> Vec<u8> {
+    (0..format.expected_len(width, height)).map(|i| (i * 7 % 251) as u8).collect()
+}
+
+/// Benchmarks converting `data` under `id` in `group`, with one compressor and output buffer
+/// reused across iterations as the app's workers do.
+fn bench_frame(
+    c: &mut Criterion,
+    group: &str,
+    id: BenchmarkId,
+    data: &[u8],
+    (width, height): (usize, usize),
+    format: RawPixelFormat,
+) {
+    let mut compressor = Compressor::new().expect("compressor");
+    compressor.set_quality(JPEG_QUALITY).expect("quality");
+    let config = ConversionConfig::default();
+    let mut output = Vec::new();
+
+    let mut group = c.benchmark_group(group);
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_with_input(id, data, |b, data| {
+        b.iter(|| {
+            compress_raw_into(black_box(data), width, height, format, &mut compressor, &config, &mut output)
+                .expect("conversion")
+        })
+    });
+    group.finish();
+}
+
+fn synthetic(c: &mut Criterion) {
+    for format in RawPixelFormat::NAMED.into_iter().filter(|format| format.is_enabled()) {
+        for (width, height) in RESOLUTIONS {
+            let data = synthetic_frame(format, width, height);
+            let id = BenchmarkId::from_parameter(format!("{width}x{height}"));
+            bench_frame(c, format.name(), id, &data, (width, height), format);
+        }
+    }
+}
+
+fn fixtures(c: &mut Criterion) {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/input");
+    for (format, filename) in FIXTURES {
+        let Ok(data) = std::fs::read(dir.join(filename)) else {
+            eprintln!("Skipping {} fixture - file {} not found", format.name(), filename);
+            continue;
+        };
+        let (width, height) = FIXTURE_SIZE;
+        let id = BenchmarkId::new("fixture", format!("{width}x{height}"));
+        bench_frame(c, format.name(), id, &data, FIXTURE_SIZE, format);
+    }
+}
+
+criterion_group!(benches, synthetic, fixtures);
+criterion_main!(benches);
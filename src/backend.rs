@@ -0,0 +1,183 @@
+//! Encoders behind the packed RGB/BGR(A) and grayscale paths.
+//!
+//! By default these paths compress with the caller's [`Compress`] implementation, normally a
+//! turbojpeg [`Compressor`](turbojpeg::Compressor). With the
+//! `pure-rust` cargo feature they use the `jpeg-encoder` crate instead, which needs no native
+//! library for them. YUV input, lossless output, progressive conversion and restart markers are
+//! still done by libjpeg-turbo in either build.
+
+use crate::{Compress, ConversionConfig, Result};
+use turbojpeg::{Image, Subsamp};
+
+/// Quality used by encoders that cannot read it from the compressor (including WebP) when
+/// [`ConversionConfig::quality`] is not set. Matches the app's `JPEG_QUALITY` default.
+#[cfg_attr(not(any(feature = "pure-rust", feature = "webp")), allow(dead_code))]
+pub(crate) const DEFAULT_QUALITY: u8 = 90;
+
+/// Compresses one packed image whose rows are `image.pitch` bytes apart.
+pub(crate) trait JpegBackend {
+    /// Compresses `image` into `output`, replacing its contents. `subsamp` applies to color
+    /// input only; `None` keeps the backend's default. Callers convert to gray themselves
+    /// rather than passing `Subsamp::Gray`.
+    fn compress(&mut self, image: Image<&[u8]>, subsamp: Option<Subsamp>, output: &mut Vec<u8>) -> Result<()>;
+}
+
+/// The default backend: the caller's compressor.
+#[cfg(not(feature = "pure-rust"))]
+pub(crate) struct Turbo<'a, C: Compress + ?Sized>(&'a mut C);
+
+#[cfg(not(feature = "pure-rust"))]
+impl<C: Compress + ?Sized> JpegBackend for Turbo<'_, C> {
+    fn compress(&mut self, image: Image<&[u8]>, subsamp: Option<Subsamp>, output: &mut Vec<u8>) -> Result<()> {
+        if image.format == turbojpeg::PixelFormat::GRAY {
+            // Through the YUV path so the compressor's subsampling stays untouched.
+            let yuv_image = turbojpeg::YuvImage {
+                pixels: image.pixels,
+                width: image.width,
+                align: 1,
+                height: image.height,
+                subsamp: Subsamp::Gray,
+            };
+            return self.0.compress_yuv(yuv_image, output);
+        }
+        if let Some(subsamp) = subsamp {
+            self.0.set_subsamp(subsamp)?;
+        }
+        self.0.compress_rgb(image, output)
+    }
+}
+
+/// Returns the backend the packed and grayscale paths encode with.
+#[cfg(not(feature = "pure-rust"))]
+pub(crate) fn select<'a, C: Compress + ?Sized>(
+    compressor: &'a mut C,
+    _config: &ConversionConfig,
+) -> impl JpegBackend + 'a {
+    Turbo(compressor)
+}
+
+/// Returns the backend the packed and grayscale paths encode with.
+#[cfg(feature = "pure-rust")]
+pub(crate) fn select<C: Compress + ?Sized>(_compressor: &mut C, config: &ConversionConfig) -> impl JpegBackend {
+    pure_rust::PureRust {
+        quality: config.quality.unwrap_or(DEFAULT_QUALITY),
+        chroma_quality: config.chroma_quality,
+        optimize_huffman: config.optimize_huffman,
+    }
+}
+
+impl<B: JpegBackend + ?Sized> JpegBackend for &mut B {
+    fn compress(&mut self, image: Image<&[u8]>, subsamp: Option<Subsamp>, output: &mut Vec<u8>) -> Result<()> {
+        (**self).compress(image, subsamp, output)
+    }
+}
+
+#[cfg(feature = "pure-rust")]
+mod pure_rust {
+    use super::JpegBackend;
+    use crate::{ConversionError, Result};
+    use jpeg_encoder::{ColorType, Encoder, QuantizationTableType, SamplingFactor};
+    use turbojpeg::{Image, PixelFormat, Subsamp};
+
+    /// Luminance quantization table of ITU-T T.81 Annex K, in row-major order.
+    const LUMA_TABLE: [u16; 64] = [
+        16, 11, 10, 16, 24, 40, 51, 61, //
+        12, 12, 14, 19, 26, 58, 60, 55, //
+        14, 13, 16, 24, 40, 57, 69, 56, //
+        14, 17, 22, 29, 51, 87, 80, 62, //
+        18, 22, 37, 56, 68, 109, 103, 77, //
+        24, 35, 55, 64, 81, 104, 113, 92, //
+        49, 64, 78, 87, 103, 121, 120, 101, //
+        72, 92, 95, 98, 112, 100, 103, 99,
+    ];
+
+    /// Chrominance quantization table of ITU-T T.81 Annex K, in row-major order.
+    const CHROMA_TABLE: [u16; 64] = [
+        17, 18, 24, 47, 99, 99, 99, 99, //
+        18, 21, 26, 66, 99, 99, 99, 99, //
+        24, 26, 56, 99, 99, 99, 99, 99, //
+        47, 66, 99, 99, 99, 99, 99, 99, //
+        99, 99, 99, 99, 99, 99, 99, 99, //
+        99, 99, 99, 99, 99, 99, 99, 99, //
+        99, 99, 99, 99, 99, 99, 99, 99, //
+        99, 99, 99, 99, 99, 99, 99, 99,
+    ];
+
+    /// Scales `base` to `quality` the way libjpeg's `jpeg_set_quality` does, limited to the
+    /// 1-255 range of baseline JPEG.
+    fn scaled_table(base: &[u16; 64], quality: u8) -> QuantizationTableType {
+        let quality = quality.clamp(1, 100) as u32;
+        let scale = if quality < 50 { 5000 / quality } else { 200 - quality * 2 };
+        let table = base.map(|value| ((value as u32 * scale + 50) / 100).clamp(1, 255) as u16);
+        QuantizationTableType::Custom(Box::new(table))
+    }
+
+    /// `jpeg-encoder` backend. Holds its own settings since it has no persistent state.
+    pub(crate) struct PureRust {
+        pub(crate) quality: u8,
+        pub(crate) chroma_quality: Option<u8>,
+        pub(crate) optimize_huffman: bool,
+    }
+
+    impl JpegBackend for PureRust {
+        fn compress(&mut self, image: Image<&[u8]>, subsamp: Option<Subsamp>, output: &mut Vec<u8>) -> Result<()> {
+            let color_type = match image.format {
+                PixelFormat::RGB => ColorType::Rgb,
+                PixelFormat::RGBA | PixelFormat::RGBX => ColorType::Rgba,
+                PixelFormat::BGR => ColorType::Bgr,
+                PixelFormat::BGRA | PixelFormat::BGRX => ColorType::Bgra,
+                PixelFormat::GRAY => ColorType::Luma,
+                other => {
+                    return Err(ConversionError::UnsupportedFormat(format!(
+                        "{:?} with the pure-rust encoder",
+                        other
+                    )))
+                }
+            };
+            let (width, height) = match (u16::try_from(image.width), u16::try_from(image.height)) {
+                (Ok(width), Ok(height)) => (width, height),
+                _ => {
+                    return Err(ConversionError::InvalidDimensions {
+                        format: "pure-rust encoder",
+                        requirement: "width and height of at most 65535",
+                        width: image.width,
+                        height: image.height,
+                    })
+                }
+            };
+
+            // jpeg-encoder has no notion of row padding
+            let row_len = image.width * image.format.size();
+            let tight: Vec<u8>;
+            let pixels = if image.pitch == row_len {
+                image.pixels
+            } else {
+                tight = (0..image.height)
+                    .flat_map(|y| &image.pixels[y * image.pitch..y * image.pitch + row_len])
+                    .copied()
+                    .collect();
+                &tight
+            };
+
+            output.clear();
+            let mut encoder = Encoder::new(&mut *output, self.quality);
+            encoder.set_optimized_huffman_tables(self.optimize_huffman);
+            if let Some(chroma_quality) = self.chroma_quality {
+                encoder.set_quantization_tables(
+                    scaled_table(&LUMA_TABLE, self.quality),
+                    scaled_table(&CHROMA_TABLE, chroma_quality),
+                );
+            }
+            match subsamp {
+                Some(Subsamp::None) => encoder.set_sampling_factor(SamplingFactor::R_4_4_4),
+                Some(Subsamp::Sub2x1) => encoder.set_sampling_factor(SamplingFactor::R_4_2_2),
+                Some(Subsamp::Sub2x2) => encoder.set_sampling_factor(SamplingFactor::R_4_2_0),
+                Some(Subsamp::Sub1x2) => encoder.set_sampling_factor(SamplingFactor::R_4_4_0),
+                Some(Subsamp::Sub4x1) => encoder.set_sampling_factor(SamplingFactor::R_4_1_1),
+                Some(Subsamp::Gray) | None => {}
+            }
+            encoder.encode(pixels, width, height, color_type)?;
+            Ok(())
+        }
+    }
+}
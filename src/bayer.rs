@@ -0,0 +1,60 @@
+//! Demosaicing of raw Bayer mosaic frames from machine-vision sensors.
+//!
+//! Only built with the `bayer` cargo feature.
+
+/// Color filter layout of the top-left 2x2 block of the sensor, read row by row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BayerPattern {
+    #[default]
+    Rggb,
+    Bggr,
+    Grbg,
+    Gbrg,
+}
+
+impl BayerPattern {
+    /// Channel (0 = red, 1 = green, 2 = blue) sampled at pixel `(x, y)`.
+    pub fn channel_at(self, x: usize, y: usize) -> usize {
+        let block = match self {
+            BayerPattern::Rggb => [0, 1, 1, 2],
+            BayerPattern::Bggr => [2, 1, 1, 0],
+            BayerPattern::Grbg => [1, 0, 2, 1],
+            BayerPattern::Gbrg => [1, 2, 0, 1],
+        };
+        block[(y % 2) * 2 + x % 2]
+    }
+}
+
+/// Bilinear demosaic of a tightly packed 8-bit `width` x `height` mosaic into packed RGB888.
+///
+/// Each missing channel is the mean of the pixels sampling it in the surrounding 3x3 window,
+/// which is plain bilinear interpolation inside the frame and clamps at the edges. `mosaic`
+/// must hold at least `width * height` bytes and both dimensions must be at least 2, so every
+/// window sees all three channels.
+pub fn demosaic_bilinear(mosaic: &[u8], width: usize, height: usize, pattern: BayerPattern) -> Vec<u8> {
+    let mut rgb = vec![0u8; width * height * 3];
+    for y in 0..height {
+        for x in 0..width {
+            let own = pattern.channel_at(x, y);
+            let mut sums = [0u32; 3];
+            let mut counts = [0u32; 3];
+            for ny in y.saturating_sub(1)..(y + 2).min(height) {
+                for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                    let channel = pattern.channel_at(nx, ny);
+                    sums[channel] += mosaic[ny * width + nx] as u32;
+                    counts[channel] += 1;
+                }
+            }
+
+            let out = &mut rgb[(y * width + x) * 3..][..3];
+            for (channel, value) in out.iter_mut().enumerate() {
+                *value = if channel == own {
+                    mosaic[y * width + x]
+                } else {
+                    ((sums[channel] + counts[channel] / 2) / counts[channel]) as u8
+                };
+            }
+        }
+    }
+    rgb
+}
@@ -0,0 +1,136 @@
+//! Writes deterministic synthetic QCIF (176x144) frames in every named raw layout
+//! ([`RawPixelFormat::NAMED`], i.e. all but Bayer mosaics), for tests and benchmarks that should
+//! not depend on recorded footage.
+//!
+//! Run with `cargo run --bin gen_fixtures [output_dir]`. Files go to `tests/data/input` unless
+//! another directory is given and are named `synthetic_<format>_qcif.yuv`, e.g.
+//! `synthetic_nv12_qcif.yuv`, so they never overwrite the recorded fixtures.
+
+use anyhow::{anyhow, Result};
+use raw_to_jpeg::RawPixelFormat;
+use std::path::PathBuf;
+
+const WIDTH: usize = 176;
+const HEIGHT: usize = 144;
+
+/// Side of the checkerboard squares in the blue channel.
+const CHECKER_SIZE: usize = 16;
+
+/// The test scene: red rises left to right, green top to bottom, and blue alternates in a
+/// checkerboard, so flipped planes, swapped channels and misaligned rows all show.
+fn scene_rgb(x: usize, y: usize) -> [u8; 3] {
+    let blue = if (x / CHECKER_SIZE + y / CHECKER_SIZE).is_multiple_of(2) { 224 } else { 32 };
+    [(x * 255 / (WIDTH - 1)) as u8, (y * 255 / (HEIGHT - 1)) as u8, blue]
+}
+
+/// Full-range (JFIF) BT.601 conversion, matching what libjpeg applies to RGB input.
+fn rgb_to_ycbcr([r, g, b]: [u8; 3]) -> [u8; 3] {
+    let (r, g, b) = (r as f32, g as f32, b as f32);
+    let clamp = |value: f32| value.round().clamp(0.0, 255.0) as u8;
+    [
+        clamp(0.299 * r + 0.587 * g + 0.114 * b),
+        clamp(128.0 - 0.168_736 * r - 0.331_264 * g + 0.5 * b),
+        clamp(128.0 + 0.5 * r - 0.418_688 * g - 0.081_312 * b),
+    ]
+}
+
+/// Component `channel` of the scene in YCbCr, averaged over `x_div` x `y_div` blocks.
+fn ycbcr_plane(channel: usize, x_div: usize, y_div: usize) -> Vec<u8> {
+    let mut plane = Vec::with_capacity(WIDTH / x_div * (HEIGHT / y_div));
+    for block_y in 0..HEIGHT / y_div {
+        for block_x in 0..WIDTH / x_div {
+            let mut sum = 0;
+            for y in block_y * y_div..(block_y + 1) * y_div {
+                for x in block_x * x_div..(block_x + 1) * x_div {
+                    sum += rgb_to_ycbcr(scene_rgb(x, y))[channel] as usize;
+                }
+            }
+            let count = x_div * y_div;
+            plane.push(((sum + count / 2) / count) as u8);
+        }
+    }
+    plane
+}
+
+/// Packed pixels built from the scene color of every pixel, row by row.
+fn packed(pixel: impl Fn([u8; 3]) -> Vec<u8>) -> Vec<u8> {
+    (0..HEIGHT)
+        .flat_map(|y| (0..WIDTH).map(move |x| (x, y)))
+        .flat_map(|(x, y)| pixel(scene_rgb(x, y)))
+        .collect()
+}
+
+/// Interleaves two chroma planes sample by sample, as in the NV12/NV21 chroma plane.
+fn interleave(first: &[u8], second: &[u8]) -> Vec<u8> {
+    first.iter().zip(second).flat_map(|(&a, &b)| [a, b]).collect()
+}
+
+/// The scene in `format`, tightly packed.
+fn frame(format: RawPixelFormat) -> Vec<u8> {
+    let luma = ycbcr_plane(0, 1, 1);
+    match format {
+        RawPixelFormat::Rgb888 => packed(|[r, g, b]| vec![r, g, b]),
+        RawPixelFormat::Rgba8888 => packed(|[r, g, b]| vec![r, g, b, 255]),
+        RawPixelFormat::Bgr888 => packed(|[r, g, b]| vec![b, g, r]),
+        RawPixelFormat::Bgra8888 => packed(|[r, g, b]| vec![b, g, r, 255]),
+        RawPixelFormat::Gray8 => luma,
+        RawPixelFormat::Yuv420 => [luma, ycbcr_plane(1, 2, 2), ycbcr_plane(2, 2, 2)].concat(),
+        RawPixelFormat::Yv12 => [luma, ycbcr_plane(2, 2, 2), ycbcr_plane(1, 2, 2)].concat(),
+        RawPixelFormat::Yuv422 => [luma, ycbcr_plane(1, 2, 1), ycbcr_plane(2, 2, 1)].concat(),
+        RawPixelFormat::Yuv411 => [luma, ycbcr_plane(1, 4, 1), ycbcr_plane(2, 4, 1)].concat(),
+        RawPixelFormat::Yuv444 => [luma, ycbcr_plane(1, 1, 1), ycbcr_plane(2, 1, 1)].concat(),
+        RawPixelFormat::Yuv444Packed => packed(|rgb| rgb_to_ycbcr(rgb).to_vec()),
+        RawPixelFormat::Nv12 => [luma, interleave(&ycbcr_plane(1, 2, 2), &ycbcr_plane(2, 2, 2))].concat(),
+        RawPixelFormat::Nv21 => [luma, interleave(&ycbcr_plane(2, 2, 2), &ycbcr_plane(1, 2, 2))].concat(),
+        RawPixelFormat::Yuyv => {
+            let (u_plane, v_plane) = (ycbcr_plane(1, 2, 1), ycbcr_plane(2, 2, 1));
+            luma.chunks_exact(2)
+                .zip(u_plane.iter().zip(&v_plane))
+                .flat_map(|(pair, (&u, &v))| [pair[0], u, pair[1], v])
+                .collect()
+        }
+        // NV12 layout with each 8-bit sample widened to 10 bits in the high bits of an LE word
+        RawPixelFormat::P010 => frame(RawPixelFormat::Nv12)
+            .into_iter()
+            .flat_map(|sample| ((sample as u16) << 8).to_le_bytes())
+            .collect(),
+        // I420 layout with each 8-bit sample widened to 10 bits in the low bits of an LE word
+        RawPixelFormat::Yuv420P10 => frame(RawPixelFormat::Yuv420)
+            .into_iter()
+            .flat_map(|sample| ((sample as u16) << 2).to_le_bytes())
+            .collect(),
+        // Each 8-bit channel widened to the full 16-bit range, in LE words
+        RawPixelFormat::Rgb161616 => frame(RawPixelFormat::Rgb888)
+            .into_iter()
+            .flat_map(|sample| (sample as u16 * 257).to_le_bytes())
+            .collect(),
+        // Naive separation: black takes the darkness shared by all channels, C/M/Y the rest
+        RawPixelFormat::Cmyk => packed(|rgb| {
+            let max = rgb.into_iter().max().unwrap_or(0) as u32;
+            let ink = |channel: u8| if max == 0 { 0 } else { ((max - channel as u32) * 255 / max) as u8 };
+            vec![ink(rgb[0]), ink(rgb[1]), ink(rgb[2]), 255 - max as u8]
+        }),
+        #[cfg(feature = "bayer")]
+        RawPixelFormat::Bayer(_) => unreachable!("Bayer mosaics are not generated"),
+    }
+}
+
+fn main() -> Result<()> {
+    let dir = match std::env::args_os().nth(1) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data/input"),
+    };
+    std::fs::create_dir_all(&dir)?;
+
+    for format in RawPixelFormat::NAMED {
+        let data = frame(format);
+        let expected = format.expected_len(WIDTH, HEIGHT);
+        if data.len() != expected {
+            return Err(anyhow!("{} frame is {} bytes, expected {}", format.name(), data.len(), expected));
+        }
+        let path = dir.join(format!("synthetic_{}_qcif.yuv", format.name().to_ascii_lowercase()));
+        std::fs::write(&path, &data)?;
+        println!("Wrote {} ({} bytes)", path.display(), data.len());
+    }
+    Ok(())
+}
@@ -0,0 +1,81 @@
+//! What this build of the converter supports, for orchestration that asks a node at runtime.
+//!
+//! Input layouts and several outputs are cargo features, so two nodes of the same version can
+//! differ; these report what was compiled in.
+
+use crate::RawPixelFormat;
+use serde_json::{json, Value};
+use std::sync::OnceLock;
+
+/// Names (see [`RawPixelFormat::name`]) of the input layouts this build converts, in
+/// [`RawPixelFormat`] declaration order. "BAYER" stands for every Bayer pattern.
+pub fn supported_formats() -> &'static [&'static str] {
+    static FORMATS: OnceLock<Vec<&'static str>> = OnceLock::new();
+    FORMATS.get_or_init(|| {
+        let mut formats: Vec<&'static str> = RawPixelFormat::NAMED
+            .into_iter()
+            .filter(|format| format.is_enabled())
+            .map(RawPixelFormat::name)
+            .collect();
+        if cfg!(feature = "bayer") {
+            formats.push("BAYER");
+        }
+        formats
+    })
+}
+
+/// Compiled-in input formats, outputs and options of this build.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Crate version.
+    pub version: &'static str,
+    /// See [`supported_formats`].
+    pub formats: &'static [&'static str],
+    /// `output_format` names accepted: "jpeg", and "webp" with the `webp` feature.
+    pub output_formats: Vec<&'static str>,
+    /// Encoder of the packed RGB/BGR(A) and grayscale paths: "libjpeg-turbo", or "jpeg-encoder"
+    /// with the `pure-rust` feature.
+    pub encoder: &'static str,
+    /// Lossless JPEG output (`lossless`), done by libjpeg-turbo in every build.
+    pub lossless: bool,
+    /// Progressive output (`progressive`), done by libjpeg-turbo in every build.
+    pub progressive: bool,
+    /// Serving frames as an MJPEG stream over HTTP (`mjpeg_port`).
+    pub mjpeg: bool,
+    /// The C entry point (`raw_to_jpeg_ffi`).
+    pub ffi: bool,
+}
+
+impl Capabilities {
+    /// JSON object with the fields above, as published on the `capabilities` topic.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "version": self.version,
+            "formats": self.formats,
+            "output_formats": self.output_formats,
+            "encoder": self.encoder,
+            "lossless": self.lossless,
+            "progressive": self.progressive,
+            "mjpeg": self.mjpeg,
+            "ffi": self.ffi,
+        })
+    }
+}
+
+/// Capabilities of this build.
+pub fn capabilities() -> Capabilities {
+    let mut output_formats = vec!["jpeg"];
+    if cfg!(feature = "webp") {
+        output_formats.push("webp");
+    }
+    Capabilities {
+        version: env!("CARGO_PKG_VERSION"),
+        formats: supported_formats(),
+        output_formats,
+        encoder: if cfg!(feature = "pure-rust") { "jpeg-encoder" } else { "libjpeg-turbo" },
+        lossless: true,
+        progressive: true,
+        mjpeg: cfg!(feature = "mjpeg"),
+        ffi: cfg!(feature = "ffi"),
+    }
+}
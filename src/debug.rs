@@ -0,0 +1,67 @@
+//! Debug frames: a converted JPEG bundled with a reference to the raw frame it came from, so an
+//! output can be traced back to its exact input without shipping the raw bytes.
+//!
+//! There is no message type pairing an image with its source, so the bundle is a
+//! `RelativePathFile`: `data` holds the JPEG, `header` the frame header and `path` the
+//! reference, a file name like `yuv420_176x144_8f3a0c1e55d2b407.jpg` naming the source layout,
+//! size and content hash.
+
+use crate::{raw_frame, RawPixelFormat};
+use make87_messages::file::RelativePathFile;
+use make87_messages::image::compressed::ImageJpeg;
+use make87_messages::image::uncompressed::ImageRawAny;
+
+/// Identifies the raw frame a JPEG was converted from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceReference {
+    pub format: RawPixelFormat,
+    pub width: usize,
+    pub height: usize,
+    /// Length of the raw data in bytes, including any padding.
+    pub len: usize,
+    /// [`fnv1a_64`] of the raw data.
+    pub hash: u64,
+}
+
+impl SourceReference {
+    /// The reference of `rgb_any`, or `None` if it carries no image.
+    pub fn of(rgb_any: &ImageRawAny) -> Option<Self> {
+        let (data, width, height, format) = raw_frame(rgb_any)?;
+        Some(SourceReference {
+            format,
+            width,
+            height,
+            len: data.len(),
+            hash: fnv1a_64(data),
+        })
+    }
+
+    /// File name carrying the reference, used as the debug frame's path.
+    pub fn file_name(&self) -> String {
+        format!(
+            "{}_{}x{}_{:016x}.jpg",
+            self.format.name().to_ascii_lowercase(),
+            self.width,
+            self.height,
+            self.hash
+        )
+    }
+}
+
+/// 64-bit FNV-1a hash. Unlike `DefaultHasher` it is fixed across builds and platforms, so a
+/// hash in a debug frame can be compared against one computed elsewhere from the raw bytes.
+pub fn fnv1a_64(data: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    data.iter()
+        .fold(OFFSET_BASIS, |hash, byte| (hash ^ *byte as u64).wrapping_mul(PRIME))
+}
+
+/// Bundles `jpeg` with the reference of its `source` into a debug frame.
+pub fn debug_frame(jpeg: &ImageJpeg, source: &SourceReference) -> RelativePathFile {
+    RelativePathFile {
+        header: jpeg.header.clone(),
+        path: source.file_name(),
+        data: jpeg.data.clone(),
+    }
+}
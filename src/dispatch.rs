@@ -0,0 +1,107 @@
+use make87_messages::core::Header;
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime};
+
+/// Releases results tagged with a sequence number in sequence order.
+///
+/// Frames are numbered as they are received and handed to whichever worker is free, so they can
+/// finish out of order. Results that complete ahead of an earlier frame are held back until the
+/// gap is filled; every sequence number must eventually be pushed (failed conversions included)
+/// or later results stay pending.
+#[derive(Debug)]
+pub struct ReorderBuffer<T> {
+    next_seq: u64,
+    pending: BTreeMap<u64, T>,
+}
+
+impl<T> ReorderBuffer<T> {
+    pub fn new() -> Self {
+        ReorderBuffer {
+            next_seq: 0,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    /// Accepts the result for `seq` and returns every result that is now ready, in order.
+    pub fn push(&mut self, seq: u64, item: T) -> Vec<T> {
+        self.pending.insert(seq, item);
+        let mut ready = Vec::new();
+        while let Some(item) = self.pending.remove(&self.next_seq) {
+            ready.push(item);
+            self.next_seq += 1;
+        }
+        ready
+    }
+
+    /// Number of results held back waiting for an earlier one.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+impl<T> Default for ReorderBuffer<T> {
+    fn default() -> Self {
+        ReorderBuffer::new()
+    }
+}
+
+/// Numbers frames for the logs, so one frame can be followed from receipt to publish.
+///
+/// Frames whose header carries a non-zero `reference_id` keep that id, which also lines them up
+/// with the producer's logs; others get the value of a local counter that advances with every
+/// frame.
+#[derive(Debug, Default)]
+pub struct FrameSequence {
+    next: u64,
+}
+
+impl FrameSequence {
+    pub fn new() -> Self {
+        FrameSequence::default()
+    }
+
+    /// Returns the sequence number to log a received frame with `header` under.
+    pub fn assign(&mut self, header: Option<&Header>) -> u64 {
+        let counted = self.next;
+        self.next += 1;
+        match header {
+            Some(header) if header.reference_id != 0 => header.reference_id,
+            _ => counted,
+        }
+    }
+}
+
+/// Drops frames whose header timestamp is older than `max_age`, so a loaded converter skips
+/// stale frames instead of falling further behind.
+///
+/// Frames without a timestamp are never dropped, and neither are frames stamped in the future
+/// (e.g. by a camera whose clock runs ahead).
+#[derive(Debug, Clone)]
+pub struct StaleFrameFilter {
+    max_age: Duration,
+    dropped: u64,
+}
+
+impl StaleFrameFilter {
+    pub fn new(max_age: Duration) -> Self {
+        StaleFrameFilter { max_age, dropped: 0 }
+    }
+
+    /// Returns the age of a frame with `header` if it is older than `max_age` at `now`, counting
+    /// it as dropped.
+    pub fn check(&mut self, header: Option<&Header>, now: SystemTime) -> Option<Duration> {
+        let timestamp = header?.timestamp.as_ref()?;
+        let (Ok(seconds), Ok(nanos)) = (u64::try_from(timestamp.seconds), u32::try_from(timestamp.nanos)) else {
+            return None;
+        };
+        let stamped = SystemTime::UNIX_EPOCH.checked_add(Duration::new(seconds, nanos))?;
+        let age = now.duration_since(stamped).ok().filter(|age| *age > self.max_age)?;
+        self.dropped += 1;
+        Some(age)
+    }
+
+    /// Frames dropped so far.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
@@ -0,0 +1,248 @@
+use crate::pixels::Roi;
+use make87_messages::core::Header;
+use std::fmt;
+
+/// Failures of the conversion functions, distinguishable without matching on message text.
+#[derive(Debug)]
+pub enum ConversionError {
+    /// The `ImageRawAny` message has no variant set. Carries the message header, if any,
+    /// so the empty frame can be traced back to its source.
+    NoImageData { header: Option<Header> },
+    /// The frame declares a width or height of zero.
+    ZeroDimension { width: usize, height: usize },
+    /// The frame declares more pixels than the configured limit allows.
+    TooManyPixels {
+        width: usize,
+        height: usize,
+        max_pixels: usize,
+    },
+    /// The pixel buffer is shorter than its declared dimensions require.
+    BufferTooSmall {
+        format: &'static str,
+        expected: usize,
+        got: usize,
+    },
+    /// The pixel buffer is not the exact size a format with a fixed packing requires.
+    BufferSizeMismatch {
+        format: &'static str,
+        expected: usize,
+        got: usize,
+    },
+    /// The byte size of a `width` x `height` frame in `format` does not fit in `usize`, as can
+    /// happen on 32-bit targets. Reported instead of letting the size wrap around.
+    DimensionOverflow {
+        format: &'static str,
+        width: usize,
+        height: usize,
+    },
+    /// The dimensions are not valid for the format (e.g. odd width for 4:2:x chroma).
+    InvalidDimensions {
+        format: &'static str,
+        requirement: &'static str,
+        width: usize,
+        height: usize,
+    },
+    /// The row stride is smaller than the row of pixels it has to hold.
+    InvalidStride {
+        format: &'static str,
+        stride: usize,
+        width: usize,
+    },
+    /// The configured region of interest is empty or extends past the frame edges.
+    RoiOutOfBounds { roi: Roi, width: usize, height: usize },
+    /// Downscaling by `factor` would leave no pixels.
+    InvalidScale { width: usize, height: usize, factor: usize },
+    /// A produced JPEG does not decode to the expected size.
+    DimensionMismatch {
+        expected: (usize, usize),
+        got: (usize, usize),
+    },
+    /// The requested encoding does not support this pixel format.
+    UnsupportedFormat(String),
+    /// A subsampling name from the config is not recognised.
+    UnsupportedSubsampling(String),
+    /// A 16-bit grayscale mapping name from the config is not recognised.
+    UnsupportedGray16Map(String),
+    /// A color space name from the config is not recognised.
+    UnsupportedColorSpace(String),
+    /// An entry of `output_profiles` in the config is invalid.
+    InvalidOutputProfile(String),
+    /// An entry of `format_quality` in the config is invalid.
+    InvalidFormatQuality(String),
+    /// A JPEG quality from the config or environment is not an integer between 1 and 100.
+    /// `setting` names where it was read from.
+    InvalidQuality { setting: &'static str, value: String },
+    /// An `aspect` from the config is not of the form `W:H` with positive integers.
+    InvalidAspectRatio(String),
+    /// A transform (flip/rotation) name from the config is not recognised.
+    UnsupportedTransform(String),
+    /// A dedup mode name from the config is not recognised.
+    UnsupportedDedupMode(String),
+    /// An alpha mode name from the config is not recognised.
+    UnsupportedAlphaMode(String),
+    /// A chroma upsampling name from the config is not recognised.
+    UnsupportedChromaUpsample(String),
+    /// A subscriber queue discipline name from the config is not recognised.
+    UnsupportedQueueDiscipline(String),
+    /// An `on_error` policy name from the config is not recognised.
+    UnsupportedErrorPolicy(String),
+    /// An output format name from the config is not recognised or not built in.
+    UnsupportedOutputFormat(String),
+    /// A JPEG header style name from the config is not recognised.
+    UnsupportedHeaderStyle(String),
+    /// The watched config file is not a JSON object of known, valid settings.
+    InvalidConfigFile(String),
+    /// A [`crate::stream::StreamingEncoder`] received more or fewer rows than the frame height.
+    RowCountMismatch { expected: usize, got: usize },
+    /// Writing the output to a sink failed.
+    Io(std::io::Error),
+    /// libjpeg-turbo rejected the input or failed to compress.
+    TurboJpeg(turbojpeg::Error),
+    /// The pure-Rust encoder rejected the input or failed to compress.
+    #[cfg(feature = "pure-rust")]
+    Encoder(jpeg_encoder::EncodingError),
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConversionError::NoImageData { header } => {
+                write!(f, "No image data in ImageRawAny")?;
+                if let Some(header) = header {
+                    match &header.timestamp {
+                        Some(timestamp) => write!(f, " (timestamp {}.{:09}", timestamp.seconds, timestamp.nanos)?,
+                        None => write!(f, " (no timestamp")?,
+                    }
+                    if !header.entity_path.is_empty() {
+                        write!(f, ", entity_path {}", header.entity_path)?;
+                    }
+                    if header.reference_id != 0 {
+                        write!(f, ", reference_id {}", header.reference_id)?;
+                    }
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+            ConversionError::ZeroDimension { width, height } => {
+                write!(f, "image has zero dimension ({}x{})", width, height)
+            }
+            ConversionError::TooManyPixels {
+                width,
+                height,
+                max_pixels,
+            } => write!(f, "image of {}x{} exceeds the limit of {} pixels", width, height, max_pixels),
+            ConversionError::BufferTooSmall { format, expected, got } => {
+                write!(f, "{} data too small: expected {}, got {}", format, expected, got)
+            }
+            ConversionError::BufferSizeMismatch { format, expected, got } => {
+                write!(f, "{} data has the wrong size: expected {}, got {}", format, expected, got)
+            }
+            ConversionError::DimensionOverflow { format, width, height } => {
+                write!(f, "{} size of {}x{} overflows the address space", format, width, height)
+            }
+            ConversionError::InvalidDimensions {
+                format,
+                requirement,
+                width,
+                height,
+            } => write!(f, "{} requires {}, got {}x{}", format, requirement, width, height),
+            ConversionError::InvalidStride { format, stride, width } => {
+                write!(f, "{} stride {} is smaller than the width {}", format, stride, width)
+            }
+            ConversionError::RoiOutOfBounds { roi, width, height } => write!(
+                f,
+                "ROI {}x{}+{}+{} does not fit in {}x{} frame",
+                roi.width, roi.height, roi.x, roi.y, width, height
+            ),
+            ConversionError::InvalidScale { width, height, factor } => {
+                write!(f, "Cannot downscale {}x{} by a factor of {}", width, height, factor)
+            }
+            ConversionError::DimensionMismatch { expected, got } => write!(
+                f,
+                "JPEG dimensions mismatch: expected {}x{}, got {}x{}",
+                expected.0, expected.1, got.0, got.1
+            ),
+            ConversionError::UnsupportedFormat(what) => write!(f, "Unsupported format: {}", what),
+            ConversionError::UnsupportedSubsampling(name) => {
+                write!(f, "Unknown subsampling '{}': expected one of 420, 422, 444, gray", name)
+            }
+            ConversionError::UnsupportedGray16Map(name) => {
+                write!(f, "Unknown gray16 mapping '{}': expected shift8 or minmax", name)
+            }
+            ConversionError::UnsupportedColorSpace(name) => {
+                write!(f, "Unknown color space '{}': expected bt601 or bt709", name)
+            }
+            ConversionError::InvalidOutputProfile(reason) => write!(f, "Invalid output profile: {}", reason),
+            ConversionError::InvalidFormatQuality(reason) => write!(f, "Invalid format quality: {}", reason),
+            ConversionError::InvalidQuality { setting, value } => {
+                write!(f, "{} must be an integer between 1 and 100, got '{}'", setting, value)
+            }
+            ConversionError::InvalidAspectRatio(value) => {
+                write!(f, "Invalid aspect ratio '{}': expected W:H, e.g. 16:9", value)
+            }
+            ConversionError::UnsupportedTransform(name) => write!(
+                f,
+                "Unknown transform '{}': expected one of none, fliph, flipv, rot90, rot180, rot270",
+                name
+            ),
+            ConversionError::UnsupportedDedupMode(name) => {
+                write!(f, "Unknown dedup mode '{}': expected off, exact or sampled", name)
+            }
+            ConversionError::UnsupportedAlphaMode(name) => {
+                write!(f, "Unknown alpha mode '{}': expected ignore or composite_over", name)
+            }
+            ConversionError::UnsupportedChromaUpsample(name) => {
+                write!(f, "Unknown chroma upsampling '{}': expected nearest or bilinear", name)
+            }
+            ConversionError::UnsupportedQueueDiscipline(name) => {
+                write!(f, "Unknown subscriber queue '{}': expected fifo or ring", name)
+            }
+            ConversionError::UnsupportedErrorPolicy(name) => {
+                write!(f, "Unknown on_error policy '{}': expected continue or stop", name)
+            }
+            ConversionError::UnsupportedOutputFormat(name) => write!(f, "Unsupported output format: {}", name),
+            ConversionError::UnsupportedHeaderStyle(name) => {
+                write!(f, "Unknown JPEG header style '{}': expected jfif, exif or minimal", name)
+            }
+            ConversionError::InvalidConfigFile(reason) => write!(f, "Invalid config file: {}", reason),
+            ConversionError::RowCountMismatch { expected, got } => {
+                write!(f, "streamed {} rows for a frame of height {}", got, expected)
+            }
+            ConversionError::Io(e) => write!(f, "I/O error: {}", e),
+            ConversionError::TurboJpeg(e) => write!(f, "turbojpeg error: {}", e),
+            #[cfg(feature = "pure-rust")]
+            ConversionError::Encoder(e) => write!(f, "jpeg-encoder error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ConversionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConversionError::TurboJpeg(e) => Some(e),
+            ConversionError::Io(e) => Some(e),
+            #[cfg(feature = "pure-rust")]
+            ConversionError::Encoder(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<turbojpeg::Error> for ConversionError {
+    fn from(e: turbojpeg::Error) -> Self {
+        ConversionError::TurboJpeg(e)
+    }
+}
+
+impl From<std::io::Error> for ConversionError {
+    fn from(e: std::io::Error) -> Self {
+        ConversionError::Io(e)
+    }
+}
+
+#[cfg(feature = "pure-rust")]
+impl From<jpeg_encoder::EncodingError> for ConversionError {
+    fn from(e: jpeg_encoder::EncodingError) -> Self {
+        ConversionError::Encoder(e)
+    }
+}
@@ -0,0 +1,135 @@
+use make87_messages::google::protobuf::Timestamp;
+
+const TAG_EXIF_IFD_POINTER: u16 = 0x8769;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+const TAG_OFFSET_TIME_ORIGINAL: u16 = 0x9011;
+const TAG_SUB_SEC_TIME_ORIGINAL: u16 = 0x9291;
+
+const EXIF_IDENTIFIER: &[u8; 6] = b"Exif\0\0";
+
+const TYPE_ASCII: u16 = 2;
+const TYPE_LONG: u16 = 4;
+
+/// Builds a complete APP1 segment (marker included) carrying `timestamp` as EXIF
+/// DateTimeOriginal / SubSecTimeOriginal, in UTC (OffsetTimeOriginal "+00:00").
+pub fn timestamp_app1(timestamp: &Timestamp) -> Vec<u8> {
+    let (date_time, sub_sec) = format_timestamp(timestamp);
+    let ascii_values: [(u16, Vec<u8>); 3] = [
+        (TAG_DATE_TIME_ORIGINAL, nul_terminated(&date_time)),
+        (TAG_OFFSET_TIME_ORIGINAL, nul_terminated("+00:00")),
+        (TAG_SUB_SEC_TIME_ORIGINAL, nul_terminated(&sub_sec)),
+    ];
+
+    // Offsets are relative to the start of the TIFF header. IFD0 only points at the Exif IFD,
+    // whose string values follow it directly (each padded to an even offset).
+    let ifd0_offset = 8u32;
+    let exif_ifd_offset = ifd0_offset + ifd_len(1);
+    let mut value_offset = exif_ifd_offset + ifd_len(ascii_values.len());
+
+    let mut tiff = Vec::new();
+    tiff.extend_from_slice(b"MM");
+    tiff.extend_from_slice(&42u16.to_be_bytes());
+    tiff.extend_from_slice(&ifd0_offset.to_be_bytes());
+
+    tiff.extend_from_slice(&1u16.to_be_bytes());
+    push_entry(&mut tiff, TAG_EXIF_IFD_POINTER, TYPE_LONG, 1, exif_ifd_offset);
+    tiff.extend_from_slice(&0u32.to_be_bytes());
+
+    tiff.extend_from_slice(&(ascii_values.len() as u16).to_be_bytes());
+    for (tag, value) in &ascii_values {
+        push_entry(&mut tiff, *tag, TYPE_ASCII, value.len() as u32, value_offset);
+        value_offset += value.len().next_multiple_of(2) as u32;
+    }
+    tiff.extend_from_slice(&0u32.to_be_bytes());
+
+    for (_, value) in &ascii_values {
+        tiff.extend_from_slice(value);
+        if !value.len().is_multiple_of(2) {
+            tiff.push(0);
+        }
+    }
+
+    app1_segment(&tiff)
+}
+
+/// Builds a complete APP1 segment (marker included) holding an EXIF header with an empty IFD0,
+/// for JPEGs that should be recognised as EXIF without carrying any tags.
+pub fn empty_app1() -> Vec<u8> {
+    let mut tiff = Vec::new();
+    tiff.extend_from_slice(b"MM");
+    tiff.extend_from_slice(&42u16.to_be_bytes());
+    tiff.extend_from_slice(&8u32.to_be_bytes());
+    tiff.extend_from_slice(&0u16.to_be_bytes());
+    tiff.extend_from_slice(&0u32.to_be_bytes());
+    app1_segment(&tiff)
+}
+
+/// Whether the APP1 `payload` (after the length field) is an EXIF segment.
+pub fn is_exif_payload(payload: &[u8]) -> bool {
+    payload.starts_with(EXIF_IDENTIFIER)
+}
+
+/// Inserts a complete marker segment directly after the SOI marker of `jpeg`.
+pub fn insert_after_soi(jpeg: &mut Vec<u8>, segment: &[u8]) {
+    jpeg.splice(2..2, segment.iter().copied());
+}
+
+/// Formats a protobuf timestamp as EXIF "YYYY:MM:DD HH:MM:SS" (UTC) plus 9-digit sub-seconds.
+fn format_timestamp(timestamp: &Timestamp) -> (String, String) {
+    let days = timestamp.seconds.div_euclid(86_400);
+    let secs_of_day = timestamp.seconds.rem_euclid(86_400);
+    let (year, month, day) = civil_from_days(days);
+    let date_time = format!(
+        "{:04}:{:02}:{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60
+    );
+    let sub_sec = format!("{:09}", timestamp.nanos.clamp(0, 999_999_999));
+    (date_time, sub_sec)
+}
+
+/// Converts days since 1970-01-01 to a proleptic Gregorian (year, month, day).
+/// Howard Hinnant's `civil_from_days` algorithm.
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Wraps a TIFF structure in an APP1 segment with the EXIF identifier.
+fn app1_segment(tiff: &[u8]) -> Vec<u8> {
+    let mut segment = Vec::with_capacity(tiff.len() + 10);
+    segment.extend_from_slice(&[0xFF, 0xE1]);
+    segment.extend_from_slice(&((tiff.len() + 8) as u16).to_be_bytes());
+    segment.extend_from_slice(EXIF_IDENTIFIER);
+    segment.extend_from_slice(tiff);
+    segment
+}
+
+fn ifd_len(entries: usize) -> u32 {
+    (2 + entries * 12 + 4) as u32
+}
+
+fn push_entry(tiff: &mut Vec<u8>, tag: u16, field_type: u16, count: u32, value_or_offset: u32) {
+    tiff.extend_from_slice(&tag.to_be_bytes());
+    tiff.extend_from_slice(&field_type.to_be_bytes());
+    tiff.extend_from_slice(&count.to_be_bytes());
+    tiff.extend_from_slice(&value_or_offset.to_be_bytes());
+}
+
+fn nul_terminated(value: &str) -> Vec<u8> {
+    let mut bytes = value.as_bytes().to_vec();
+    bytes.push(0);
+    bytes
+}
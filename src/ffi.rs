@@ -0,0 +1,120 @@
+//! C-compatible entry point for hosts outside the make87 stack (C, Python via ctypes/cffi).
+//!
+//! Built with `--features ffi`; `cargo rustc --lib --release --features ffi --crate-type cdylib`
+//! produces the shared library. [`raw_to_jpeg_ffi`] compresses one raw frame with
+//! [`compress_raw`]; the JPEG it returns is owned by this library and must be released with
+//! [`raw_to_jpeg_free`], never with the host's `free`.
+
+use crate::{compress_raw, RawPixelFormat, Result};
+use std::panic::{self, AssertUnwindSafe};
+use std::{ptr, slice};
+use turbojpeg::Compressor;
+
+/// The JPEG was written to `out_ptr`/`out_len`.
+pub const RAW_TO_JPEG_OK: i32 = 0;
+/// A required pointer was null.
+pub const RAW_TO_JPEG_ERR_NULL: i32 = -1;
+/// `format_code` is not one of [`FORMAT_CODES`].
+pub const RAW_TO_JPEG_ERR_FORMAT: i32 = -2;
+/// `quality` is not within 1-100.
+pub const RAW_TO_JPEG_ERR_QUALITY: i32 = -3;
+/// The frame did not convert: wrong size for its dimensions, unsupported layout, or a
+/// libjpeg-turbo failure.
+pub const RAW_TO_JPEG_ERR_CONVERSION: i32 = -4;
+/// The conversion panicked; the panic was caught at the boundary.
+pub const RAW_TO_JPEG_ERR_PANIC: i32 = -5;
+
+/// Layouts by their `format_code`, which is the index into this table. Codes are stable: new
+/// layouts are only ever appended.
+pub const FORMAT_CODES: [RawPixelFormat; 18] = [
+    RawPixelFormat::Rgb888,
+    RawPixelFormat::Rgba8888,
+    RawPixelFormat::Bgr888,
+    RawPixelFormat::Bgra8888,
+    RawPixelFormat::Gray8,
+    RawPixelFormat::Yuv420,
+    RawPixelFormat::Yv12,
+    RawPixelFormat::Yuv422,
+    RawPixelFormat::Yuv444,
+    RawPixelFormat::Yuv444Packed,
+    RawPixelFormat::Nv12,
+    RawPixelFormat::Nv21,
+    RawPixelFormat::Yuyv,
+    RawPixelFormat::P010,
+    RawPixelFormat::Rgb161616,
+    RawPixelFormat::Cmyk,
+    RawPixelFormat::Yuv411,
+    RawPixelFormat::Yuv420P10,
+];
+
+/// Compresses the `len` bytes at `data`, a `width` x `height` frame in the layout
+/// `format_code` (see [`FORMAT_CODES`]), as a JPEG at `quality` (1-100) with a fresh compressor.
+///
+/// Returns [`RAW_TO_JPEG_OK`] and stores the JPEG's address and length in `*out_ptr` and
+/// `*out_len`; the caller owns the JPEG and must pass both to [`raw_to_jpeg_free`]. On any
+/// other return code `*out_ptr` is set to null and `*out_len` to 0 (if they are not null
+/// themselves), and nothing needs freeing.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes, and `out_ptr` and `out_len` must be valid for
+/// writes. None of them may be used by another thread during the call.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn raw_to_jpeg_ffi(
+    data: *const u8,
+    len: usize,
+    width: u32,
+    height: u32,
+    format_code: u32,
+    quality: i32,
+    out_ptr: *mut *mut u8,
+    out_len: *mut usize,
+) -> i32 {
+    if out_ptr.is_null() || out_len.is_null() {
+        return RAW_TO_JPEG_ERR_NULL;
+    }
+    *out_ptr = ptr::null_mut();
+    *out_len = 0;
+    if data.is_null() {
+        return RAW_TO_JPEG_ERR_NULL;
+    }
+    let Some(format) = FORMAT_CODES.get(format_code as usize).copied() else {
+        return RAW_TO_JPEG_ERR_FORMAT;
+    };
+    if !(1..=100).contains(&quality) {
+        return RAW_TO_JPEG_ERR_QUALITY;
+    }
+
+    let input = slice::from_raw_parts(data, len);
+    // A panic must not unwind into the host's frames
+    let converted = panic::catch_unwind(AssertUnwindSafe(|| -> Result<Vec<u8>> {
+        let mut compressor = Compressor::new()?;
+        compressor.set_quality(quality)?;
+        compress_raw(input, width as usize, height as usize, format, &mut compressor)
+    }));
+    match converted {
+        Ok(Ok(jpeg)) => {
+            let jpeg = Box::into_raw(jpeg.into_boxed_slice());
+            *out_len = jpeg.len();
+            *out_ptr = jpeg.cast::<u8>();
+            RAW_TO_JPEG_OK
+        }
+        Ok(Err(_)) => RAW_TO_JPEG_ERR_CONVERSION,
+        Err(_) => RAW_TO_JPEG_ERR_PANIC,
+    }
+}
+
+/// Releases a JPEG returned by [`raw_to_jpeg_ffi`]. A null `ptr` is ignored.
+///
+/// # Safety
+///
+/// `ptr` and `len` must be exactly what [`raw_to_jpeg_ffi`] returned, and the JPEG must not be
+/// used or freed again afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn raw_to_jpeg_free(ptr: *mut u8, len: usize) {
+    if ptr.is_null() {
+        return;
+    }
+    drop(Box::from_raw(ptr::slice_from_raw_parts_mut(ptr, len)));
+}
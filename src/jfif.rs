@@ -0,0 +1,75 @@
+//! Pixel density in the JFIF APP0 header, for print and document pipelines.
+//!
+//! libjpeg-turbo always writes a JFIF header with an aspect-ratio-only density (units 0, 1:1)
+//! and the safe `Compressor` cannot change it, so the fields are patched in the finished JPEG.
+
+const MARKER_APP0: u8 = 0xE0;
+const JFIF_IDENTIFIER: &[u8; 5] = b"JFIF\0";
+
+/// JFIF density unit: no unit, the values only give the pixel aspect ratio.
+pub const UNITS_ASPECT_RATIO: u8 = 0;
+/// JFIF density unit: dots per inch.
+pub const UNITS_DPI: u8 = 1;
+/// JFIF density unit: dots per centimetre.
+pub const UNITS_DPCM: u8 = 2;
+
+/// Horizontal and vertical resolution in dots per inch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Density {
+    pub x: u16,
+    pub y: u16,
+}
+
+/// Writes `density` as DPI into the JFIF APP0 segment of `jpeg`. JPEGs without one (lossless
+/// RGB output carries no JFIF header) get a JFIF 1.01 APP0 segment directly after SOI.
+pub fn set_density(jpeg: &mut Vec<u8>, density: Density) {
+    let fields = [
+        &[UNITS_DPI][..],
+        &density.x.to_be_bytes(),
+        &density.y.to_be_bytes(),
+    ]
+    .concat();
+    match find_jfif_app0(jpeg) {
+        // Units and densities follow the identifier and the two version bytes
+        Some(payload) => jpeg[payload + 7..payload + 12].copy_from_slice(&fields),
+        None => {
+            let mut segment = vec![0xFF, MARKER_APP0, 0, 16];
+            segment.extend_from_slice(JFIF_IDENTIFIER);
+            segment.extend_from_slice(&[1, 1]);
+            segment.extend_from_slice(&fields);
+            // No thumbnail
+            segment.extend_from_slice(&[0, 0]);
+            jpeg.splice(2..2, segment);
+        }
+    }
+}
+
+/// Reads the density units and x/y values from the JFIF APP0 segment of `jpeg`, if it has one.
+pub fn read_density(jpeg: &[u8]) -> Option<(u8, u16, u16)> {
+    let payload = find_jfif_app0(jpeg)?;
+    let fields = &jpeg[payload + 7..payload + 12];
+    Some((
+        fields[0],
+        u16::from_be_bytes([fields[1], fields[2]]),
+        u16::from_be_bytes([fields[3], fields[4]]),
+    ))
+}
+
+/// Whether the APP0 `payload` (after the length field) is a JFIF segment.
+pub fn is_jfif_payload(payload: &[u8]) -> bool {
+    payload.starts_with(JFIF_IDENTIFIER)
+}
+
+/// Offset of the payload of the first JFIF APP0 segment among the APPn segments after SOI.
+fn find_jfif_app0(jpeg: &[u8]) -> Option<usize> {
+    let mut pos = 2;
+    while pos + 4 <= jpeg.len() && jpeg[pos] == 0xFF && (0xE0..=0xEF).contains(&jpeg[pos + 1]) {
+        let len = u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]) as usize;
+        let payload = pos + 4;
+        if jpeg[pos + 1] == MARKER_APP0 && len >= 14 && jpeg.get(payload..payload + 5) == Some(JFIF_IDENTIFIER) {
+            return (payload + 12 <= jpeg.len()).then_some(payload);
+        }
+        pos += 2 + len;
+    }
+    None
+}
@@ -1,145 +1,2605 @@
-use anyhow::{Result, anyhow};
+#[cfg(feature = "bayer")]
+pub mod bayer;
+mod backend;
+pub mod capabilities;
+pub mod debug;
+pub mod dispatch;
+pub mod error;
+pub mod exif;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod jfif;
+pub mod lossless;
+pub mod metrics;
+pub mod mjpeg;
+pub mod phash;
+pub mod pixels;
+pub mod record;
+pub mod reload;
+pub mod restart;
+pub mod shutdown;
+pub mod stream;
+pub mod tile;
+mod tj3;
+pub mod topics;
+
+pub use error::ConversionError;
+use make87_messages::core::Header;
 use make87_messages::image::compressed::ImageJpeg;
 use make87_messages::image::uncompressed::ImageRawAny;
-use turbojpeg::{Compressor, Image, PixelFormat, YuvImage, Subsamp};
+use backend::JpegBackend;
+use jfif::Density;
+use pixels::{
+    AlphaMode, AspectRatio, ColorMatrix, DepthReduction, GammaLut, Gray16Map, Orientation, PackedFrame, Roi,
+    TenBitReduction, Yuv420Planes,
+};
+use restart::RestartInterval;
+use std::borrow::Cow;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use turbojpeg::{Compressor, Decompressor, Image, PixelFormat, Transform, YuvImage, Subsamp};
+
+/// Result of the conversion functions in this crate.
+pub type Result<T, E = ConversionError> = std::result::Result<T, E>;
+
+/// Grows `output` to `max_len` only if it is shorter, lets `compress` write the JPEG into it and
+/// truncates it to the written length, so a buffer reused across frames keeps its allocation.
+fn compress_into_vec(
+    output: &mut Vec<u8>,
+    max_len: usize,
+    compress: impl FnOnce(&mut [u8]) -> Result<usize>,
+) -> Result<()> {
+    if output.len() < max_len {
+        output.resize(max_len, 0);
+    }
+    let len = compress(output)?;
+    output.truncate(len);
+    Ok(())
+}
+
+/// The compression steps the conversion functions need from an encoder. Implemented by
+/// turbojpeg's [`Compressor`]; a compressor pool can implement it on the handle it lends out, so
+/// [`rgb_to_jpeg`] does not need one `&mut Compressor` borrowed for a whole loop.
+pub trait Compress {
+    /// Compresses the packed (or grayscale) `image` into `output`, replacing its contents.
+    fn compress_rgb(&mut self, image: Image<&[u8]>, output: &mut Vec<u8>) -> Result<()>;
+    /// Compresses the planar `image` into `output`, replacing its contents. The image's own
+    /// subsampling applies, not [`set_subsamp`](Self::set_subsamp).
+    fn compress_yuv(&mut self, image: YuvImage<&[u8]>, output: &mut Vec<u8>) -> Result<()>;
+    /// Quality (1-100) of the following compressions.
+    fn set_quality(&mut self, quality: i32) -> Result<()>;
+    /// Chroma subsampling of the following [`compress_rgb`](Self::compress_rgb) calls.
+    fn set_subsamp(&mut self, subsamp: Subsamp) -> Result<()>;
+    /// Whether the following compressions compute optimal Huffman tables.
+    fn set_optimize(&mut self, optimize: bool) -> Result<()>;
+}
+
+impl Compress for Compressor {
+    /// Writes into `output` bounded by libjpeg-turbo's own worst-case size for the current
+    /// subsampling, then truncates it to the actual JPEG length.
+    fn compress_rgb(&mut self, image: Image<&[u8]>, output: &mut Vec<u8>) -> Result<()> {
+        let max_len = self.buf_len(image.width, image.height)?;
+        compress_into_vec(output, max_len, |buf| Ok(self.compress_to_slice(image, buf)?))
+    }
+
+    fn compress_yuv(&mut self, image: YuvImage<&[u8]>, output: &mut Vec<u8>) -> Result<()> {
+        let max_len = turbojpeg::compressed_buf_len(image.width, image.height, image.subsamp)?;
+        compress_into_vec(output, max_len, |buf| Ok(self.compress_yuv_to_slice(image, buf)?))
+    }
+
+    fn set_quality(&mut self, quality: i32) -> Result<()> {
+        Ok(Compressor::set_quality(self, quality)?)
+    }
+
+    fn set_subsamp(&mut self, subsamp: Subsamp) -> Result<()> {
+        Ok(Compressor::set_subsamp(self, subsamp)?)
+    }
+
+    fn set_optimize(&mut self, optimize: bool) -> Result<()> {
+        Ok(Compressor::set_optimize(self, optimize)?)
+    }
+}
+
+impl<C: Compress + ?Sized> Compress for &mut C {
+    fn compress_rgb(&mut self, image: Image<&[u8]>, output: &mut Vec<u8>) -> Result<()> {
+        (**self).compress_rgb(image, output)
+    }
+
+    fn compress_yuv(&mut self, image: YuvImage<&[u8]>, output: &mut Vec<u8>) -> Result<()> {
+        (**self).compress_yuv(image, output)
+    }
+
+    fn set_quality(&mut self, quality: i32) -> Result<()> {
+        (**self).set_quality(quality)
+    }
+
+    fn set_subsamp(&mut self, subsamp: Subsamp) -> Result<()> {
+        (**self).set_subsamp(subsamp)
+    }
+
+    fn set_optimize(&mut self, optimize: bool) -> Result<()> {
+        (**self).set_optimize(optimize)
+    }
+}
+
+/// Compresses a tightly packed interleaved buffer (no row padding) to JPEG.
+///
+/// `ImageRawAny` only carries RGB-ordered packed variants, so sources delivering other byte
+/// orders (e.g. OpenCV-style `PixelFormat::BGR` / `PixelFormat::BGRA`, or compositor output with
+/// alpha first as `PixelFormat::ARGB` / `PixelFormat::ABGR`) can call this directly with the
+/// matching `format` instead of swapping channels upstream. The pitch follows from
+/// `format.size()`, i.e. `width * 4` for the four-byte orders.
+pub fn packed_to_jpeg(
+    pixels: &[u8],
+    width: usize,
+    height: usize,
+    format: PixelFormat,
+    compressor: &mut Compressor,
+) -> Result<Vec<u8>> {
+    let image = Image {
+        pixels,
+        width,
+        pitch: width * format.size(),
+        height,
+        format,
+    };
+    let mut jpeg_data = Vec::new();
+    compressor.compress_rgb(image, &mut jpeg_data)?;
+    Ok(jpeg_data)
+}
+
+/// Compresses an 8-bit single-channel buffer (pitch = width) to a lossless grayscale JPEG.
+/// Decoding it as `PixelFormat::GRAY` returns exactly `pixels`.
+pub fn gray_to_jpeg_lossless(pixels: &[u8], width: usize, height: usize) -> Result<Vec<u8>> {
+    lossless::compress(pixels, width, height, PixelFormat::GRAY)
+}
+
+/// Compresses planar RGB (separate R, G and B planes of `width * height` bytes each, as handed
+/// off by some GPU pipelines) by interleaving the planes into packed RGB first.
+pub fn planar_rgb_to_jpeg(
+    planes: [&[u8]; 3],
+    width: usize,
+    height: usize,
+    compressor: &mut Compressor,
+) -> Result<Vec<u8>> {
+    // One byte per pixel in each plane
+    let plane_len = width.checked_mul(height).ok_or(ConversionError::DimensionOverflow {
+        format: "Planar RGB",
+        width,
+        height,
+    })?;
+    for plane in planes {
+        if plane.len() < plane_len {
+            return Err(ConversionError::BufferTooSmall {
+                format: "Planar RGB",
+                expected: plane_len,
+                got: plane.len(),
+            });
+        }
+    }
+
+    let [r, g, b] = planes;
+    let packed = pixels::interleave_planes(&r[..plane_len], &g[..plane_len], &b[..plane_len]);
+    packed_to_jpeg(&packed, width, height, PixelFormat::RGB, compressor)
+}
+
+/// Compresses an 8-bit single-channel buffer (pitch = width) to a grayscale JPEG.
+///
+/// The luma plane is fed through the YUV path with `Subsamp::Gray`, which yields a true
+/// one-component JPEG without touching the compressor's subsampling used for color frames.
+pub fn gray_to_jpeg(pixels: &[u8], width: usize, height: usize, compressor: &mut Compressor) -> Result<Vec<u8>> {
+    compress_raw(pixels, width, height, RawPixelFormat::Gray8, compressor)
+}
+
+/// Compresses 16-bit little-endian single-channel data (depth or thermal cameras) as a
+/// grayscale JPEG, reducing it to 8 bits with `map` first.
+pub fn gray16_to_jpeg(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    map: Gray16Map,
+    compressor: &mut Compressor,
+) -> Result<Vec<u8>> {
+    let expected_len = width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(2))
+        .ok_or(ConversionError::DimensionOverflow {
+            format: "GRAY16",
+            width,
+            height,
+        })?;
+    if data.len() < expected_len {
+        return Err(ConversionError::BufferTooSmall {
+            format: "GRAY16",
+            expected: expected_len,
+            got: data.len(),
+        });
+    }
+
+    let gray8 = pixels::tone_map_gray16(&data[..expected_len], map);
+    gray_to_jpeg(&gray8, width, height, compressor)
+}
+
+/// Compresses 16-bit-per-channel little-endian RGB (scientific cameras) as a color JPEG,
+/// reducing each channel to 8 bits with `map` first. `data` must be exactly
+/// `width * height * 6` bytes.
+pub fn rgb161616_to_jpeg(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    map: Gray16Map,
+    compressor: &mut Compressor,
+) -> Result<Vec<u8>> {
+    let config = ConversionConfig {
+        rgb16_map: map,
+        ..Default::default()
+    };
+    let mut jpeg_data = Vec::new();
+    compress_raw_into(data, width, height, RawPixelFormat::Rgb161616, compressor, &config, &mut jpeg_data)?;
+    Ok(jpeg_data)
+}
+
+/// Shift for [`mono12_packed_to_jpeg`] that keeps the top 8 of the 12 bits.
+pub const MONO12_DEFAULT_SHIFT: u8 = 4;
+
+/// Compresses GigE Vision Mono12Packed data (two 12-bit samples in every 3 bytes) as a
+/// grayscale JPEG, reducing each sample to 8 bits as `sample >> shift`, saturated at 255.
+/// Smaller shifts brighten dark scenes at the cost of clipping highlights. `data` must be
+/// exactly `ceil(width * height * 3 / 2)` bytes.
+pub fn mono12_packed_to_jpeg(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    shift: u8,
+    compressor: &mut Compressor,
+) -> Result<Vec<u8>> {
+    // Three bytes per pair of samples
+    let expected_len = width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(3))
+        .ok_or(ConversionError::DimensionOverflow {
+            format: "MONO12_PACKED",
+            width,
+            height,
+        })?
+        .div_ceil(2);
+    if data.len() != expected_len {
+        return Err(ConversionError::BufferSizeMismatch {
+            format: "MONO12_PACKED",
+            expected: expected_len,
+            got: data.len(),
+        });
+    }
+
+    let gray8 = pixels::unpack_mono12_packed(data, width * height, shift);
+    gray_to_jpeg(&gray8, width, height, compressor)
+}
+
+/// Compresses P010 (10-bit NV12 in 16-bit little-endian words) as 4:2:0 JPEG, reducing the
+/// samples to 8 bits with `reduction` first.
+pub fn p010_to_jpeg(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    reduction: DepthReduction,
+    compressor: &mut Compressor,
+) -> Result<Vec<u8>> {
+    let config = ConversionConfig {
+        p010_reduction: reduction,
+        ..Default::default()
+    };
+    let mut jpeg_data = Vec::new();
+    compress_raw_into(data, width, height, RawPixelFormat::P010, compressor, &config, &mut jpeg_data)?;
+    Ok(jpeg_data)
+}
+
+/// Demosaics an 8-bit Bayer mosaic laid out as `pattern` to RGB and compresses it with the
+/// compressor's current settings.
+#[cfg(feature = "bayer")]
+pub fn bayer_to_jpeg(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    pattern: bayer::BayerPattern,
+    compressor: &mut Compressor,
+) -> Result<Vec<u8>> {
+    compress_raw(data, width, height, RawPixelFormat::Bayer(pattern), compressor)
+}
+
+/// Byte order of the interleaved chroma plane in semi-planar 4:2:0 data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChromaOrder {
+    /// U first, as in NV12.
+    Uv,
+    /// V first, as in NV21 (common on Android/Qualcomm pipelines).
+    Vu,
+}
+
+/// Repacks NV12 (Y plane followed by interleaved `U V` rows) into planar I420, the layout
+/// libjpeg-turbo compresses and the one to resample chroma in. Both planes use rows of `stride`
+/// bytes, of which the first `width` hold pixels; the padding after the last row may be missing.
+pub fn nv12_to_i420(data: &[u8], width: usize, height: usize, stride: usize) -> Result<Vec<u8>> {
+    semi_planar_to_i420(data, width, height, stride, ChromaOrder::Uv)
+}
+
+/// Repacks semi-planar 4:2:0 data (Y plane followed by interleaved chroma) into planar YUV420.
+///
+/// Both planes use rows of `stride` bytes, of which the first `width` hold pixels; the chroma
+/// plane starts right after `height` luma rows. Hardware buffers often pad rows to 16 or 64
+/// bytes, so this walks row by row instead of assuming `stride == width`. The padding after the
+/// very last row may be missing.
+fn semi_planar_to_i420(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    stride: usize,
+    order: ChromaOrder,
+) -> Result<Vec<u8>> {
+    let name = match order {
+        ChromaOrder::Uv => "NV12",
+        ChromaOrder::Vu => "NV21",
+    };
+
+    if !width.is_multiple_of(2) || !height.is_multiple_of(2) {
+        return Err(ConversionError::InvalidDimensions {
+            format: name,
+            requirement: "even width and height",
+            width,
+            height,
+        });
+    }
+    if stride < width {
+        return Err(ConversionError::InvalidStride {
+            format: name,
+            stride,
+            width,
+        });
+    }
+
+    // Y rows followed by interleaved chroma rows (one per two luma rows)
+    let chroma_height = height / 2;
+    let expected_len = stride
+        .checked_mul((height + chroma_height).saturating_sub(1))
+        .and_then(|len| len.checked_add(width))
+        .ok_or(ConversionError::DimensionOverflow {
+            format: name,
+            width,
+            height,
+        })?;
+    if data.len() < expected_len {
+        return Err(ConversionError::BufferTooSmall {
+            format: name,
+            expected: expected_len,
+            got: data.len(),
+        });
+    }
+
+    // Create planar YUV420 data
+    let y_size = width * height;
+    let chroma_size = y_size / 4;
+    let mut yuv420_data = vec![0u8; y_size + 2 * chroma_size];
+    let (y_plane, chroma_planes) = yuv420_data.split_at_mut(y_size);
+    let (u_plane, v_plane) = chroma_planes.split_at_mut(chroma_size);
+
+    // Copy the visible part of each Y row
+    for (row, out) in y_plane.chunks_exact_mut(width).enumerate() {
+        out.copy_from_slice(&data[row * stride..row * stride + width]);
+    }
+
+    // Split each interleaved chroma row into separate U and V rows
+    let (u_offset, v_offset) = match order {
+        ChromaOrder::Uv => (0, 1),
+        ChromaOrder::Vu => (1, 0),
+    };
+    let chroma_start = stride * height;
+    let chroma_width = width / 2;
+    for row in 0..chroma_height {
+        let start = chroma_start + row * stride;
+        let pairs = data[start..start + width].chunks_exact(2);
+        let u_row = &mut u_plane[row * chroma_width..(row + 1) * chroma_width];
+        let v_row = &mut v_plane[row * chroma_width..(row + 1) * chroma_width];
+        for ((pair, u), v) in pairs.zip(u_row).zip(v_row) {
+            *u = pair[u_offset];
+            *v = pair[v_offset];
+        }
+    }
+
+    Ok(yuv420_data)
+}
+
+/// Compresses semi-planar 4:2:0 data (Y plane followed by interleaved chroma) by repacking it
+/// into planar YUV420. Covers NV12 (`ChromaOrder::Uv`) and NV21 (`ChromaOrder::Vu`).
+pub fn semi_planar_to_jpeg(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    order: ChromaOrder,
+    compressor: &mut Compressor,
+) -> Result<Vec<u8>> {
+    semi_planar_to_jpeg_strided(data, width, height, width, order, compressor)
+}
+
+/// [`semi_planar_to_jpeg`] for buffers whose rows are padded to `stride` bytes.
+pub fn semi_planar_to_jpeg_strided(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    stride: usize,
+    order: ChromaOrder,
+    compressor: &mut Compressor,
+) -> Result<Vec<u8>> {
+    let yuv420_data = semi_planar_to_i420(data, width, height, stride, order)?;
+    let yuv_image = YuvImage {
+        pixels: yuv420_data.as_slice(),
+        width,
+        align: 1,
+        height,
+        subsamp: Subsamp::Sub2x2, // YUV420 (converted from semi-planar)
+    };
+    let mut jpeg_data = Vec::new();
+    compressor.compress_yuv(yuv_image, &mut jpeg_data)?;
+    Ok(jpeg_data)
+}
+
+/// Memory layout of a raw frame passed to [`compress_raw`]. Covers every `ImageRawAny` variant
+/// plus the layouts that only exist outside the message set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawPixelFormat {
+    Rgb888,
+    Rgba8888,
+    Bgr888,
+    Bgra8888,
+    Gray8,
+    /// Planar I420: Y plane, then quarter-size U and V planes.
+    Yuv420,
+    /// Planar YV12: like I420 but with the V plane before the U plane.
+    Yv12,
+    /// Planar 4:2:2: Y plane, then half-width U and V planes.
+    Yuv422,
+    /// Planar 4:1:1, as recorded by DV sources: Y plane, then quarter-width, full-height U and
+    /// V planes.
+    Yuv411,
+    /// Planar 4:4:4: three full-size planes.
+    Yuv444,
+    /// Interleaved 4:4:4: `Y U V` for every pixel.
+    Yuv444Packed,
+    Nv12,
+    Nv21,
+    /// Packed YUYV (YUY2) 4:2:2.
+    Yuyv,
+    /// NV12 layout with 10-bit samples in the high bits of little-endian 16-bit words, as
+    /// output by HDR-capable cameras. Reduced to 8 bits before compression.
+    P010,
+    /// I420 layout with 10-bit samples in the low bits of little-endian 16-bit words: a Y plane
+    /// and quarter-size U and V planes. Reduced to 8-bit I420 with
+    /// [`ConversionConfig::yuv420p10_reduction`] before compression.
+    Yuv420P10,
+    /// 16 bits per channel little-endian RGB, as output by scientific cameras. Tone-mapped to
+    /// RGB888 with [`ConversionConfig::rgb16_map`] and then handled like packed RGB input.
+    /// Rows are always tight; `rgb_stride` does not apply.
+    Rgb161616,
+    /// Interleaved 8-bit `C M Y K` per pixel, from print-oriented sources such as document
+    /// scanners. libjpeg-turbo writes it as a four-component (YCCK) JPEG; `force_grayscale`
+    /// and `lossless` do not apply.
+    Cmyk,
+    /// 8-bit raw Bayer mosaic with the given filter layout, demosaiced to RGB888 and then
+    /// handled like packed RGB input.
+    #[cfg(feature = "bayer")]
+    Bayer(bayer::BayerPattern),
+}
+
+impl RawPixelFormat {
+    /// Name used in error messages.
+    pub fn name(self) -> &'static str {
+        match self {
+            RawPixelFormat::Rgb888 => "RGB888",
+            RawPixelFormat::Rgba8888 => "RGBA8888",
+            RawPixelFormat::Bgr888 => "BGR888",
+            RawPixelFormat::Bgra8888 => "BGRA8888",
+            RawPixelFormat::Gray8 => "GRAY8",
+            RawPixelFormat::Yuv420 => "YUV420",
+            RawPixelFormat::Yv12 => "YV12",
+            RawPixelFormat::Yuv422 => "YUV422",
+            RawPixelFormat::Yuv411 => "YUV411",
+            RawPixelFormat::Yuv444 => "YUV444",
+            RawPixelFormat::Yuv444Packed => "YUV444_PACKED",
+            RawPixelFormat::Nv12 => "NV12",
+            RawPixelFormat::Nv21 => "NV21",
+            RawPixelFormat::Yuyv => "YUYV",
+            RawPixelFormat::P010 => "P010",
+            RawPixelFormat::Yuv420P10 => "YUV420P10",
+            RawPixelFormat::Rgb161616 => "RGB161616",
+            RawPixelFormat::Cmyk => "CMYK",
+            #[cfg(feature = "bayer")]
+            RawPixelFormat::Bayer(_) => "BAYER",
+        }
+    }
+
+    /// Bytes a tightly packed `width` x `height` frame takes in this layout. Saturates at
+    /// `usize::MAX` where [`checked_len`](Self::checked_len) overflows, so a buffer length
+    /// check against it fails rather than passing on a wrapped-around size.
+    pub fn expected_len(self, width: usize, height: usize) -> usize {
+        self.checked_len(width, height).unwrap_or(usize::MAX)
+    }
+
+    /// [`expected_len`](Self::expected_len), or `None` if it does not fit in `usize`.
+    pub fn checked_len(self, width: usize, height: usize) -> Option<usize> {
+        let pixels = width.checked_mul(height)?;
+        match self {
+            RawPixelFormat::Gray8 => Some(pixels),
+            #[cfg(feature = "bayer")]
+            RawPixelFormat::Bayer(_) => Some(pixels),
+            // Halved before multiplying so frames near the limit do not overflow on the way
+            RawPixelFormat::Yuv420
+            | RawPixelFormat::Yv12
+            | RawPixelFormat::Yuv411
+            | RawPixelFormat::Nv12
+            | RawPixelFormat::Nv21 => (pixels / 2).checked_mul(3)?.checked_add(pixels % 2),
+            RawPixelFormat::Yuv422 | RawPixelFormat::Yuyv => pixels.checked_mul(2),
+            RawPixelFormat::Rgb888
+            | RawPixelFormat::Bgr888
+            | RawPixelFormat::Yuv444
+            | RawPixelFormat::Yuv444Packed
+            | RawPixelFormat::P010 => pixels.checked_mul(3),
+            RawPixelFormat::Rgba8888 | RawPixelFormat::Bgra8888 | RawPixelFormat::Cmyk => pixels.checked_mul(4),
+            RawPixelFormat::Rgb161616 => pixels.checked_mul(6),
+            RawPixelFormat::Yuv420P10 => (pixels / 2).checked_mul(3)?.checked_add(pixels % 2)?.checked_mul(2),
+        }
+    }
+
+    /// Cargo feature that compiles in the conversion of this layout. All of them are on by
+    /// default; builds that only ever see a few formats can drop the rest.
+    pub fn feature(self) -> &'static str {
+        match self {
+            RawPixelFormat::Rgb888
+            | RawPixelFormat::Rgba8888
+            | RawPixelFormat::Bgr888
+            | RawPixelFormat::Bgra8888
+            | RawPixelFormat::Rgb161616 => "rgb",
+            RawPixelFormat::Gray8 => "gray",
+            RawPixelFormat::Yuv420 | RawPixelFormat::Yv12 => "yuv420",
+            RawPixelFormat::Yuv422 | RawPixelFormat::Yuyv => "yuv422",
+            RawPixelFormat::Yuv411 => "yuv411",
+            RawPixelFormat::Yuv444 | RawPixelFormat::Yuv444Packed => "yuv444",
+            RawPixelFormat::Nv12 | RawPixelFormat::Nv21 => "nv12",
+            RawPixelFormat::P010 | RawPixelFormat::Yuv420P10 => "p010",
+            RawPixelFormat::Cmyk => "cmyk",
+            #[cfg(feature = "bayer")]
+            RawPixelFormat::Bayer(_) => "bayer",
+        }
+    }
+
+    /// Whether this build has the [`feature`](Self::feature) of the layout enabled. Frames in a
+    /// disabled layout fail validation with [`ConversionError::UnsupportedFormat`].
+    pub fn is_enabled(self) -> bool {
+        match self.feature() {
+            "rgb" => cfg!(feature = "rgb"),
+            "gray" => cfg!(feature = "gray"),
+            "yuv420" => cfg!(feature = "yuv420"),
+            "yuv422" => cfg!(feature = "yuv422"),
+            "yuv411" => cfg!(feature = "yuv411"),
+            "yuv444" => cfg!(feature = "yuv444"),
+            "nv12" => cfg!(feature = "nv12"),
+            "p010" => cfg!(feature = "p010"),
+            "cmyk" => cfg!(feature = "cmyk"),
+            // The variant itself only exists with its feature
+            _ => true,
+        }
+    }
+
+    /// Whether frames in this layout go through the packed path, where cropping, downscaling
+    /// and subsampling apply. Bayer mosaics take it once demosaiced, 16-bit RGB once tone-mapped.
+    pub fn is_packed(self) -> bool {
+        match self {
+            RawPixelFormat::Rgb888
+            | RawPixelFormat::Rgba8888
+            | RawPixelFormat::Bgr888
+            | RawPixelFormat::Bgra8888
+            | RawPixelFormat::Rgb161616 => true,
+            #[cfg(feature = "bayer")]
+            RawPixelFormat::Bayer(_) => true,
+            _ => false,
+        }
+    }
 
-pub fn rgb_to_jpeg(rgb_any: &ImageRawAny, compressor: &mut Compressor) -> Result<ImageJpeg> {
+    /// Whether [`ConversionConfig::lossless`] applies to this layout with `config`.
+    pub fn supports_lossless(self, config: &ConversionConfig) -> bool {
+        self.is_packed() || self == RawPixelFormat::Gray8 || (config.force_grayscale && self != RawPixelFormat::Cmyk)
+    }
+
+    /// Whether [`ConversionConfig::chroma_quality`] can be applied to this layout with `config`:
+    /// grayscale output has no chroma, and only the `pure-rust` encoder of packed input scales
+    /// the chroma table on its own.
+    pub fn supports_chroma_quality(self, config: &ConversionConfig) -> bool {
+        self == RawPixelFormat::Gray8
+            || (config.force_grayscale && self != RawPixelFormat::Cmyk)
+            || (cfg!(feature = "pure-rust") && self.is_packed())
+    }
+
+    /// Looks up a format by its [`name`](Self::name), ignoring case ("yuv420", "RGB888").
+    /// Bayer mosaics carry a pattern and are not named here.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::NAMED.into_iter().find(|format| format.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Every layout except Bayer mosaics, which carry a pattern, whether or not its feature is
+    /// enabled.
+    pub const NAMED: [RawPixelFormat; 18] = [
+        RawPixelFormat::Rgb888,
+        RawPixelFormat::Rgba8888,
+        RawPixelFormat::Bgr888,
+        RawPixelFormat::Bgra8888,
+        RawPixelFormat::Gray8,
+        RawPixelFormat::Yuv420,
+        RawPixelFormat::Yv12,
+        RawPixelFormat::Yuv422,
+        RawPixelFormat::Yuv411,
+        RawPixelFormat::Yuv444,
+        RawPixelFormat::Yuv444Packed,
+        RawPixelFormat::Nv12,
+        RawPixelFormat::Nv21,
+        RawPixelFormat::Yuyv,
+        RawPixelFormat::P010,
+        RawPixelFormat::Yuv420P10,
+        RawPixelFormat::Rgb161616,
+        RawPixelFormat::Cmyk,
+    ];
+}
+
+/// JPEG quality chosen by input format, e.g. lower for noisy YUV sources than for clean RGB
+/// renders.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FormatQuality {
+    /// Quality of formats without their own entry. `None` leaves them at the caller's quality.
+    pub default: Option<u8>,
+    pub formats: Vec<(RawPixelFormat, u8)>,
+}
+
+impl FormatQuality {
+    /// Parses `(name, quality)` pairs as used in the app config, e.g. `("yuv420", 80)`; the
+    /// name "default" sets [`default`](Self::default). Qualities must be within 1-100.
+    pub fn parse<'a>(entries: impl IntoIterator<Item = (&'a str, i64)>) -> Result<Self> {
+        let mut format_quality = FormatQuality::default();
+        for (name, quality) in entries {
+            let quality = u8::try_from(quality).ok().filter(|q| (1..=100).contains(q)).ok_or_else(|| {
+                ConversionError::InvalidFormatQuality(format!("'{}': quality {} is not within 1-100", name, quality))
+            })?;
+            if name == "default" {
+                format_quality.default = Some(quality);
+                continue;
+            }
+            let format = RawPixelFormat::from_name(name)
+                .ok_or_else(|| ConversionError::InvalidFormatQuality(format!("unknown format '{}'", name)))?;
+            format_quality.formats.retain(|(existing, _)| *existing != format);
+            format_quality.formats.push((format, quality));
+        }
+        Ok(format_quality)
+    }
+
+    /// Quality for a frame in `format`: its own entry, else `default`, else `fallback`.
+    pub fn resolve(&self, format: RawPixelFormat, fallback: u8) -> u8 {
+        self.formats
+            .iter()
+            .find(|(entry, _)| *entry == format)
+            .map(|(_, quality)| *quality)
+            .or(self.default)
+            .unwrap_or(fallback)
+    }
+}
+
+/// Clamps `quality` into libjpeg-turbo's valid 1-100 range, applies it to `compressor` and returns the
+/// value actually set.
+pub fn set_quality_clamped(compressor: &mut Compressor, quality: i64) -> Result<u8> {
+    let clamped = quality.clamp(1, 100) as u8;
+    compressor.set_quality(clamped as i32)?;
+    Ok(clamped)
+}
+
+/// Environment variable that overrides the `jpeg_quality` config value, for quick changes
+/// without editing the app config.
+pub const JPEG_QUALITY_ENV: &str = "JPEG_QUALITY";
+
+/// Quality used when neither [`JPEG_QUALITY_ENV`] nor `jpeg_quality` is set.
+pub const DEFAULT_JPEG_QUALITY: u8 = 90;
+
+/// Where [`resolve_quality`] took the quality from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QualitySource {
+    Env,
+    Config,
+    Default,
+}
+
+/// Resolves the JPEG quality from [`JPEG_QUALITY_ENV`] if it is set and not empty, else from
+/// `config` (the `jpeg_quality` config value as text), else [`DEFAULT_JPEG_QUALITY`]. A value
+/// that is set but not an integer between 1 and 100 is an error rather than falling through to
+/// the next source.
+pub fn resolve_quality(config: Option<&str>) -> Result<(u8, QualitySource)> {
+    let parse = |setting: &'static str, value: &str| {
+        value
+            .trim()
+            .parse::<u8>()
+            .ok()
+            .filter(|quality| (1..=100).contains(quality))
+            .ok_or_else(|| ConversionError::InvalidQuality {
+                setting,
+                value: value.to_string(),
+            })
+    };
+    if let Some(value) = std::env::var(JPEG_QUALITY_ENV).ok().filter(|value| !value.trim().is_empty()) {
+        return Ok((parse(JPEG_QUALITY_ENV, &value)?, QualitySource::Env));
+    }
+    match config {
+        Some(value) => Ok((parse("jpeg_quality", value)?, QualitySource::Config)),
+        None => Ok((DEFAULT_JPEG_QUALITY, QualitySource::Default)),
+    }
+}
+
+/// Runs `compress` with `compressor`, retrying up to `retries` times if libjpeg-turbo fails.
+///
+/// libjpeg-turbo occasionally fails under memory pressure and a fresh compressor often
+/// recovers, so before every retry `compressor` is replaced by a new one with `quality`
+/// applied. Other errors (bad dimensions, short buffers) would fail the same way again and are
+/// returned right away.
+pub fn compress_with_retry<T>(
+    compressor: &mut Compressor,
+    quality: u8,
+    retries: u32,
+    mut compress: impl FnMut(&mut Compressor) -> Result<T>,
+) -> Result<T> {
+    let mut attempt = 0;
+    loop {
+        match compress(compressor) {
+            Err(ConversionError::TurboJpeg(e)) if attempt < retries => {
+                attempt += 1;
+                log::warn!("Compression failed ({e}), retrying with a new compressor ({attempt}/{retries})");
+                *compressor = Compressor::new()?;
+                compressor.set_quality(quality as i32)?;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Repacks YUYV (YUY2) 4:2:2 data, stored as `Y0 U Y1 V` per pixel pair, into planar YUV422.
+#[cfg(feature = "yuv422")]
+fn yuyv_to_i422(data: &[u8], width: usize, height: usize) -> Result<Vec<u8>> {
+    if !width.is_multiple_of(2) {
+        return Err(ConversionError::InvalidDimensions {
+            format: "YUYV",
+            requirement: "an even width",
+            width,
+            height,
+        });
+    }
+
+    let expected_len = RawPixelFormat::Yuyv
+        .checked_len(width, height)
+        .ok_or(ConversionError::DimensionOverflow {
+            format: "YUYV",
+            width,
+            height,
+        })?;
+    if data.len() < expected_len {
+        return Err(ConversionError::BufferTooSmall {
+            format: "YUYV",
+            expected: expected_len,
+            got: data.len(),
+        });
+    }
+
+    let y_size = width * height;
+    let chroma_size = y_size / 2;
+    let mut yuv422_data = vec![0u8; y_size + 2 * chroma_size];
+    let (y_plane, chroma_planes) = yuv422_data.split_at_mut(y_size);
+    let (u_plane, v_plane) = chroma_planes.split_at_mut(chroma_size);
+
+    for (i, macropixel) in data[..expected_len].chunks_exact(4).enumerate() {
+        y_plane[2 * i] = macropixel[0];
+        u_plane[i] = macropixel[1];
+        y_plane[2 * i + 1] = macropixel[2];
+        v_plane[i] = macropixel[3];
+    }
+
+    Ok(yuv422_data)
+}
+
+/// Swaps the chroma planes of YV12 data (Y, V, U) into the I420 order (Y, U, V) turbojpeg
+/// expects. `data` must hold at least a full frame.
+#[cfg(feature = "yuv420")]
+fn yv12_to_i420(data: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let y_size = width * height;
+    let chroma_size = y_size / 4;
+    let (y_plane, chroma_planes) = data.split_at(y_size);
+    let (v_plane, u_plane) = chroma_planes[..2 * chroma_size].split_at(chroma_size);
+    [y_plane, u_plane, v_plane].concat()
+}
+
+/// Splits interleaved 4:4:4 data (`Y U V` per pixel) into the Y, U and V planes turbojpeg
+/// expects. `data` must hold at least a full frame.
+#[cfg(feature = "yuv444")]
+fn yuv444_packed_to_planar(data: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let pixels = width * height;
+    let mut planar = vec![0u8; pixels * 3];
+    for (i, pixel) in data[..pixels * 3].chunks_exact(3).enumerate() {
+        planar[i] = pixel[0];
+        planar[pixels + i] = pixel[1];
+        planar[2 * pixels + i] = pixel[2];
+    }
+    planar
+}
+
+/// Compresses packed YUYV (YUY2) 4:2:2 data, as delivered by most UVC webcams.
+pub fn yuyv_to_jpeg(data: &[u8], width: usize, height: usize, compressor: &mut Compressor) -> Result<Vec<u8>> {
+    compress_raw(data, width, height, RawPixelFormat::Yuyv, compressor)
+}
+
+/// Per-conversion settings applied on top of the compressor's own state (e.g. quality).
+#[derive(Debug, Clone, Default)]
+pub struct ConversionConfig {
+    /// Chroma subsampling for the RGB/RGBA path. `None` keeps whatever the compressor is set to.
+    /// YUV inputs encode with their native subsampling unless `chroma_upsample` is set.
+    pub subsamp: Option<Subsamp>,
+    /// Emit progressive instead of baseline JPEG, for both RGB and YUV input.
+    pub progressive: bool,
+    /// Integer box-filter downscale applied to RGB/RGBA input before compression
+    /// (2 = half resolution). 0 and 1 keep the original size.
+    pub scale_denom: usize,
+    /// Region of RGB/RGBA input to keep, applied before downscaling.
+    pub roi: Option<Roi>,
+    /// Center-crop RGB/RGBA input (within `roi`, if set) to this aspect ratio before
+    /// downscaling.
+    pub aspect: Option<AspectRatio>,
+    /// Flip or rotation of RGB/RGBA input, applied after cropping and downscaling (so `roi` is in
+    /// the camera's own coordinates). 90° and 270° swap the output width and height.
+    pub orientation: Orientation,
+    /// Embed the header timestamp as an EXIF DateTimeOriginal/SubSecTimeOriginal APP1 segment.
+    /// Frames without a header timestamp are left untouched.
+    pub exif_timestamp: bool,
+    /// Text written into a COM segment of every output JPEG, e.g. a source identifier. Empty
+    /// text writes nothing; text over [`MAX_COMMENT_LEN`] bytes is cut to fit one segment.
+    pub comment: Option<String>,
+    /// Insert restart markers at this interval so a corrupted byte only damages part of the
+    /// frame. Not applied to lossless output.
+    pub restart: Option<RestartInterval>,
+    /// Compute Huffman tables per image instead of using the standard ones. Output is typically a
+    /// few percent smaller, at the cost of an extra pass over the coefficients (roughly 10-20%
+    /// more compression time). `false` keeps whatever the compressor is set to.
+    pub optimize_huffman: bool,
+    /// Drop color and encode a single-component JPEG: BT.601 luma for RGB/BGR input, the Y plane
+    /// for YUV input. Applied after cropping/downscaling; `subsamp` does not apply.
+    pub force_grayscale: bool,
+    /// Row stride in bytes of NV12 input whose rows are padded (e.g. to 16 or 64 bytes).
+    /// `None` means rows are exactly `width` bytes.
+    pub nv12_stride: Option<usize>,
+    /// Row stride in bytes of packed RGB/BGR(A) input whose rows are padded. `None` means rows
+    /// are exactly `width * bytes_per_pixel` bytes. The buffer must then hold `stride * height`
+    /// bytes.
+    pub rgb_stride: Option<usize>,
+    /// Offsets and strides of the planes of YUV420 input that is not tightly packed. `None`
+    /// means the Y, U and V planes follow each other without padding.
+    pub yuv420_planes: Option<Yuv420Planes>,
+    /// Encode YUV input with subsampled chroma (4:2:0, 4:2:2) as 4:4:4, upsampling its chroma
+    /// this way first. `None` keeps the input's own subsampling.
+    pub chroma_upsample: Option<ChromaUpsample>,
+    /// `ImageYuv444` frames carry interleaved `Y U V` pixels ([`RawPixelFormat::Yuv444Packed`])
+    /// instead of three planes.
+    pub yuv444_packed: bool,
+    /// Encode packed RGB/BGR(A) and Gray8 input as lossless JPEG. Quality, subsampling and `progressive` do
+    /// not apply to lossless output; other layouts are always encoded lossy.
+    pub lossless: bool,
+    /// Whether RGBA/BGRA input is blended over a background color before its alpha is dropped.
+    /// Applied after cropping, downscaling and rotation.
+    pub alpha_mode: AlphaMode,
+    /// Gamma correction of packed RGB/BGR(A) input, for linear-light sources that look too
+    /// dark encoded as is. Applied after alpha compositing; `None` leaves the pixels untouched.
+    pub gamma: Option<GammaLut>,
+    /// Tone mapping for 16-bit grayscale input passed to [`gray16_to_jpeg`].
+    pub gray16_map: Gray16Map,
+    /// Tone mapping of [`RawPixelFormat::Rgb161616`] input to 8 bits per channel. `MinMax`
+    /// stretches over the range of all three channels together, keeping the color balance.
+    pub rgb16_map: Gray16Map,
+    /// How P010 samples are reduced to 8 bits.
+    pub p010_reduction: DepthReduction,
+    /// How [`RawPixelFormat::Yuv420P10`] samples are reduced to 8 bits.
+    pub yuv420p10_reduction: TenBitReduction,
+    /// Matrix for the RGB-to-luma conversion done here for `force_grayscale` and gray
+    /// subsampling. libjpeg-turbo's own RGB-to-YCbCr conversion for color output is always BT.601.
+    pub color_space: ColorMatrix,
+    /// Quality for the `pure-rust` encoder, which cannot read it from the compressor. `None`
+    /// means 90. The default turbojpeg build uses the compressor's quality, which only
+    /// [`rgb_to_jpeg`] sets from this.
+    pub quality: Option<u8>,
+    /// Quality the chroma quantization table is scaled to, `quality` then only setting the luma
+    /// table, so color can be compressed harder than detail. Only the `pure-rust` encoder
+    /// applies it (packed RGB/BGR(A) input); libjpeg-turbo scales both tables by one quality,
+    /// so other color input is rejected rather than encoded at `quality` (see
+    /// [`RawPixelFormat::supports_chroma_quality`]). `None` uses `quality` for both.
+    pub chroma_quality: Option<u8>,
+    /// Frames with more pixels than this are rejected before anything is allocated for them, so
+    /// a corrupt header cannot trigger a huge allocation. `None` means [`DEFAULT_MAX_PIXELS`].
+    pub max_pixels: Option<usize>,
+    /// Publish frames whose bytes start with a JPEG SOI marker and do not fit their declared
+    /// raw layout unchanged (with a warning) instead of failing, for sources that mislabel
+    /// JPEGs as raw. Nothing else in the config is applied to them.
+    pub passthrough_jpeg: bool,
+    /// Resolution written into the JFIF APP0 density fields (units DPI). `None` keeps
+    /// libjpeg-turbo's aspect-ratio-only 1:1 density.
+    pub density: Option<Density>,
+    /// APP segments identifying the output (JFIF, EXIF or neither), for parsers that reject a
+    /// JPEG carrying both. `None` keeps libjpeg-turbo's JFIF header and adds the EXIF timestamp
+    /// if `exif_timestamp` is set.
+    pub header_style: Option<HeaderStyle>,
+}
+
+impl ConversionConfig {
+    /// Starts from the defaults, which convert frames as they come: native size and
+    /// orientation, the compressor's quality and subsampling, baseline, standard Huffman tables.
+    pub fn builder() -> ConversionConfigBuilder {
+        ConversionConfigBuilder::default()
+    }
+}
+
+/// Builds a [`ConversionConfig`] from the settings most callers need; the rest keep their
+/// defaults and can still be set on the built config's fields.
+#[derive(Debug, Clone, Default)]
+pub struct ConversionConfigBuilder {
+    config: ConversionConfig,
+}
+
+impl ConversionConfigBuilder {
+    /// JPEG quality, clamped to 1-100. Applied to the compressor by [`rgb_to_jpeg`].
+    pub fn quality(mut self, quality: u8) -> Self {
+        self.config.quality = Some(quality.clamp(1, 100));
+        self
+    }
+
+    pub fn subsamp(mut self, subsamp: Subsamp) -> Self {
+        self.config.subsamp = Some(subsamp);
+        self
+    }
+
+    pub fn progressive(mut self, progressive: bool) -> Self {
+        self.config.progressive = progressive;
+        self
+    }
+
+    pub fn optimize_huffman(mut self, optimize_huffman: bool) -> Self {
+        self.config.optimize_huffman = optimize_huffman;
+        self
+    }
+
+    /// Flip or rotation, see [`ConversionConfig::orientation`].
+    pub fn transform(mut self, orientation: Orientation) -> Self {
+        self.config.orientation = orientation;
+        self
+    }
+
+    pub fn force_grayscale(mut self, force_grayscale: bool) -> Self {
+        self.config.force_grayscale = force_grayscale;
+        self
+    }
+
+    pub fn build(self) -> ConversionConfig {
+        self.config
+    }
+}
+
+/// Pixel limit applied when [`ConversionConfig::max_pixels`] is not set: 100 megapixels.
+pub const DEFAULT_MAX_PIXELS: usize = 100_000_000;
+
+/// Parses a 16-bit grayscale mapping name as used in the app config ("shift8", "minmax").
+pub fn parse_gray16_map(value: &str) -> Result<Gray16Map> {
+    match value {
+        "shift8" => Ok(Gray16Map::Shift8),
+        "minmax" => Ok(Gray16Map::MinMax),
+        other => Err(ConversionError::UnsupportedGray16Map(other.to_string())),
+    }
+}
+
+/// Parses an alpha mode name as used in the app config ("ignore", "composite_over");
+/// `background` (R, G, B) and `premultiplied` only apply to "composite_over".
+pub fn parse_alpha_mode(value: &str, background: [u8; 3], premultiplied: bool) -> Result<AlphaMode> {
+    match value {
+        "ignore" => Ok(AlphaMode::Ignore),
+        "composite_over" => Ok(AlphaMode::CompositeOver {
+            background,
+            premultiplied,
+        }),
+        other => Err(ConversionError::UnsupportedAlphaMode(other.to_string())),
+    }
+}
+
+/// Parses a chroma upsampling name as used in the app config ("nearest", "bilinear").
+pub fn parse_chroma_upsample(value: &str) -> Result<ChromaUpsample> {
+    match value {
+        "nearest" => Ok(ChromaUpsample::Nearest),
+        "bilinear" => Ok(ChromaUpsample::Bilinear),
+        other => Err(ConversionError::UnsupportedChromaUpsample(other.to_string())),
+    }
+}
+
+/// Parses a color space name as used in the app config ("bt601", "bt709").
+pub fn parse_color_space(value: &str) -> Result<ColorMatrix> {
+    match value {
+        "bt601" => Ok(ColorMatrix::Bt601),
+        "bt709" => Ok(ColorMatrix::Bt709),
+        other => Err(ConversionError::UnsupportedColorSpace(other.to_string())),
+    }
+}
+
+/// Parses an aspect ratio as used in the app config ("16:9", "4:3", ...).
+pub fn parse_aspect_ratio(value: &str) -> Result<AspectRatio> {
+    let invalid = || ConversionError::InvalidAspectRatio(value.to_string());
+    let (width, height) = value.split_once(':').ok_or_else(invalid)?;
+    let width: u32 = width.trim().parse().map_err(|_| invalid())?;
+    let height: u32 = height.trim().parse().map_err(|_| invalid())?;
+    if width == 0 || height == 0 {
+        return Err(invalid());
+    }
+    Ok(AspectRatio { width, height })
+}
+
+/// Parses a transform name as used in the app config ("none", "fliph", "flipv", "rot90",
+/// "rot180", "rot270").
+pub fn parse_orientation(value: &str) -> Result<Orientation> {
+    match value {
+        "none" => Ok(Orientation::None),
+        "fliph" => Ok(Orientation::FlipH),
+        "flipv" => Ok(Orientation::FlipV),
+        "rot90" => Ok(Orientation::Rot90),
+        "rot180" => Ok(Orientation::Rot180),
+        "rot270" => Ok(Orientation::Rot270),
+        other => Err(ConversionError::UnsupportedTransform(other.to_string())),
+    }
+}
+
+/// Parses a subsampling name as used in the app config ("420", "422", "444", "gray").
+pub fn parse_subsamp(value: &str) -> Result<Subsamp> {
+    match value {
+        "420" => Ok(Subsamp::Sub2x2),
+        "422" => Ok(Subsamp::Sub2x1),
+        "444" => Ok(Subsamp::None),
+        "gray" => Ok(Subsamp::Gray),
+        other => Err(ConversionError::UnsupportedSubsampling(other.to_string())),
+    }
+}
+
+/// Which APP segments identify the output JPEG, see [`ConversionConfig::header_style`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderStyle {
+    /// The JFIF APP0 segment only; no EXIF segment is written, even with `exif_timestamp`.
+    Jfif,
+    /// An EXIF APP1 segment directly after SOI and no JFIF segment, so `density` is dropped. The
+    /// timestamp segment if `exif_timestamp` wrote one, an EXIF header without tags otherwise.
+    Exif,
+    /// Neither segment, for the smallest output.
+    Minimal,
+}
+
+/// Parses a JPEG header style name as used in the app config ("jfif", "exif", "minimal").
+pub fn parse_header_style(value: &str) -> Result<HeaderStyle> {
+    match value {
+        "jfif" => Ok(HeaderStyle::Jfif),
+        "exif" => Ok(HeaderStyle::Exif),
+        "minimal" => Ok(HeaderStyle::Minimal),
+        other => Err(ConversionError::UnsupportedHeaderStyle(other.to_string())),
+    }
+}
+
+/// Lowest quality [`auto_subsamp`] keeps full horizontal chroma resolution (4:2:2) at.
+pub const AUTO_SUBSAMP_422_QUALITY: u8 = 75;
+/// Lowest quality [`auto_subsamp`] keeps full chroma resolution (4:4:4) at.
+pub const AUTO_SUBSAMP_444_QUALITY: u8 = 90;
+
+/// Chroma subsampling matching `quality`: 4:2:0 below [`AUTO_SUBSAMP_422_QUALITY`], 4:2:2
+/// below [`AUTO_SUBSAMP_444_QUALITY`] and 4:4:4 from there on. At low quality the chroma
+/// detail is quantized away anyway, so halving it saves bytes at no visible cost.
+pub fn auto_subsamp(quality: u8) -> Subsamp {
+    match quality {
+        q if q >= AUTO_SUBSAMP_444_QUALITY => Subsamp::None,
+        q if q >= AUTO_SUBSAMP_422_QUALITY => Subsamp::Sub2x1,
+        _ => Subsamp::Sub2x2,
+    }
+}
+
+/// Compresses `rgb_any` with `config`, first setting `compressor` to `config.quality` if one is
+/// given. [`rgb_to_jpeg_with_config`] leaves the compressor's quality alone.
+pub fn rgb_to_jpeg<C: Compress + ?Sized>(
+    rgb_any: &ImageRawAny,
+    compressor: &mut C,
+    config: &ConversionConfig,
+) -> Result<ImageJpeg> {
+    if let Some(quality) = config.quality {
+        compressor.set_quality(quality as i32)?;
+    }
+    rgb_to_jpeg_with_config(rgb_any, compressor, config)
+}
+
+/// Compresses `rgb_any` with `config`. The returned `ImageJpeg` carries a clone of
+/// [`frame_header`]; [`rgb_to_jpeg_with_header`] moves a header in instead.
+pub fn rgb_to_jpeg_with_config<C: Compress + ?Sized>(
+    rgb_any: &ImageRawAny,
+    compressor: &mut C,
+    config: &ConversionConfig,
+) -> Result<ImageJpeg> {
+    rgb_to_jpeg_with_header(rgb_any, frame_header(rgb_any).cloned(), compressor, config)
+}
+
+/// Compresses `rgb_any` with `config` into an `ImageJpeg` that owns `header`, so headers with
+/// large metadata are moved rather than copied: take it out of the frame with
+/// `msg.header.take()` and pass it here. `rgb_any.header` is not read; the EXIF timestamp comes
+/// from `header` as well.
+pub fn rgb_to_jpeg_with_header<C: Compress + ?Sized>(
+    rgb_any: &ImageRawAny,
+    header: Option<Header>,
+    compressor: &mut C,
+    config: &ConversionConfig,
+) -> Result<ImageJpeg> {
+    let mut jpeg_data = Vec::new();
+    compress_frame_into(rgb_any, header.as_ref(), compressor, config, &mut jpeg_data)?;
+    Ok(ImageJpeg {
+        header,
+        data: jpeg_data,
+    })
+}
+
+/// Checks that `rgb_any` would convert with the default config, without compressing it.
+pub fn validate_frame(rgb_any: &ImageRawAny) -> Result<()> {
+    validate_frame_with_config(rgb_any, &ConversionConfig::default())
+}
+
+/// Runs the same dimension and buffer-size checks as [`rgb_to_jpeg_with_config`] but skips
+/// compression, for ingest validation.
+pub fn validate_frame_with_config(rgb_any: &ImageRawAny, config: &ConversionConfig) -> Result<()> {
+    let (data, width, height, format) =
+        configured_raw_frame(rgb_any, config).ok_or_else(|| ConversionError::NoImageData {
+            header: frame_header(rgb_any).cloned(),
+        })?;
+    validate_raw(data, width, height, format, config)
+}
+
+/// Largest comment a single COM segment can carry (its length field counts itself).
+pub const MAX_COMMENT_LEN: usize = 65_533;
+
+/// Builds a COM segment for `comment`, cut to [`MAX_COMMENT_LEN`] bytes at a character
+/// boundary. Returns `None` for an empty comment.
+fn comment_segment(comment: &str) -> Option<Vec<u8>> {
+    let mut end = comment.len().min(MAX_COMMENT_LEN);
+    while !comment.is_char_boundary(end) {
+        end -= 1;
+    }
+    let text = &comment.as_bytes()[..end];
+    if text.is_empty() {
+        return None;
+    }
+
+    let mut segment = Vec::with_capacity(text.len() + 4);
+    segment.extend_from_slice(&[0xFF, 0xFE]);
+    segment.extend_from_slice(&((text.len() + 2) as u16).to_be_bytes());
+    segment.extend_from_slice(text);
+    Some(segment)
+}
+
+/// Inserts `segment` after the APPn segments that follow SOI, so JFIF/EXIF stay in front.
+fn insert_after_app_segments(jpeg: &mut Vec<u8>, segment: &[u8]) {
+    let mut pos = 2;
+    while pos + 4 <= jpeg.len() && jpeg[pos] == 0xFF && (0xE0..=0xEF).contains(&jpeg[pos + 1]) {
+        pos += 2 + u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]) as usize;
+    }
+    let pos = pos.min(jpeg.len());
+    jpeg.splice(pos..pos, segment.iter().copied());
+}
+
+/// Removes the JFIF and EXIF segments `style` leaves out from the APPn segments after SOI, and
+/// inserts an empty EXIF segment after SOI if [`HeaderStyle::Exif`] finds none.
+fn apply_header_style(jpeg: &mut Vec<u8>, style: HeaderStyle) {
+    let mut has_exif = false;
+    let mut pos = 2;
+    while pos + 4 <= jpeg.len() && jpeg[pos] == 0xFF && (0xE0..=0xEF).contains(&jpeg[pos + 1]) {
+        let len = u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]) as usize;
+        let end = (pos + 2 + len).clamp(pos + 4, jpeg.len());
+        let payload = &jpeg[pos + 4..end];
+        let (is_jfif, is_exif) = match jpeg[pos + 1] {
+            0xE0 => (jfif::is_jfif_payload(payload), false),
+            0xE1 => (false, exif::is_exif_payload(payload)),
+            _ => (false, false),
+        };
+        if (is_jfif && style != HeaderStyle::Jfif) || (is_exif && style != HeaderStyle::Exif) {
+            jpeg.drain(pos..end);
+        } else {
+            has_exif |= is_exif;
+            pos = end;
+        }
+    }
+    if style == HeaderStyle::Exif && !has_exif {
+        exif::insert_after_soi(jpeg, &exif::empty_app1());
+    }
+}
+
+/// Sizes and encoding of one finished conversion.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConversionStats {
+    /// Length of the raw pixel buffer that was converted.
+    pub input_bytes: usize,
+    /// Length of the produced JPEG.
+    pub output_bytes: usize,
+    /// `input_bytes / output_bytes`; 0 if there is no output.
+    pub ratio: f64,
+    /// Chroma subsampling as recorded in the JPEG header.
+    pub subsamp_used: Subsamp,
+}
+
+impl ConversionStats {
+    /// Measures `jpeg`, produced from `input_bytes` of raw data. Reads the JPEG header so the
+    /// reported subsampling is what was actually encoded, whatever the compressor was set to.
+    pub fn measure(input_bytes: usize, jpeg: &[u8]) -> Result<Self> {
+        let header = turbojpeg::read_header(jpeg)?;
+        Ok(ConversionStats {
+            input_bytes,
+            output_bytes: jpeg.len(),
+            ratio: ConversionStats::ratio_of(input_bytes, jpeg.len()),
+            subsamp_used: header.subsamp,
+        })
+    }
+
+    /// The [`ratio`](Self::ratio) of `input_bytes` compressed to `output_bytes`, for callers
+    /// that know the sizes without measuring the JPEG.
+    pub fn ratio_of(input_bytes: usize, output_bytes: usize) -> f64 {
+        match output_bytes {
+            0 => 0.0,
+            output_bytes => input_bytes as f64 / output_bytes as f64,
+        }
+    }
+}
+
+/// [`rgb_to_jpeg_with_config`] that also reports [`ConversionStats`] for the frame.
+pub fn rgb_to_jpeg_with_stats(
+    rgb_any: &ImageRawAny,
+    compressor: &mut Compressor,
+    config: &ConversionConfig,
+) -> Result<(ImageJpeg, ConversionStats)> {
+    let jpeg = rgb_to_jpeg_with_config(rgb_any, compressor, config)?;
+    let input_bytes = frame_data(rgb_any).map_or(0, |data| data.len());
+    let stats = ConversionStats::measure(input_bytes, &jpeg.data)?;
+    Ok((jpeg, stats))
+}
+
+/// Compression ratios [`RatioMonitor`] accepts by default: 2x to 100x.
+pub const DEFAULT_RATIO_BAND: (f64, f64) = (2.0, 100.0);
+
+/// Watches the compression ratio ([`ConversionStats::ratio`]) of consecutive frames for values
+/// outside `[min, max]`. A ratio that suddenly drops usually means sensor noise or a frame
+/// decoded with the wrong layout; one far above the band usually means a blank frame.
+///
+/// Only the first frame of a run outside the band is reported, so a camera stuck on a bad
+/// stream warns once instead of on every frame. A frame back inside the band ends the run.
+#[derive(Debug, Clone)]
+pub struct RatioMonitor {
+    min: f64,
+    max: f64,
+    out_of_band: bool,
+}
+
+impl RatioMonitor {
+    pub fn new(min: f64, max: f64) -> Self {
+        RatioMonitor {
+            min,
+            max,
+            out_of_band: false,
+        }
+    }
+
+    /// Records the [`ConversionStats::ratio`] of one conversion and returns it if it starts a
+    /// run outside the band.
+    pub fn check(&mut self, ratio: f64) -> Option<f64> {
+        let was_out_of_band = self.out_of_band;
+        self.out_of_band = ratio < self.min || ratio > self.max;
+        (self.out_of_band && !was_out_of_band).then_some(ratio)
+    }
+}
+
+impl Default for RatioMonitor {
+    fn default() -> Self {
+        let (min, max) = DEFAULT_RATIO_BAND;
+        RatioMonitor::new(min, max)
+    }
+}
+
+/// Codec of the images the app publishes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Jpeg,
+    /// Only available with the `webp` cargo feature, and only for RGB/RGBA input.
+    Webp,
+}
+
+/// Parses an output format name as used in the app config ("jpeg", "webp"). "webp" is
+/// rejected when the crate is built without the `webp` feature.
+pub fn parse_output_format(value: &str) -> Result<OutputFormat> {
+    match value {
+        "jpeg" => Ok(OutputFormat::Jpeg),
+        "webp" if cfg!(feature = "webp") => Ok(OutputFormat::Webp),
+        "webp" => Err(ConversionError::UnsupportedOutputFormat(
+            "webp (built without the webp feature)".to_string(),
+        )),
+        other => Err(ConversionError::UnsupportedOutputFormat(other.to_string())),
+    }
+}
+
+/// A compressed frame in any [`OutputFormat`], with the header of the raw frame it came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EncodedImage {
+    pub header: Option<Header>,
+    pub format: OutputFormat,
+    pub data: Vec<u8>,
+}
+
+/// Compresses `rgb_any` as `format`. JPEG takes the same path as [`rgb_to_jpeg_with_config`];
+/// WebP goes through [`encode_webp`].
+pub fn encode_image(
+    rgb_any: &ImageRawAny,
+    format: OutputFormat,
+    compressor: &mut Compressor,
+    config: &ConversionConfig,
+) -> Result<EncodedImage> {
+    let data = match format {
+        OutputFormat::Jpeg => rgb_to_jpeg_with_config(rgb_any, compressor, config)?.data,
+        #[cfg(feature = "webp")]
+        OutputFormat::Webp => encode_webp(rgb_any, config)?,
+        #[cfg(not(feature = "webp"))]
+        OutputFormat::Webp => return Err(ConversionError::UnsupportedOutputFormat("webp".to_string())),
+    };
+    Ok(EncodedImage {
+        header: frame_header(rgb_any).cloned(),
+        format,
+        data,
+    })
+}
+
+/// Compresses an RGB888 or RGBA8888 frame as lossy WebP at `config.quality` (90 if unset),
+/// after the configured crop, downscale and orientation. Other settings are JPEG-specific and
+/// ignored.
+#[cfg(feature = "webp")]
+pub fn encode_webp(rgb_any: &ImageRawAny, config: &ConversionConfig) -> Result<Vec<u8>> {
+    let (data, width, height, format) = raw_frame(rgb_any).ok_or_else(|| ConversionError::NoImageData {
+        header: frame_header(rgb_any).cloned(),
+    })?;
+    let bytes_per_pixel = match format {
+        RawPixelFormat::Rgb888 => 3,
+        RawPixelFormat::Rgba8888 => 4,
+        other => {
+            return Err(ConversionError::UnsupportedFormat(format!("{} with WebP output", other.name())));
+        }
+    };
+    validate_raw(data, width, height, format, config)?;
+
+    let stride = config.rgb_stride.unwrap_or(width * bytes_per_pixel);
+    let frame = PackedFrame::strided(data, width, height, bytes_per_pixel, stride);
+    let frame = preprocess_packed(frame, config)?.into_tight();
+    let pixels = frame.pixels.as_ref();
+    let (width, height) = (frame.width as u32, frame.height as u32);
+    let encoder = match bytes_per_pixel {
+        3 => webp::Encoder::from_rgb(pixels, width, height),
+        _ => webp::Encoder::from_rgba(pixels, width, height),
+    };
+    let quality = config.quality.unwrap_or(backend::DEFAULT_QUALITY);
+    Ok(encoder.encode(quality as f32).to_vec())
+}
+
+/// Color of the frames built by [`make_error_frame`]: a dark red that stands out in a feed.
+pub const ERROR_FRAME_COLOR: [u8; 3] = [160, 0, 0];
+
+/// Compresses a solid [`ERROR_FRAME_COLOR`] frame of `width` x `height`. Meant to be built once
+/// and published in place of frames that fail to convert, so viewers see an error instead of
+/// a stall.
+pub fn make_error_frame(width: usize, height: usize, compressor: &mut Compressor) -> Result<Vec<u8>> {
+    let pixels = ERROR_FRAME_COLOR.repeat(width * height);
+    compress_raw(&pixels, width, height, RawPixelFormat::Rgb888, compressor)
+}
+
+/// Side of the square frame [`warmup`] compresses: two 4:2:0 MCUs across and down.
+const WARMUP_SIZE: usize = 32;
+
+/// Compresses a small synthetic RGB frame with `compressor` and discards the result, so
+/// libjpeg-turbo's one-time setup (SIMD detection, table and buffer allocation) happens at
+/// startup instead of on the first real frame. Settings of `compressor` are left untouched.
+pub fn warmup(compressor: &mut Compressor) -> Result<()> {
+    // A gradient rather than a flat frame, so every coding stage sees nonzero coefficients
+    let pixels: Vec<u8> = (0..WARMUP_SIZE * WARMUP_SIZE * 3).map(|i| (i % 251) as u8).collect();
+    let image = Image {
+        pixels: pixels.as_slice(),
+        width: WARMUP_SIZE,
+        pitch: WARMUP_SIZE * 3,
+        height: WARMUP_SIZE,
+        format: PixelFormat::RGB,
+    };
+    compressor.compress_to_vec(image)?;
+    Ok(())
+}
+
+/// Thumbnail width used when the app config does not set one.
+pub const DEFAULT_THUMBNAIL_WIDTH: usize = 160;
+
+/// Size of a thumbnail `thumb_width` pixels wide of a `width` x `height` frame, keeping the
+/// aspect ratio. Frames narrower than `thumb_width` are not enlarged.
+pub fn thumbnail_size(width: usize, height: usize, thumb_width: usize) -> (usize, usize) {
+    let out_width = thumb_width.clamp(1, width.max(1));
+    let out_height = ((height * out_width + width / 2) / width.max(1)).max(1);
+    (out_width, out_height)
+}
+
+/// Compresses a thumbnail of `rgb_any` `thumb_width` pixels wide (see [`thumbnail_size`])
+/// straight from the frame's raw pixels, so nothing has to be decoded again. Packed input is
+/// cropped, scaled and rotated as configured first, as for the full frame; other layouts are
+/// resized whole. Quality, subsampling, grayscale and progressive output follow `config`.
+pub fn make_thumbnail(
+    rgb_any: &ImageRawAny,
+    thumb_width: usize,
+    compressor: &mut Compressor,
+    config: &ConversionConfig,
+) -> Result<ImageJpeg> {
+    let (data, width, height, format) =
+        configured_raw_frame(rgb_any, config).ok_or_else(|| ConversionError::NoImageData {
+            header: frame_header(rgb_any).cloned(),
+        })?;
+    validate_raw(data, width, height, format, config)?;
+    let (data, format) = repack_planes(data, width, height, format, config);
+    let data = data.as_ref();
+
+    let (out_width, out_height, pixels) = match packed_frame(data, width, height, format, config) {
+        Some((frame, pixel_format)) => {
+            let frame = preprocess_packed(frame, config)?.into_tight();
+            let (out_width, out_height) = thumbnail_size(frame.width, frame.height, thumb_width);
+            let (red, blue) = match pixel_format {
+                PixelFormat::BGR | PixelFormat::BGRA => (2, 0),
+                _ => (0, 2),
+            };
+            let bpp = frame.bytes_per_pixel;
+            let pixels = pixels::box_resize(frame.width, frame.height, out_width, out_height, |x, y| {
+                let pixel = &frame.pixels[(y * frame.width + x) * bpp..];
+                let rgb = [pixel[red], pixel[1], pixel[blue]];
+                let rgb = match config.alpha_mode {
+                    AlphaMode::CompositeOver { background, premultiplied } if bpp == 4 => {
+                        pixels::composite_pixel(rgb, pixel[3], background, premultiplied)
+                    }
+                    _ => rgb,
+                };
+                match &config.gamma {
+                    Some(lut) => rgb.map(|value| lut.map(value)),
+                    None => rgb,
+                }
+            });
+            (out_width, out_height, pixels)
+        }
+        None => {
+            let (out_width, out_height) = thumbnail_size(width, height, thumb_width);
+            let data = match format {
+                RawPixelFormat::P010 => Cow::Owned(pixels::reduce_p010(
+                    &data[..format.expected_len(width, height)],
+                    config.p010_reduction,
+                )),
+                _ => Cow::Borrowed(data),
+            };
+            let layout = YuvLayout::of(format, width, height, config);
+            let ycbcr = pixels::box_resize(width, height, out_width, out_height, |x, y| layout.sample(&data, x, y));
+            let pixels = ycbcr.chunks_exact(3).flat_map(|s| pixels::ycbcr_to_rgb([s[0], s[1], s[2]])).collect();
+            (out_width, out_height, pixels)
+        }
+    };
+
+    let thumb_config = ConversionConfig {
+        subsamp: config.subsamp,
+        progressive: config.progressive,
+        optimize_huffman: config.optimize_huffman,
+        force_grayscale: config.force_grayscale,
+        color_space: config.color_space,
+        quality: config.quality,
+        ..Default::default()
+    };
+    let mut jpeg_data = Vec::new();
+    compress_raw_into(
+        &pixels,
+        out_width,
+        out_height,
+        RawPixelFormat::Rgb888,
+        compressor,
+        &thumb_config,
+        &mut jpeg_data,
+    )?;
+    Ok(ImageJpeg {
+        header: frame_header(rgb_any).cloned(),
+        data: jpeg_data,
+    })
+}
+
+/// Wraps packed input, or demosaiced Bayer or tone-mapped 16-bit RGB input, as a [`PackedFrame`] together with its
+/// turbojpeg pixel format. `None` for YUV-family and Gray8 input.
+fn packed_frame<'a>(
+    data: &'a [u8],
+    width: usize,
+    height: usize,
+    format: RawPixelFormat,
+    config: &ConversionConfig,
+) -> Option<(PackedFrame<'a>, PixelFormat)> {
+    let (pixel_format, bytes_per_pixel) = match format {
+        RawPixelFormat::Rgb888 => (PixelFormat::RGB, 3),
+        RawPixelFormat::Rgba8888 => (PixelFormat::RGBA, 4),
+        RawPixelFormat::Bgr888 => (PixelFormat::BGR, 3),
+        RawPixelFormat::Bgra8888 => (PixelFormat::BGRA, 4),
+        RawPixelFormat::Rgb161616 => {
+            let rgb = pixels::tone_map_gray16(&data[..format.expected_len(width, height)], config.rgb16_map);
+            return Some((PackedFrame::tight(rgb, width, height, 3), PixelFormat::RGB));
+        }
+        #[cfg(feature = "bayer")]
+        RawPixelFormat::Bayer(pattern) => {
+            let rgb = bayer::demosaic_bilinear(data, width, height, pattern);
+            return Some((PackedFrame::tight(rgb, width, height, 3), PixelFormat::RGB));
+        }
+        _ => return None,
+    };
+    let stride = config.rgb_stride.unwrap_or(width * bytes_per_pixel);
+    Some((PackedFrame::strided(data, width, height, bytes_per_pixel, stride), pixel_format))
+}
+
+/// Where the Y, Cb and Cr samples of a pixel sit in YUV-family or Gray8 data, so any of those
+/// layouts can be read pixel by pixel. P010 is described after reduction to 8-bit NV12,
+/// planar 10-bit YUV420 after reduction to 8-bit I420.
+struct YuvLayout {
+    luma_stride: usize,
+    luma_step: usize,
+    /// Offsets of the first Cb and Cr samples, or `None` for Gray8.
+    chroma_offsets: Option<(usize, usize)>,
+    chroma_stride: usize,
+    chroma_step: usize,
+    /// Right shifts mapping a pixel's x and y to its chroma sample.
+    chroma_shift: (u32, u32),
+}
+
+impl YuvLayout {
+    fn of(format: RawPixelFormat, width: usize, height: usize, config: &ConversionConfig) -> Self {
+        let luma_size = width * height;
+        let planar = |shift: (u32, u32), cb_first: bool| {
+            let chroma_width = width >> shift.0;
+            let chroma_size = chroma_width * (height >> shift.1);
+            let (cb, cr) = (luma_size, luma_size + chroma_size);
+            YuvLayout {
+                luma_stride: width,
+                luma_step: 1,
+                chroma_offsets: Some(if cb_first { (cb, cr) } else { (cr, cb) }),
+                chroma_stride: chroma_width,
+                chroma_step: 1,
+                chroma_shift: shift,
+            }
+        };
+        let semi_planar = |stride: usize, cb_first: bool| {
+            let chroma = stride * height;
+            YuvLayout {
+                luma_stride: stride,
+                luma_step: 1,
+                chroma_offsets: Some(if cb_first { (chroma, chroma + 1) } else { (chroma + 1, chroma) }),
+                chroma_stride: stride,
+                chroma_step: 2,
+                chroma_shift: (1, 1),
+            }
+        };
+
+        match format {
+            RawPixelFormat::Yuv420 | RawPixelFormat::Yuv420P10 => planar((1, 1), true),
+            RawPixelFormat::Yv12 => planar((1, 1), false),
+            RawPixelFormat::Yuv422 => planar((1, 0), true),
+            RawPixelFormat::Yuv411 => planar((2, 0), true),
+            RawPixelFormat::Yuv444 => planar((0, 0), true),
+            RawPixelFormat::Nv12 => semi_planar(config.nv12_stride.unwrap_or(width), true),
+            RawPixelFormat::Nv21 => semi_planar(width, false),
+            RawPixelFormat::P010 => semi_planar(width, true),
+            RawPixelFormat::Yuv444Packed => YuvLayout {
+                luma_stride: width * 3,
+                luma_step: 3,
+                chroma_offsets: Some((1, 2)),
+                chroma_stride: width * 3,
+                chroma_step: 3,
+                chroma_shift: (0, 0),
+            },
+            // Y0 U Y1 V per pixel pair
+            RawPixelFormat::Yuyv => YuvLayout {
+                luma_stride: width * 2,
+                luma_step: 2,
+                chroma_offsets: Some((1, 3)),
+                chroma_stride: width * 2,
+                chroma_step: 4,
+                chroma_shift: (1, 0),
+            },
+            _ => YuvLayout {
+                luma_stride: width,
+                luma_step: 1,
+                chroma_offsets: None,
+                chroma_stride: 0,
+                chroma_step: 0,
+                chroma_shift: (0, 0),
+            },
+        }
+    }
+
+    fn sample(&self, data: &[u8], x: usize, y: usize) -> [u8; 3] {
+        let luma = data[y * self.luma_stride + x * self.luma_step];
+        let Some((cb, cr)) = self.chroma_offsets else {
+            return [luma, 128, 128];
+        };
+        let chroma = (y >> self.chroma_shift.1) * self.chroma_stride + (x >> self.chroma_shift.0) * self.chroma_step;
+        [luma, data[cb + chroma], data[cr + chroma]]
+    }
+}
+
+/// How subsampled chroma is brought up to full resolution for a 4:4:4 encode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChromaUpsample {
+    /// Repeats each chroma sample over the pixels it covers. Cheap, but shows as 2x2 blocks
+    /// along colored edges.
+    Nearest,
+    /// Interpolates between the nearest chroma samples, taken as sited at the center of the
+    /// pixels they cover (as JPEG and MPEG-1 do).
+    Bilinear,
+}
+
+/// Scales a `width` x `height` chroma plane up to `out_width` x `out_height`.
+pub fn upsample_chroma(
+    plane: &[u8],
+    width: usize,
+    height: usize,
+    out_width: usize,
+    out_height: usize,
+    mode: ChromaUpsample,
+) -> Vec<u8> {
+    let mut out = Vec::with_capacity(out_width * out_height);
+    if width == 0 || height == 0 {
+        out.resize(out_width * out_height, 128);
+        return out;
+    }
+    match mode {
+        ChromaUpsample::Nearest => {
+            for y in 0..out_height {
+                let row = &plane[(y * height / out_height) * width..];
+                out.extend((0..out_width).map(|x| row[x * width / out_width]));
+            }
+        }
+        ChromaUpsample::Bilinear => {
+            // Source position of output index `i` and the two samples to blend with its weight
+            let taps = |i: usize, len: usize, out_len: usize| {
+                let position = ((i as f32 + 0.5) * len as f32 / out_len as f32 - 0.5).max(0.0);
+                let first = (position as usize).min(len - 1);
+                (first, (first + 1).min(len - 1), position - first as f32)
+            };
+            let columns: Vec<_> = (0..out_width).map(|x| taps(x, width, out_width)).collect();
+            for y in 0..out_height {
+                let (y0, y1, wy) = taps(y, height, out_height);
+                let (row0, row1) = (&plane[y0 * width..], &plane[y1 * width..]);
+                out.extend(columns.iter().map(|&(x0, x1, wx)| {
+                    let top = row0[x0] as f32 * (1.0 - wx) + row0[x1] as f32 * wx;
+                    let bottom = row1[x0] as f32 * (1.0 - wx) + row1[x1] as f32 * wx;
+                    (top * (1.0 - wy) + bottom * wy).round() as u8
+                }));
+            }
+        }
+    }
+    out
+}
+
+/// Gathers the planes of any subsampled YUV `layout` into tightly packed 4:4:4 planar data,
+/// upsampling chroma with `mode`.
+fn yuv444_planes(data: &[u8], width: usize, height: usize, layout: &YuvLayout, mode: ChromaUpsample) -> Vec<u8> {
+    let (chroma_width, chroma_height) = (width >> layout.chroma_shift.0, height >> layout.chroma_shift.1);
+    let mut planar = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        planar.extend((0..width).map(|x| data[y * layout.luma_stride + x * layout.luma_step]));
+    }
+    if let Some((cb, cr)) = layout.chroma_offsets {
+        for offset in [cb, cr] {
+            let plane: Vec<u8> = (0..chroma_height)
+                .flat_map(|y| (0..chroma_width).map(move |x| (y, x)))
+                .map(|(y, x)| data[offset + y * layout.chroma_stride + x * layout.chroma_step])
+                .collect();
+            planar.extend(upsample_chroma(&plane, chroma_width, chroma_height, width, height, mode));
+        }
+    }
+    planar
+}
+
+/// Quality range searched by [`compress_to_target_size`].
+pub const TARGET_SIZE_QUALITY_RANGE: (u8, u8) = (10, 95);
+/// Compressions [`compress_to_target_size`] tries before settling.
+const TARGET_SIZE_MAX_ITERATIONS: usize = 4;
+
+/// Converts `rgb_any` at the highest quality whose output fits in `max_bytes`, for streams
+/// with a fixed per-frame byte budget. Binary-searches [`TARGET_SIZE_QUALITY_RANGE`] with at
+/// most four compressions (plus one if nothing fitted), so the result is close to, not
+/// necessarily at, the best quality. If no tried quality fits, the frame is encoded at the
+/// lowest quality and may exceed the budget. Returns the quality used and the JPEG; the
+/// compressor is left set to that quality.
+pub fn compress_to_target_size(
+    rgb_any: &ImageRawAny,
+    max_bytes: usize,
+    compressor: &mut Compressor,
+) -> Result<(u8, ImageJpeg)> {
+    let config = ConversionConfig::default();
+    let (min_quality, max_quality) = TARGET_SIZE_QUALITY_RANGE;
+    let (mut low, mut high) = (min_quality, max_quality);
+    let mut best: Option<(u8, Vec<u8>)> = None;
+    let mut output = Vec::new();
+    let finish_at = |quality: u8, data: Vec<u8>| {
+        let jpeg = ImageJpeg {
+            header: frame_header(rgb_any).cloned(),
+            data,
+        };
+        Ok((quality, jpeg))
+    };
+
+    for _ in 0..TARGET_SIZE_MAX_ITERATIONS {
+        if low > high {
+            break;
+        }
+        let quality = low + (high - low).div_ceil(2);
+        compressor.set_quality(quality as i32)?;
+        rgb_to_jpeg_into(rgb_any, compressor, &config, &mut output)?;
+        if output.len() <= max_bytes {
+            best = Some((quality, std::mem::take(&mut output)));
+            low = quality + 1;
+        } else if quality == min_quality {
+            // `output` already holds the fallback
+            return finish_at(quality, output);
+        } else {
+            high = quality - 1;
+        }
+    }
+
+    match best {
+        Some((quality, data)) => {
+            compressor.set_quality(quality as i32)?;
+            finish_at(quality, data)
+        }
+        None => {
+            compressor.set_quality(min_quality as i32)?;
+            rgb_to_jpeg_into(rgb_any, compressor, &config, &mut output)?;
+            finish_at(min_quality, output)
+        }
+    }
+}
+
+/// Every how many bytes [`DedupMode::Sampled`] reads one. Prime, so the samples do not line up
+/// with the channels or rows of common frame layouts.
+pub const DEDUP_SAMPLE_STEP: usize = 97;
+
+/// How [`FrameDedup`] decides that a frame repeats the previous one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DedupMode {
+    /// Every frame is converted.
+    #[default]
+    Off,
+    /// Byte-identical frames are skipped. Compares a hash of the whole buffer.
+    Exact,
+    /// Frames whose bytes, sampled every [`DEDUP_SAMPLE_STEP`], differ from the previous frame's
+    /// by at most `threshold` on average are skipped. Tolerates sensor noise on static scenes.
+    Sampled { threshold: u8 },
+}
+
+/// Remembers the last frame converted so repeats of it can be skipped on static scenes.
+#[derive(Debug, Clone, Default)]
+pub struct FrameDedup {
+    mode: DedupMode,
+    last_hash: Option<u64>,
+    last_samples: Option<Vec<u8>>,
+}
+
+impl FrameDedup {
+    pub fn new(mode: DedupMode) -> Self {
+        FrameDedup {
+            mode,
+            ..Default::default()
+        }
+    }
+
+    /// Whether `bytes` differ enough from the last frame this returned `true` for to be
+    /// converted. Skipped frames are not remembered, so a slow drift still gets through
+    /// once it adds up past the threshold.
+    pub fn should_convert(&mut self, bytes: &[u8]) -> bool {
+        match self.mode {
+            DedupMode::Off => true,
+            DedupMode::Exact => {
+                let mut hasher = DefaultHasher::new();
+                bytes.hash(&mut hasher);
+                let hash = hasher.finish();
+                self.last_hash.replace(hash) != Some(hash)
+            }
+            DedupMode::Sampled { threshold } => {
+                let samples = sample_frame(bytes);
+                let repeated = match &self.last_samples {
+                    // A change of frame size is always a new frame
+                    Some(last) => sample_difference(last, &samples).is_some_and(|diff| diff <= threshold as f64),
+                    None => false,
+                };
+                if !repeated {
+                    self.last_samples = Some(samples);
+                }
+                !repeated
+            }
+        }
+    }
+}
+
+/// Every [`DEDUP_SAMPLE_STEP`]-th byte of a frame, the part of it [`sample_difference`] compares.
+pub fn sample_frame(bytes: &[u8]) -> Vec<u8> {
+    bytes.iter().step_by(DEDUP_SAMPLE_STEP).copied().collect()
+}
+
+/// Mean absolute difference between two frames' [`sample_frame`] samples, from 0 (identical) to
+/// 255. `None` if the sample counts differ, i.e. the frames differ in size, or there are none.
+pub fn sample_difference(previous: &[u8], current: &[u8]) -> Option<f64> {
+    if previous.len() != current.len() || current.is_empty() {
+        return None;
+    }
+    let total: u64 = previous.iter().zip(current).map(|(a, b)| a.abs_diff(*b) as u64).sum();
+    Some(total as f64 / current.len() as f64)
+}
+
+/// Raises the quality of the first frame after a scene cut, which otherwise shows the most
+/// compression artifacts while the viewer takes in the new scene.
+#[derive(Debug, Clone, Default)]
+pub struct SceneChangeDetector {
+    threshold: u8,
+    boost: u8,
+    last_samples: Option<Vec<u8>>,
+}
+
+impl SceneChangeDetector {
+    /// Frames whose [`sample_difference`] to the previous frame exceeds `threshold` count as a
+    /// scene change and get `boost` added to their quality.
+    pub fn new(threshold: u8, boost: u8) -> Self {
+        SceneChangeDetector {
+            threshold,
+            boost,
+            last_samples: None,
+        }
+    }
+
+    /// Whether `bytes` starts a new scene, remembering them as the previous frame. The first
+    /// frame has nothing to differ from and is not a change; a change of frame size is.
+    pub fn is_scene_change(&mut self, bytes: &[u8]) -> bool {
+        let samples = sample_frame(bytes);
+        let changed = match &self.last_samples {
+            Some(last) => sample_difference(last, &samples).is_none_or(|diff| diff > self.threshold as f64),
+            None => false,
+        };
+        self.last_samples = Some(samples);
+        changed
+    }
+
+    /// Quality boost for the frame `bytes`: the configured boost on a scene change, else 0.
+    pub fn boost(&mut self, bytes: &[u8]) -> u8 {
+        match self.is_scene_change(bytes) {
+            true => self.boost,
+            false => 0,
+        }
+    }
+}
+
+/// Parses a dedup mode name as used in the app config ("off", "exact", "sampled"); `threshold`
+/// only applies to "sampled".
+pub fn parse_dedup_mode(value: &str, threshold: u8) -> Result<DedupMode> {
+    match value {
+        "off" => Ok(DedupMode::Off),
+        "exact" => Ok(DedupMode::Exact),
+        "sampled" => Ok(DedupMode::Sampled { threshold }),
+        other => Err(ConversionError::UnsupportedDedupMode(other.to_string())),
+    }
+}
+
+/// Converts a burst of frames with one compressor, returning one result per frame in order.
+/// A frame that fails to convert does not stop the rest of the batch.
+pub fn convert_batch(frames: &[ImageRawAny], compressor: &mut Compressor) -> Vec<Result<ImageJpeg>> {
+    frames.iter().map(|frame| rgb_to_jpeg(frame, compressor, &ConversionConfig::default())).collect()
+}
+
+/// Unpacks whichever variant is set into its pixel bytes, dimensions and layout.
+pub fn raw_frame(rgb_any: &ImageRawAny) -> Option<(&[u8], usize, usize, RawPixelFormat)> {
     use make87_messages::image::uncompressed::image_raw_any::Image as RawImageVariant;
 
-    match &rgb_any.image {
-        Some(RawImageVariant::Rgb888(rgb888)) => {
-            let pixels = rgb888.data.as_slice();
-            let width = rgb888.width as usize;
-            let height = rgb888.height as usize;
-            let pitch = width * 3;
-            let image = Image {
-                pixels,
+    let (data, width, height, format) = match rgb_any.image.as_ref()? {
+        RawImageVariant::Rgb888(image) => (&image.data, image.width, image.height, RawPixelFormat::Rgb888),
+        RawImageVariant::Rgba8888(image) => (&image.data, image.width, image.height, RawPixelFormat::Rgba8888),
+        RawImageVariant::Yuv420(image) => (&image.data, image.width, image.height, RawPixelFormat::Yuv420),
+        RawImageVariant::Yuv422(image) => (&image.data, image.width, image.height, RawPixelFormat::Yuv422),
+        RawImageVariant::Yuv444(image) => (&image.data, image.width, image.height, RawPixelFormat::Yuv444),
+        RawImageVariant::Nv12(image) => (&image.data, image.width, image.height, RawPixelFormat::Nv12),
+    };
+    Some((data.as_slice(), width as usize, height as usize, format))
+}
+
+/// Returns the header of `rgb_any`: the outer one, or the one on the image variant for legacy
+/// producers that only set that.
+pub fn frame_header(rgb_any: &ImageRawAny) -> Option<&Header> {
+    use make87_messages::image::uncompressed::image_raw_any::Image as RawImageVariant;
+
+    if rgb_any.header.is_some() {
+        return rgb_any.header.as_ref();
+    }
+    match rgb_any.image.as_ref()? {
+        RawImageVariant::Rgb888(image) => image.header.as_ref(),
+        RawImageVariant::Rgba8888(image) => image.header.as_ref(),
+        RawImageVariant::Yuv420(image) => image.header.as_ref(),
+        RawImageVariant::Yuv422(image) => image.header.as_ref(),
+        RawImageVariant::Yuv444(image) => image.header.as_ref(),
+        RawImageVariant::Nv12(image) => image.header.as_ref(),
+    }
+}
+
+/// Moves the image variant's header to the outer one when only the variant carries it, so code
+/// reading `rgb_any.header` sees the header [`frame_header`] finds.
+pub fn promote_frame_header(rgb_any: &mut ImageRawAny) {
+    use make87_messages::image::uncompressed::image_raw_any::Image as RawImageVariant;
+
+    if rgb_any.header.is_some() {
+        return;
+    }
+    rgb_any.header = match rgb_any.image.as_mut() {
+        Some(RawImageVariant::Rgb888(image)) => image.header.take(),
+        Some(RawImageVariant::Rgba8888(image)) => image.header.take(),
+        Some(RawImageVariant::Yuv420(image)) => image.header.take(),
+        Some(RawImageVariant::Yuv422(image)) => image.header.take(),
+        Some(RawImageVariant::Yuv444(image)) => image.header.take(),
+        Some(RawImageVariant::Nv12(image)) => image.header.take(),
+        None => None,
+    };
+}
+
+/// Like [`raw_frame`], but with the layout adjusted for `config` (interleaved YUV444).
+fn configured_raw_frame<'a>(
+    rgb_any: &'a ImageRawAny,
+    config: &ConversionConfig,
+) -> Option<(&'a [u8], usize, usize, RawPixelFormat)> {
+    raw_frame(rgb_any).map(|(data, width, height, format)| match format {
+        RawPixelFormat::Yuv444 if config.yuv444_packed => (data, width, height, RawPixelFormat::Yuv444Packed),
+        _ => (data, width, height, format),
+    })
+}
+
+/// Returns the `(width, height)` carried by whichever variant is set, if any.
+pub fn frame_dimensions(rgb_any: &ImageRawAny) -> Option<(usize, usize)> {
+    raw_frame(rgb_any).map(|(_, width, height, _)| (width, height))
+}
+
+/// Returns the `(width, height)` the JPEG for `rgb_any` will have under `config`, after any
+/// crop, downscale and rotation of packed input.
+pub fn output_dimensions(rgb_any: &ImageRawAny, config: &ConversionConfig) -> Option<(usize, usize)> {
+    let (_, mut width, mut height, format) = raw_frame(rgb_any)?;
+    if format.is_packed() {
+        if let Some(crop) = crop_area(config.roi, config.aspect, width, height) {
+            (width, height) = (crop.width, crop.height);
+        }
+        if config.scale_denom > 1 {
+            (width, height) = (width / config.scale_denom, height / config.scale_denom);
+        }
+        if config.orientation.swaps_dimensions() {
+            (width, height) = (height, width);
+        }
+    }
+    Some((width, height))
+}
+
+/// Returns the raw pixel bytes of whichever variant is set, if any.
+pub fn frame_data(rgb_any: &ImageRawAny) -> Option<&[u8]> {
+    raw_frame(rgb_any).map(|(data, ..)| data)
+}
+
+/// Reads back the header of a produced JPEG and checks it decodes to the expected size.
+/// Meant as a cheap self-test against silently corrupted output.
+pub fn verify_roundtrip(jpeg: &ImageJpeg, expected_w: usize, expected_h: usize) -> Result<()> {
+    let mut decompressor = Decompressor::new()?;
+    let header = decompressor.read_header(&jpeg.data)?;
+    if header.width != expected_w || header.height != expected_h {
+        return Err(ConversionError::DimensionMismatch {
+            expected: (expected_w, expected_h),
+            got: (header.width, header.height),
+        });
+    }
+    Ok(())
+}
+
+/// Losslessly re-encodes a baseline JPEG with progressive entropy coding.
+///
+/// The safe `Compressor` API has no progressive switch, so this goes through libjpeg-turbo's
+/// transformer, which rewrites the Huffman-coded scans without touching the DCT coefficients.
+fn make_progressive(jpeg_data: &mut Vec<u8>) -> Result<()> {
+    let transform = Transform {
+        progressive: true,
+        ..Transform::default()
+    };
+    let progressive = turbojpeg::transform(&transform, jpeg_data)?;
+    jpeg_data.clear();
+    jpeg_data.extend_from_slice(&progressive);
+    Ok(())
+}
+
+/// Compresses `rgb_any` into `output`, replacing its contents but keeping its allocation.
+///
+/// Holding one buffer across frames avoids a fresh allocation per conversion; the caller is
+/// responsible for wrapping the bytes into an `ImageJpeg` with the frame header.
+pub fn rgb_to_jpeg_into<C: Compress + ?Sized>(
+    rgb_any: &ImageRawAny,
+    compressor: &mut C,
+    config: &ConversionConfig,
+    output: &mut Vec<u8>,
+) -> Result<()> {
+    compress_frame_into(rgb_any, frame_header(rgb_any), compressor, config, output)
+}
+
+/// Compresses `rgb_any` into `output`, taking the EXIF timestamp from `header`.
+fn compress_frame_into<C: Compress + ?Sized>(
+    rgb_any: &ImageRawAny,
+    header: Option<&Header>,
+    compressor: &mut C,
+    config: &ConversionConfig,
+    output: &mut Vec<u8>,
+) -> Result<()> {
+    let (data, width, height, format) =
+        configured_raw_frame(rgb_any, config).ok_or_else(|| ConversionError::NoImageData {
+            header: header.cloned(),
+        })?;
+    if config.passthrough_jpeg && data.starts_with(&[0xFF, 0xD8]) {
+        if let Err(e) = validate_raw(data, width, height, format, config) {
+            log::warn!("Frame is not valid {} ({e}) but starts like a JPEG, passing it through", format.name());
+            output.clear();
+            output.extend_from_slice(data);
+            return Ok(());
+        }
+    }
+    compress_raw_into(data, width, height, format, compressor, config, output)?;
+    // Lossless JPEGs cannot go through the transformer
+    let lossless = config.lossless && format.supports_lossless(config);
+    match config.restart {
+        Some(interval) if !lossless => *output = restart::add_restart_markers(output, interval, config.progressive)?,
+        _ if config.progressive && !lossless => make_progressive(output)?,
+        _ => {}
+    }
+    if let Some(density) = config.density {
+        jfif::set_density(output, density);
+    }
+    if let Some(segment) = config.comment.as_deref().and_then(comment_segment) {
+        insert_after_app_segments(output, &segment);
+    }
+    if config.exif_timestamp {
+        if let Some(timestamp) = header.and_then(|header| header.timestamp.as_ref()) {
+            exif::insert_after_soi(output, &exif::timestamp_app1(timestamp));
+        }
+    }
+    if let Some(style) = config.header_style {
+        apply_header_style(output, style);
+    }
+    Ok(())
+}
+
+/// Region of a `width` x `height` packed frame kept by `roi` and then `aspect`, or `None` if
+/// neither is set. Not checked against the frame; see [`Roi::check_fits`].
+fn crop_area(roi: Option<Roi>, aspect: Option<AspectRatio>, width: usize, height: usize) -> Option<Roi> {
+    match aspect {
+        Some(aspect) => Some(aspect.center_crop(roi.unwrap_or(Roi {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        }))),
+        None => roi,
+    }
+}
+
+/// Checks the configured `roi` against a `width` x `height` packed frame and returns the region
+/// to keep after the `aspect` crop, if any.
+fn checked_crop_area(config: &ConversionConfig, width: usize, height: usize) -> Result<Option<Roi>> {
+    if let Some(roi) = config.roi {
+        roi.check_fits(width, height)?;
+    }
+    let crop = crop_area(config.roi, config.aspect, width, height);
+    if let Some(crop) = crop {
+        // An extreme aspect ratio can leave nothing of a small frame
+        crop.check_fits(width, height)?;
+    }
+    Ok(crop)
+}
+
+/// Applies the configured crop, downscale and orientation to a packed frame, in that order.
+fn preprocess_packed<'a>(mut frame: PackedFrame<'a>, config: &ConversionConfig) -> Result<PackedFrame<'a>> {
+    if let Some(crop) = checked_crop_area(config, frame.width, frame.height)? {
+        frame = frame.crop(crop)?;
+    }
+    if config.scale_denom > 1 {
+        frame = frame.downscale(config.scale_denom)?;
+    }
+    Ok(frame.orient(config.orientation))
+}
+
+/// Applies the configured preprocessing to a packed RGB/RGBA frame and compresses it.
+fn compress_packed_frame_into<C: Compress + ?Sized>(
+    frame: PackedFrame,
+    format: PixelFormat,
+    compressor: &mut C,
+    config: &ConversionConfig,
+    output: &mut Vec<u8>,
+) -> Result<()> {
+    let frame = preprocess_packed(frame, config)?;
+
+    let (frame, format) = match (config.alpha_mode, format) {
+        (AlphaMode::CompositeOver { background, premultiplied }, PixelFormat::RGBA | PixelFormat::BGRA) => {
+            let frame = frame.into_tight();
+            let (background, format) = match format {
+                PixelFormat::BGRA => ([background[2], background[1], background[0]], PixelFormat::BGR),
+                _ => (background, PixelFormat::RGB),
+            };
+            let pixels = pixels::composite_over(frame.pixels.as_ref(), background, premultiplied);
+            (PackedFrame::tight(pixels, frame.width, frame.height, 3), format)
+        }
+        _ => (frame, format),
+    };
+
+    let frame = match &config.gamma {
+        Some(lut) => {
+            let frame = frame.into_tight();
+            let mut pixels = frame.pixels.into_owned();
+            lut.apply(&mut pixels, frame.bytes_per_pixel);
+            PackedFrame::tight(pixels, frame.width, frame.height, frame.bytes_per_pixel)
+        }
+        None => frame,
+    };
+
+    // Gray subsampling goes through the same conversion so the configured matrix applies
+    if config.force_grayscale || (config.subsamp == Some(Subsamp::Gray) && !config.lossless) {
+        let (red, blue) = match format {
+            PixelFormat::BGR | PixelFormat::BGRA => (2, 0),
+            _ => (0, 2),
+        };
+        let frame = frame.into_tight();
+        let luma = pixels::luma(frame.pixels.as_ref(), frame.bytes_per_pixel, red, blue, config.color_space);
+        return compress_gray_into(&luma, frame.width, frame.height, compressor, config, output);
+    }
+
+    if config.lossless {
+        let frame = frame.into_tight();
+        *output = lossless::compress(frame.pixels.as_ref(), frame.width, frame.height, format)?;
+        return Ok(());
+    }
+
+    let image = Image {
+        pixels: frame.pixels.as_ref(),
+        width: frame.width,
+        pitch: frame.pitch(),
+        height: frame.height,
+        format,
+    };
+    backend::select(compressor, config).compress(image, config.subsamp, output)
+}
+
+/// Compresses a tightly packed 8-bit luma plane as a single-component JPEG, losslessly if
+/// configured.
+fn compress_gray_into<C: Compress + ?Sized>(
+    luma: &[u8],
+    width: usize,
+    height: usize,
+    compressor: &mut C,
+    config: &ConversionConfig,
+    output: &mut Vec<u8>,
+) -> Result<()> {
+    if config.lossless {
+        *output = lossless::compress(luma, width, height, PixelFormat::GRAY)?;
+        return Ok(());
+    }
+
+    let image = Image {
+        pixels: luma,
+        width,
+        pitch: width,
+        height,
+        format: PixelFormat::GRAY,
+    };
+    backend::select(compressor, config).compress(image, None, output)
+}
+
+/// Returns the Y plane of a YUV-family frame, tightly packed. Used to drop chroma when
+/// `force_grayscale` is set; `data` must already have passed [`validate_raw`].
+fn luma_plane<'a>(
+    data: &'a [u8],
+    width: usize,
+    height: usize,
+    format: RawPixelFormat,
+    config: &ConversionConfig,
+) -> Cow<'a, [u8]> {
+    let stride = match format {
+        RawPixelFormat::Nv12 => config.nv12_stride.unwrap_or(width),
+        _ => width,
+    };
+
+    match format {
+        RawPixelFormat::P010 => Cow::Owned(pixels::reduce_p010(&data[..width * height * 2], config.p010_reduction)),
+        // YUYV interleaves luma with chroma: Y0 U Y1 V
+        RawPixelFormat::Yuyv => Cow::Owned(data[..width * height * 2].iter().step_by(2).copied().collect()),
+        RawPixelFormat::Yuv444Packed => Cow::Owned(data[..width * height * 3].iter().step_by(3).copied().collect()),
+        _ if stride == width => Cow::Borrowed(&data[..width * height]),
+        _ => Cow::Owned(
+            (0..height)
+                .flat_map(|row| &data[row * stride..row * stride + width])
+                .copied()
+                .collect(),
+        ),
+    }
+}
+
+/// Copies YUV420 planes at the configured [`ConversionConfig::yuv420_planes`] offsets into
+/// tightly packed I420 and reduces planar 10-bit YUV420 to 8-bit I420, returning the layout
+/// the result is in; any other input is borrowed as is. `data` must already have passed
+/// [`validate_raw`].
+fn repack_planes<'a>(
+    data: &'a [u8],
+    width: usize,
+    height: usize,
+    format: RawPixelFormat,
+    config: &ConversionConfig,
+) -> (Cow<'a, [u8]>, RawPixelFormat) {
+    match (format, config.yuv420_planes) {
+        (RawPixelFormat::Yuv420, Some(planes)) => (Cow::Owned(planes.repack(data, width, height)), format),
+        (RawPixelFormat::Yuv420P10, _) => {
+            let len = format.expected_len(width, height);
+            let i420 = pixels::reduce_lsb10(&data[..len], config.yuv420p10_reduction);
+            (Cow::Owned(i420), RawPixelFormat::Yuv420)
+        }
+        _ => (Cow::Borrowed(data), format),
+    }
+}
+
+/// Compresses tightly packed `data` in the given layout to JPEG, without needing an
+/// `ImageRawAny` message. Uses the default [`ConversionConfig`].
+pub fn compress_raw(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    format: RawPixelFormat,
+    compressor: &mut Compressor,
+) -> Result<Vec<u8>> {
+    let mut jpeg_data = Vec::new();
+    compress_raw_into(data, width, height, format, compressor, &ConversionConfig::default(), &mut jpeg_data)?;
+    Ok(jpeg_data)
+}
+
+/// Runs every dimension and buffer-size check [`compress_raw_into`] applies with `config`,
+/// without compressing anything.
+pub fn validate_raw(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    format: RawPixelFormat,
+    config: &ConversionConfig,
+) -> Result<()> {
+    if !format.is_enabled() {
+        return Err(ConversionError::UnsupportedFormat(format!(
+            "{} (built without the `{}` feature)",
+            format.name(),
+            format.feature()
+        )));
+    }
+    // Failing beats silently encoding chroma at `quality`
+    if config.chroma_quality.is_some() && !format.supports_chroma_quality(config) {
+        return Err(ConversionError::UnsupportedFormat(format!("{} with chroma_quality", format.name())));
+    }
+    // libjpeg-turbo would otherwise be handed a degenerate image
+    if width == 0 || height == 0 {
+        return Err(ConversionError::ZeroDimension { width, height });
+    }
+    let max_pixels = config.max_pixels.unwrap_or(DEFAULT_MAX_PIXELS);
+    if width.checked_mul(height).is_none_or(|pixels| pixels > max_pixels) {
+        return Err(ConversionError::TooManyPixels {
+            width,
+            height,
+            max_pixels,
+        });
+    }
+
+    let name = format.name();
+    let odd_width = !width.is_multiple_of(2);
+    let odd_height = !height.is_multiple_of(2);
+
+    // Only the Y plane is used when dropping chroma, so chroma alignment does not matter then
+    let luma_only = config.force_grayscale
+        && !format.is_packed()
+        && !matches!(format, RawPixelFormat::Gray8 | RawPixelFormat::Cmyk);
+    let requirement = match format {
+        _ if luma_only => None,
+        RawPixelFormat::Yuv420
+        | RawPixelFormat::Yv12
+        | RawPixelFormat::Nv12
+        | RawPixelFormat::Nv21
+        | RawPixelFormat::P010
+        | RawPixelFormat::Yuv420P10 => (odd_width || odd_height).then_some("even width and height"),
+        RawPixelFormat::Yuv422 | RawPixelFormat::Yuyv => odd_width.then_some("an even width"),
+        RawPixelFormat::Yuv411 => (!width.is_multiple_of(4)).then_some("a width that is a multiple of 4"),
+        // Smaller mosaics miss a color channel entirely
+        #[cfg(feature = "bayer")]
+        RawPixelFormat::Bayer(_) => (width < 2 || height < 2).then_some("width and height of at least 2"),
+        _ => None,
+    };
+    if let Some(requirement) = requirement {
+        return Err(ConversionError::InvalidDimensions {
+            format: name,
+            requirement,
+            width,
+            height,
+        });
+    }
+
+    // Sizes are computed with checked arithmetic: on 32-bit targets a wrapped-around size would
+    // pass the length check and let later reads run past the buffer
+    let overflow = || ConversionError::DimensionOverflow {
+        format: name,
+        width,
+        height,
+    };
+    let expected_len = match format {
+        RawPixelFormat::Nv12 | RawPixelFormat::Nv21 => {
+            let stride = match format {
+                RawPixelFormat::Nv12 => config.nv12_stride.unwrap_or(width),
+                _ => width,
+            };
+            if stride < width {
+                return Err(ConversionError::InvalidStride {
+                    format: name,
+                    stride,
+                    width,
+                });
+            }
+            // The padding after the very last row may be missing
+            stride
+                .checked_mul((height + height / 2).saturating_sub(1))
+                .and_then(|len| len.checked_add(width))
+                .ok_or_else(overflow)?
+        }
+        RawPixelFormat::Rgb888 | RawPixelFormat::Rgba8888 | RawPixelFormat::Bgr888 | RawPixelFormat::Bgra8888 => {
+            let row_len = format.checked_len(width, 1).ok_or_else(overflow)?;
+            let stride = config.rgb_stride.unwrap_or(row_len);
+            if stride < row_len {
+                return Err(ConversionError::InvalidStride {
+                    format: name,
+                    stride,
+                    width: row_len,
+                });
+            }
+            stride.checked_mul(height).ok_or_else(overflow)?
+        }
+        // A 16-bit frame of any other size is most likely another layout misread as this one
+        RawPixelFormat::Rgb161616 => {
+            let expected = format.checked_len(width, height).ok_or_else(overflow)?;
+            if data.len() != expected {
+                return Err(ConversionError::BufferSizeMismatch {
+                    format: name,
+                    expected,
+                    got: data.len(),
+                });
+            }
+            expected
+        }
+        RawPixelFormat::Yuv420 => match config.yuv420_planes {
+            Some(planes) => {
+                planes.check_fits(width, height, data.len())?;
+                0
+            }
+            None => format.checked_len(width, height).ok_or_else(overflow)?,
+        },
+        RawPixelFormat::Yuv420P10 => {
+            pixels::check_yuv420p10_fits(width, height, data.len())?;
+            format.checked_len(width, height).ok_or_else(overflow)?
+        }
+        _ => format.checked_len(width, height).ok_or_else(overflow)?,
+    };
+    if data.len() < expected_len {
+        return Err(ConversionError::BufferTooSmall {
+            format: name,
+            expected: expected_len,
+            got: data.len(),
+        });
+    }
+
+    if format.is_packed() {
+        let (mut width, mut height) = (width, height);
+        if let Some(crop) = checked_crop_area(config, width, height)? {
+            (width, height) = (crop.width, crop.height);
+        }
+        if config.scale_denom > 1 {
+            pixels::scaled_size(width, height, config.scale_denom)?;
+        }
+    }
+    Ok(())
+}
+
+/// Validates `data` against `format` with [`validate_raw`] and compresses it into `output`.
+/// Shared by [`compress_raw`] and the `ImageRawAny` path.
+pub fn compress_raw_into<C: Compress + ?Sized>(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    format: RawPixelFormat,
+    compressor: &mut C,
+    config: &ConversionConfig,
+    output: &mut Vec<u8>,
+) -> Result<()> {
+    validate_raw(data, width, height, format, config)?;
+    let (data, format) = repack_planes(data, width, height, format, config);
+    let data = data.as_ref();
+    let expected_len = format.expected_len(width, height);
+
+    // Set either way, so a reused compressor does not keep the flag from an earlier config
+    compressor.set_optimize(config.optimize_huffman)?;
+
+    if config.force_grayscale
+        && !format.is_packed()
+        && !matches!(format, RawPixelFormat::Gray8 | RawPixelFormat::Cmyk)
+    {
+        let luma = luma_plane(data, width, height, format, config);
+        return compress_gray_into(&luma, width, height, compressor, config, output);
+    }
+
+    if let Some(mode) = config.chroma_upsample.filter(|_| !format.is_packed()) {
+        let layout = YuvLayout::of(format, width, height, config);
+        if layout.chroma_offsets.is_some() && layout.chroma_shift != (0, 0) {
+            let data = match format {
+                RawPixelFormat::P010 => Cow::Owned(pixels::reduce_p010(&data[..expected_len], config.p010_reduction)),
+                _ => Cow::Borrowed(data),
+            };
+            let yuv444_data = yuv444_planes(&data, width, height, &layout, mode);
+            let yuv_image = YuvImage {
+                pixels: yuv444_data.as_slice(),
                 width,
-                pitch,
+                align: 1,
                 height,
-                format: PixelFormat::RGB,
+                subsamp: Subsamp::None, // YUV444 (upsampled from subsampled chroma)
             };
-            let jpeg_data = compressor.compress_to_vec(image)?;
-            Ok(ImageJpeg {
-                header: rgb_any.header.clone(),
-                data: jpeg_data,
-            })
+            return compressor.compress_yuv(yuv_image, output);
         }
-        Some(RawImageVariant::Rgba8888(rgba8888)) => {
-            let pixels = rgba8888.data.as_slice();
-            let width = rgba8888.width as usize;
-            let height = rgba8888.height as usize;
-            let pitch = width * 4;
-            let image = Image {
-                pixels,
+    }
+
+    match format {
+        #[cfg(feature = "rgb")]
+        RawPixelFormat::Rgb888 | RawPixelFormat::Rgba8888 | RawPixelFormat::Bgr888 | RawPixelFormat::Bgra8888 => {
+            let (pixel_format, bytes_per_pixel) = match format {
+                RawPixelFormat::Rgb888 => (PixelFormat::RGB, 3),
+                RawPixelFormat::Rgba8888 => (PixelFormat::RGBA, 4),
+                RawPixelFormat::Bgr888 => (PixelFormat::BGR, 3),
+                _ => (PixelFormat::BGRA, 4),
+            };
+            let stride = config.rgb_stride.unwrap_or(width * bytes_per_pixel);
+            let frame = PackedFrame::strided(data, width, height, bytes_per_pixel, stride);
+            compress_packed_frame_into(frame, pixel_format, compressor, config, output)
+        }
+        #[cfg(feature = "gray")]
+        RawPixelFormat::Gray8 => {
+            compress_gray_into(&data[..expected_len], width, height, compressor, config, output)
+        }
+        // Too small to split; validation already rejects whichever of these is disabled. Planar
+        // 10-bit YUV420 arrives here as YUV420, so it needs this arm with only `p010` on.
+        #[cfg(any(
+            feature = "yuv420",
+            feature = "yuv422",
+            feature = "yuv411",
+            feature = "yuv444",
+            feature = "p010"
+        ))]
+        RawPixelFormat::Yuv420 | RawPixelFormat::Yuv422 | RawPixelFormat::Yuv411 | RawPixelFormat::Yuv444 => {
+            let subsamp = match format {
+                RawPixelFormat::Yuv420 => Subsamp::Sub2x2,
+                RawPixelFormat::Yuv422 => Subsamp::Sub2x1,
+                RawPixelFormat::Yuv411 => Subsamp::Sub4x1,
+                _ => Subsamp::None,
+            };
+
+            let yuv_image = YuvImage {
+                pixels: data,
                 width,
-                pitch,
+                align: 1,
                 height,
-                format: PixelFormat::RGBA,
+                subsamp,
             };
-            let jpeg_data = compressor.compress_to_vec(image)?;
-            Ok(ImageJpeg {
-                header: rgb_any.header.clone(),
-                data: jpeg_data,
-            })
+            compressor.compress_yuv(yuv_image, output)
         }
-        Some(RawImageVariant::Yuv420(yuv420)) => {
-            let width = yuv420.width as usize;
-            let height = yuv420.height as usize;
-            let yuv_data = yuv420.data.as_slice();
+        #[cfg(feature = "yuv444")]
+        RawPixelFormat::Yuv444Packed => {
+            let yuv444_data = yuv444_packed_to_planar(data, width, height);
+
             let yuv_image = YuvImage {
-                pixels: yuv_data,
+                pixels: yuv444_data.as_slice(),
                 width,
                 align: 1,
                 height,
-                subsamp: Subsamp::Sub2x2, // YUV420
+                subsamp: Subsamp::None, // YUV444 (converted from interleaved)
             };
-            let jpeg_data = compressor.compress_yuv_to_vec(yuv_image)?;
-            Ok(ImageJpeg {
-                header: rgb_any.header.clone(),
-                data: jpeg_data,
-            })
+            compressor.compress_yuv(yuv_image, output)
         }
-        Some(RawImageVariant::Yuv422(yuv422)) => {
-            let width = yuv422.width as usize;
-            let height = yuv422.height as usize;
-            let yuv_data = yuv422.data.as_slice();
+        #[cfg(feature = "yuv420")]
+        RawPixelFormat::Yv12 => {
+            let yuv420_data = yv12_to_i420(data, width, height);
+
             let yuv_image = YuvImage {
-                pixels: yuv_data,
+                pixels: yuv420_data.as_slice(),
                 width,
                 align: 1,
                 height,
-                subsamp: Subsamp::Sub2x1, // YUV422
+                subsamp: Subsamp::Sub2x2, // YUV420 (converted from YV12)
             };
-            let jpeg_data = compressor.compress_yuv_to_vec(yuv_image)?;
-            Ok(ImageJpeg {
-                header: rgb_any.header.clone(),
-                data: jpeg_data,
-            })
+            compressor.compress_yuv(yuv_image, output)
         }
-        Some(RawImageVariant::Yuv444(yuv444)) => {
-            let width = yuv444.width as usize;
-            let height = yuv444.height as usize;
-            let yuv_data = yuv444.data.as_slice();
+        #[cfg(feature = "nv12")]
+        RawPixelFormat::Nv12 | RawPixelFormat::Nv21 => {
+            let yuv420_data = match format {
+                RawPixelFormat::Nv12 => nv12_to_i420(data, width, height, config.nv12_stride.unwrap_or(width))?,
+                _ => semi_planar_to_i420(data, width, height, width, ChromaOrder::Vu)?,
+            };
+
             let yuv_image = YuvImage {
-                pixels: yuv_data,
+                pixels: yuv420_data.as_slice(),
                 width,
                 align: 1,
                 height,
-                subsamp: Subsamp::None, // YUV444
+                subsamp: Subsamp::Sub2x2, // YUV420 (converted from semi-planar)
             };
-            let jpeg_data = compressor.compress_yuv_to_vec(yuv_image)?;
-            Ok(ImageJpeg {
-                header: rgb_any.header.clone(),
-                data: jpeg_data,
-            })
+            compressor.compress_yuv(yuv_image, output)
         }
-        Some(RawImageVariant::Nv12(nv12)) => {
-            let width = nv12.width as usize;
-            let height = nv12.height as usize;
-            let nv12_data = nv12.data.as_slice();
-
-            // NV12 format: Y plane followed by interleaved UV plane
-            let y_size = width * height;
-            let uv_size = y_size / 2; // UV plane is half the size (2x2 subsampling)
-
-            if nv12_data.len() < y_size + uv_size {
-                return Err(anyhow!("NV12 data too small: expected {}, got {}", y_size + uv_size, nv12_data.len()));
-            }
-
-            // Create planar YUV420 data
-            let mut yuv420_data = Vec::with_capacity(y_size + uv_size);
-
-            // Copy Y plane as-is
-            yuv420_data.extend_from_slice(&nv12_data[0..y_size]);
-
-            // Convert interleaved UV to separate U and V planes
-            let uv_plane = &nv12_data[y_size..y_size + uv_size];
+        #[cfg(feature = "p010")]
+        RawPixelFormat::P010 => {
+            let nv12_data = pixels::reduce_p010(&data[..expected_len], config.p010_reduction);
+            let yuv420_data = nv12_to_i420(&nv12_data, width, height, width)?;
 
-            // Extract U components (even indices in UV plane)
-            for i in (0..uv_size).step_by(2) {
-                yuv420_data.push(uv_plane[i]);
-            }
-
-            // Extract V components (odd indices in UV plane)
-            for i in (1..uv_size).step_by(2) {
-                yuv420_data.push(uv_plane[i]);
+            let yuv_image = YuvImage {
+                pixels: yuv420_data.as_slice(),
+                width,
+                align: 1,
+                height,
+                subsamp: Subsamp::Sub2x2, // YUV420 (converted from 8-bit NV12)
+            };
+            compressor.compress_yuv(yuv_image, output)
+        }
+        #[cfg(feature = "rgb")]
+        RawPixelFormat::Rgb161616 => {
+            let rgb = pixels::tone_map_gray16(&data[..expected_len], config.rgb16_map);
+            let frame = PackedFrame::tight(rgb, width, height, 3);
+            compress_packed_frame_into(frame, PixelFormat::RGB, compressor, config, output)
+        }
+        // Straight to libjpeg-turbo in either build, as with YUV input
+        #[cfg(feature = "cmyk")]
+        RawPixelFormat::Cmyk => {
+            let image = Image {
+                pixels: &data[..expected_len],
+                width,
+                pitch: width * 4,
+                height,
+                format: PixelFormat::CMYK,
+            };
+            if let Some(subsamp) = config.subsamp.filter(|subsamp| *subsamp != Subsamp::Gray) {
+                compressor.set_subsamp(subsamp)?;
             }
+            compressor.compress_rgb(image, output)
+        }
+        #[cfg(feature = "bayer")]
+        RawPixelFormat::Bayer(pattern) => {
+            let frame = PackedFrame::tight(bayer::demosaic_bilinear(data, width, height, pattern), width, height, 3);
+            compress_packed_frame_into(frame, PixelFormat::RGB, compressor, config, output)
+        }
+        #[cfg(feature = "yuv422")]
+        RawPixelFormat::Yuyv => {
+            let yuv422_data = yuyv_to_i422(data, width, height)?;
 
             let yuv_image = YuvImage {
-                pixels: yuv420_data.as_slice(),
+                pixels: yuv422_data.as_slice(),
                 width,
                 align: 1,
                 height,
-                subsamp: Subsamp::Sub2x2, // YUV420 (converted from NV12)
+                subsamp: Subsamp::Sub2x1, // YUV422 (converted from YUYV)
             };
-            let jpeg_data = compressor.compress_yuv_to_vec(yuv_image)?;
-            Ok(ImageJpeg {
-                header: rgb_any.header.clone(),
-                data: jpeg_data,
-            })
+            compressor.compress_yuv(yuv_image, output)
         }
-        None => Err(anyhow!("No image data in ImageRawAny")),
+        // Only reachable with some format features disabled, which validate_raw rejects first
+        #[allow(unreachable_patterns)]
+        _ => Err(ConversionError::UnsupportedFormat(format.name().to_string())),
     }
 }
-
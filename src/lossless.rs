@@ -0,0 +1,72 @@
+//! Lossless JPEG (ITU-T T.81 process 14) encoding.
+//!
+//! The safe `turbojpeg::Compressor` has no lossless switch, so this drives libjpeg-turbo's tj3
+//! API through `turbojpeg::raw` directly. Each call uses a short-lived handle; lossless frames
+//! are large and slow enough that the extra `tj3Init` does not show up.
+
+use crate::error::ConversionError;
+use crate::tj3::{take_tj_buffer, Handle};
+use std::ptr;
+use turbojpeg::{raw, PixelFormat};
+
+/// Predictor selection value (1 = pixel to the left), the cheapest of the seven predictors.
+const PREDICTOR: i32 = 1;
+/// Point transform; 0 keeps every bit, anything higher would be lossy.
+const POINT_TRANSFORM: i32 = 0;
+
+/// Encodes a tightly packed RGB(A), BGR(A) or grayscale buffer as lossless JPEG.
+///
+/// Color input is stored in the RGB colorspace without subsampling, since the RGB-to-YCbCr
+/// conversion would round; decoding the result to the same `format` gives back the input bytes.
+/// Quality does not apply to lossless encoding.
+pub fn compress(
+    pixels: &[u8],
+    width: usize,
+    height: usize,
+    format: PixelFormat,
+) -> Result<Vec<u8>, ConversionError> {
+    let (tj_format, subsamp, colorspace) = match format {
+        PixelFormat::RGB => (raw::TJPF_TJPF_RGB, raw::TJSAMP_TJSAMP_444, raw::TJCS_TJCS_RGB),
+        PixelFormat::RGBA => (raw::TJPF_TJPF_RGBA, raw::TJSAMP_TJSAMP_444, raw::TJCS_TJCS_RGB),
+        PixelFormat::BGR => (raw::TJPF_TJPF_BGR, raw::TJSAMP_TJSAMP_444, raw::TJCS_TJCS_RGB),
+        PixelFormat::BGRA => (raw::TJPF_TJPF_BGRA, raw::TJSAMP_TJSAMP_444, raw::TJCS_TJCS_RGB),
+        PixelFormat::GRAY => (raw::TJPF_TJPF_GRAY, raw::TJSAMP_TJSAMP_GRAY, raw::TJCS_TJCS_GRAY),
+        other => return Err(ConversionError::UnsupportedFormat(format!("{:?} for lossless encoding", other))),
+    };
+
+    let pitch = width * format.size();
+    if pixels.len() < pitch * height {
+        return Err(ConversionError::BufferTooSmall {
+            format: "Lossless",
+            expected: pitch * height,
+            got: pixels.len(),
+        });
+    }
+
+    let handle = Handle::new(raw::TJINIT_TJINIT_COMPRESS)?;
+    handle.set(raw::TJPARAM_TJPARAM_LOSSLESS, 1)?;
+    handle.set(raw::TJPARAM_TJPARAM_LOSSLESSPSV, PREDICTOR)?;
+    handle.set(raw::TJPARAM_TJPARAM_LOSSLESSPT, POINT_TRANSFORM)?;
+    handle.set(raw::TJPARAM_TJPARAM_SUBSAMP, subsamp as _)?;
+    handle.set(raw::TJPARAM_TJPARAM_COLORSPACE, colorspace as _)?;
+
+    let mut jpeg_buf: *mut u8 = ptr::null_mut();
+    let mut jpeg_size: usize = 0;
+    let status = unsafe {
+        raw::tj3Compress8(
+            handle.as_raw(),
+            pixels.as_ptr(),
+            width as _,
+            pitch as _,
+            height as _,
+            tj_format as _,
+            &mut jpeg_buf,
+            &mut jpeg_size as *mut usize as *mut _,
+        )
+    };
+    let jpeg = unsafe { take_tj_buffer(jpeg_buf, jpeg_size) };
+    if status != 0 {
+        return Err(handle.last_error());
+    }
+    Ok(jpeg)
+}
@@ -1,42 +1,928 @@
 use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 use anyhow::{Result, anyhow};
 use make87;
 use make87::interfaces::zenoh::{ConfiguredSubscriber, ZenohInterface};
 use make87::encodings::Encoder;
+use make87::models::ApplicationEnvConfig;
+use make87_messages::core::Header;
+use make87_messages::file::RelativePathFile;
+use make87_messages::google::protobuf::Timestamp;
 use make87_messages::image::compressed::ImageJpeg;
 use make87_messages::image::uncompressed::ImageRawAny;
 use turbojpeg::Compressor;
 use log::{info, warn, error};
-use raw_to_jpeg::rgb_to_jpeg;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use raw_to_jpeg::capabilities::capabilities;
+use raw_to_jpeg::debug::{debug_frame, SourceReference};
+use raw_to_jpeg::dispatch::{FrameSequence, ReorderBuffer, StaleFrameFilter};
+use raw_to_jpeg::jfif::Density;
+use raw_to_jpeg::metrics::{DecodeFailures, FormatTracker, Heartbeat, Metrics, DEFAULT_HEARTBEAT_INTERVAL};
+use raw_to_jpeg::mjpeg::MjpegStream;
+use raw_to_jpeg::phash::phash_jpeg;
+use raw_to_jpeg::pixels::{GammaLut, Orientation, Plane, Roi, Yuv420Planes};
+use raw_to_jpeg::record::FrameRecorder;
+use raw_to_jpeg::reload::{reload_config, LiveConfig};
+use raw_to_jpeg::restart::parse_restart_interval;
+use raw_to_jpeg::shutdown::{next_step, on_conversion_error, parse_error_policy, ErrorPolicy, LoopStep, StopReason};
+use raw_to_jpeg::tile::{rgb_to_jpeg_tiles, rgb_to_jpeg_tiles_padded, TileSize};
+use raw_to_jpeg::topics::{
+    parse_queue_discipline, resolve_output_profiles, OutputProfile, QueueDiscipline, SubscriberQueue, Topics,
+};
+use raw_to_jpeg::{
+    auto_subsamp, compress_with_retry, encode_image, frame_data, make_error_frame, make_thumbnail, output_dimensions,
+    parse_alpha_mode, parse_aspect_ratio, parse_chroma_upsample, parse_color_space, parse_dedup_mode, parse_gray16_map, parse_header_style, parse_orientation, parse_output_format,
+    parse_subsamp, promote_frame_header, raw_frame, resolve_quality, rgb_to_jpeg_into, set_quality_clamped,
+    validate_frame_with_config, verify_roundtrip, warmup, ConversionConfig, ConversionStats, DedupMode, FormatQuality,
+    FrameDedup, OutputFormat, QualitySource, RatioMonitor, SceneChangeDetector, DEFAULT_MAX_PIXELS,
+    DEFAULT_THUMBNAIL_WIDTH, JPEG_QUALITY_ENV, MAX_COMMENT_LEN,
+};
 
-macro_rules! convert_and_publish {
-    ($sub:expr, $publisher:expr, $jpeg_quality:expr) => {{
+/// Settings resolved once from the application config at startup.
+#[derive(Clone)]
+struct Settings {
+    jpeg_quality: u8,
+    /// Per-format replacements for `jpeg_quality`, until quality is changed at runtime.
+    format_quality: FormatQuality,
+    /// Pick the subsampling of RGB input from each profile's quality instead of `jpeg_subsamp`.
+    auto_subsamp: bool,
+    conversion_config: ConversionConfig,
+    /// Conversion settings reloaded from `config_file`, if set; they replace `conversion_config`
+    /// from the next frame on.
+    live_config: Option<LiveConfig>,
+    verify_output: bool,
+    /// Log a metrics snapshot every this many converted frames; 0 disables it.
+    metrics_log_interval: u64,
+    /// Publish a liveness message on `heartbeat` this long after the last one or the last
+    /// converted frame, if set.
+    heartbeat_interval: Option<Duration>,
+    /// Warn when the compression ratio leaves this `(min, max)` band, if set.
+    ratio_band: Option<(f64, f64)>,
+    /// Number of conversion threads, each with its own compressor.
+    worker_threads: usize,
+    /// Publish in arrival order even when a later frame finishes converting first.
+    preserve_order: bool,
+    /// Only check that frames are convertible and log the outcome; nothing is published.
+    validate_only: bool,
+    /// Pre-rendered JPEG published in place of frames that fail to convert, if enabled.
+    error_frame: Option<Arc<[u8]>>,
+    /// Compress a synthetic frame with each worker's compressor before the first real frame.
+    warmup: bool,
+    /// Whether a frame that fails to convert (and is not replaced by the error frame) ends the loop.
+    on_error: ErrorPolicy,
+    /// How often a compression that libjpeg-turbo fails is retried with a new compressor.
+    compress_retries: u32,
+    /// How repeats of the previous frame are detected and skipped.
+    dedup: DedupMode,
+    /// Sampled difference to the previous frame above which a frame gets a quality boost, and
+    /// the boost, if enabled.
+    scene_change: Option<(u8, u8)>,
+    /// Frames whose header timestamp is older than this on arrival are dropped, if set.
+    max_age: Option<Duration>,
+    /// Codec of the published images.
+    output_format: OutputFormat,
+    /// Packed RGB frames are published as a grid of tiles of this size, if set.
+    tile: Option<TileSize>,
+    /// Color (R, G, B) the tiles cut short at the frame edges are padded to full size with, if set.
+    tile_pad: Option<[u8; 3]>,
+    /// Width of the thumbnails published on `jpeg_thumb`, if enabled.
+    thumbnail_width: Option<usize>,
+    /// Publish a perceptual hash of the first profile's JPEG on `jpeg_phash`.
+    phash: bool,
+    /// Publish the first profile's JPEG with a reference to its raw source on `debug_frame`.
+    debug_frame: bool,
+    /// Streams produced from every frame, in the order of the publishers.
+    profiles: Vec<OutputProfile>,
+    /// Directory every n-th converted frame is written to, and n, if recording is enabled.
+    record: Option<(PathBuf, u64)>,
+    /// Debug MJPEG stream that also receives the first profile's JPEGs, if `mjpeg_port` is set.
+    mjpeg: Option<Arc<MjpegStream>>,
+}
+
+/// Size of the JPEG published in place of frames that fail to convert.
+const ERROR_FRAME_SIZE: (usize, usize) = (320, 240);
+
+/// Qualities the conversion workers pick up before their next frame, kept apart by source so a
+/// config file does not count as a quality control update.
+#[derive(Debug)]
+struct PendingQuality {
+    /// `jpeg_quality`, or the config file's quality once one is applied. Per-format qualities
+    /// still resolve on top of it.
+    base: AtomicU8,
+    /// Latest quality from the control topic, 0 until one arrives. Overrides the base and
+    /// per-format qualities from then on.
+    control: AtomicU8,
+}
+
+impl PendingQuality {
+    fn new(base: u8) -> Self {
+        PendingQuality {
+            base: AtomicU8::new(base),
+            control: AtomicU8::new(0),
+        }
+    }
+}
+
+/// Forwards integer quality values received on the control topic into `pending_quality`.
+///
+/// The control task never touches a compressor: it only publishes the latest clamped value,
+/// and each conversion worker picks it up before its next frame. A frame already being
+/// compressed finishes at its old quality, and if several updates arrive during one frame only
+/// the last one applies.
+macro_rules! forward_quality_updates {
+    ($sub:expr, $pending_quality:expr) => {{
         let subscriber = $sub;
+        let pending_quality: Arc<PendingQuality> = $pending_quality;
+        while let Ok(sample) = subscriber.recv_async().await {
+            let payload = sample.payload().to_bytes();
+            match String::from_utf8_lossy(&payload).trim().parse::<i64>() {
+                Ok(quality) => {
+                    let clamped = quality.clamp(1, 100) as u8;
+                    log::info!("Received quality update {quality}, applying {clamped} from next frame");
+                    pending_quality.control.store(clamped, Ordering::Relaxed);
+                }
+                Err(e) => log::error!("Invalid quality control payload: {e}"),
+            }
+        }
+    }};
+}
+
+/// Reads the config file at `path` and applies it on top of `base`: the conversion settings go
+/// to `live` and the quality becomes the base quality in `pending_quality`, which per-format
+/// qualities resolve on top of. Without `jpeg_quality` in the file the base reverts to
+/// `default_quality`.
+fn apply_config_file(
+    path: &Path,
+    base: &ConversionConfig,
+    live: &LiveConfig,
+    pending_quality: &PendingQuality,
+    default_quality: u8,
+) -> Result<()> {
+    let contents = fs::read_to_string(path)?;
+    let config = reload_config(live, base, &contents)?;
+    let quality = config.quality.unwrap_or(default_quality);
+    pending_quality.base.store(quality, Ordering::Relaxed);
+    info!("Applied {}: quality {quality}, subsamp {:?}", path.display(), config.subsamp);
+    Ok(())
+}
+
+/// Applies the config file at `path` and re-applies it whenever it changes, until the returned
+/// watcher is dropped. The directory is watched rather than the file, since editors and mounted
+/// config maps replace the file instead of writing to it. A file that fails to parse is logged
+/// and the settings in effect are kept.
+fn watch_config_file(
+    path: PathBuf,
+    base: ConversionConfig,
+    live: LiveConfig,
+    pending_quality: Arc<PendingQuality>,
+    default_quality: u8,
+) -> Result<RecommendedWatcher> {
+    apply_config_file(&path, &base, &live, &pending_quality, default_quality)
+        .map_err(|e| anyhow!("Cannot apply config_file {}: {e}", path.display()))?;
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let file_name = path.file_name().map(|name| name.to_os_string());
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+        Ok(event) if event.kind.is_access() => {}
+        Ok(event) if event.paths.iter().any(|changed| changed.file_name() == file_name.as_deref()) => {
+            if let Err(e) = apply_config_file(&path, &base, &live, &pending_quality, default_quality) {
+                error!("Failed to reload {}, keeping the current settings: {e}", path.display());
+            }
+        }
+        Ok(_) => {}
+        Err(e) => error!("Watching {} failed: {e}", path.display()),
+    })?;
+    watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+    Ok(watcher)
+}
+
+/// Cancels `shutdown` on the first SIGINT or SIGTERM.
+async fn wait_for_shutdown_signal(shutdown: CancellationToken) -> Result<()> {
+    let mut sigterm = signal(SignalKind::terminate())?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => {
+            result?;
+            info!("Received SIGINT, shutting down");
+        },
+        _ = sigterm.recv() => info!("Received SIGTERM, shutting down"),
+    }
+    shutdown.cancel();
+    Ok(())
+}
+
+/// Publishes a liveness message on `publisher` whenever `heartbeat` falls due, whether frames
+/// arrive or not, until `shutdown` is cancelled.
+macro_rules! publish_heartbeats {
+    ($publisher:expr, $heartbeat:expr, $shutdown:expr) => {{
         let publisher = $publisher;
-        let jpeg_quality: u8 = $jpeg_quality;
-        let image_raw_encoder = make87::encodings::ProtobufEncoder::<ImageRawAny>::new();
-        let image_jpeg_encoder = make87::encodings::ProtobufEncoder::<ImageJpeg>::new();
+        let heartbeat: Arc<Mutex<Heartbeat>> = $heartbeat;
+        let shutdown: CancellationToken = $shutdown;
+        loop {
+            let Ok(due) = heartbeat.lock().map(|heartbeat| heartbeat.next_due()) else {
+                break;
+            };
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                _ = tokio::time::sleep_until(due.into()) => {}
+            }
+            // A frame converted while sleeping pushes the heartbeat back, leaving nothing due yet
+            let status = match heartbeat.lock() {
+                Ok(mut heartbeat) => heartbeat.poll(Instant::now()),
+                Err(_) => break,
+            };
+            if let Some(status) = status {
+                if let Err(e) = publisher.put(status.to_string()).await {
+                    log::warn!("Heartbeat publish failed: {e}");
+                }
+            }
+        }
+    }};
+}
+
+/// A decoded frame tagged with its arrival order and how it appears in the logs, and the
+/// quality boost it gets for starting a new scene.
+type Job = (u64, FrameTag, ImageRawAny, u8);
+
+/// Outcome of converting one frame, tagged with the sequence number of its job.
+struct Completed {
+    seq: u64,
+    tag: FrameTag,
+    outcome: Result<Converted>,
+}
+
+/// Identifies a frame in log lines by its sequence number and input format.
+#[derive(Clone, Copy)]
+struct FrameTag {
+    id: u64,
+    format: &'static str,
+}
 
+impl FrameTag {
+    fn of(msg: &ImageRawAny, sequence: &mut FrameSequence) -> Self {
+        FrameTag {
+            id: sequence.assign(msg.header.as_ref()),
+            format: raw_frame(msg).map_or("none", |(_, _, _, format)| format.name()),
+        }
+    }
+}
+
+impl fmt::Display for FrameTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "frame={} format={}", self.id, self.format)
+    }
+}
+
+/// Encoded `ImageJpeg`s ready to publish, one per output profile, plus what the metrics need
+/// to know about them.
+struct Converted {
+    /// Messages for each output profile's topic: the frame's image, or one image per tile.
+    payloads: Vec<Vec<Vec<u8>>>,
+    input_bytes: usize,
+    /// Total JPEG bytes over all profiles.
+    output_bytes: usize,
+    latency: Duration,
+    /// Encoded thumbnail `ImageJpeg`, if thumbnails are enabled and this one converted.
+    thumbnail: Option<Vec<u8>>,
+    /// Perceptual hash of the first profile's JPEG, if enabled and it could be decoded.
+    phash: Option<u64>,
+    /// Encoded debug frame of the first profile's JPEG, if enabled.
+    debug_frame: Option<Vec<u8>>,
+    /// Bare JPEG of the first profile, kept if the MJPEG stream or recording needs it.
+    jpeg: Option<Vec<u8>>,
+    /// Header timestamp of the frame, if it has one.
+    timestamp: Option<Timestamp>,
+    /// Stands in for a frame that failed to convert; kept out of the metrics.
+    is_error_frame: bool,
+}
+
+/// Queues connecting the receive loop, the conversion threads and the publisher.
+struct WorkerPool {
+    jobs: mpsc::Sender<Job>,
+    results: mpsc::Receiver<Completed>,
+    workers: Vec<thread::JoinHandle<()>>,
+}
+
+/// Starts `settings.worker_threads` conversion threads, each owning its own `Compressor`.
+///
+/// Both queues hold at most `worker_threads` entries: once they are full, sending a job waits,
+/// which pauses the receive loop instead of letting frames pile up in memory.
+fn spawn_workers(
+    settings: &Settings,
+    pending_quality: &Arc<PendingQuality>,
+) -> Result<WorkerPool> {
+    let (job_tx, job_rx) = mpsc::channel::<Job>(settings.worker_threads);
+    let (result_tx, result_rx) = mpsc::channel::<Completed>(settings.worker_threads);
+    let job_rx = Arc::new(Mutex::new(job_rx));
+
+    let mut workers = Vec::with_capacity(settings.worker_threads);
+    for index in 0..settings.worker_threads {
         let mut compressor = Compressor::new()?;
-        compressor.set_quality(jpeg_quality as i32)?;
+        let quality = set_quality_clamped(&mut compressor, settings.jpeg_quality as i64)?;
+        if settings.warmup {
+            warmup(&mut compressor)?;
+        }
+        let jobs = job_rx.clone();
+        let results = result_tx.clone();
+        let settings = settings.clone();
+        let pending_quality = pending_quality.clone();
+        let worker = thread::Builder::new()
+            .name(format!("jpeg-worker-{index}"))
+            .spawn(move || run_worker(compressor, quality, jobs, results, settings, pending_quality))?;
+        workers.push(worker);
+    }
+    Ok(WorkerPool {
+        jobs: job_tx,
+        results: result_rx,
+        workers,
+    })
+}
 
-        while let Ok(sample) = subscriber.recv_async().await {
-            let message_decoded = image_raw_encoder.decode(&sample.payload().to_bytes());
-            match message_decoded {
-                Ok(msg) => {
-                    log::info!("Received image frame");
-                    match rgb_to_jpeg(&msg, &mut compressor) {
-                        Ok(jpeg) => {
-                            let jpeg_encoded = image_jpeg_encoder.encode(&jpeg).unwrap();
-                            publisher.put(&jpeg_encoded).await?;
+/// Converts jobs until the job queue closes or the result queue is dropped.
+fn run_worker(
+    mut compressor: Compressor,
+    mut current_quality: u8,
+    jobs: Arc<Mutex<mpsc::Receiver<Job>>>,
+    results: mpsc::Sender<Completed>,
+    mut settings: Settings,
+    pending_quality: Arc<PendingQuality>,
+) {
+    let image_jpeg_encoder = make87::encodings::ProtobufEncoder::<ImageJpeg>::new();
+    // Reused across frames so the compressor writes into the same allocation every time
+    let mut jpeg_buffer: Vec<u8> = Vec::new();
+    // Quality the profile configs were last resolved with; per-format quality changes it by frame
+    let mut configs_quality = current_quality;
+    let mut profile_configs = resolve_profile_configs(&settings, configs_quality);
+    let mut quality_controlled = false;
+    let mut live_snapshot = settings.live_config.as_ref().map(LiveConfig::load);
+
+    loop {
+        // Idle workers queue up on the lock; it is released before the frame is converted
+        let job = match jobs.lock() {
+            Ok(mut jobs) => jobs.blocking_recv(),
+            Err(_) => None,
+        };
+        let Some((seq, tag, msg, boost)) = job else { break };
+
+        let requested_quality = match pending_quality.control.load(Ordering::Relaxed) {
+            0 => pending_quality.base.load(Ordering::Relaxed),
+            control => {
+                quality_controlled = true;
+                control
+            }
+        };
+        if requested_quality != current_quality {
+            match set_quality_clamped(&mut compressor, requested_quality as i64) {
+                Ok(quality) => current_quality = quality,
+                Err(e) => log::error!("Failed to apply quality {requested_quality}: {e}"),
+            }
+        }
+
+        // A quality set on the control topic applies to every format
+        let frame_quality = match raw_frame(&msg) {
+            Some((_, _, _, format)) if !quality_controlled => settings.format_quality.resolve(format, current_quality),
+            _ => current_quality,
+        };
+        // Only this frame gets the scene change boost; profiles with a fixed quality keep it
+        let frame_quality = frame_quality.saturating_add(boost).min(100);
+        // Settings reloaded from the config file apply from the next frame, like quality updates
+        let live_config = settings.live_config.as_ref().map(LiveConfig::load);
+        let reloaded = match (&live_config, &live_snapshot) {
+            (Some(config), Some(seen)) => !Arc::ptr_eq(config, seen),
+            _ => false,
+        };
+        if reloaded {
+            if let Some(config) = live_config {
+                settings.conversion_config = (*config).clone();
+                live_snapshot = Some(config);
+            }
+        }
+        if reloaded || frame_quality != configs_quality {
+            configs_quality = frame_quality;
+            profile_configs = resolve_profile_configs(&settings, frame_quality);
+        }
+
+        // Only kept for the error frame; otherwise the header is never copied
+        let header = settings.error_frame.as_ref().and_then(|_| msg.header.clone());
+        let outcome = convert_frame(
+            msg,
+            tag,
+            &mut compressor,
+            &mut jpeg_buffer,
+            &profile_configs,
+            &settings,
+            &image_jpeg_encoder,
+        );
+        let outcome = match (outcome, settings.error_frame.as_deref()) {
+            (Err(e), Some(error_frame)) => {
+                log::error!("Error converting to JPEG, publishing the error frame instead: {tag} {e}");
+                encode_error_frame(header, error_frame, settings.profiles.len(), &image_jpeg_encoder)
+            }
+            (outcome, _) => outcome,
+        };
+        if results.blocking_send(Completed { seq, tag, outcome }).is_err() {
+            break;
+        }
+    }
+}
+
+/// Writes one recorded JPEG to `path`, creating its directory if it has gone missing since
+/// startup. Never overwrites an existing file.
+fn write_recording(path: &Path, jpeg: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::OpenOptions::new().write(true).create_new(true).open(path)?.write_all(jpeg)
+}
+
+/// Replaces the queue the deployment binds the `topic` subscriber of the zenoh interface with
+/// by the app config's preferences, before the interface builds it. Returns the queue the
+/// subscriber will get, if known.
+fn apply_subscriber_preferences(
+    config: &mut ApplicationEnvConfig,
+    topic: &str,
+    discipline: Option<QueueDiscipline>,
+    depth: Option<u32>,
+) -> Option<SubscriberQueue> {
+    let subscriber_config = &mut config.interfaces.get_mut("zenoh")?.subscribers.get_mut(topic)?.config.config;
+    let bound = subscriber_config.get("handler").and_then(SubscriberQueue::from_handler);
+    let queue = SubscriberQueue::with_preferences(bound, discipline, depth)?;
+    if Some(queue) != bound {
+        subscriber_config.insert("handler".to_string(), queue.to_handler());
+    }
+    Some(queue)
+}
+
+/// Encodes the pre-rendered error frame under the `header` of a frame that failed to convert,
+/// once for each of the `profiles` output streams.
+fn encode_error_frame(
+    header: Option<Header>,
+    error_frame: &[u8],
+    profiles: usize,
+    image_jpeg_encoder: &make87::encodings::ProtobufEncoder<ImageJpeg>,
+) -> Result<Converted> {
+    let jpeg = ImageJpeg {
+        header,
+        data: error_frame.to_vec(),
+    };
+    let payload = image_jpeg_encoder.encode(&jpeg).map_err(|e| anyhow!("Encode error: {e}"))?;
+    Ok(Converted {
+        payloads: vec![vec![payload]; profiles],
+        input_bytes: 0,
+        output_bytes: jpeg.data.len() * profiles,
+        latency: Duration::ZERO,
+        thumbnail: None,
+        phash: None,
+        debug_frame: None,
+        jpeg: None,
+        timestamp: None,
+        is_error_frame: true,
+    })
+}
+
+/// The conversion settings of each output profile, with `quality` for those that do not fix
+/// their own.
+fn resolve_profile_configs(settings: &Settings, quality: u8) -> Vec<ConversionConfig> {
+    settings
+        .profiles
+        .iter()
+        .map(|profile| {
+            let quality = profile.quality.unwrap_or(quality);
+            let subsamp = match settings.auto_subsamp {
+                true => Some(auto_subsamp(quality)),
+                false => settings.conversion_config.subsamp,
+            };
+            ConversionConfig {
+                quality: Some(quality),
+                subsamp: profile.subsamp.or(subsamp),
+                ..settings.conversion_config.clone()
+            }
+        })
+        .collect()
+}
+
+/// Converts one frame once per output profile and encodes the resulting `ImageJpeg`s for
+/// publishing. The frame fails if any profile fails.
+fn convert_frame(
+    mut msg: ImageRawAny,
+    tag: FrameTag,
+    compressor: &mut Compressor,
+    jpeg_buffer: &mut Vec<u8>,
+    profile_configs: &[ConversionConfig],
+    settings: &Settings,
+    image_jpeg_encoder: &make87::encodings::ProtobufEncoder<ImageJpeg>,
+) -> Result<Converted> {
+    let input_bytes = frame_data(&msg).map_or(0, |data| data.len());
+    let mut payloads = Vec::with_capacity(profile_configs.len());
+    let mut bare_jpeg = None;
+    let mut phash = None;
+    let mut debug_payload = None;
+    let mut output_bytes = 0;
+    let mut latency = Duration::ZERO;
+
+    for config in profile_configs {
+        if let Some(quality) = config.quality {
+            compressor.set_quality(quality as i32)?;
+        }
+        let started = Instant::now();
+        if settings.output_format == OutputFormat::Webp {
+            // Published as a bare WebP file: there is no WebP message type to carry the header
+            let image = encode_image(&msg, OutputFormat::Webp, compressor, config)?;
+            latency += started.elapsed();
+            output_bytes += image.data.len();
+            payloads.push(vec![image.data]);
+            continue;
+        }
+        if let Some(tile_size) = settings.tile.filter(|_| raw_frame(&msg).is_some_and(|(_, _, _, f)| f.is_packed())) {
+            let tiles = match settings.tile_pad {
+                Some(pad) => rgb_to_jpeg_tiles_padded(&msg, tile_size, pad, compressor, config)?,
+                None => rgb_to_jpeg_tiles(&msg, tile_size, compressor, config)?,
+            };
+            latency += started.elapsed();
+            let mut messages = Vec::with_capacity(tiles.len());
+            for (_, jpeg) in tiles {
+                output_bytes += jpeg.data.len();
+                messages.push(image_jpeg_encoder.encode(&jpeg).map_err(|e| anyhow!("Encode error: {e}"))?);
+            }
+            payloads.push(messages);
+            continue;
+        }
+        let quality = config.quality.unwrap_or(settings.jpeg_quality);
+        compress_with_retry(compressor, quality, settings.compress_retries, |compressor| {
+            rgb_to_jpeg_into(&msg, compressor, config, jpeg_buffer)
+        })?;
+        latency += started.elapsed();
+
+        // The header is lent to the output and handed back below instead of cloned per profile
+        let jpeg = ImageJpeg {
+            header: msg.header.take(),
+            data: std::mem::take(jpeg_buffer),
+        };
+        // Reading the header back costs a decompressor per frame, so only when it is logged
+        if log::log_enabled!(log::Level::Debug) {
+            match ConversionStats::measure(input_bytes, &jpeg.data) {
+                Ok(stats) => log::debug!(
+                    "Converted {tag} {} -> {} bytes (ratio {:.1}, subsampling {:?})",
+                    stats.input_bytes,
+                    stats.output_bytes,
+                    stats.ratio,
+                    stats.subsamp_used
+                ),
+                Err(e) => log::debug!("Could not measure output: {tag} {e}"),
+            }
+        }
+        if settings.verify_output {
+            if let Some((width, height)) = output_dimensions(&msg, config) {
+                if let Err(e) = verify_roundtrip(&jpeg, width, height) {
+                    log::warn!("Output verification failed: {tag} {e}");
+                }
+            }
+        }
+        if settings.phash && payloads.is_empty() {
+            match phash_jpeg(&jpeg.data) {
+                Ok(hash) => phash = Some(hash),
+                Err(e) => log::warn!("Perceptual hash failed: {tag} {e}"),
+            }
+        }
+        if settings.debug_frame && payloads.is_empty() {
+            if let Some(source) = SourceReference::of(&msg) {
+                let encoder = make87::encodings::ProtobufEncoder::<RelativePathFile>::new();
+                let frame = debug_frame(&jpeg, &source);
+                debug_payload = Some(encoder.encode(&frame).map_err(|e| anyhow!("Encode error: {e}"))?);
+            }
+        }
+        if (settings.mjpeg.is_some() || settings.record.is_some()) && payloads.is_empty() {
+            bare_jpeg = Some(jpeg.data.clone());
+        }
+        payloads.push(vec![image_jpeg_encoder.encode(&jpeg).map_err(|e| anyhow!("Encode error: {e}"))?]);
+        output_bytes += jpeg.data.len();
+        msg.header = jpeg.header;
+        *jpeg_buffer = jpeg.data;
+    }
+
+    // Built with the first profile's settings; a failed thumbnail does not fail the frame
+    let thumbnail = match (settings.thumbnail_width, profile_configs.first()) {
+        (Some(thumb_width), Some(config)) => {
+            if let Some(quality) = config.quality {
+                compressor.set_quality(quality as i32)?;
+            }
+            let header = msg.header.take();
+            match make_thumbnail(&msg, thumb_width, compressor, config) {
+                Ok(mut thumbnail) => {
+                    thumbnail.header = header;
+                    Some(image_jpeg_encoder.encode(&thumbnail).map_err(|e| anyhow!("Encode error: {e}"))?)
+                }
+                Err(e) => {
+                    log::warn!("Thumbnail failed: {tag} {e}");
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+
+    Ok(Converted {
+        payloads,
+        input_bytes,
+        output_bytes,
+        latency,
+        thumbnail,
+        phash,
+        debug_frame: debug_payload,
+        jpeg: bare_jpeg,
+        timestamp: msg.header.as_ref().and_then(|header| header.timestamp),
+        is_error_frame: false,
+    })
+}
+
+/// Feeds frames to the worker pool and publishes the results until the subscriber closes or
+/// `shutdown` is cancelled.
+///
+/// Only the wait for the next sample races against the shutdown signal. Once the receive loop
+/// stops, the job queue is closed and every frame already queued or being converted is still
+/// published before this returns, so no partial publish is left behind. With `preserve_order`
+/// results are published in arrival order; otherwise each is published as soon as it is ready.
+macro_rules! convert_and_publish {
+    (
+        $sub:expr,
+        $publishers:expr,
+        $thumb_publisher:expr,
+        $phash_publisher:expr,
+        $debug_publisher:expr,
+        $status_publisher:expr,
+        $heartbeat:expr,
+        $settings:expr,
+        $pending_quality:expr,
+        $shutdown:expr
+    ) => {{
+        let subscriber = $sub;
+        let publishers = $publishers;
+        let thumb_publisher = $thumb_publisher;
+        let phash_publisher = $phash_publisher;
+        let debug_publisher = $debug_publisher;
+        let status_publisher = $status_publisher;
+        let heartbeat: Option<&Arc<Mutex<Heartbeat>>> = $heartbeat;
+        let settings: &Settings = $settings;
+        let pending_quality: &Arc<PendingQuality> = $pending_quality;
+        let shutdown: &CancellationToken = $shutdown;
+        let image_raw_encoder = make87::encodings::ProtobufEncoder::<ImageRawAny>::new();
+
+        let WorkerPool {
+            jobs: job_tx,
+            results: mut result_rx,
+            workers,
+        } = spawn_workers(settings, pending_quality)?;
+
+        let receive = async move {
+            let mut next_seq: u64 = 0;
+            let mut sequence = FrameSequence::new();
+            let mut dedup = FrameDedup::new(settings.dedup);
+            let mut scene_change =
+                settings.scene_change.map(|(threshold, boost)| SceneChangeDetector::new(threshold, boost));
+            let mut decode_failures = DecodeFailures::default();
+            let mut stale_filter = settings.max_age.map(StaleFrameFilter::new);
+            let mut formats = FormatTracker::new();
+            let stop_reason = loop {
+                let received = tokio::select! {
+                    biased;
+                    _ = shutdown.cancelled() => None,
+                    result = subscriber.recv_async() => Some(result),
+                };
+                let sample = match next_step(received) {
+                    LoopStep::Process(sample) => sample,
+                    LoopStep::Stop(reason) => break reason,
+                };
+                let decoded = image_raw_encoder.decode(&sample.payload().to_bytes());
+                // Failures held back by the throttle go out with the next frame after the interval
+                let status = match &decoded {
+                    Ok(_) => decode_failures.poll(Instant::now()),
+                    Err(_) => decode_failures.record(Instant::now()),
+                };
+                if let (Some(publisher), Some(status)) = (status_publisher, status) {
+                    if let Err(e) = publisher.put(status.to_string()).await {
+                        log::warn!("Status publish failed: {e}");
+                    }
+                }
+                match decoded {
+                    Ok(mut msg) => {
+                        promote_frame_header(&mut msg);
+                        let tag = FrameTag::of(&msg, &mut sequence);
+                        log::info!("Received image frame {tag}");
+                        if let Some((_, _, _, format)) = raw_frame(&msg) {
+                            if let Some(previous) = formats.observe(format) {
+                                log::warn!(
+                                    "Input format changed from {} to {}: {tag} (frames so far: {formats})",
+                                    previous.name(),
+                                    format.name()
+                                );
+                            }
+                        }
+                        if let Some(filter) = &mut stale_filter {
+                            if let Some(age) = filter.check(msg.header.as_ref(), SystemTime::now()) {
+                                log::warn!(
+                                    "Dropping stale frame {tag} age_ms={} dropped_total={}",
+                                    age.as_millis(),
+                                    filter.dropped()
+                                );
+                                continue;
+                            }
+                        }
+                        if frame_data(&msg).is_some_and(|data| !dedup.should_convert(data)) {
+                            log::debug!("Skipping frame, it repeats the previous one: {tag}");
+                            continue;
+                        }
+                        let boost = match (&mut scene_change, frame_data(&msg)) {
+                            (Some(detector), Some(data)) => detector.boost(data),
+                            _ => 0,
+                        };
+                        if boost > 0 {
+                            log::debug!("Scene change, boosting quality by {boost}: {tag}");
+                        }
+                        // Waits while the queue is full, which is what bounds memory use
+                        if job_tx.send((next_seq, tag, msg, boost)).await.is_err() {
+                            break StopReason::WorkersStopped;
                         }
-                        Err(e) => log::error!("Error converting to JPEG: {e}"),
+                        next_seq += 1;
                     }
-                },
-                Err(e) => log::error!("Decode error: {e}"),
+                    Err(e) => log::error!("Decode error: {e} ({} so far)", decode_failures.total()),
+                }
+            };
+            (stop_reason, formats)
+        };
+
+        let publish = async move {
+            let mut metrics = Metrics::default();
+            let mut recorder = settings.record.as_ref().map(|(dir, every)| FrameRecorder::new(dir, *every));
+            let mut ratio_monitor = settings.ratio_band.map(|(min, max)| RatioMonitor::new(min, max));
+            let mut reorder = ReorderBuffer::new();
+            while let Some(completed) = result_rx.recv().await {
+                let ready = if settings.preserve_order {
+                    reorder.push(completed.seq, completed)
+                } else {
+                    vec![completed]
+                };
+                for completed in ready {
+                    let tag = completed.tag;
+                    match completed.outcome {
+                        Ok(converted) => {
+                            log::debug!(
+                                "Converted image frame {tag} input_bytes={} output_bytes={} latency_us={} \
+                                 outputs={} error_frame={}",
+                                converted.input_bytes,
+                                converted.output_bytes,
+                                converted.latency.as_micros(),
+                                converted.payloads.len(),
+                                converted.is_error_frame
+                            );
+                            if !converted.is_error_frame {
+                                if let Some(Ok(mut heartbeat)) = heartbeat.map(|heartbeat| heartbeat.lock()) {
+                                    heartbeat.frame_converted(Instant::now());
+                                }
+                                metrics.record(converted.input_bytes, converted.output_bytes, converted.latency);
+                                let snapshot = metrics.snapshot();
+                                if settings.metrics_log_interval > 0
+                                    && snapshot.frames_converted % settings.metrics_log_interval == 0
+                                {
+                                    log::info!("Conversion metrics: {snapshot}");
+                                }
+                                if let (Some(monitor), Some((min, max))) = (&mut ratio_monitor, settings.ratio_band) {
+                                    // Compared per profile: the output bytes are summed over all of them
+                                    let input_bytes = converted.input_bytes * converted.payloads.len();
+                                    let ratio = ConversionStats::ratio_of(input_bytes, converted.output_bytes);
+                                    if let Some(ratio) = monitor.check(ratio) {
+                                        log::warn!(
+                                            "Compression ratio {ratio:.1} outside {min}-{max}, input may be noise \
+                                             or misread: {tag}"
+                                        );
+                                    }
+                                }
+                            }
+                            for (publisher, messages) in publishers.iter().zip(&converted.payloads) {
+                                for payload in messages {
+                                    if let Err(e) = publisher.put(payload).await {
+                                        // Stop receiving so the workers wind down instead of converting for nobody
+                                        shutdown.cancel();
+                                        return Err(anyhow!("Publish failed: {e}"));
+                                    }
+                                }
+                            }
+                            if let (Some(publisher), Some(thumbnail)) = (thumb_publisher, &converted.thumbnail) {
+                                if let Err(e) = publisher.put(thumbnail).await {
+                                    shutdown.cancel();
+                                    return Err(anyhow!("Thumbnail publish failed: {e}"));
+                                }
+                            }
+                            if let Some(hash) = converted.phash {
+                                log::info!("Perceptual hash {tag} phash={hash:016x}");
+                                if let Some(publisher) = phash_publisher {
+                                    if let Err(e) = publisher.put(format!("{hash:016x}")).await {
+                                        shutdown.cancel();
+                                        return Err(anyhow!("Perceptual hash publish failed: {e}"));
+                                    }
+                                }
+                            }
+                            if let (Some(publisher), Some(frame)) = (debug_publisher, &converted.debug_frame) {
+                                if let Err(e) = publisher.put(frame).await {
+                                    shutdown.cancel();
+                                    return Err(anyhow!("Debug frame publish failed: {e}"));
+                                }
+                            }
+                            if let (Some(stream), Some(jpeg)) = (&settings.mjpeg, &converted.jpeg) {
+                                stream.publish(jpeg);
+                            }
+                            if let (Some(recorder), Some(jpeg)) = (&mut recorder, converted.jpeg) {
+                                let timestamp = converted.timestamp.unwrap_or_else(Timestamp::get_current_time);
+                                if let Some(path) = recorder.next_path(&timestamp) {
+                                    // Off the publish loop: a slow disk must not hold back publishing
+                                    tokio::task::spawn_blocking(move || {
+                                        if let Err(e) = write_recording(&path, &jpeg) {
+                                            log::warn!("Recording {} failed: {e}", path.display());
+                                        }
+                                    });
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            log::error!("Error converting to JPEG: {tag} {e}");
+                            if let Err(e) = on_conversion_error(settings.on_error, e) {
+                                shutdown.cancel();
+                                return Err(anyhow!("Conversion failed with on_error stop: {tag} {e}"));
+                            }
+                        }
+                    }
+                }
             }
+            Ok(metrics) as Result<Metrics, anyhow::Error>
+        };
+
+        let ((stop_reason, formats), published) = tokio::join!(receive, publish);
+        for worker in workers {
+            if worker.join().is_err() {
+                log::error!("A conversion worker panicked");
+            }
+        }
+        let metrics = published?;
+        log::info!("Frame loop stopped ({stop_reason:?}). Final metrics: {}", metrics.snapshot());
+        if formats.counts().len() > 1 {
+            log::info!("Frames received per format: {formats}");
         }
-        Ok(()) as Result<(), anyhow::Error>
+        Ok(stop_reason) as Result<StopReason, anyhow::Error>
+    }};
+}
+
+/// Checks each received frame with `validate_frame_with_config` and logs whether it would
+/// convert, until the subscriber closes or `shutdown` is cancelled. Nothing is compressed or
+/// published.
+macro_rules! validate_frames {
+    ($sub:expr, $settings:expr, $shutdown:expr) => {{
+        let subscriber = $sub;
+        let settings: &Settings = $settings;
+        let shutdown: &CancellationToken = $shutdown;
+        let image_raw_encoder = make87::encodings::ProtobufEncoder::<ImageRawAny>::new();
+        let (mut passed, mut failed) = (0u64, 0u64);
+        let mut sequence = FrameSequence::new();
+
+        let stop_reason = loop {
+            let received = tokio::select! {
+                biased;
+                _ = shutdown.cancelled() => None,
+                result = subscriber.recv_async() => Some(result),
+            };
+            let sample = match next_step(received) {
+                LoopStep::Process(sample) => sample,
+                LoopStep::Stop(reason) => break reason,
+            };
+            match image_raw_encoder.decode(&sample.payload().to_bytes()) {
+                Ok(mut msg) => {
+                    promote_frame_header(&mut msg);
+                    let tag = FrameTag::of(&msg, &mut sequence);
+                    match validate_frame_with_config(&msg, &settings.conversion_config) {
+                        Ok(()) => {
+                            passed += 1;
+                            log::info!("Frame valid: {tag}");
+                        }
+                        Err(e) => {
+                            failed += 1;
+                            log::warn!("Frame invalid: {tag} {e}");
+                        }
+                    }
+                }
+                Err(e) => log::error!("Decode error: {e}"),
+            }
+        };
+        log::info!("Validation stopped ({stop_reason:?}): {passed} valid, {failed} invalid");
+        stop_reason
     }};
 }
 
@@ -46,30 +932,829 @@ async fn main() -> std::result::Result<(), Box<dyn Error + Send + Sync>> {
 
     let application_config = make87::config::load_config_from_default_env()?;
 
-    let jpeg_quality: u8 = match application_config.config.get("jpeg_quality") {
+    let config_quality = application_config.config.get("jpeg_quality").map(|val| val.to_string());
+    let (jpeg_quality, quality_source) = resolve_quality(config_quality.as_deref())?;
+    match quality_source {
+        QualitySource::Env => info!("Using jpeg_quality {jpeg_quality} from {JPEG_QUALITY_ENV}"),
+        QualitySource::Config => {}
+        QualitySource::Default => warn!("jpeg_quality not found in config, using default value {jpeg_quality}"),
+    }
+
+    let component_quality = |key: &str| match application_config.config.get(key) {
+        Some(val) => val.as_u64()
+            .filter(|quality| (1..=100).contains(quality))
+            .map(|quality| Some(quality as u8))
+            .ok_or_else(|| anyhow!("{key} must be an integer between 1 and 100")),
+        None => Ok(None),
+    };
+    let (jpeg_quality, chroma_quality) = match (component_quality("luma_quality")?, component_quality("chroma_quality")?) {
+        (Some(_), Some(_)) if cfg!(not(feature = "pure-rust")) => {
+            return Err(anyhow!(
+                "luma_quality and chroma_quality need the pure-rust feature: libjpeg-turbo scales luma and chroma by one quality"
+            )
+            .into());
+        }
+        // JPEG_QUALITY is the deployment override and wins over luma_quality as it does over jpeg_quality
+        (Some(_), Some(chroma)) if quality_source == QualitySource::Env => (jpeg_quality, Some(chroma)),
+        (Some(luma), Some(chroma)) => (luma, Some(chroma)),
+        (None, None) => (jpeg_quality, None),
+        _ => return Err(anyhow!("luma_quality and chroma_quality must be set together").into()),
+    };
+
+    let format_quality = match application_config.config.get("format_quality") {
         Some(val) => {
-            let parsed = val.to_string().parse::<u8>()
-                .map_err(|_| anyhow!("jpeg_quality must be an integer between 0 and 100"))?;
-            if parsed > 100 {
-                return Err(anyhow!("jpeg_quality must be between 0 and 100").into());
+            let entries = val.as_object()
+                .ok_or_else(|| anyhow!("format_quality must map format names to qualities"))?
+                .iter()
+                .map(|(name, quality)| {
+                    let quality = quality.as_i64()
+                        .ok_or_else(|| anyhow!("format_quality.{name} must be an integer between 1 and 100"))?;
+                    Ok((name.as_str(), quality))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            FormatQuality::parse(entries)?
+        }
+        None => FormatQuality::default(),
+    };
+
+    let subsamp = match application_config.config.get("jpeg_subsamp") {
+        Some(val) => {
+            let name = val.as_str()
+                .ok_or_else(|| anyhow!("jpeg_subsamp must be a string (420, 422, 444 or gray)"))?;
+            Some(parse_subsamp(name)?)
+        }
+        None => None,
+    };
+    let auto_subsamp = match application_config.config.get("auto_subsamp") {
+        Some(val) => val.as_bool()
+            .ok_or_else(|| anyhow!("auto_subsamp must be a boolean"))?,
+        None => false,
+    };
+    if auto_subsamp && subsamp.is_some() {
+        warn!("auto_subsamp is set, jpeg_subsamp is ignored");
+    }
+    let progressive = match application_config.config.get("progressive") {
+        Some(val) => val.as_bool()
+            .ok_or_else(|| anyhow!("progressive must be a boolean"))?,
+        None => false,
+    };
+    let scale_denom = match application_config.config.get("scale_denom") {
+        Some(val) => val.as_u64()
+            .filter(|denom| *denom >= 1)
+            .ok_or_else(|| anyhow!("scale_denom must be a positive integer"))? as usize,
+        None => 1,
+    };
+    let orientation = match application_config.config.get("transform") {
+        Some(val) => {
+            let name = val.as_str()
+                .ok_or_else(|| anyhow!("transform must be a string (none, fliph, flipv, rot90, rot180 or rot270)"))?;
+            parse_orientation(name)?
+        }
+        None => Default::default(),
+    };
+    let roi = match application_config.config.get("roi") {
+        Some(val) => {
+            let field = |name: &str| {
+                val.get(name)
+                    .and_then(|v| v.as_u64())
+                    .map(|v| v as usize)
+                    .ok_or_else(|| anyhow!("roi.{name} must be a non-negative integer"))
+            };
+            Some(Roi {
+                x: field("x")?,
+                y: field("y")?,
+                width: field("width")?,
+                height: field("height")?,
+            })
+        }
+        None => None,
+    };
+    let aspect = match application_config.config.get("aspect") {
+        Some(val) => {
+            let ratio = val.as_str()
+                .ok_or_else(|| anyhow!("aspect must be a string like 16:9"))?;
+            Some(parse_aspect_ratio(ratio)?)
+        }
+        None => None,
+    };
+    let restart_interval = match application_config.config.get("restart_interval") {
+        Some(val) => val.as_u64()
+            .and_then(|interval| u16::try_from(interval).ok())
+            .ok_or_else(|| anyhow!("restart_interval must be an integer between 0 and 65535"))?,
+        None => 0,
+    };
+    let restart_unit = match application_config.config.get("restart_unit") {
+        Some(val) => val.as_str()
+            .ok_or_else(|| anyhow!("restart_unit must be a string (rows or mcus)"))?,
+        None => "rows",
+    };
+    let restart = parse_restart_interval(restart_interval, restart_unit)?;
+    let optimize_huffman = match application_config.config.get("optimize_huffman") {
+        Some(val) => val.as_bool()
+            .ok_or_else(|| anyhow!("optimize_huffman must be a boolean"))?,
+        None => false,
+    };
+    let force_grayscale = match application_config.config.get("force_grayscale") {
+        Some(val) => val.as_bool()
+            .ok_or_else(|| anyhow!("force_grayscale must be a boolean"))?,
+        None => false,
+    };
+    let nv12_stride = match application_config.config.get("nv12_stride") {
+        Some(val) => Some(val.as_u64()
+            .ok_or_else(|| anyhow!("nv12_stride must be a non-negative integer"))? as usize),
+        None => None,
+    };
+    let yuv420_planes = match application_config.config.get("yuv420_planes") {
+        Some(val) => {
+            let plane = |name: &str| {
+                let field = |key: &str| {
+                    val.get(name)
+                        .and_then(|plane| plane.get(key))
+                        .and_then(|v| v.as_u64())
+                        .map(|v| v as usize)
+                        .ok_or_else(|| anyhow!("yuv420_planes.{name}.{key} must be a non-negative integer"))
+                };
+                Ok::<_, anyhow::Error>(Plane {
+                    offset: field("offset")?,
+                    stride: field("stride")?,
+                })
+            };
+            Some(Yuv420Planes {
+                y: plane("y")?,
+                u: plane("u")?,
+                v: plane("v")?,
+            })
+        }
+        None => None,
+    };
+    let rgb_stride = match application_config.config.get("rgb_stride") {
+        Some(val) => Some(val.as_u64()
+            .ok_or_else(|| anyhow!("rgb_stride must be a non-negative integer"))? as usize),
+        None => None,
+    };
+    let max_pixels = match application_config.config.get("max_pixels") {
+        Some(val) => Some(val.as_u64()
+            .filter(|max| *max >= 1)
+            .ok_or_else(|| anyhow!("max_pixels must be a positive integer"))? as usize),
+        None => None,
+    };
+    let dpi = |key: &str| -> Result<Option<u16>> {
+        match application_config.config.get(key) {
+            Some(val) => Ok(Some(val.as_u64()
+                .filter(|dpi| (1..=u16::MAX as u64).contains(dpi))
+                .ok_or_else(|| anyhow!("{key} must be an integer between 1 and 65535"))? as u16)),
+            None => Ok(None),
+        }
+    };
+    // Setting only one of the two keeps square pixels
+    let density = match (dpi("dpi_x")?, dpi("dpi_y")?) {
+        (Some(x), Some(y)) => Some(Density { x, y }),
+        (Some(both), None) | (None, Some(both)) => Some(Density { x: both, y: both }),
+        (None, None) => None,
+    };
+    let exif_timestamp = match application_config.config.get("exif_timestamp") {
+        Some(val) => val.as_bool()
+            .ok_or_else(|| anyhow!("exif_timestamp must be a boolean"))?,
+        None => false,
+    };
+    let passthrough_jpeg = match application_config.config.get("passthrough_jpeg") {
+        Some(val) => val.as_bool()
+            .ok_or_else(|| anyhow!("passthrough_jpeg must be a boolean"))?,
+        None => false,
+    };
+    let comment = match application_config.config.get("jpeg_comment") {
+        Some(val) => {
+            let text = val.as_str()
+                .ok_or_else(|| anyhow!("jpeg_comment must be a string"))?;
+            if text.len() > MAX_COMMENT_LEN {
+                warn!("jpeg_comment is {} bytes, only the first {MAX_COMMENT_LEN} are written", text.len());
             }
-            parsed
+            (!text.is_empty()).then(|| text.to_string())
+        }
+        None => None,
+    };
+    let header_style = match application_config.config.get("jpeg_header_style") {
+        Some(val) => {
+            let name = val.as_str()
+                .ok_or_else(|| anyhow!("jpeg_header_style must be a string (jfif, exif or minimal)"))?;
+            Some(parse_header_style(name)?)
+        }
+        None => None,
+    };
+    let gray16_map = match application_config.config.get("gray16_map") {
+        Some(val) => {
+            let name = val.as_str()
+                .ok_or_else(|| anyhow!("gray16_map must be a string (shift8 or minmax)"))?;
+            parse_gray16_map(name)?
         }
-        None => {
-            warn!("jpeg_quality not found in config, using default value 90");
-            90
+        None => Default::default(),
+    };
+    let color_space = match application_config.config.get("color_space") {
+        Some(val) => {
+            let name = val.as_str()
+                .ok_or_else(|| anyhow!("color_space must be a string (bt601 or bt709)"))?;
+            parse_color_space(name)?
+        }
+        None => Default::default(),
+    };
+    let rgb_color = |key: &str| -> Result<Option<[u8; 3]>> {
+        match application_config.config.get(key) {
+            Some(val) => {
+                let channels = val.as_array()
+                    .filter(|channels| channels.len() == 3)
+                    .and_then(|channels| {
+                        channels.iter()
+                            .map(|channel| channel.as_u64().and_then(|v| u8::try_from(v).ok()))
+                            .collect::<Option<Vec<u8>>>()
+                    })
+                    .ok_or_else(|| anyhow!("{key} must be [r, g, b] with values between 0 and 255"))?;
+                Ok(Some([channels[0], channels[1], channels[2]]))
+            }
+            None => Ok(None),
+        }
+    };
+    let alpha_background = rgb_color("alpha_background")?.unwrap_or([255, 255, 255]);
+    let alpha_premultiplied = match application_config.config.get("alpha_premultiplied") {
+        Some(val) => val.as_bool()
+            .ok_or_else(|| anyhow!("alpha_premultiplied must be a boolean"))?,
+        None => false,
+    };
+    let alpha_mode = match application_config.config.get("alpha_mode") {
+        Some(val) => {
+            let name = val.as_str()
+                .ok_or_else(|| anyhow!("alpha_mode must be a string (ignore or composite_over)"))?;
+            parse_alpha_mode(name, alpha_background, alpha_premultiplied)?
+        }
+        None => Default::default(),
+    };
+    let gamma = match application_config.config.get("gamma") {
+        Some(val) => {
+            let gamma = val.as_f64()
+                .filter(|gamma| gamma.is_finite() && *gamma > 0.0)
+                .ok_or_else(|| anyhow!("gamma must be a positive number"))?;
+            // 1.0 would only copy every frame through an identity table
+            (gamma != 1.0).then(|| GammaLut::new(gamma))
+        }
+        None => None,
+    };
+    let chroma_upsample = match application_config.config.get("chroma_upsample") {
+        Some(val) => {
+            let name = val.as_str()
+                .ok_or_else(|| anyhow!("chroma_upsample must be a string (nearest or bilinear)"))?;
+            Some(parse_chroma_upsample(name)?)
+        }
+        None => None,
+    };
+    let yuv444_packed = match application_config.config.get("yuv444_packed") {
+        Some(val) => val.as_bool()
+            .ok_or_else(|| anyhow!("yuv444_packed must be a boolean"))?,
+        None => false,
+    };
+    let lossless = match application_config.config.get("lossless") {
+        Some(val) => val.as_bool()
+            .ok_or_else(|| anyhow!("lossless must be a boolean"))?,
+        None => false,
+    };
+    if lossless && application_config.config.contains_key("jpeg_quality") {
+        warn!("lossless is set, jpeg_quality is ignored for RGB/RGBA input");
+    }
+    let conversion_config = ConversionConfig {
+        subsamp,
+        progressive,
+        scale_denom,
+        roi,
+        aspect,
+        orientation,
+        exif_timestamp,
+        comment,
+        restart,
+        optimize_huffman,
+        force_grayscale,
+        nv12_stride,
+        yuv420_planes,
+        rgb_stride,
+        yuv444_packed,
+        chroma_upsample,
+        lossless,
+        gray16_map,
+        // RGB161616 has no ImageRawAny variant either
+        rgb16_map: Default::default(),
+        // P010 and planar 10-bit YUV have no ImageRawAny variant, so the app never receives them
+        p010_reduction: Default::default(),
+        yuv420p10_reduction: Default::default(),
+        color_space,
+        alpha_mode,
+        gamma,
+        quality: Some(jpeg_quality),
+        chroma_quality,
+        max_pixels,
+        passthrough_jpeg,
+        density,
+        header_style,
+    };
+
+    let verify_output = match application_config.config.get("verify_output") {
+        Some(val) => val.as_bool()
+            .ok_or_else(|| anyhow!("verify_output must be a boolean"))?,
+        None => false,
+    };
+
+    let metrics_log_interval = match application_config.config.get("metrics_log_interval") {
+        Some(val) => val.as_u64()
+            .ok_or_else(|| anyhow!("metrics_log_interval must be a non-negative integer"))?,
+        None => 100,
+    };
+
+    let heartbeat_interval = match application_config.config.get("heartbeat_interval") {
+        Some(val) => val.as_u64()
+            .ok_or_else(|| anyhow!("heartbeat_interval must be a non-negative number of seconds"))?,
+        None => DEFAULT_HEARTBEAT_INTERVAL.as_secs(),
+    };
+    // 0 turns the heartbeat off
+    let heartbeat_interval = (heartbeat_interval > 0).then(|| Duration::from_secs(heartbeat_interval));
+
+    let ratio_band = match application_config.config.get("ratio_band") {
+        Some(val) => {
+            let field = |name: &str| {
+                val.get(name)
+                    .and_then(|v| v.as_f64())
+                    .filter(|v| *v >= 0.0)
+                    .ok_or_else(|| anyhow!("ratio_band.{name} must be a non-negative number"))
+            };
+            let (min, max) = (field("min")?, field("max")?);
+            if min > max {
+                return Err(anyhow!("ratio_band.min must not exceed ratio_band.max").into());
+            }
+            Some((min, max))
+        }
+        None => None,
+    };
+
+    let worker_threads = match application_config.config.get("worker_threads") {
+        Some(val) => val.as_u64()
+            .filter(|threads| *threads >= 1)
+            .ok_or_else(|| anyhow!("worker_threads must be a positive integer"))? as usize,
+        None => 1,
+    };
+
+    let preserve_order = match application_config.config.get("preserve_order") {
+        Some(val) => val.as_bool()
+            .ok_or_else(|| anyhow!("preserve_order must be a boolean"))?,
+        None => true,
+    };
+
+    let output_format = match application_config.config.get("output_format") {
+        Some(val) => {
+            let name = val.as_str()
+                .ok_or_else(|| anyhow!("output_format must be a string (jpeg or webp)"))?;
+            parse_output_format(name)?
+        }
+        None => OutputFormat::Jpeg,
+    };
+
+    let compress_retries = match application_config.config.get("compress_retries") {
+        Some(val) => val.as_u64()
+            .and_then(|retries| u32::try_from(retries).ok())
+            .ok_or_else(|| anyhow!("compress_retries must be a non-negative integer"))?,
+        None => 1,
+    };
+
+    let error_frame = match application_config.config.get("error_frame") {
+        Some(val) => val.as_bool()
+            .ok_or_else(|| anyhow!("error_frame must be a boolean"))?,
+        None => false,
+    };
+    if error_frame && output_format != OutputFormat::Jpeg {
+        warn!("error_frame is only supported with jpeg output, failed frames are skipped");
+    }
+    let error_frame = if error_frame && output_format == OutputFormat::Jpeg {
+        let mut compressor = Compressor::new()?;
+        compressor.set_quality(jpeg_quality as i32)?;
+        let (width, height) = ERROR_FRAME_SIZE;
+        Some(Arc::from(make_error_frame(width, height, &mut compressor)?))
+    } else {
+        None
+    };
+
+    let on_error = match application_config.config.get("on_error") {
+        Some(val) => {
+            let name = val.as_str()
+                .ok_or_else(|| anyhow!("on_error must be a string (continue or stop)"))?;
+            parse_error_policy(name)?
+        }
+        None => ErrorPolicy::Continue,
+    };
+    if on_error == ErrorPolicy::Stop && error_frame.is_some() {
+        // Failed frames would be replaced by the error frame and never reach the policy
+        return Err(anyhow!("on_error stop cannot be combined with error_frame").into());
+    }
+
+    let dedup_threshold = match application_config.config.get("dedup_threshold") {
+        Some(val) => val.as_u64()
+            .and_then(|threshold| u8::try_from(threshold).ok())
+            .ok_or_else(|| anyhow!("dedup_threshold must be an integer between 0 and 255"))?,
+        None => 2,
+    };
+    let dedup = match application_config.config.get("dedup") {
+        Some(val) => {
+            let name = val.as_str()
+                .ok_or_else(|| anyhow!("dedup must be a string (off, exact or sampled)"))?;
+            parse_dedup_mode(name, dedup_threshold)?
+        }
+        None => DedupMode::Off,
+    };
+    let scene_change_boost = match application_config.config.get("scene_change_boost") {
+        Some(val) => val.as_u64()
+            .filter(|boost| *boost <= 100)
+            .ok_or_else(|| anyhow!("scene_change_boost must be an integer between 0 and 100"))? as u8,
+        None => 10,
+    };
+    let scene_change = match application_config.config.get("scene_change_threshold") {
+        Some(val) => {
+            let threshold = val.as_u64()
+                .and_then(|threshold| u8::try_from(threshold).ok())
+                .ok_or_else(|| anyhow!("scene_change_threshold must be an integer between 0 and 255"))?;
+            Some((threshold, scene_change_boost))
         }
+        None => None,
     };
 
-    let zenoh_interface = ZenohInterface::from_default_env("zenoh")?;
+    let record_every = match application_config.config.get("record_every") {
+        Some(val) => val.as_u64()
+            .filter(|every| *every >= 1)
+            .ok_or_else(|| anyhow!("record_every must be a positive integer"))?,
+        None => 1,
+    };
+    let record = match application_config.config.get("record_dir") {
+        Some(val) => {
+            let dir = PathBuf::from(val.as_str()
+                .ok_or_else(|| anyhow!("record_dir must be a string"))?);
+            fs::create_dir_all(&dir)
+                .map_err(|e| anyhow!("Cannot create record_dir {}: {e}", dir.display()))?;
+            if output_format != OutputFormat::Jpeg {
+                warn!("record_dir is only supported with jpeg output, nothing is recorded");
+            }
+            Some((dir, record_every)).filter(|_| output_format == OutputFormat::Jpeg)
+        }
+        None => None,
+    };
+
+    let config_file = match application_config.config.get("config_file") {
+        Some(val) => Some(PathBuf::from(val.as_str()
+            .ok_or_else(|| anyhow!("config_file must be a string"))?)),
+        None => None,
+    };
+    let live_config = config_file.as_ref().map(|_| LiveConfig::new(conversion_config.clone()));
+
+    let max_age = match application_config.config.get("max_age_ms") {
+        Some(val) => Some(Duration::from_millis(val.as_u64()
+            .ok_or_else(|| anyhow!("max_age_ms must be a non-negative integer"))?)),
+        None => None,
+    };
+
+    let tile = match application_config.config.get("tile") {
+        Some(val) => {
+            let field = |name: &str| {
+                val.get(name)
+                    .and_then(|v| v.as_u64())
+                    .filter(|v| *v >= 1)
+                    .map(|v| v as usize)
+                    .ok_or_else(|| anyhow!("tile.{name} must be a positive integer"))
+            };
+            let tile = TileSize {
+                width: field("tile_w")?,
+                height: field("tile_h")?,
+            };
+            if output_format != OutputFormat::Jpeg {
+                warn!("tile is only supported with jpeg output, frames are published whole");
+            }
+            if conversion_config.orientation != Orientation::None {
+                warn!("transform does not apply to tiled frames");
+            }
+            Some(tile).filter(|_| output_format == OutputFormat::Jpeg)
+        }
+        None => None,
+    };
+    let tile_pad = rgb_color("tile_pad")?;
+    if tile_pad.is_some() && tile.is_none() {
+        warn!("tile_pad only applies to tiled frames");
+    }
+    // Padded tiles are allocated at the full tile size, whatever the frame size
+    if let (Some(tile), Some(_)) = (tile, tile_pad) {
+        let max_pixels = max_pixels.unwrap_or(DEFAULT_MAX_PIXELS);
+        if tile.width.checked_mul(tile.height).is_none_or(|pixels| pixels > max_pixels) {
+            let (width, height) = (tile.width, tile.height);
+            return Err(anyhow!("tile {width}x{height} with tile_pad exceeds max_pixels {max_pixels}").into());
+        }
+    }
+
+    let thumbnail = match application_config.config.get("thumbnail") {
+        Some(val) => val.as_bool()
+            .ok_or_else(|| anyhow!("thumbnail must be a boolean"))?,
+        None => false,
+    };
+    let thumbnail_width = match application_config.config.get("thumbnail_width") {
+        Some(val) => val.as_u64()
+            .filter(|width| *width >= 1)
+            .ok_or_else(|| anyhow!("thumbnail_width must be a positive integer"))? as usize,
+        None => DEFAULT_THUMBNAIL_WIDTH,
+    };
+    let thumbnail_width = thumbnail.then_some(thumbnail_width);
+
+    let warmup = match application_config.config.get("warmup") {
+        Some(val) => val.as_bool()
+            .ok_or_else(|| anyhow!("warmup must be a boolean"))?,
+        None => false,
+    };
+    let debug_frame = match application_config.config.get("debug_frame") {
+        Some(val) => val.as_bool()
+            .ok_or_else(|| anyhow!("debug_frame must be a boolean"))?,
+        None => false,
+    };
+    if debug_frame && output_format != OutputFormat::Jpeg {
+        warn!("debug_frame is only supported with jpeg output, no debug frames are published");
+    }
+    let debug_frame = debug_frame && output_format == OutputFormat::Jpeg;
+    let phash = match application_config.config.get("phash") {
+        Some(val) => val.as_bool()
+            .ok_or_else(|| anyhow!("phash must be a boolean"))?,
+        None => false,
+    };
+    if phash && output_format != OutputFormat::Jpeg {
+        warn!("phash is only supported with jpeg output, no hashes are published");
+    }
+    let phash = phash && output_format == OutputFormat::Jpeg;
+
+    let mjpeg_port = match application_config.config.get("mjpeg_port") {
+        Some(val) => Some(val.as_u64()
+            .and_then(|port| u16::try_from(port).ok())
+            .ok_or_else(|| anyhow!("mjpeg_port must be an integer between 0 and 65535"))?),
+        None => None,
+    };
+    if mjpeg_port.is_some() && output_format != OutputFormat::Jpeg {
+        warn!("mjpeg_port is only supported with jpeg output, no MJPEG stream is served");
+    }
+    let mjpeg_port = mjpeg_port.filter(|_| output_format == OutputFormat::Jpeg);
+    if cfg!(not(feature = "mjpeg")) && mjpeg_port.is_some() {
+        return Err(anyhow!("mjpeg_port is set, but the app was built without the mjpeg feature").into());
+    }
+    let mjpeg = mjpeg_port.map(|_| MjpegStream::new());
+
+    let validate_only = match application_config.config.get("validate_only") {
+        Some(val) => val.as_bool()
+            .ok_or_else(|| anyhow!("validate_only must be a boolean"))?,
+        None => false,
+    };
+
+    let topic_name = |key: &str| match application_config.config.get(key) {
+        Some(val) => val.as_str()
+            .map(Some)
+            .ok_or_else(|| anyhow!("{key} must be a string")),
+        None => Ok(None),
+    };
+    let topics = Topics::resolve(topic_name("input_topic")?, topic_name("output_topic")?);
+
+    let subscriber_queue = match application_config.config.get("subscriber_queue") {
+        Some(val) => {
+            let name = val.as_str()
+                .ok_or_else(|| anyhow!("subscriber_queue must be a string (fifo or ring)"))?;
+            Some(parse_queue_discipline(name)?)
+        }
+        None => None,
+    };
+    let subscriber_depth = match application_config.config.get("subscriber_depth") {
+        Some(val) => Some(val.as_u64()
+            .filter(|depth| *depth >= 1)
+            .and_then(|depth| u32::try_from(depth).ok())
+            .ok_or_else(|| anyhow!("subscriber_depth must be a positive integer"))?),
+        None => None,
+    };
+
+    let profiles = match application_config.config.get("output_profiles") {
+        Some(val) => {
+            let entries = val.as_array()
+                .ok_or_else(|| anyhow!("output_profiles must be a list of {{topic, quality, subsamp}}"))?;
+            if application_config.config.contains_key("output_topic") {
+                warn!("output_profiles is set, output_topic is ignored");
+            }
+            entries
+                .iter()
+                .map(|entry| {
+                    let topic = entry.get("topic")
+                        .and_then(|v| v.as_str())
+                        .ok_or_else(|| anyhow!("output_profiles[].topic must be a string"))?;
+                    let quality = match entry.get("quality") {
+                        Some(v) => Some(v.as_i64()
+                            .ok_or_else(|| anyhow!("output_profiles[].quality must be an integer"))?),
+                        None => None,
+                    };
+                    let subsamp = match entry.get("subsamp") {
+                        Some(v) => Some(v.as_str()
+                            .ok_or_else(|| anyhow!("output_profiles[].subsamp must be a string"))?),
+                        None => None,
+                    };
+                    Ok(OutputProfile::parse(topic, quality, subsamp)?)
+                })
+                .collect::<Result<Vec<_>>>()?
+        }
+        None => Vec::new(),
+    };
+    let profiles = resolve_output_profiles(profiles, &topics.output)?;
+
+    let settings = Settings {
+        jpeg_quality,
+        format_quality,
+        auto_subsamp,
+        conversion_config,
+        live_config,
+        verify_output,
+        metrics_log_interval,
+        heartbeat_interval,
+        ratio_band,
+        worker_threads,
+        preserve_order,
+        validate_only,
+        error_frame,
+        on_error,
+        warmup,
+        compress_retries,
+        dedup,
+        scene_change,
+        max_age,
+        record,
+        output_format,
+        tile,
+        tile_pad,
+        thumbnail_width,
+        phash,
+        debug_frame,
+        profiles,
+        mjpeg,
+    };
+
+    let output_topics: Vec<&str> = settings.profiles.iter().map(|profile| profile.topic.as_str()).collect();
+    info!("Subscribing to '{}', publishing to '{}'", topics.input, output_topics.join("', '"));
+
+    let mut interface_config = application_config.clone();
+    let input_queue =
+        apply_subscriber_preferences(&mut interface_config, &topics.input, subscriber_queue, subscriber_depth);
+    if input_queue.is_none() && (subscriber_queue.is_some() || subscriber_depth.is_some()) {
+        warn!("No queue config bound for '{}', subscriber_queue and subscriber_depth must both be set", topics.input);
+    }
+    let zenoh_interface = ZenohInterface::new(interface_config, "zenoh");
     let session = zenoh_interface.get_session().await?;
 
-    let configured_subscriber = zenoh_interface.get_subscriber(&session,"raw_frame").await?;
-    let publisher = zenoh_interface.get_publisher(&session, "jpeg_frame").await?;
+    let configured_subscriber = zenoh_interface.get_subscriber(&session, &topics.input).await?;
+    let obtained = match configured_subscriber {
+        ConfiguredSubscriber::Fifo(_) => QueueDiscipline::Fifo,
+        ConfiguredSubscriber::Ring(_) => QueueDiscipline::Ring,
+    };
+    match input_queue {
+        Some(queue) => info!("Input subscriber uses a {} queue of depth {}", obtained.handler_type(), queue.depth),
+        None => info!("Input subscriber uses a {} queue", obtained.handler_type()),
+    }
+    if let Some(preferred) = subscriber_queue.filter(|preferred| *preferred != obtained) {
+        warn!(
+            "subscriber_queue asked for {} but the interface built {}",
+            preferred.handler_type(),
+            obtained.handler_type()
+        );
+    }
+
+    let pending_quality = Arc::new(PendingQuality::new(settings.jpeg_quality));
+    match zenoh_interface.get_subscriber(&session, "quality_control").await {
+        Ok(control_subscriber) => {
+            let pending_quality = pending_quality.clone();
+            tokio::spawn(async move {
+                match control_subscriber {
+                    ConfiguredSubscriber::Fifo(sub) => forward_quality_updates!(&sub, pending_quality),
+                    ConfiguredSubscriber::Ring(sub) => forward_quality_updates!(&sub, pending_quality),
+                }
+            });
+        }
+        Err(e) => warn!("quality_control subscriber unavailable, quality is fixed at {}: {e}", settings.jpeg_quality),
+    }
+    // Kept alive until the app exits; dropping it stops the reloads
+    let _config_watcher = match (config_file, &settings.live_config) {
+        (Some(path), Some(live)) => Some(watch_config_file(
+            path,
+            settings.conversion_config.clone(),
+            live.clone(),
+            pending_quality.clone(),
+            settings.jpeg_quality,
+        )?),
+        _ => None,
+    };
+
+    let shutdown = CancellationToken::new();
+    {
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move {
+            if let Err(e) = wait_for_shutdown_signal(shutdown).await {
+                error!("Failed to install shutdown signal handler: {e}");
+            }
+        });
+    }
+
+    // Published once; orchestration reads it to pick a node that handles its formats
+    match zenoh_interface.get_publisher(&session, "capabilities").await {
+        Ok(publisher) => {
+            if let Err(e) = publisher.put(capabilities().to_json().to_string()).await {
+                warn!("Capabilities publish failed: {e}");
+            }
+        }
+        Err(e) => warn!("capabilities publisher unavailable, capabilities are not announced: {e}"),
+    }
+
+    if settings.validate_only {
+        info!("validate_only is set, frames are checked but not converted or published");
+        let stop_reason = match configured_subscriber {
+            ConfiguredSubscriber::Fifo(sub) => validate_frames!(&sub, &settings, &shutdown),
+            ConfiguredSubscriber::Ring(sub) => validate_frames!(&sub, &settings, &shutdown),
+        };
+        if stop_reason == StopReason::ShutdownRequested {
+            info!("Shutdown complete");
+        }
+        return Ok(());
+    }
+
+    // Kept alive until the frame loop stops; dropping it ends the clients' streams
+    #[cfg(feature = "mjpeg")]
+    let _mjpeg_server = match (&settings.mjpeg, mjpeg_port) {
+        (Some(stream), Some(port)) => {
+            let server = raw_to_jpeg::mjpeg::serve(stream.clone(), &format!("0.0.0.0:{port}"))?;
+            info!("Serving MJPEG stream on port {port}");
+            Some(server)
+        }
+        _ => None,
+    };
 
-    match configured_subscriber {
-        ConfiguredSubscriber::Fifo(sub) => convert_and_publish!(&sub, &publisher, jpeg_quality)?,
-        ConfiguredSubscriber::Ring(sub) => convert_and_publish!(&sub, &publisher, jpeg_quality)?,
+    let mut publishers = Vec::with_capacity(settings.profiles.len());
+    for profile in &settings.profiles {
+        publishers.push(zenoh_interface.get_publisher(&session, &profile.topic).await?);
+    }
+    let thumb_publisher = match settings.thumbnail_width {
+        Some(_) => Some(zenoh_interface.get_publisher(&session, "jpeg_thumb").await?),
+        None => None,
+    };
+    let phash_publisher = match settings.phash {
+        true => Some(zenoh_interface.get_publisher(&session, "jpeg_phash").await?),
+        false => None,
+    };
+    let debug_publisher = match settings.debug_frame {
+        true => Some(zenoh_interface.get_publisher(&session, "debug_frame").await?),
+        false => None,
+    };
+    let status_publisher = match zenoh_interface.get_publisher(&session, "status").await {
+        Ok(publisher) => Some(publisher),
+        Err(e) => {
+            warn!("status publisher unavailable, decode failures are only logged: {e}");
+            None
+        }
+    };
+    let heartbeat = match settings.heartbeat_interval {
+        Some(interval) => match zenoh_interface.get_publisher(&session, "heartbeat").await {
+            Ok(publisher) => {
+                let heartbeat = Arc::new(Mutex::new(Heartbeat::new(interval, Instant::now())));
+                let (task_heartbeat, shutdown) = (heartbeat.clone(), shutdown.clone());
+                tokio::spawn(async move { publish_heartbeats!(publisher, task_heartbeat, shutdown) });
+                Some(heartbeat)
+            }
+            Err(e) => {
+                warn!("heartbeat publisher unavailable, no liveness messages are sent: {e}");
+                None
+            }
+        },
+        None => None,
+    };
+    let stop_reason = match configured_subscriber {
+        ConfiguredSubscriber::Fifo(sub) => convert_and_publish!(
+            &sub,
+            &publishers,
+            &thumb_publisher,
+            &phash_publisher,
+            &debug_publisher,
+            &status_publisher,
+            heartbeat.as_ref(),
+            &settings,
+            &pending_quality,
+            &shutdown
+        )?,
+        ConfiguredSubscriber::Ring(sub) => convert_and_publish!(
+            &sub,
+            &publishers,
+            &thumb_publisher,
+            &phash_publisher,
+            &debug_publisher,
+            &status_publisher,
+            heartbeat.as_ref(),
+            &settings,
+            &pending_quality,
+            &shutdown
+        )?,
+    };
+    if stop_reason == StopReason::ShutdownRequested {
+        info!("Shutdown complete");
     }
 
     Ok(())
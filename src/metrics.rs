@@ -0,0 +1,302 @@
+use crate::RawPixelFormat;
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Number of recent conversions the latency average is taken over by default.
+pub const DEFAULT_LATENCY_WINDOW: usize = 100;
+
+/// Running conversion counters plus a rolling average of compression latency.
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    frames_converted: u64,
+    total_input_bytes: u64,
+    total_output_bytes: u64,
+    latency_window: usize,
+    latencies: VecDeque<Duration>,
+    latency_sum: Duration,
+}
+
+/// Point-in-time copy of [`Metrics`], suitable for logging.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    pub frames_converted: u64,
+    pub total_input_bytes: u64,
+    pub total_output_bytes: u64,
+    /// Mean latency over the last `latency_window` conversions.
+    pub average_latency: Duration,
+}
+
+impl Metrics {
+    /// Creates empty metrics averaging latency over the last `latency_window` conversions
+    /// (at least one).
+    pub fn new(latency_window: usize) -> Self {
+        let latency_window = latency_window.max(1);
+        Metrics {
+            frames_converted: 0,
+            total_input_bytes: 0,
+            total_output_bytes: 0,
+            latency_window,
+            latencies: VecDeque::with_capacity(latency_window),
+            latency_sum: Duration::ZERO,
+        }
+    }
+
+    /// Records one successful conversion.
+    pub fn record(&mut self, input_bytes: usize, output_bytes: usize, latency: Duration) {
+        self.frames_converted += 1;
+        self.total_input_bytes += input_bytes as u64;
+        self.total_output_bytes += output_bytes as u64;
+
+        if self.latencies.len() == self.latency_window {
+            if let Some(oldest) = self.latencies.pop_front() {
+                self.latency_sum -= oldest;
+            }
+        }
+        self.latencies.push_back(latency);
+        self.latency_sum += latency;
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let average_latency = match self.latencies.len() {
+            0 => Duration::ZERO,
+            n => self.latency_sum / n as u32,
+        };
+        MetricsSnapshot {
+            frames_converted: self.frames_converted,
+            total_input_bytes: self.total_input_bytes,
+            total_output_bytes: self.total_output_bytes,
+            average_latency,
+        }
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new(DEFAULT_LATENCY_WINDOW)
+    }
+}
+
+impl fmt::Display for MetricsSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "frames={} input_bytes={} output_bytes={} avg_latency={:?}",
+            self.frames_converted, self.total_input_bytes, self.total_output_bytes, self.average_latency
+        )
+    }
+}
+
+/// Follows the input format of a topic that mixes formats, counting frames per format and
+/// noticing when the format changes from one frame to the next.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FormatTracker {
+    last_format: Option<RawPixelFormat>,
+    /// Frames per format, in the order the formats first appeared.
+    counts: Vec<(RawPixelFormat, u64)>,
+}
+
+impl FormatTracker {
+    pub fn new() -> Self {
+        FormatTracker::default()
+    }
+
+    /// Records one frame in `format`. Returns the previous frame's format if it was a different
+    /// one; the first frame is not a change.
+    pub fn observe(&mut self, format: RawPixelFormat) -> Option<RawPixelFormat> {
+        match self.counts.iter_mut().find(|(counted, _)| *counted == format) {
+            Some((_, count)) => *count += 1,
+            None => self.counts.push((format, 1)),
+        }
+        self.last_format.replace(format).filter(|last| *last != format)
+    }
+
+    /// Format of the last frame observed, if any.
+    pub fn last_format(&self) -> Option<RawPixelFormat> {
+        self.last_format
+    }
+
+    /// Frames observed in `format`.
+    pub fn count(&self, format: RawPixelFormat) -> u64 {
+        self.counts
+            .iter()
+            .find(|(counted, _)| *counted == format)
+            .map_or(0, |(_, count)| *count)
+    }
+
+    /// Frames per format, in the order the formats first appeared.
+    pub fn counts(&self) -> &[(RawPixelFormat, u64)] {
+        &self.counts
+    }
+}
+
+impl fmt::Display for FormatTracker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut separator = "";
+        for (format, count) in &self.counts {
+            write!(f, "{}{}={}", separator, format.name(), count)?;
+            separator = " ";
+        }
+        Ok(())
+    }
+}
+
+/// Minimum time between two decode failure reports by default.
+pub const DEFAULT_STATUS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Counts received messages that could not be decoded and decides when the count is due to be
+/// published, so a stream of corrupt input yields one status message per interval rather than
+/// one per frame.
+///
+/// The first failure is reported right away. Failures within `interval` of the last report are
+/// held back until a later [`record`](DecodeFailures::record) or [`poll`](DecodeFailures::poll)
+/// finds the interval elapsed.
+#[derive(Debug, Clone)]
+pub struct DecodeFailures {
+    interval: Duration,
+    total: u64,
+    unreported: u64,
+    last_report: Option<Instant>,
+}
+
+/// Decode failure counts published on the status topic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeStatus {
+    /// Failures since startup.
+    pub total: u64,
+    /// Failures since the previous report.
+    pub since_last: u64,
+}
+
+impl DecodeFailures {
+    pub fn new(interval: Duration) -> Self {
+        DecodeFailures {
+            interval,
+            total: 0,
+            unreported: 0,
+            last_report: None,
+        }
+    }
+
+    /// Records one failure at `now` and returns the status to publish if a report is due.
+    pub fn record(&mut self, now: Instant) -> Option<DecodeStatus> {
+        self.total += 1;
+        self.unreported += 1;
+        self.poll(now)
+    }
+
+    /// Returns the status to publish if failures are waiting and the interval has elapsed.
+    pub fn poll(&mut self, now: Instant) -> Option<DecodeStatus> {
+        let due = match self.last_report {
+            Some(last) => now.saturating_duration_since(last) >= self.interval,
+            None => true,
+        };
+        if self.unreported == 0 || !due {
+            return None;
+        }
+        self.last_report = Some(now);
+        let since_last = std::mem::take(&mut self.unreported);
+        Some(DecodeStatus {
+            total: self.total,
+            since_last,
+        })
+    }
+
+    /// Failures since startup.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+}
+
+impl Default for DecodeFailures {
+    fn default() -> Self {
+        DecodeFailures::new(DEFAULT_STATUS_INTERVAL)
+    }
+}
+
+impl fmt::Display for DecodeStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "decode_failures={} since_last={}", self.total, self.since_last)
+    }
+}
+
+/// Interval of [`Heartbeat`] when the config does not set one.
+pub const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Decides when a liveness message is due, so subscribers can tell a stalled source (heartbeats
+/// but no frames) from a stopped node (neither).
+///
+/// A heartbeat falls due `interval` after the previous one or after the last converted frame,
+/// whichever is later: frames flowing already show the node is alive.
+#[derive(Debug, Clone)]
+pub struct Heartbeat {
+    interval: Duration,
+    started: Instant,
+    last_activity: Instant,
+    last_frame: Option<Instant>,
+    frames_converted: u64,
+    beats: u64,
+}
+
+/// Liveness message published on the heartbeat topic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeartbeatStatus {
+    /// Heartbeats since startup, this one included.
+    pub seq: u64,
+    /// Frames converted since startup.
+    pub frames_converted: u64,
+    /// Time since the last converted frame, or since startup before the first.
+    pub idle: Duration,
+}
+
+impl Heartbeat {
+    /// Starts the timer at `now`; the first heartbeat is due `interval` later.
+    pub fn new(interval: Duration, now: Instant) -> Self {
+        Heartbeat {
+            interval,
+            started: now,
+            last_activity: now,
+            last_frame: None,
+            frames_converted: 0,
+            beats: 0,
+        }
+    }
+
+    /// Records a frame converted at `now`, restarting the timer.
+    pub fn frame_converted(&mut self, now: Instant) {
+        self.frames_converted += 1;
+        self.last_frame = Some(now);
+        self.last_activity = self.last_activity.max(now);
+    }
+
+    /// When the next heartbeat falls due if no frame is converted before then.
+    pub fn next_due(&self) -> Instant {
+        self.last_activity + self.interval
+    }
+
+    /// Returns the heartbeat to publish if one is due at `now`, restarting the timer.
+    pub fn poll(&mut self, now: Instant) -> Option<HeartbeatStatus> {
+        if now < self.next_due() {
+            return None;
+        }
+        self.last_activity = now;
+        self.beats += 1;
+        Some(HeartbeatStatus {
+            seq: self.beats,
+            frames_converted: self.frames_converted,
+            idle: now.saturating_duration_since(self.last_frame.unwrap_or(self.started)),
+        })
+    }
+}
+
+impl fmt::Display for HeartbeatStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "alive seq={} frames_converted={} idle_ms={}",
+            self.seq,
+            self.frames_converted,
+            self.idle.as_millis()
+        )
+    }
+}
@@ -0,0 +1,168 @@
+use std::io::{self, Read};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+
+/// Separator between the JPEGs of an MJPEG stream.
+pub const BOUNDARY: &str = "mjpegframe";
+
+/// `Content-Type` of the HTTP response carrying the stream.
+pub fn content_type() -> String {
+    format!("multipart/x-mixed-replace; boundary={BOUNDARY}")
+}
+
+/// Wraps `jpeg` as one part of a `multipart/x-mixed-replace` body: boundary line, part headers,
+/// the JPEG itself and a closing CRLF.
+pub fn multipart_frame(jpeg: &[u8]) -> Vec<u8> {
+    let head = format!(
+        "--{BOUNDARY}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+        jpeg.len()
+    );
+    let mut part = Vec::with_capacity(head.len() + jpeg.len() + 2);
+    part.extend_from_slice(head.as_bytes());
+    part.extend_from_slice(jpeg);
+    part.extend_from_slice(b"\r\n");
+    part
+}
+
+#[derive(Default)]
+struct Slot {
+    /// Number of frames published so far.
+    seq: u64,
+    /// Latest frame, already framed as a multipart part.
+    part: Option<Arc<[u8]>>,
+    closed: bool,
+}
+
+/// Latest converted frame, shared between the publisher and every connected MJPEG client.
+///
+/// Only the newest frame is kept: a client that reads slower than frames arrive skips ahead
+/// instead of queueing old ones, so a stalled browser tab never holds back the app.
+#[derive(Default)]
+pub struct MjpegStream {
+    slot: Mutex<Slot>,
+    updated: Condvar,
+}
+
+impl MjpegStream {
+    pub fn new() -> Arc<Self> {
+        Arc::new(MjpegStream::default())
+    }
+
+    /// Replaces the frame sent to clients with `jpeg`.
+    pub fn publish(&self, jpeg: &[u8]) {
+        let part = Arc::from(multipart_frame(jpeg));
+        let mut slot = self.slot.lock().unwrap_or_else(PoisonError::into_inner);
+        slot.seq += 1;
+        slot.part = Some(part);
+        self.updated.notify_all();
+    }
+
+    /// Ends every client's stream once it has read its current part.
+    pub fn close(&self) {
+        self.slot.lock().unwrap_or_else(PoisonError::into_inner).closed = true;
+        self.updated.notify_all();
+    }
+
+    /// Body of one client's response: the latest frame, if any, then every frame published
+    /// after it. Reads block until the next frame and return end of file after [`close`].
+    ///
+    /// [`close`]: MjpegStream::close
+    pub fn reader(self: &Arc<Self>) -> MjpegReader {
+        MjpegReader {
+            stream: self.clone(),
+            seen: 0,
+            part: Arc::from(Vec::new()),
+            pos: 0,
+        }
+    }
+}
+
+/// Blocking reader over the parts of an [`MjpegStream`].
+pub struct MjpegReader {
+    stream: Arc<MjpegStream>,
+    seen: u64,
+    part: Arc<[u8]>,
+    pos: usize,
+}
+
+impl Read for MjpegReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.part.len() {
+            let mut slot = self.stream.slot.lock().unwrap_or_else(PoisonError::into_inner);
+            while slot.seq == self.seen && !slot.closed {
+                slot = self.stream.updated.wait(slot).unwrap_or_else(PoisonError::into_inner);
+            }
+            match &slot.part {
+                Some(part) if !slot.closed => {
+                    self.seen = slot.seq;
+                    self.part = part.clone();
+                    self.pos = 0;
+                }
+                _ => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.part.len() - self.pos);
+        buf[..len].copy_from_slice(&self.part[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+/// Serves `stream` over HTTP on `addr` (e.g. `0.0.0.0:8080`) until the returned server is
+/// dropped. Every request, whatever its path, gets the live stream, one thread per client.
+#[cfg(feature = "mjpeg")]
+pub fn serve(stream: Arc<MjpegStream>, addr: &str) -> io::Result<MjpegServer> {
+    use std::thread;
+    use tiny_http::{Header, Response, Server, StatusCode};
+
+    let server = Arc::new(Server::http(addr).map_err(io::Error::other)?);
+    let content_type =
+        Header::from_bytes("Content-Type", content_type()).map_err(|()| io::Error::other("invalid header"))?;
+    let accept = {
+        let server = server.clone();
+        let stream = stream.clone();
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let response = Response::new(StatusCode(200), vec![content_type.clone()], stream.reader(), None, None);
+                thread::spawn(move || {
+                    // A client closing its connection ends up here as an error; nothing to do
+                    if let Err(e) = request.respond(response) {
+                        log::debug!("MJPEG client disconnected: {e}");
+                    }
+                });
+            }
+        })
+    };
+    Ok(MjpegServer {
+        server,
+        stream,
+        accept: Some(accept),
+    })
+}
+
+/// Running MJPEG HTTP server started by [`serve`]. Dropping it stops accepting clients and
+/// ends the open streams.
+#[cfg(feature = "mjpeg")]
+pub struct MjpegServer {
+    server: Arc<tiny_http::Server>,
+    stream: Arc<MjpegStream>,
+    accept: Option<std::thread::JoinHandle<()>>,
+}
+
+#[cfg(feature = "mjpeg")]
+impl MjpegServer {
+    /// Address the server listens on, useful when it was started on port 0.
+    pub fn local_addr(&self) -> Option<std::net::SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+}
+
+#[cfg(feature = "mjpeg")]
+impl Drop for MjpegServer {
+    fn drop(&mut self) {
+        self.stream.close();
+        self.server.unblock();
+        if let Some(accept) = self.accept.take() {
+            let _ = accept.join();
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use crate::pixels::luma_bt601;
+use crate::Result;
+use std::f32::consts::PI;
+use turbojpeg::PixelFormat;
+
+/// Side of the luma thumbnail the DCT is taken over.
+const DCT_SIZE: usize = 32;
+/// Side of the block of low-frequency coefficients that makes up the hash, 64 bits in all.
+const HASH_SIZE: usize = 8;
+
+/// DCT-based perceptual hash of a packed RGB frame, for spotting near-duplicates: frames that
+/// look alike hash to values a small [`hamming_distance`] apart, whatever their encoding.
+///
+/// Returns 0 for an empty frame.
+pub fn phash(rgb_pixels: &[u8], width: usize, height: usize) -> u64 {
+    phash_luma(&luma_bt601(rgb_pixels, 3, 0, 2), width, height)
+}
+
+/// [`phash`] of an 8-bit luma plane, `width * height` bytes without row padding.
+///
+/// The plane is averaged down to 32x32 and the 8x8 lowest frequencies of its DCT are kept,
+/// skipping the DC term so that brightness changes do not move the hash. Each bit tells
+/// whether one of those coefficients lies above their median.
+pub fn phash_luma(luma: &[u8], width: usize, height: usize) -> u64 {
+    if width == 0 || height == 0 || luma.len() < width * height {
+        return 0;
+    }
+    // Source range covered by output index `i` along an axis of length `len`
+    let span = |i: usize, len: usize| {
+        let start = i * len / DCT_SIZE;
+        start..((i + 1) * len / DCT_SIZE).max(start + 1)
+    };
+    let mut small = [[0f32; DCT_SIZE]; DCT_SIZE];
+    for (out_y, row) in small.iter_mut().enumerate() {
+        let rows = span(out_y, height);
+        for (out_x, value) in row.iter_mut().enumerate() {
+            let cols = span(out_x, width);
+            let sum: u32 = rows.clone().flat_map(|y| luma[y * width..][cols.clone()].iter()).map(|&v| v as u32).sum();
+            *value = sum as f32 / (rows.len() * cols.len()) as f32;
+        }
+    }
+
+    // cos((2x + 1) * u * pi / 2N) for the frequencies 1..=HASH_SIZE the hash uses
+    let mut basis = [[0f32; DCT_SIZE]; HASH_SIZE];
+    for (u, row) in basis.iter_mut().enumerate() {
+        for (x, value) in row.iter_mut().enumerate() {
+            *value = ((2 * x + 1) as f32 * (u + 1) as f32 * PI / (2 * DCT_SIZE) as f32).cos();
+        }
+    }
+    // Separable 2D DCT-II: along the rows first, then down the columns
+    let mut rows = [[0f32; HASH_SIZE]; DCT_SIZE];
+    for (y, row) in rows.iter_mut().enumerate() {
+        for (u, value) in row.iter_mut().enumerate() {
+            *value = small[y].iter().zip(&basis[u]).map(|(a, b)| a * b).sum();
+        }
+    }
+    let mut coefficients = [0f32; HASH_SIZE * HASH_SIZE];
+    for v in 0..HASH_SIZE {
+        for u in 0..HASH_SIZE {
+            coefficients[v * HASH_SIZE + u] = rows.iter().zip(&basis[v]).map(|(row, b)| row[u] * b).sum();
+        }
+    }
+
+    let mut sorted = coefficients;
+    sorted.sort_by(f32::total_cmp);
+    let median = (sorted[sorted.len() / 2 - 1] + sorted[sorted.len() / 2]) / 2.0;
+    coefficients
+        .iter()
+        .enumerate()
+        .filter(|(_, &c)| c > median)
+        .fold(0u64, |hash, (bit, _)| hash | 1 << bit)
+}
+
+/// [`phash`] of an encoded JPEG, taken from its decoded luma.
+pub fn phash_jpeg(jpeg: &[u8]) -> Result<u64> {
+    let image = turbojpeg::decompress(jpeg, PixelFormat::GRAY)?;
+    let luma: Vec<u8> = image
+        .pixels
+        .chunks(image.pitch)
+        .take(image.height)
+        .flat_map(|row| &row[..image.width])
+        .copied()
+        .collect();
+    Ok(phash_luma(&luma, image.width, image.height))
+}
+
+/// Number of bits in which two hashes differ; 0 for identical frames, around 32 for unrelated
+/// ones.
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
@@ -0,0 +1,615 @@
+use crate::error::ConversionError;
+use std::borrow::Cow;
+
+/// Rectangular region of interest in pixel coordinates of the source frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Roi {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Roi {
+    /// Fails if the region is empty or extends past the edges of a `width` x `height` frame.
+    pub fn check_fits(self, width: usize, height: usize) -> Result<(), ConversionError> {
+        let fits = self.x.checked_add(self.width).is_some_and(|right| right <= width)
+            && self.y.checked_add(self.height).is_some_and(|bottom| bottom <= height);
+        if self.width == 0 || self.height == 0 || !fits {
+            return Err(ConversionError::RoiOutOfBounds {
+                roi: self,
+                width,
+                height,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Target `width:height` ratio that packed input is center-cropped to, e.g. 16:9 for displays
+/// fed by 4:3 sensors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AspectRatio {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl AspectRatio {
+    /// Largest region of `area` with this aspect ratio, centered in it. The dimension that is
+    /// too long is cut (rounding the kept size down), the other is kept whole.
+    pub fn center_crop(self, area: Roi) -> Roi {
+        let (ratio_w, ratio_h) = (self.width as u128, self.height as u128);
+        let (width, height) = (area.width as u128, area.height as u128);
+        if width * ratio_h > height * ratio_w {
+            let kept = (height * ratio_w / ratio_h) as usize;
+            Roi {
+                x: area.x + (area.width - kept) / 2,
+                width: kept,
+                ..area
+            }
+        } else {
+            let kept = (width * ratio_h / ratio_w) as usize;
+            Roi {
+                y: area.y + (area.height - kept) / 2,
+                height: kept,
+                ..area
+            }
+        }
+    }
+}
+
+/// Where one plane of planar YUV input lies within the frame buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Plane {
+    /// Byte offset of the plane's first row.
+    pub offset: usize,
+    /// Bytes from the start of one row to the next, at least the plane's width.
+    pub stride: usize,
+}
+
+impl Plane {
+    /// Fails unless `rows` rows of `row_len` bytes fit in `len` bytes.
+    fn check_fits(self, plane: &'static str, row_len: usize, rows: usize, len: usize) -> Result<(), ConversionError> {
+        if self.stride < row_len {
+            return Err(ConversionError::InvalidStride {
+                format: plane,
+                stride: self.stride,
+                width: row_len,
+            });
+        }
+        // The padding after the last row may be missing
+        let end = self
+            .stride
+            .checked_mul(rows.saturating_sub(1))
+            .and_then(|len| len.checked_add(self.offset))
+            .and_then(|len| len.checked_add(row_len))
+            .ok_or(ConversionError::DimensionOverflow {
+                format: plane,
+                width: row_len,
+                height: rows,
+            })?;
+        if len < end {
+            return Err(ConversionError::BufferTooSmall {
+                format: plane,
+                expected: end,
+                got: len,
+            });
+        }
+        Ok(())
+    }
+
+    fn copy_rows(self, data: &[u8], row_len: usize, rows: usize, out: &mut Vec<u8>) {
+        for row in 0..rows {
+            let start = self.offset + row * self.stride;
+            out.extend_from_slice(&data[start..start + row_len]);
+        }
+    }
+}
+
+/// Positions of the Y, U and V planes of I420 input whose planes are padded or not back to
+/// back, as some hardware encoders and capture devices hand them over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Yuv420Planes {
+    pub y: Plane,
+    pub u: Plane,
+    pub v: Plane,
+}
+
+impl Yuv420Planes {
+    /// Fails if any plane of a `width` x `height` frame has rows shorter than the plane is wide
+    /// or extends past the `len` bytes of the buffer.
+    pub fn check_fits(&self, width: usize, height: usize, len: usize) -> Result<(), ConversionError> {
+        self.y.check_fits("YUV420 Y plane", width, height, len)?;
+        self.u.check_fits("YUV420 U plane", width / 2, height / 2, len)?;
+        self.v.check_fits("YUV420 V plane", width / 2, height / 2, len)
+    }
+
+    /// Copies the planes out of `data` into tightly packed I420. `data` must have passed
+    /// [`check_fits`](Yuv420Planes::check_fits).
+    pub fn repack(&self, data: &[u8], width: usize, height: usize) -> Vec<u8> {
+        let mut packed = Vec::with_capacity(width * height + 2 * (width / 2) * (height / 2));
+        self.y.copy_rows(data, width, height, &mut packed);
+        self.u.copy_rows(data, width / 2, height / 2, &mut packed);
+        self.v.copy_rows(data, width / 2, height / 2, &mut packed);
+        packed
+    }
+}
+
+/// Flip or rotation applied to packed frames, for cameras mounted upside down or sideways.
+/// Rotations are clockwise.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Orientation {
+    #[default]
+    None,
+    FlipH,
+    FlipV,
+    Rot90,
+    Rot180,
+    Rot270,
+}
+
+impl Orientation {
+    /// Whether this orientation swaps width and height.
+    pub fn swaps_dimensions(self) -> bool {
+        matches!(self, Orientation::Rot90 | Orientation::Rot270)
+    }
+}
+
+/// Size of a `width` x `height` frame after [`PackedFrame::downscale`] by `factor`. Fails if
+/// no pixels would be left.
+pub fn scaled_size(width: usize, height: usize, factor: usize) -> Result<(usize, usize), ConversionError> {
+    let out_width = width / factor.max(1);
+    let out_height = height / factor.max(1);
+    if out_width == 0 || out_height == 0 {
+        return Err(ConversionError::InvalidScale { width, height, factor });
+    }
+    Ok((out_width, out_height))
+}
+
+/// An interleaved frame moving through the preprocessing steps applied before compression.
+/// Starts out borrowing the message data, whose rows may be padded to `stride` bytes, and only
+/// becomes owned once a step actually rewrites pixels. Every step outputs tightly packed rows.
+#[derive(Debug, Clone)]
+pub struct PackedFrame<'a> {
+    pub pixels: Cow<'a, [u8]>,
+    pub width: usize,
+    pub height: usize,
+    pub bytes_per_pixel: usize,
+    /// Bytes from the start of one row to the next, at least `width * bytes_per_pixel`.
+    pub stride: usize,
+}
+
+impl<'a> PackedFrame<'a> {
+    pub fn borrowed(pixels: &'a [u8], width: usize, height: usize, bytes_per_pixel: usize) -> Self {
+        Self::strided(pixels, width, height, bytes_per_pixel, width * bytes_per_pixel)
+    }
+
+    /// Borrows a frame whose rows are `stride` bytes apart.
+    pub fn strided(pixels: &'a [u8], width: usize, height: usize, bytes_per_pixel: usize, stride: usize) -> Self {
+        PackedFrame {
+            pixels: Cow::Borrowed(pixels),
+            width,
+            height,
+            bytes_per_pixel,
+            stride,
+        }
+    }
+
+    /// Builds a frame from tightly packed rows.
+    pub fn tight(pixels: Vec<u8>, width: usize, height: usize, bytes_per_pixel: usize) -> PackedFrame<'static> {
+        PackedFrame {
+            pixels: Cow::Owned(pixels),
+            width,
+            height,
+            bytes_per_pixel,
+            stride: width * bytes_per_pixel,
+        }
+    }
+
+    pub fn pitch(&self) -> usize {
+        self.stride
+    }
+
+    /// Drops any row padding, copying only if there is some.
+    pub fn into_tight(self) -> PackedFrame<'a> {
+        let row_len = self.width * self.bytes_per_pixel;
+        if self.stride == row_len {
+            return self;
+        }
+        let pixels = (0..self.height)
+            .flat_map(|y| &self.pixels[y * self.stride..y * self.stride + row_len])
+            .copied()
+            .collect();
+        PackedFrame::tight(pixels, self.width, self.height, self.bytes_per_pixel)
+    }
+
+    /// Copies `roi` out into a new tightly packed frame. Fails if the region is empty or
+    /// extends past the frame edges.
+    pub fn crop(&self, roi: Roi) -> Result<PackedFrame<'static>, ConversionError> {
+        roi.check_fits(self.width, self.height)?;
+
+        let bpp = self.bytes_per_pixel;
+        let pitch = self.pitch();
+        let row_len = roi.width * bpp;
+        let mut pixels = Vec::with_capacity(row_len * roi.height);
+        for y in roi.y..roi.y + roi.height {
+            let start = y * pitch + roi.x * bpp;
+            pixels.extend_from_slice(&self.pixels[start..start + row_len]);
+        }
+
+        Ok(PackedFrame::tight(pixels, roi.width, roi.height, bpp))
+    }
+
+    /// Reorders the pixels for `orientation` into a new tightly packed frame; width and height
+    /// swap for 90° and 270°. [`Orientation::None`] keeps the frame as is.
+    pub fn orient(self, orientation: Orientation) -> PackedFrame<'a> {
+        let (width, height, bpp) = (self.width, self.height, self.bytes_per_pixel);
+        // Maps an output pixel to the source pixel it is taken from
+        let source: fn(usize, usize, usize, usize) -> (usize, usize) = match orientation {
+            Orientation::None => return self,
+            Orientation::FlipH => |x, y, w, _| (w - 1 - x, y),
+            Orientation::FlipV => |x, y, _, h| (x, h - 1 - y),
+            Orientation::Rot90 => |x, y, _, h| (y, h - 1 - x),
+            Orientation::Rot180 => |x, y, w, h| (w - 1 - x, h - 1 - y),
+            Orientation::Rot270 => |x, y, w, _| (w - 1 - y, x),
+        };
+        let (out_width, out_height) = if orientation.swaps_dimensions() {
+            (height, width)
+        } else {
+            (width, height)
+        };
+
+        let pitch = self.pitch();
+        let mut pixels = Vec::with_capacity(out_width * out_height * bpp);
+        for y in 0..out_height {
+            for x in 0..out_width {
+                let (src_x, src_y) = source(x, y, width, height);
+                let start = src_y * pitch + src_x * bpp;
+                pixels.extend_from_slice(&self.pixels[start..start + bpp]);
+            }
+        }
+
+        PackedFrame::tight(pixels, out_width, out_height, bpp)
+    }
+
+    /// Shrinks the frame by an integer `factor` in both directions, averaging each
+    /// `factor`x`factor` block per channel. Trailing rows/columns that don't fill a whole block
+    /// are dropped.
+    pub fn downscale(&self, factor: usize) -> Result<PackedFrame<'static>, ConversionError> {
+        let (out_width, out_height) = scaled_size(self.width, self.height, factor)?;
+
+        let bpp = self.bytes_per_pixel;
+        let pitch = self.pitch();
+        let block_area = (factor * factor) as u32;
+        let mut pixels = Vec::with_capacity(out_width * out_height * bpp);
+        let mut sums = vec![0u32; bpp];
+
+        for out_y in 0..out_height {
+            for out_x in 0..out_width {
+                sums.fill(0);
+                for y in out_y * factor..(out_y + 1) * factor {
+                    let row = &self.pixels[y * pitch..y * pitch + self.width * bpp];
+                    for pixel in row[out_x * factor * bpp..(out_x + 1) * factor * bpp].chunks_exact(bpp) {
+                        for (sum, &value) in sums.iter_mut().zip(pixel) {
+                            *sum += value as u32;
+                        }
+                    }
+                }
+                pixels.extend(sums.iter().map(|sum| ((sum + block_area / 2) / block_area) as u8));
+            }
+        }
+
+        Ok(PackedFrame::tight(pixels, out_width, out_height, bpp))
+    }
+}
+
+/// Color matrix used when deriving luma from RGB.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorMatrix {
+    /// `0.299 R + 0.587 G + 0.114 B`, what JPEG/JFIF and libjpeg assume.
+    #[default]
+    Bt601,
+    /// `0.2126 R + 0.7152 G + 0.0722 B`, the HD video matrix.
+    Bt709,
+}
+
+impl ColorMatrix {
+    /// R, G and B weights scaled by 2^16. Each set sums to exactly 65536 so white stays 255.
+    fn weights(self) -> [u32; 3] {
+        match self {
+            ColorMatrix::Bt601 => [19_595, 38_470, 7_471],
+            ColorMatrix::Bt709 => [13_933, 46_871, 4_732],
+        }
+    }
+}
+
+/// Computes full-range luma with `matrix` for each pixel of a packed buffer. `red` and `blue`
+/// are the byte offsets of those channels within a pixel, so RGB(A) and BGR(A) are both
+/// covered; green is always at offset 1.
+pub fn luma(pixels: &[u8], bytes_per_pixel: usize, red: usize, blue: usize, matrix: ColorMatrix) -> Vec<u8> {
+    let [wr, wg, wb] = matrix.weights();
+    pixels
+        .chunks_exact(bytes_per_pixel)
+        .map(|pixel| {
+            let weighted = wr * pixel[red] as u32 + wg * pixel[1] as u32 + wb * pixel[blue] as u32;
+            ((weighted + 32_768) >> 16) as u8
+        })
+        .collect()
+}
+
+/// [`luma`] with the BT.601 matrix JPEG uses.
+pub fn luma_bt601(pixels: &[u8], bytes_per_pixel: usize, red: usize, blue: usize) -> Vec<u8> {
+    luma(pixels, bytes_per_pixel, red, blue, ColorMatrix::Bt601)
+}
+
+/// How the alpha channel of RGBA/BGRA input is treated, JPEG having none.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AlphaMode {
+    /// Drop alpha and keep the color channels as they are.
+    #[default]
+    Ignore,
+    /// Blend every pixel over `background` (R, G, B) by its alpha before dropping it, see
+    /// [`composite_pixel`]. With `premultiplied`, the color channels are taken as already
+    /// multiplied by alpha.
+    CompositeOver { background: [u8; 3], premultiplied: bool },
+}
+
+/// Blends `color` with coverage `alpha` over `background`, both in the same channel order.
+/// Alpha 0 keys the pixel out to exactly `background` and 255 keeps `color` exactly; values in
+/// between blend linearly.
+pub fn composite_pixel(color: [u8; 3], alpha: u8, background: [u8; 3], premultiplied: bool) -> [u8; 3] {
+    match alpha {
+        // Overlay sources leave arbitrary color under fully transparent pixels, which would
+        // otherwise show through in premultiplied mode
+        0 => return background,
+        255 => return color,
+        _ => {}
+    }
+    let (alpha, rest) = (alpha as u32, 255 - alpha as u32);
+    let mut out = [0; 3];
+    for ((out, color), background) in out.iter_mut().zip(color).zip(background) {
+        *out = if premultiplied {
+            (color as u32 + (background as u32 * rest + 127) / 255).min(255) as u8
+        } else {
+            ((color as u32 * alpha + background as u32 * rest + 127) / 255) as u8
+        };
+    }
+    out
+}
+
+/// Composites tightly packed 4-byte pixels with alpha last (RGBA or BGRA) over `background`,
+/// given in the pixels' own channel order, and returns 3-byte pixels in that order.
+pub fn composite_over(pixels: &[u8], background: [u8; 3], premultiplied: bool) -> Vec<u8> {
+    pixels
+        .chunks_exact(4)
+        .flat_map(|pixel| composite_pixel([pixel[0], pixel[1], pixel[2]], pixel[3], background, premultiplied))
+        .collect()
+}
+
+/// Gamma correction of 8-bit samples through a table computed once up front, so applying it
+/// costs one lookup per sample.
+#[derive(Clone, PartialEq)]
+pub struct GammaLut {
+    gamma: f64,
+    table: [u8; 256],
+}
+
+impl GammaLut {
+    /// Table for `255 * (v / 255)^(1 / gamma)`: a `gamma` above 1 brightens mid-tones, 2.2
+    /// roughly encoding linear-light input for display. `gamma` must be positive.
+    pub fn new(gamma: f64) -> Self {
+        let mut table = [0; 256];
+        for (value, out) in table.iter_mut().enumerate() {
+            *out = (255.0 * (value as f64 / 255.0).powf(1.0 / gamma)).round() as u8;
+        }
+        GammaLut { gamma, table }
+    }
+
+    pub fn gamma(&self) -> f64 {
+        self.gamma
+    }
+
+    pub fn map(&self, value: u8) -> u8 {
+        self.table[value as usize]
+    }
+
+    /// Corrects the color channels of tightly packed pixels in place, leaving a fourth (alpha)
+    /// channel as it is.
+    pub fn apply(&self, pixels: &mut [u8], bytes_per_pixel: usize) {
+        for pixel in pixels.chunks_exact_mut(bytes_per_pixel) {
+            for value in &mut pixel[..3] {
+                *value = self.map(*value);
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for GammaLut {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("GammaLut").field("gamma", &self.gamma).finish_non_exhaustive()
+    }
+}
+
+/// Converts one full-range (JFIF) YCbCr sample to RGB, the inverse of what libjpeg applies.
+pub fn ycbcr_to_rgb([y, cb, cr]: [u8; 3]) -> [u8; 3] {
+    let (y, cb, cr) = (y as f32, cb as f32 - 128.0, cr as f32 - 128.0);
+    let clamp = |value: f32| value.round().clamp(0.0, 255.0) as u8;
+    [
+        clamp(y + 1.402 * cr),
+        clamp(y - 0.344_136 * cb - 0.714_136 * cr),
+        clamp(y + 1.772 * cb),
+    ]
+}
+
+/// Resizes a `width` x `height` image to `out_width` x `out_height` (both no larger) by
+/// averaging, for each output pixel, the three channels `sample(x, y)` returns over the block
+/// of source pixels it covers. Returns packed three-channel pixels.
+pub fn box_resize(
+    width: usize,
+    height: usize,
+    out_width: usize,
+    out_height: usize,
+    sample: impl Fn(usize, usize) -> [u8; 3],
+) -> Vec<u8> {
+    // Source range covered by output index `i` out of `out` along an axis of length `len`
+    let span = |i: usize, out: usize, len: usize| {
+        let start = i * len / out;
+        start..((i + 1) * len / out).max(start + 1)
+    };
+
+    let mut pixels = Vec::with_capacity(out_width * out_height * 3);
+    for out_y in 0..out_height {
+        let rows = span(out_y, out_height, height);
+        for out_x in 0..out_width {
+            let cols = span(out_x, out_width, width);
+            let mut sums = [0u32; 3];
+            for y in rows.clone() {
+                for x in cols.clone() {
+                    for (sum, value) in sums.iter_mut().zip(sample(x, y)) {
+                        *sum += value as u32;
+                    }
+                }
+            }
+            let count = (rows.len() * cols.len()) as u32;
+            pixels.extend(sums.map(|sum| ((sum + count / 2) / count) as u8));
+        }
+    }
+    pixels
+}
+
+/// Interleaves three equally sized planes into one packed buffer (`r g b r g b ...`).
+/// Walks all planes front to back in lockstep, so every read and write is sequential.
+pub fn interleave_planes(r: &[u8], g: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut packed = vec![0u8; r.len() * 3];
+    for (((pixel, &r), &g), &b) in packed.chunks_exact_mut(3).zip(r).zip(g).zip(b) {
+        pixel[0] = r;
+        pixel[1] = g;
+        pixel[2] = b;
+    }
+    packed
+}
+
+/// How 16-bit grayscale samples are reduced to 8 bits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Gray16Map {
+    /// Keep the high byte (`value >> 8`). Cheap and stable across frames.
+    #[default]
+    Shift8,
+    /// Stretch the frame's own min..max range linearly onto 0..255. Brings out detail in
+    /// depth/thermal frames that only use a narrow part of the 16-bit range.
+    MinMax,
+}
+
+/// Tone-maps little-endian 16-bit grayscale samples to one byte per pixel.
+/// A trailing odd byte is ignored. Interleaved multi-channel samples (16-bit RGB) map the same
+/// way, one byte per sample.
+pub fn tone_map_gray16(data: &[u8], map: Gray16Map) -> Vec<u8> {
+    let samples = data.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]]));
+    match map {
+        Gray16Map::Shift8 => samples.map(|value| (value >> 8) as u8).collect(),
+        Gray16Map::MinMax => {
+            let (min, max) = samples
+                .clone()
+                .fold((u16::MAX, u16::MIN), |(min, max), value| (min.min(value), max.max(value)));
+            let range = max.saturating_sub(min) as u32;
+            if range == 0 {
+                return vec![0; data.len() / 2];
+            }
+            samples
+                .map(|value| (((value - min) as u32 * 255 + range / 2) / range) as u8)
+                .collect()
+        }
+    }
+}
+
+/// How MSB-aligned 10-bit samples (P010) are reduced to 8 bits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DepthReduction {
+    /// Drop the two least significant bits. Cheapest, biased slightly towards dark.
+    #[default]
+    Truncate,
+    /// Round to the nearest 8-bit value, saturating at 255.
+    Round,
+}
+
+/// How LSB-aligned 10-bit samples (planar 10-bit YUV) are reduced to 8 bits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TenBitReduction {
+    /// Drop the two least significant bits.
+    #[default]
+    Shift,
+    /// Scale 0-1023 onto 0-255 with rounding, so full scale maps to 255 exactly. Costs a
+    /// multiply and divide per sample.
+    Scale,
+}
+
+/// Unpacks `pixels` GigE Vision Mono12Packed samples to one byte each, as `sample >> shift`
+/// saturated at 255 (shift 4 keeps the top 8 bits).
+///
+/// Every 3 bytes hold two samples: the high 8 bits of the first, both low nibbles (first in the
+/// low half), then the high 8 bits of the second. An odd last sample takes 2 bytes. `data` must
+/// hold at least `ceil(pixels * 3 / 2)` bytes.
+pub fn unpack_mono12_packed(data: &[u8], pixels: usize, shift: u8) -> Vec<u8> {
+    let reduce = |sample: u16| sample.checked_shr(shift as u32).unwrap_or(0).min(255) as u8;
+    (0..pixels)
+        .map(|i| {
+            let group = &data[i / 2 * 3..];
+            let sample = match i % 2 {
+                0 => (group[0] as u16) << 4 | (group[1] & 0x0F) as u16,
+                _ => (group[2] as u16) << 4 | (group[1] >> 4) as u16,
+            };
+            reduce(sample)
+        })
+        .collect()
+}
+
+/// Reduces little-endian 16-bit samples carrying 10 bits in their high bits (as in P010) to
+/// one byte each. A trailing odd byte is ignored.
+pub fn reduce_p010(data: &[u8], reduction: DepthReduction) -> Vec<u8> {
+    let samples = data.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]]));
+    match reduction {
+        DepthReduction::Truncate => samples.map(|value| (value >> 8) as u8).collect(),
+        DepthReduction::Round => samples.map(|value| ((value as u32 + 0x80) >> 8).min(255) as u8).collect(),
+    }
+}
+
+/// Reduces little-endian 16-bit samples carrying 10 bits in their low bits (as in planar 10-bit
+/// YUV) to one byte each. Samples above 1023 saturate at 255. A trailing odd byte is ignored.
+pub fn reduce_lsb10(data: &[u8], reduction: TenBitReduction) -> Vec<u8> {
+    let samples = data
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]).min(1023) as u32);
+    match reduction {
+        TenBitReduction::Shift => samples.map(|value| (value >> 2) as u8).collect(),
+        TenBitReduction::Scale => samples.map(|value| ((value * 255 + 511) / 1023) as u8).collect(),
+    }
+}
+
+/// Fails unless the Y, U and V planes of a tightly packed `width` x `height` planar 10-bit
+/// YUV420 frame (two bytes per sample) fit in `len` bytes, naming the first plane cut short.
+pub fn check_yuv420p10_fits(width: usize, height: usize, len: usize) -> Result<(), ConversionError> {
+    let overflow = || ConversionError::DimensionOverflow {
+        format: "YUV420P10",
+        width,
+        height,
+    };
+    let luma_row = width.checked_mul(2).ok_or_else(overflow)?;
+    let luma_len = luma_row.checked_mul(height).ok_or_else(overflow)?;
+    let chroma_row = width / 2 * 2;
+    let chroma_len = chroma_row * (height / 2);
+    let y = Plane {
+        offset: 0,
+        stride: luma_row,
+    };
+    let u = Plane {
+        offset: luma_len,
+        stride: chroma_row,
+    };
+    let v = Plane {
+        offset: luma_len.checked_add(chroma_len).ok_or_else(overflow)?,
+        stride: chroma_row,
+    };
+    y.check_fits("YUV420P10 Y plane", luma_row, height, len)?;
+    u.check_fits("YUV420P10 U plane", chroma_row, height / 2, len)?;
+    v.check_fits("YUV420P10 V plane", chroma_row, height / 2, len)
+}
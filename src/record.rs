@@ -0,0 +1,70 @@
+use crate::exif::civil_from_days;
+use make87_messages::google::protobuf::Timestamp;
+use std::path::PathBuf;
+
+/// Picks which converted frames are written to disk for offline debugging and names their files.
+///
+/// Every `every`-th frame is recorded, starting with the first. Files are named after the frame
+/// timestamp (see [`record_file_name`]); a frame stamped the same as the previous recorded one
+/// gets a `-1`, `-2`, ... suffix, so no recording overwrites another within a run.
+#[derive(Debug, Clone)]
+pub struct FrameRecorder {
+    dir: PathBuf,
+    every: u64,
+    seen: u64,
+    last_stamp: Option<(i64, i32)>,
+    repeats: u32,
+}
+
+impl FrameRecorder {
+    /// Records every `every`-th frame (at least every frame) into `dir`.
+    pub fn new(dir: impl Into<PathBuf>, every: u64) -> Self {
+        FrameRecorder {
+            dir: dir.into(),
+            every: every.max(1),
+            seen: 0,
+            last_stamp: None,
+            repeats: 0,
+        }
+    }
+
+    /// Counts one converted frame stamped `timestamp` and returns the path to write it to, if
+    /// it is due for recording.
+    pub fn next_path(&mut self, timestamp: &Timestamp) -> Option<PathBuf> {
+        let due = self.seen % self.every == 0;
+        self.seen += 1;
+        if !due {
+            return None;
+        }
+        let stamp = (timestamp.seconds, timestamp.nanos);
+        self.repeats = match self.last_stamp {
+            Some(last) if last == stamp => self.repeats + 1,
+            _ => 0,
+        };
+        self.last_stamp = Some(stamp);
+        Some(self.dir.join(record_file_name(timestamp, self.repeats)))
+    }
+}
+
+/// File name of a recorded frame: its timestamp in UTC as `YYYYMMDD-HHMMSS.nnnnnnnnn.jpg`, so
+/// names sort chronologically. `repeat` > 0 appends `-<repeat>` before the extension.
+pub fn record_file_name(timestamp: &Timestamp, repeat: u32) -> String {
+    let days = timestamp.seconds.div_euclid(86_400);
+    let secs_of_day = timestamp.seconds.rem_euclid(86_400);
+    let (year, month, day) = civil_from_days(days);
+    let suffix = match repeat {
+        0 => String::new(),
+        n => format!("-{n}"),
+    };
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}.{:09}{}.jpg",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60,
+        timestamp.nanos.clamp(0, 999_999_999),
+        suffix
+    )
+}
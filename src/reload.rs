@@ -0,0 +1,80 @@
+//! Live reload of conversion settings from a watched config file, so quality and subsampling
+//! can be tuned without redeploying.
+//!
+//! The file holds a JSON object with any of `jpeg_quality` (1-100) and `jpeg_subsamp` (420,
+//! 422, 444 or gray). Keys left out fall back to the settings the app started with, so deleting
+//! a key reverts it. The watcher itself lives in the app; this module only parses the file and
+//! swaps the result into the [`LiveConfig`] the conversion workers read.
+
+use crate::error::ConversionError;
+use crate::{parse_subsamp, ConversionConfig};
+use arc_swap::ArcSwap;
+use serde_json::{Map, Value};
+use std::sync::Arc;
+
+/// Conversion settings shared between the config file watcher and the conversion workers.
+/// Workers take a snapshot per frame; a frame already being converted keeps its settings.
+#[derive(Clone)]
+pub struct LiveConfig {
+    current: Arc<ArcSwap<ConversionConfig>>,
+}
+
+impl LiveConfig {
+    pub fn new(config: ConversionConfig) -> Self {
+        LiveConfig {
+            current: Arc::new(ArcSwap::from_pointee(config)),
+        }
+    }
+
+    /// The settings currently in effect. Compare snapshots with [`Arc::ptr_eq`] to notice a reload.
+    pub fn load(&self) -> Arc<ConversionConfig> {
+        self.current.load_full()
+    }
+
+    pub fn store(&self, config: ConversionConfig) {
+        self.current.store(Arc::new(config));
+    }
+}
+
+/// Applies the config file `contents` on top of `base`, the settings from startup.
+/// `jpeg_quality` ends up in [`ConversionConfig::quality`]; unknown keys are rejected so a typo
+/// is not silently ignored.
+pub fn parse_config_file(contents: &str, base: &ConversionConfig) -> Result<ConversionConfig, ConversionError> {
+    let value: Value = serde_json::from_str(contents).map_err(|e| ConversionError::InvalidConfigFile(e.to_string()))?;
+    let object: &Map<String, Value> = value
+        .as_object()
+        .ok_or_else(|| ConversionError::InvalidConfigFile("expected a JSON object".to_string()))?;
+
+    let mut config = base.clone();
+    for (key, value) in object {
+        match key.as_str() {
+            "jpeg_quality" => {
+                let quality = value
+                    .as_u64()
+                    .filter(|quality| (1..=100).contains(quality))
+                    .ok_or_else(|| ConversionError::InvalidConfigFile("jpeg_quality must be 1-100".to_string()))?;
+                config.quality = Some(quality as u8);
+            }
+            "jpeg_subsamp" => {
+                let name = value
+                    .as_str()
+                    .ok_or_else(|| ConversionError::InvalidConfigFile("jpeg_subsamp must be a string".to_string()))?;
+                config.subsamp = Some(parse_subsamp(name)?);
+            }
+            other => return Err(ConversionError::InvalidConfigFile(format!("unknown key '{}'", other))),
+        }
+    }
+    Ok(config)
+}
+
+/// Parses the config file `contents` on top of `base` and swaps the result into `live`.
+/// On error the settings in effect are left unchanged.
+pub fn reload_config(
+    live: &LiveConfig,
+    base: &ConversionConfig,
+    contents: &str,
+) -> Result<Arc<ConversionConfig>, ConversionError> {
+    let config = parse_config_file(contents, base)?;
+    live.store(config);
+    Ok(live.load())
+}
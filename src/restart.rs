@@ -0,0 +1,74 @@
+//! Restart markers (DRI + RSTn) for error resilience over lossy links.
+//!
+//! With restart markers the entropy decoder resynchronises at every interval, so a corrupted
+//! byte only damages the rest of its interval instead of the remainder of the frame. The safe
+//! `Compressor` has no restart setting, so markers are added by a lossless tj3 transform, which
+//! re-emits the existing DCT coefficients with the requested interval. The linked libjpeg-turbo
+//! must honour the restart parameters in `tj3Transform` (3.1 or newer).
+
+use crate::error::ConversionError;
+use crate::tj3::{take_tj_buffer, Handle};
+use std::ptr;
+use turbojpeg::raw;
+
+/// Spacing of restart markers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartInterval {
+    /// A marker every N rows of MCUs.
+    Rows(u16),
+    /// A marker every N MCUs.
+    Mcus(u16),
+}
+
+/// Parses a restart unit name as used in the app config ("rows", "mcus") with its interval.
+/// An interval of 0 disables restart markers.
+pub fn parse_restart_interval(interval: u16, unit: &str) -> Result<Option<RestartInterval>, ConversionError> {
+    if interval == 0 {
+        return Ok(None);
+    }
+    match unit {
+        "rows" => Ok(Some(RestartInterval::Rows(interval))),
+        "mcus" => Ok(Some(RestartInterval::Mcus(interval))),
+        other => Err(ConversionError::UnsupportedFormat(format!(
+            "restart unit '{}': expected rows or mcus",
+            other
+        ))),
+    }
+}
+
+/// Re-emits `jpeg` with restart markers at `interval`, optionally as progressive JPEG.
+///
+/// The transform writes a sequential JPEG unless told otherwise, so progressive output has to be
+/// requested here too rather than in a separate pass.
+pub fn add_restart_markers(jpeg: &[u8], interval: RestartInterval, progressive: bool) -> Result<Vec<u8>, ConversionError> {
+    let handle = Handle::new(raw::TJINIT_TJINIT_TRANSFORM)?;
+    match interval {
+        RestartInterval::Rows(rows) => handle.set(raw::TJPARAM_TJPARAM_RESTARTROWS, rows as i32)?,
+        RestartInterval::Mcus(mcus) => handle.set(raw::TJPARAM_TJPARAM_RESTARTBLOCKS, mcus as i32)?,
+    }
+
+    let mut transform: raw::tjtransform = unsafe { std::mem::zeroed() };
+    transform.op = raw::TJXOP_TJXOP_NONE as _;
+    if progressive {
+        transform.options |= raw::TJXOPT_PROGRESSIVE as i32;
+    }
+
+    let mut dst_buf: *mut u8 = ptr::null_mut();
+    let mut dst_size: usize = 0;
+    let status = unsafe {
+        raw::tj3Transform(
+            handle.as_raw(),
+            jpeg.as_ptr(),
+            jpeg.len() as _,
+            1,
+            &mut dst_buf,
+            &mut dst_size as *mut usize as *mut _,
+            &transform,
+        )
+    };
+    let output = unsafe { take_tj_buffer(dst_buf, dst_size) };
+    if status != 0 {
+        return Err(handle.last_error());
+    }
+    Ok(output)
+}
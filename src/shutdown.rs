@@ -0,0 +1,58 @@
+use crate::error::ConversionError;
+
+/// Why the frame loop stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// SIGINT/SIGTERM was received.
+    ShutdownRequested,
+    /// The subscriber stopped delivering samples (session closed or errored).
+    SubscriberClosed,
+    /// The conversion workers stopped accepting frames (e.g. after a failed publish).
+    WorkersStopped,
+}
+
+/// What the frame loop does after one round of waiting.
+#[derive(Debug, PartialEq, Eq)]
+pub enum LoopStep<T> {
+    Process(T),
+    Stop(StopReason),
+}
+
+/// Maps the outcome of racing the subscriber against the shutdown signal to the next loop step.
+/// `None` means the shutdown signal won; a receive error ends the loop the same way the plain
+/// `while let Ok(..)` loop used to.
+pub fn next_step<T, E>(received: Option<Result<T, E>>) -> LoopStep<T> {
+    match received {
+        None => LoopStep::Stop(StopReason::ShutdownRequested),
+        Some(Ok(sample)) => LoopStep::Process(sample),
+        Some(Err(_)) => LoopStep::Stop(StopReason::SubscriberClosed),
+    }
+}
+
+/// What the frame loop does when a frame fails to convert (`on_error` in the app config).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorPolicy {
+    /// Log the error and go on with the next frame.
+    #[default]
+    Continue,
+    /// End the frame loop with the error, for pipelines that must not skip frames silently.
+    Stop,
+}
+
+/// Parses an error policy name as used in the app config ("continue", "stop").
+pub fn parse_error_policy(value: &str) -> Result<ErrorPolicy, ConversionError> {
+    match value {
+        "continue" => Ok(ErrorPolicy::Continue),
+        "stop" => Ok(ErrorPolicy::Stop),
+        other => Err(ConversionError::UnsupportedErrorPolicy(other.to_string())),
+    }
+}
+
+/// Applies `policy` to a frame that failed to convert. `Ok` means the loop carries on with the
+/// next frame (the caller has logged `error`); `Err` hands the error back for the loop to return.
+pub fn on_conversion_error<E>(policy: ErrorPolicy, error: E) -> Result<(), E> {
+    match policy {
+        ErrorPolicy::Continue => Ok(()),
+        ErrorPolicy::Stop => Err(error),
+    }
+}
@@ -0,0 +1,699 @@
+//! Row-streaming baseline JPEG encoding of RGB888 for memory-constrained devices.
+//!
+//! `Compressor` needs the whole frame plus an output buffer sized for the worst case, and the
+//! `turbojpeg` crate does not expose libjpeg-turbo's scanline API. [`StreamingEncoder`] instead
+//! takes rows in batches, holds one MCU row (8 or 16 pixel rows) of samples and writes the
+//! entropy-coded data to a [`Write`] sink as each MCU row completes.
+//!
+//! The encoder follows libjpeg-turbo's baseline pipeline step for step: the same fixed-point
+//! color conversion and downsampling, edge replication, ISLOW DCT, reciprocal quantizer,
+//! standard Huffman tables and marker layout. For the subsamplings it supports its output is
+//! byte-identical to `Compressor` at the same quality.
+
+use crate::error::ConversionError;
+use std::io::Write;
+use turbojpeg::Subsamp;
+
+const DCT_SIZE: usize = 8;
+
+/// Natural (row-major) index of each coefficient in zigzag order.
+const NATURAL_ORDER: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20, 13, 6, 7, 14, 21,
+    28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59, 52, 45, 38, 31, 39, 46, 53, 60, 61, 54,
+    47, 55, 62, 63,
+];
+
+/// Base quantization tables of the JPEG spec (Annex K), in natural order, scaled by quality.
+const LUMA_QUANT: [u16; 64] = [
+    16, 11, 10, 16, 24, 40, 51, 61, 12, 12, 14, 19, 26, 58, 60, 55, 14, 13, 16, 24, 40, 57, 69, 56, 14, 17, 22, 29, 51,
+    87, 80, 62, 18, 22, 37, 56, 68, 109, 103, 77, 24, 35, 55, 64, 81, 104, 113, 92, 49, 64, 78, 87, 103, 121, 120, 101,
+    72, 92, 95, 98, 112, 100, 103, 99,
+];
+const CHROMA_QUANT: [u16; 64] = [
+    17, 18, 24, 47, 99, 99, 99, 99, 18, 21, 26, 66, 99, 99, 99, 99, 24, 26, 56, 99, 99, 99, 99, 99, 47, 66, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99,
+];
+
+/// Code length counts (1 to 16 bits) and symbols of a Huffman table.
+struct HuffmanSpec {
+    bits: [u8; 16],
+    values: &'static [u8],
+}
+
+/// Standard Huffman tables of the JPEG spec (Annex K), as libjpeg-turbo installs them.
+const DC_LUMA: HuffmanSpec = HuffmanSpec {
+    bits: [0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0],
+    values: &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+};
+const DC_CHROMA: HuffmanSpec = HuffmanSpec {
+    bits: [0, 3, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0],
+    values: &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+};
+const AC_LUMA: HuffmanSpec = HuffmanSpec {
+    bits: [0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7d],
+    values: &[
+        0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61, 0x07, 0x22, 0x71,
+        0x14, 0x32, 0x81, 0x91, 0xa1, 0x08, 0x23, 0x42, 0xb1, 0xc1, 0x15, 0x52, 0xd1, 0xf0, 0x24, 0x33, 0x62, 0x72,
+        0x82, 0x09, 0x0a, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x25, 0x26, 0x27, 0x28, 0x29, 0x2a, 0x34, 0x35, 0x36, 0x37,
+        0x38, 0x39, 0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49, 0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59,
+        0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69, 0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x83,
+        0x84, 0x85, 0x86, 0x87, 0x88, 0x89, 0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3,
+        0xa4, 0xa5, 0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3,
+        0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda, 0xe1, 0xe2,
+        0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0xfa,
+    ],
+};
+const AC_CHROMA: HuffmanSpec = HuffmanSpec {
+    bits: [0, 2, 1, 2, 4, 4, 3, 4, 7, 5, 4, 4, 0, 1, 2, 0x77],
+    values: &[
+        0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21, 0x31, 0x06, 0x12, 0x41, 0x51, 0x07, 0x61, 0x71, 0x13, 0x22,
+        0x32, 0x81, 0x08, 0x14, 0x42, 0x91, 0xa1, 0xb1, 0xc1, 0x09, 0x23, 0x33, 0x52, 0xf0, 0x15, 0x62, 0x72, 0xd1,
+        0x0a, 0x16, 0x24, 0x34, 0xe1, 0x25, 0xf1, 0x17, 0x18, 0x19, 0x1a, 0x26, 0x27, 0x28, 0x29, 0x2a, 0x35, 0x36,
+        0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49, 0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58,
+        0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69, 0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a,
+        0x82, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89, 0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a,
+        0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba,
+        0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda,
+        0xe2, 0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0xfa,
+    ],
+};
+
+/// Code and code length of every symbol of a [`HuffmanSpec`].
+struct HuffmanCodes([(u16, u8); 256]);
+
+impl HuffmanCodes {
+    /// Assigns canonical codes (JPEG spec Annex C).
+    fn new(spec: &HuffmanSpec) -> Self {
+        let mut codes = [(0, 0); 256];
+        let mut code = 0u16;
+        let mut values = spec.values.iter();
+        for (len, &count) in (1..=16u8).zip(spec.bits.iter()) {
+            for &value in values.by_ref().take(count as usize) {
+                codes[value as usize] = (code, len);
+                code += 1;
+            }
+            code <<= 1;
+        }
+        HuffmanCodes(codes)
+    }
+}
+
+/// Quantization table at a quality, in natural order, as `jpeg_set_quality(.., force_baseline)`
+/// scales it.
+fn scale_quant_table(base: &[u16; 64], quality: u8) -> [u16; 64] {
+    let quality = quality.clamp(1, 100) as u32;
+    let scale = if quality < 50 {
+        5000 / quality
+    } else {
+        200 - quality * 2
+    };
+    base.map(|q| ((q as u32 * scale + 50) / 100).clamp(1, 255) as u16)
+}
+
+/// Multiply-and-shift replacement for dividing by one quantizer step, exactly as libjpeg-turbo's
+/// `compute_reciprocal` derives it for its 16-bit SIMD quantizer.
+#[derive(Clone, Copy)]
+struct Divisor {
+    reciprocal: u32,
+    correction: u32,
+    shift: u32,
+}
+
+impl Divisor {
+    fn new(quant: u16) -> Self {
+        // The ISLOW DCT output is scaled up by 8
+        let divisor = (quant as u32) << 3;
+        let mut shift = 16 + divisor.ilog2();
+        let mut reciprocal = (1 << shift) / divisor;
+        let remainder = (1 << shift) % divisor;
+        let mut correction = divisor / 2;
+        if remainder == 0 {
+            reciprocal >>= 1;
+            shift -= 1;
+        } else if remainder <= divisor / 2 {
+            correction += 1;
+        } else {
+            reciprocal += 1;
+        }
+        Divisor {
+            reciprocal,
+            correction,
+            shift,
+        }
+    }
+
+    fn quantize(self, coefficient: i32) -> i32 {
+        let magnitude = ((coefficient.unsigned_abs() + self.correction) * self.reciprocal) >> self.shift;
+        if coefficient < 0 {
+            -(magnitude as i32)
+        } else {
+            magnitude as i32
+        }
+    }
+}
+
+/// libjpeg's accurate integer forward DCT (`jpeg_fdct_islow`) on level-shifted samples, in place.
+/// The output is scaled up by 8.
+fn fdct_islow(data: &mut [i32; 64]) {
+    const CONST_BITS: u32 = 13;
+    const PASS1_BITS: u32 = 2;
+    const FIX_0_298631336: i32 = 2446;
+    const FIX_0_390180644: i32 = 3196;
+    const FIX_0_541196100: i32 = 4433;
+    const FIX_0_765366865: i32 = 6270;
+    const FIX_0_899976223: i32 = 7373;
+    const FIX_1_175875602: i32 = 9633;
+    const FIX_1_501321110: i32 = 12299;
+    const FIX_1_847759065: i32 = 15137;
+    const FIX_1_961570560: i32 = 16069;
+    const FIX_2_053119869: i32 = 16819;
+    const FIX_2_562915447: i32 = 20995;
+    const FIX_3_072711026: i32 = 25172;
+
+    fn descale(x: i32, n: u32) -> i32 {
+        (x + (1 << (n - 1))) >> n
+    }
+
+    // Rows, then columns; `step` is the distance between the eight samples of one pass
+    for (stride, step, pass) in [(DCT_SIZE, 1, 1), (1, DCT_SIZE, 2)] {
+        for i in 0..DCT_SIZE {
+            let base = i * stride;
+            let at = |k: usize| base + k * step;
+            let d = |k: usize| data[at(k)];
+
+            let tmp0 = d(0) + d(7);
+            let tmp7 = d(0) - d(7);
+            let tmp1 = d(1) + d(6);
+            let tmp6 = d(1) - d(6);
+            let tmp2 = d(2) + d(5);
+            let tmp5 = d(2) - d(5);
+            let tmp3 = d(3) + d(4);
+            let tmp4 = d(3) - d(4);
+
+            let tmp10 = tmp0 + tmp3;
+            let tmp13 = tmp0 - tmp3;
+            let tmp11 = tmp1 + tmp2;
+            let tmp12 = tmp1 - tmp2;
+
+            // The first pass leaves PASS1_BITS of extra precision, the second removes it
+            let shift = if pass == 1 {
+                CONST_BITS - PASS1_BITS
+            } else {
+                CONST_BITS + PASS1_BITS
+            };
+            let (out0, out4) = if pass == 1 {
+                ((tmp10 + tmp11) << PASS1_BITS, (tmp10 - tmp11) << PASS1_BITS)
+            } else {
+                (descale(tmp10 + tmp11, PASS1_BITS), descale(tmp10 - tmp11, PASS1_BITS))
+            };
+
+            let z1 = (tmp12 + tmp13) * FIX_0_541196100;
+            let out2 = descale(z1 + tmp13 * FIX_0_765366865, shift);
+            let out6 = descale(z1 - tmp12 * FIX_1_847759065, shift);
+
+            let z1 = tmp4 + tmp7;
+            let z2 = tmp5 + tmp6;
+            let z3 = tmp4 + tmp6;
+            let z4 = tmp5 + tmp7;
+            let z5 = (z3 + z4) * FIX_1_175875602;
+
+            let tmp4 = tmp4 * FIX_0_298631336;
+            let tmp5 = tmp5 * FIX_2_053119869;
+            let tmp6 = tmp6 * FIX_3_072711026;
+            let tmp7 = tmp7 * FIX_1_501321110;
+            let z1 = z1 * -FIX_0_899976223;
+            let z2 = z2 * -FIX_2_562915447;
+            let z3 = z3 * -FIX_1_961570560 + z5;
+            let z4 = z4 * -FIX_0_390180644 + z5;
+
+            data[at(0)] = out0;
+            data[at(4)] = out4;
+            data[at(2)] = out2;
+            data[at(6)] = out6;
+            data[at(7)] = descale(tmp4 + z1 + z3, shift);
+            data[at(5)] = descale(tmp5 + z2 + z4, shift);
+            data[at(3)] = descale(tmp6 + z2 + z3, shift);
+            data[at(1)] = descale(tmp7 + z1 + z4, shift);
+        }
+    }
+}
+
+/// Fixed-point RGB to YCbCr conversion of `jccolor.c` (16 fractional bits).
+fn rgb_to_ycbcr(r: u8, g: u8, b: u8) -> [u8; 3] {
+    const ONE_HALF: i32 = 1 << 15;
+    const CBCR_OFFSET: i32 = 128 << 16;
+    let (r, g, b) = (r as i32, g as i32, b as i32);
+    let y = (19595 * r + 38470 * g + 7471 * b + ONE_HALF) >> 16;
+    let cb = (-11059 * r - 21709 * g + 32768 * b + CBCR_OFFSET + ONE_HALF - 1) >> 16;
+    let cr = (32768 * r - 27439 * g - 5329 * b + CBCR_OFFSET + ONE_HALF - 1) >> 16;
+    [y as u8, cb as u8, cr as u8]
+}
+
+/// Accumulates entropy-coded bits, stuffing a zero byte after every 0xFF.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u32,
+    len: u32,
+}
+
+impl BitWriter {
+    fn put(&mut self, bits: u32, len: u8) {
+        if len == 0 {
+            return;
+        }
+        self.acc = (self.acc << len) | (bits & ((1 << len) - 1));
+        self.len += len as u32;
+        while self.len >= 8 {
+            self.len -= 8;
+            let byte = (self.acc >> self.len) as u8;
+            self.bytes.push(byte);
+            if byte == 0xFF {
+                self.bytes.push(0);
+            }
+        }
+        self.acc &= (1 << self.len) - 1;
+    }
+
+    /// Pads the last byte with 1-bits.
+    fn pad(&mut self) {
+        if self.len > 0 {
+            self.put(0x7F, (8 - self.len) as u8);
+        }
+    }
+}
+
+/// Number of bits needed for the magnitude of `value`.
+fn magnitude_bits(value: i32) -> u8 {
+    (32 - value.unsigned_abs().leading_zeros()) as u8
+}
+
+/// One color component: sampling factors, block geometry and the samples of the current MCU
+/// row, downsampled and padded on the right to whole blocks.
+struct Component {
+    id: u8,
+    h: usize,
+    v: usize,
+    table: usize,
+    width_in_blocks: usize,
+    height_in_blocks: usize,
+    samples: Vec<u8>,
+    rows: usize,
+    last_dc: i32,
+}
+
+impl Component {
+    fn stride(&self) -> usize {
+        self.width_in_blocks * DCT_SIZE
+    }
+}
+
+/// Baseline JPEG encoder that takes RGB888 rows in batches and writes the JPEG to a sink as it
+/// goes.
+///
+/// The headers are written by [`StreamingEncoder::new`], entropy-coded data by
+/// [`StreamingEncoder::write_rows`] whenever an MCU row is complete, and the rest by
+/// [`StreamingEncoder::finish`]. Besides the sink, memory use is a few MCU rows of samples.
+///
+/// ```no_run
+/// # use raw_to_jpeg::stream::StreamingEncoder;
+/// # fn rows() -> Vec<Vec<u8>> { Vec::new() }
+/// let file = std::fs::File::create("frame.jpg")?;
+/// let mut encoder = StreamingEncoder::new(file, 640, 480, 90, turbojpeg::Subsamp::Sub2x2)?;
+/// for batch in rows() {
+///     encoder.write_rows(&batch)?;
+/// }
+/// encoder.finish()?;
+/// # Ok::<(), raw_to_jpeg::ConversionError>(())
+/// ```
+pub struct StreamingEncoder<W: Write> {
+    sink: W,
+    width: usize,
+    height: usize,
+    max_h: usize,
+    max_v: usize,
+    mcus_per_row: usize,
+    components: Vec<Component>,
+    divisors: [[Divisor; 64]; 2],
+    dc_codes: [HuffmanCodes; 2],
+    ac_codes: [HuffmanCodes; 2],
+    /// One row group (`max_v` rows) of full-size samples per component, padded on the right
+    /// by replicating the last column.
+    group: Vec<Vec<u8>>,
+    group_width: usize,
+    group_rows: usize,
+    /// Bytes of a row that arrived split across batches.
+    partial_row: Vec<u8>,
+    rows_in: usize,
+    mcu_row: usize,
+    bits: BitWriter,
+}
+
+impl<W: Write> StreamingEncoder<W> {
+    /// Starts a `width` x `height` JPEG at `quality` (1-100) and writes its headers to `sink`.
+    ///
+    /// `subsamp` may be `Subsamp::None` (4:4:4), `Subsamp::Sub2x1` (4:2:2), `Subsamp::Sub2x2`
+    /// (4:2:0) or `Subsamp::Gray`, which encodes only the luma of the RGB input.
+    pub fn new(
+        mut sink: W,
+        width: usize,
+        height: usize,
+        quality: u8,
+        subsamp: Subsamp,
+    ) -> Result<Self, ConversionError> {
+        if width == 0 || height == 0 {
+            return Err(ConversionError::ZeroDimension { width, height });
+        }
+        if width > u16::MAX as usize || height > u16::MAX as usize {
+            return Err(ConversionError::InvalidDimensions {
+                format: "JPEG",
+                requirement: "width and height of at most 65535",
+                width,
+                height,
+            });
+        }
+        let (max_h, max_v, gray) = match subsamp {
+            Subsamp::None => (1, 1, false),
+            Subsamp::Sub2x1 => (2, 1, false),
+            Subsamp::Sub2x2 => (2, 2, false),
+            Subsamp::Gray => (1, 1, true),
+            other => return Err(ConversionError::UnsupportedSubsampling(format!("{:?}", other))),
+        };
+
+        let mut components = vec![(1, max_h, max_v, 0)];
+        if !gray {
+            components.extend([(2, 1, 1, 1), (3, 1, 1, 1)]);
+        }
+        let components: Vec<Component> = components
+            .into_iter()
+            .map(|(id, h, v, table)| {
+                let width_in_blocks = (width * h).div_ceil(max_h * DCT_SIZE);
+                Component {
+                    id,
+                    h,
+                    v,
+                    table,
+                    width_in_blocks,
+                    height_in_blocks: (height * v).div_ceil(max_v * DCT_SIZE),
+                    samples: vec![0; width_in_blocks * DCT_SIZE * v * DCT_SIZE],
+                    rows: 0,
+                    last_dc: 0,
+                }
+            })
+            .collect();
+        // Downsampling reads `max_h / h` input columns per output column of each block
+        let group_width = components
+            .iter()
+            .map(|c| c.stride() * max_h / c.h)
+            .max()
+            .unwrap_or(width);
+
+        let quant = [
+            scale_quant_table(&LUMA_QUANT, quality),
+            scale_quant_table(&CHROMA_QUANT, quality),
+        ];
+        let mut header = Vec::with_capacity(1024);
+        header.extend_from_slice(&[0xFF, 0xD8]);
+        // JFIF 1.01, no density unit, 1:1 aspect ratio, no thumbnail
+        header.extend_from_slice(&[0xFF, 0xE0, 0, 16, b'J', b'F', b'I', b'F', 0, 1, 1, 0, 0, 1, 0, 1, 0, 0]);
+        for (id, table) in quant.iter().enumerate().take(if gray { 1 } else { 2 }) {
+            header.extend_from_slice(&[0xFF, 0xDB, 0, 67, id as u8]);
+            header.extend(NATURAL_ORDER.iter().map(|&k| table[k] as u8));
+        }
+        let sof_len = 8 + 3 * components.len() as u16;
+        header.extend_from_slice(&[0xFF, 0xC0]);
+        header.extend_from_slice(&sof_len.to_be_bytes());
+        header.push(8);
+        header.extend_from_slice(&(height as u16).to_be_bytes());
+        header.extend_from_slice(&(width as u16).to_be_bytes());
+        header.push(components.len() as u8);
+        for c in &components {
+            header.extend_from_slice(&[c.id, ((c.h << 4) | c.v) as u8, c.table as u8]);
+        }
+        let tables = [
+            (0x00, &DC_LUMA),
+            (0x10, &AC_LUMA),
+            (0x01, &DC_CHROMA),
+            (0x11, &AC_CHROMA),
+        ];
+        for (class_id, spec) in tables.iter().take(if gray { 2 } else { 4 }) {
+            let len = 19 + spec.values.len() as u16;
+            header.extend_from_slice(&[0xFF, 0xC4]);
+            header.extend_from_slice(&len.to_be_bytes());
+            header.push(*class_id);
+            header.extend_from_slice(&spec.bits);
+            header.extend_from_slice(spec.values);
+        }
+        let sos_len = 6 + 2 * components.len() as u16;
+        header.extend_from_slice(&[0xFF, 0xDA]);
+        header.extend_from_slice(&sos_len.to_be_bytes());
+        header.push(components.len() as u8);
+        for c in &components {
+            header.extend_from_slice(&[c.id, ((c.table << 4) | c.table) as u8]);
+        }
+        // Spectral selection 0-63, no successive approximation
+        header.extend_from_slice(&[0, 63, 0]);
+        sink.write_all(&header)?;
+
+        Ok(StreamingEncoder {
+            sink,
+            width,
+            height,
+            max_h,
+            max_v,
+            mcus_per_row: width.div_ceil(max_h * DCT_SIZE),
+            group: vec![vec![0; group_width * max_v]; components.len()],
+            components,
+            divisors: quant.map(|table| table.map(Divisor::new)),
+            dc_codes: [HuffmanCodes::new(&DC_LUMA), HuffmanCodes::new(&DC_CHROMA)],
+            ac_codes: [HuffmanCodes::new(&AC_LUMA), HuffmanCodes::new(&AC_CHROMA)],
+            group_width,
+            group_rows: 0,
+            partial_row: Vec::new(),
+            rows_in: 0,
+            mcu_row: 0,
+            bits: BitWriter::default(),
+        })
+    }
+
+    /// Number of complete rows received so far.
+    pub fn rows_written(&self) -> usize {
+        self.rows_in
+    }
+
+    /// Feeds the next bytes of tightly packed RGB888 rows. Batches need not end on a row
+    /// boundary; a split row is completed by the next call. Every completed MCU row is encoded
+    /// and written to the sink before returning.
+    pub fn write_rows(&mut self, data: &[u8]) -> Result<(), ConversionError> {
+        let row_len = self.width * 3;
+        let received = self.rows_in * row_len + self.partial_row.len() + data.len();
+        if received > row_len * self.height {
+            return Err(ConversionError::RowCountMismatch {
+                expected: self.height,
+                got: received.div_ceil(row_len),
+            });
+        }
+
+        let mut data = data;
+        if !self.partial_row.is_empty() {
+            let take = (row_len - self.partial_row.len()).min(data.len());
+            self.partial_row.extend_from_slice(&data[..take]);
+            data = &data[take..];
+            if self.partial_row.len() < row_len {
+                return Ok(());
+            }
+            let row = std::mem::take(&mut self.partial_row);
+            self.push_row(&row)?;
+        }
+        let mut rows = data.chunks_exact(row_len);
+        for row in rows.by_ref() {
+            self.push_row(row)?;
+        }
+        self.partial_row.extend_from_slice(rows.remainder());
+        Ok(())
+    }
+
+    /// Encodes the last MCU row, writes the end-of-image marker and returns the sink. Fails if
+    /// fewer rows than the frame height were written.
+    pub fn finish(mut self) -> Result<W, ConversionError> {
+        if self.rows_in < self.height {
+            return Err(ConversionError::RowCountMismatch {
+                expected: self.height,
+                got: self.rows_in + usize::from(!self.partial_row.is_empty()),
+            });
+        }
+        if self.group_rows > 0 {
+            // Bottom edge: replicate the last row through the rest of the row group
+            for plane in &mut self.group {
+                let last = (self.group_rows - 1) * self.group_width;
+                for row in self.group_rows..self.max_v {
+                    plane.copy_within(last..last + self.group_width, row * self.group_width);
+                }
+            }
+            self.group_rows = self.max_v;
+            self.downsample_group()?;
+        }
+        if self.components[0].rows > 0 {
+            // ... then the last downsampled row through the rest of the MCU row
+            for c in &mut self.components {
+                let stride = c.stride();
+                let last = (c.rows - 1) * stride;
+                for row in c.rows..c.v * DCT_SIZE {
+                    c.samples.copy_within(last..last + stride, row * stride);
+                }
+                c.rows = c.v * DCT_SIZE;
+            }
+            self.encode_mcu_row()?;
+        }
+        self.bits.pad();
+        self.bits.bytes.extend_from_slice(&[0xFF, 0xD9]);
+        self.sink.write_all(&self.bits.bytes)?;
+        self.sink.flush()?;
+        Ok(self.sink)
+    }
+
+    fn push_row(&mut self, row: &[u8]) -> Result<(), ConversionError> {
+        let offset = self.group_rows * self.group_width;
+        let gray = self.components.len() == 1;
+        for (x, rgb) in row.chunks_exact(3).enumerate() {
+            let ycc = rgb_to_ycbcr(rgb[0], rgb[1], rgb[2]);
+            for (plane, &sample) in self.group.iter_mut().zip(if gray { &ycc[..1] } else { &ycc[..] }) {
+                plane[offset + x] = sample;
+            }
+        }
+        for plane in &mut self.group {
+            let edge = plane[offset + self.width - 1];
+            plane[offset + self.width..offset + self.group_width].fill(edge);
+        }
+        self.rows_in += 1;
+        self.group_rows += 1;
+        if self.group_rows == self.max_v {
+            self.downsample_group()?;
+        }
+        Ok(())
+    }
+
+    /// Downsamples the full row group into each component's MCU row buffer with libjpeg's
+    /// alternating rounding bias, and encodes the MCU row once it is complete.
+    fn downsample_group(&mut self) -> Result<(), ConversionError> {
+        for (c, plane) in self.components.iter_mut().zip(&self.group) {
+            let stride = c.stride();
+            let h_expand = self.max_h / c.h;
+            let v_expand = self.max_v / c.v;
+            for out_row in 0..c.v {
+                let dst = &mut c.samples[(c.rows + out_row) * stride..][..stride];
+                let in0 = &plane[out_row * v_expand * self.group_width..][..self.group_width];
+                match (h_expand, v_expand) {
+                    (1, 1) => dst.copy_from_slice(&in0[..stride]),
+                    (2, 1) => {
+                        for (x, out) in dst.iter_mut().enumerate() {
+                            let bias = (x & 1) as u32;
+                            *out = ((in0[2 * x] as u32 + in0[2 * x + 1] as u32 + bias) >> 1) as u8;
+                        }
+                    }
+                    _ => {
+                        let in1 = &plane[(out_row * 2 + 1) * self.group_width..][..self.group_width];
+                        for (x, out) in dst.iter_mut().enumerate() {
+                            let bias = 1 + (x & 1) as u32;
+                            let sum =
+                                in0[2 * x] as u32 + in0[2 * x + 1] as u32 + in1[2 * x] as u32 + in1[2 * x + 1] as u32;
+                            *out = ((sum + bias) >> 2) as u8;
+                        }
+                    }
+                }
+            }
+            c.rows += c.v;
+        }
+        self.group_rows = 0;
+        if self.components[0].rows == self.components[0].v * DCT_SIZE {
+            self.encode_mcu_row()?;
+        }
+        Ok(())
+    }
+
+    /// Transforms, quantizes and entropy-codes one MCU row and writes it to the sink.
+    ///
+    /// Blocks of an MCU past the right or bottom edge of a component are not transformed; like
+    /// libjpeg they are coded with only the DC value of the preceding block.
+    fn encode_mcu_row(&mut self) -> Result<(), ConversionError> {
+        let mut block = [0i32; 64];
+        let mut coefficients = [0i32; 64];
+        for mcu_col in 0..self.mcus_per_row {
+            for c in &mut self.components {
+                let stride = c.stride();
+                let divisors = &self.divisors[c.table];
+                for y in 0..c.v {
+                    for x in 0..c.h {
+                        let bx = mcu_col * c.h + x;
+                        let by = self.mcu_row * c.v + y;
+                        if bx < c.width_in_blocks && by < c.height_in_blocks {
+                            for (row, out) in block.chunks_exact_mut(DCT_SIZE).enumerate() {
+                                let src = &c.samples[(y * DCT_SIZE + row) * stride + bx * DCT_SIZE..][..DCT_SIZE];
+                                for (o, &s) in out.iter_mut().zip(src) {
+                                    *o = s as i32 - 128;
+                                }
+                            }
+                            fdct_islow(&mut block);
+                            for ((q, &b), d) in coefficients.iter_mut().zip(&block).zip(divisors) {
+                                *q = d.quantize(b);
+                            }
+                        } else {
+                            let dc = coefficients[0];
+                            coefficients = [0; 64];
+                            coefficients[0] = dc;
+                        }
+                        encode_block(
+                            &mut self.bits,
+                            &coefficients,
+                            &mut c.last_dc,
+                            &self.dc_codes[c.table],
+                            &self.ac_codes[c.table],
+                        );
+                    }
+                }
+            }
+        }
+        for c in &mut self.components {
+            c.rows = 0;
+        }
+        self.mcu_row += 1;
+        self.sink.write_all(&self.bits.bytes)?;
+        self.bits.bytes.clear();
+        Ok(())
+    }
+}
+
+/// Huffman-codes one quantized block (natural order) with DC prediction from `last_dc`.
+fn encode_block(bits: &mut BitWriter, block: &[i32; 64], last_dc: &mut i32, dc: &HuffmanCodes, ac: &HuffmanCodes) {
+    let diff = block[0] - *last_dc;
+    *last_dc = block[0];
+    let len = magnitude_bits(diff);
+    let (code, code_len) = dc.0[len as usize];
+    bits.put(code as u32, code_len);
+    // Negative values are sent as their one's complement
+    bits.put((diff - i32::from(diff < 0)) as u32, len);
+
+    let mut run = 0;
+    for &k in &NATURAL_ORDER[1..] {
+        let value = block[k];
+        if value == 0 {
+            run += 1;
+            continue;
+        }
+        while run > 15 {
+            let (code, code_len) = ac.0[0xF0];
+            bits.put(code as u32, code_len);
+            run -= 16;
+        }
+        let len = magnitude_bits(value);
+        let (code, code_len) = ac.0[(run << 4) | len as usize];
+        bits.put(code as u32, code_len);
+        bits.put((value - i32::from(value < 0)) as u32, len);
+        run = 0;
+    }
+    if run > 0 {
+        let (code, code_len) = ac.0[0x00];
+        bits.put(code as u32, code_len);
+    }
+}
@@ -0,0 +1,199 @@
+use crate::pixels::{Orientation, Roi};
+use crate::{
+    checked_crop_area, frame_header, raw_frame, rgb_to_jpeg_with_header, validate_raw, ConversionConfig,
+    ConversionError, RawPixelFormat, Result, DEFAULT_MAX_PIXELS,
+};
+use make87_messages::image::compressed::ImageJpeg;
+use make87_messages::image::uncompressed::image_raw_any::Image as RawImageVariant;
+use make87_messages::image::uncompressed::{ImageRawAny, ImageRgb888, ImageRgba8888};
+use turbojpeg::Compressor;
+
+/// Size of the tiles a large frame is split into, so clients can fetch an 8K panorama piece
+/// by piece instead of as one JPEG.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileSize {
+    pub width: usize,
+    pub height: usize,
+}
+
+/// One tile of a frame: its place in the grid and the region of the frame it covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+    pub row: usize,
+    pub col: usize,
+    pub roi: Roi,
+}
+
+impl TileSize {
+    /// Splits `area` into tiles, row by row. Tiles in the last row and column are cut short
+    /// where `area` ends, so every pixel is in exactly one tile.
+    pub fn grid(self, area: Roi) -> Vec<Tile> {
+        let (tile_width, tile_height) = (self.width.max(1), self.height.max(1));
+        let mut tiles = Vec::new();
+        for (row, y) in (0..area.height).step_by(tile_height).enumerate() {
+            for (col, x) in (0..area.width).step_by(tile_width).enumerate() {
+                let roi = Roi {
+                    x: area.x + x,
+                    y: area.y + y,
+                    width: tile_width.min(area.width - x),
+                    height: tile_height.min(area.height - y),
+                };
+                tiles.push(Tile { row, col, roi });
+            }
+        }
+        tiles
+    }
+}
+
+/// Entity path of a tile's header: the frame's own path with `tile/<row>/<col>` appended,
+/// which is how clients tell the tiles of a frame apart and put them back together.
+pub fn tile_entity_path(entity_path: &str, row: usize, col: usize) -> String {
+    match entity_path.trim_end_matches('/') {
+        "" => format!("tile/{row}/{col}"),
+        path => format!("{path}/tile/{row}/{col}"),
+    }
+}
+
+/// Compresses every tile of packed RGB/BGR(A) input as its own JPEG, in grid order. Each
+/// carries the frame header with its grid position in the entity path (see
+/// [`tile_entity_path`]); the timestamp and reference id stay those of the frame.
+///
+/// The grid covers `config.roi`, or the whole frame without one, cropped to `config.aspect`,
+/// and tiles are cut through the same crop as a configured ROI. Downscaling applies to each tile; `config.orientation`
+/// does not, as rotating tiles in place would scramble the grid.
+pub fn rgb_to_jpeg_tiles(
+    rgb_any: &ImageRawAny,
+    tile_size: TileSize,
+    compressor: &mut Compressor,
+    config: &ConversionConfig,
+) -> Result<Vec<(Tile, ImageJpeg)>> {
+    compress_tiles(rgb_any, tile_size, None, compressor, config)
+}
+
+/// [`rgb_to_jpeg_tiles`], with the tiles cut short at the right and bottom edges padded to the
+/// full `tile_size` with the `pad` color (R, G, B; opaque for RGBA), so every tile decodes to
+/// the same size. [`Tile::roi`] still gives the part of the frame a tile shows.
+pub fn rgb_to_jpeg_tiles_padded(
+    rgb_any: &ImageRawAny,
+    tile_size: TileSize,
+    pad: [u8; 3],
+    compressor: &mut Compressor,
+    config: &ConversionConfig,
+) -> Result<Vec<(Tile, ImageJpeg)>> {
+    compress_tiles(rgb_any, tile_size, Some(pad), compressor, config)
+}
+
+fn compress_tiles(
+    rgb_any: &ImageRawAny,
+    tile_size: TileSize,
+    pad: Option<[u8; 3]>,
+    compressor: &mut Compressor,
+    config: &ConversionConfig,
+) -> Result<Vec<(Tile, ImageJpeg)>> {
+    let (data, width, height, format) = raw_frame(rgb_any).ok_or_else(|| ConversionError::NoImageData {
+        header: frame_header(rgb_any).cloned(),
+    })?;
+    if !format.is_packed() {
+        return Err(ConversionError::UnsupportedFormat(format!("{} for tiling", format.name())));
+    }
+    let area = checked_crop_area(config, width, height)?.unwrap_or(Roi {
+        x: 0,
+        y: 0,
+        width,
+        height,
+    });
+    // One pixel's worth of bytes: 3 for RGB888, 4 for RGBA8888
+    let bytes_per_pixel = format.expected_len(1, 1);
+    let full_size = TileSize {
+        width: tile_size.width.max(1),
+        height: tile_size.height.max(1),
+    };
+    if pad.is_some() {
+        // Padded tiles are copied out of the frame, so it must hold all of it
+        validate_raw(data, width, height, format, config)?;
+        // and each is allocated at the full tile size, however small the frame
+        let max_pixels = config.max_pixels.unwrap_or(DEFAULT_MAX_PIXELS);
+        if full_size.width.checked_mul(full_size.height).is_none_or(|pixels| pixels > max_pixels) {
+            return Err(ConversionError::TooManyPixels {
+                width: full_size.width,
+                height: full_size.height,
+                max_pixels,
+            });
+        }
+    }
+
+    let mut tile_config = ConversionConfig {
+        orientation: Orientation::None,
+        // Already applied to the grid area
+        aspect: None,
+        ..config.clone()
+    };
+    // A padded tile is a frame of its own, tightly packed
+    let padded_config = ConversionConfig {
+        roi: None,
+        rgb_stride: None,
+        ..tile_config.clone()
+    };
+    tile_size
+        .grid(area)
+        .into_iter()
+        .map(|tile| {
+            let mut header = frame_header(rgb_any).cloned().unwrap_or_default();
+            header.entity_path = tile_entity_path(&header.entity_path, tile.row, tile.col);
+            let partial = tile.roi.width < full_size.width || tile.roi.height < full_size.height;
+            let jpeg = match pad.filter(|_| partial) {
+                Some(pad) => {
+                    let stride = config.rgb_stride.unwrap_or(width * bytes_per_pixel);
+                    let padded = padded_tile(data, stride, format, tile.roi, full_size, pad);
+                    rgb_to_jpeg_with_header(&padded, Some(header), compressor, &padded_config)?
+                }
+                None => {
+                    tile_config.roi = Some(tile.roi);
+                    rgb_to_jpeg_with_header(rgb_any, Some(header), compressor, &tile_config)?
+                }
+            };
+            Ok((tile, jpeg))
+        })
+        .collect()
+}
+
+/// Copies the `roi` region of packed `data` (rows `stride` bytes apart) into the top-left
+/// corner of a `size` frame filled with `pad`.
+fn padded_tile(
+    data: &[u8],
+    stride: usize,
+    format: RawPixelFormat,
+    roi: Roi,
+    size: TileSize,
+    pad: [u8; 3],
+) -> ImageRawAny {
+    let bytes_per_pixel = format.expected_len(1, 1);
+    let fill = [pad[0], pad[1], pad[2], 255];
+    let mut pixels = fill[..bytes_per_pixel].repeat(size.width * size.height);
+    let row_len = roi.width * bytes_per_pixel;
+    for y in 0..roi.height {
+        let src = (roi.y + y) * stride + roi.x * bytes_per_pixel;
+        let dst = y * size.width * bytes_per_pixel;
+        pixels[dst..dst + row_len].copy_from_slice(&data[src..src + row_len]);
+    }
+
+    let (width, height) = (size.width as u32, size.height as u32);
+    let image = match format {
+        RawPixelFormat::Rgba8888 => RawImageVariant::Rgba8888(ImageRgba8888 {
+            header: None,
+            width,
+            height,
+            data: pixels,
+        }),
+        _ => RawImageVariant::Rgb888(ImageRgb888 {
+            header: None,
+            width,
+            height,
+            data: pixels,
+        }),
+    };
+    ImageRawAny {
+        header: None,
+        image: Some(image),
+    }
+}
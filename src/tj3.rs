@@ -0,0 +1,59 @@
+//! Thin owner for raw tj3 handles, for the few libjpeg-turbo parameters the safe
+//! `turbojpeg` API does not expose.
+
+use crate::error::ConversionError;
+use std::ffi::CStr;
+use turbojpeg::raw;
+
+/// Owned tj3 handle, destroyed on drop.
+pub(crate) struct Handle(raw::tjhandle);
+
+impl Handle {
+    /// Creates a handle for `init` (`TJINIT_COMPRESS`, `TJINIT_TRANSFORM`, ...).
+    pub(crate) fn new(init: raw::TJINIT) -> Result<Self, ConversionError> {
+        let handle = unsafe { raw::tj3Init(init as _) };
+        if handle.is_null() {
+            return Err(tj_error("tj3Init failed"));
+        }
+        Ok(Handle(handle))
+    }
+
+    pub(crate) fn as_raw(&self) -> raw::tjhandle {
+        self.0
+    }
+
+    pub(crate) fn set(&self, param: raw::TJPARAM, value: i32) -> Result<(), ConversionError> {
+        if unsafe { raw::tj3Set(self.0, param as _, value as _) } != 0 {
+            return Err(self.last_error());
+        }
+        Ok(())
+    }
+
+    pub(crate) fn last_error(&self) -> ConversionError {
+        let message = unsafe { CStr::from_ptr(raw::tj3GetErrorStr(self.0)) };
+        tj_error(&message.to_string_lossy())
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        unsafe { raw::tj3Destroy(self.0) };
+    }
+}
+
+/// Copies a buffer allocated by libjpeg-turbo into a `Vec` and frees it.
+///
+/// # Safety
+/// `buf` must be null or a tj3-allocated buffer of at least `len` bytes.
+pub(crate) unsafe fn take_tj_buffer(buf: *mut u8, len: usize) -> Vec<u8> {
+    if buf.is_null() {
+        return Vec::new();
+    }
+    let data = std::slice::from_raw_parts(buf, len).to_vec();
+    raw::tj3Free(buf as *mut _);
+    data
+}
+
+pub(crate) fn tj_error(message: &str) -> ConversionError {
+    ConversionError::TurboJpeg(turbojpeg::Error::TurboJpegError(message.to_string()))
+}
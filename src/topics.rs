@@ -0,0 +1,152 @@
+use crate::{parse_subsamp, ConversionError};
+use serde_json::{json, Value};
+use turbojpeg::Subsamp;
+
+/// Subscriber topic used when the config has no `input_topic`.
+pub const DEFAULT_INPUT_TOPIC: &str = "raw_frame";
+/// Publisher topic used when the config has no `output_topic`.
+pub const DEFAULT_OUTPUT_TOPIC: &str = "jpeg_frame";
+
+/// Names of the topics frames are read from and JPEGs are published to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Topics {
+    pub input: String,
+    pub output: String,
+}
+
+impl Topics {
+    /// Resolves the configured topic names, falling back to the defaults for missing keys.
+    pub fn resolve(input: Option<&str>, output: Option<&str>) -> Self {
+        Topics {
+            input: input.unwrap_or(DEFAULT_INPUT_TOPIC).to_string(),
+            output: output.unwrap_or(DEFAULT_OUTPUT_TOPIC).to_string(),
+        }
+    }
+}
+
+/// One JPEG stream published for every input frame, e.g. a high-quality archive next to a
+/// low-quality preview. Unset fields fall back to the global `jpeg_quality` / `jpeg_subsamp`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputProfile {
+    pub topic: String,
+    pub quality: Option<u8>,
+    pub subsamp: Option<Subsamp>,
+}
+
+impl OutputProfile {
+    /// Builds a profile from raw config values, checking the topic is not empty, `quality` is
+    /// within 1-100 and `subsamp` is a known name (see [`parse_subsamp`]).
+    pub fn parse(topic: &str, quality: Option<i64>, subsamp: Option<&str>) -> Result<Self, ConversionError> {
+        if topic.is_empty() {
+            return Err(ConversionError::InvalidOutputProfile("topic must not be empty".to_string()));
+        }
+        let quality = quality
+            .map(|quality| {
+                u8::try_from(quality).ok().filter(|q| (1..=100).contains(q)).ok_or_else(|| {
+                    let reason = format!("'{}': quality {} is not within 1-100", topic, quality);
+                    ConversionError::InvalidOutputProfile(reason)
+                })
+            })
+            .transpose()?;
+        Ok(OutputProfile {
+            topic: topic.to_string(),
+            quality,
+            subsamp: subsamp.map(parse_subsamp).transpose()?,
+        })
+    }
+}
+
+/// Returns `profiles`, or a single profile publishing to `default_topic` with the global
+/// settings if none are configured. Fails if two profiles share a topic.
+pub fn resolve_output_profiles(
+    profiles: Vec<OutputProfile>,
+    default_topic: &str,
+) -> Result<Vec<OutputProfile>, ConversionError> {
+    if profiles.is_empty() {
+        return Ok(vec![OutputProfile {
+            topic: default_topic.to_string(),
+            quality: None,
+            subsamp: None,
+        }]);
+    }
+    for (index, profile) in profiles.iter().enumerate() {
+        if profiles[..index].iter().any(|earlier| earlier.topic == profile.topic) {
+            return Err(ConversionError::InvalidOutputProfile(format!(
+                "topic '{}' is used by more than one profile",
+                profile.topic
+            )));
+        }
+    }
+    Ok(profiles)
+}
+
+/// How the input subscriber queues samples the app has not taken yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueueDiscipline {
+    /// Keeps every sample; once full, zenoh waits for room, pushing back on the publisher.
+    Fifo,
+    /// Keeps the newest samples; once full, the oldest one is dropped.
+    Ring,
+}
+
+impl QueueDiscipline {
+    /// Name as used in the make87 `handler_type` config.
+    pub fn handler_type(self) -> &'static str {
+        match self {
+            QueueDiscipline::Fifo => "FIFO",
+            QueueDiscipline::Ring => "RING",
+        }
+    }
+}
+
+/// Parses a queue discipline name as used in the app config ("fifo", "ring").
+pub fn parse_queue_discipline(value: &str) -> Result<QueueDiscipline, ConversionError> {
+    match value {
+        "fifo" => Ok(QueueDiscipline::Fifo),
+        "ring" => Ok(QueueDiscipline::Ring),
+        other => Err(ConversionError::UnsupportedQueueDiscipline(other.to_string())),
+    }
+}
+
+/// Queue of a subscriber, as the make87 zenoh interface builds it from the `handler` entry
+/// of the subscriber's config (`{"handler_type": "FIFO", "capacity": 100}`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubscriberQueue {
+    pub discipline: QueueDiscipline,
+    pub depth: u32,
+}
+
+impl SubscriberQueue {
+    /// Reads a `handler` config entry; `None` if it is not a recognised one.
+    pub fn from_handler(handler: &Value) -> Option<Self> {
+        let discipline = match handler.get("handler_type")?.as_str()? {
+            "FIFO" => QueueDiscipline::Fifo,
+            "RING" => QueueDiscipline::Ring,
+            _ => return None,
+        };
+        let depth = u32::try_from(handler.get("capacity")?.as_u64()?).ok()?;
+        Some(SubscriberQueue { discipline, depth })
+    }
+
+    pub fn to_handler(self) -> Value {
+        json!({ "handler_type": self.discipline.handler_type(), "capacity": self.depth })
+    }
+
+    /// Applies the app config's preferences to the queue the deployment `bound` the subscriber
+    /// with: each of `discipline` and `depth` replaces the bound value if set. Without a bound
+    /// queue both must be set; otherwise the bound config is left to the interface as it is.
+    pub fn with_preferences(
+        bound: Option<SubscriberQueue>,
+        discipline: Option<QueueDiscipline>,
+        depth: Option<u32>,
+    ) -> Option<SubscriberQueue> {
+        match (bound, discipline, depth) {
+            (_, Some(discipline), Some(depth)) => Some(SubscriberQueue { discipline, depth }),
+            (Some(bound), discipline, depth) => Some(SubscriberQueue {
+                discipline: discipline.unwrap_or(bound.discipline),
+                depth: depth.unwrap_or(bound.depth),
+            }),
+            (None, _, _) => None,
+        }
+    }
+}
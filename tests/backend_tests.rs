@@ -0,0 +1,115 @@
+use anyhow::Result;
+use make87_messages::image::uncompressed::image_raw_any::Image as RawImageVariant;
+use make87_messages::image::uncompressed::{ImageRawAny, ImageRgb888, ImageYuv420};
+use raw_to_jpeg::{rgb_to_jpeg, rgb_to_jpeg_with_config, ConversionConfig, ConversionError};
+use turbojpeg::{Compressor, PixelFormat};
+
+const TEST_WIDTH: u32 = 176;
+const TEST_HEIGHT: u32 = 144;
+
+fn gradient_frame() -> ImageRawAny {
+    let data = (0..TEST_HEIGHT)
+        .flat_map(|y| (0..TEST_WIDTH).flat_map(move |x| [(x % 256) as u8, (y % 256) as u8, 128]))
+        .collect();
+    ImageRawAny {
+        header: None,
+        image: Some(RawImageVariant::Rgb888(ImageRgb888 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data,
+        })),
+    }
+}
+
+fn assert_jpeg(data: &[u8]) {
+    assert!(data.len() > 4);
+    assert_eq!(data[..2], [0xFF, 0xD8]);
+    assert_eq!(data[data.len() - 2..], [0xFF, 0xD9]);
+}
+
+fn encode_rgb_and_gray() -> Result<()> {
+    let frame = gradient_frame();
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(80)?;
+
+    assert_jpeg(&rgb_to_jpeg(&frame, &mut compressor, &ConversionConfig::default())?.data);
+
+    let gray = ConversionConfig {
+        force_grayscale: true,
+        ..Default::default()
+    };
+    assert_jpeg(&rgb_to_jpeg_with_config(&frame, &mut compressor, &gray)?.data);
+    Ok(())
+}
+
+#[cfg(not(feature = "pure-rust"))]
+#[test]
+fn test_turbojpeg_backend_output() -> Result<()> {
+    encode_rgb_and_gray()
+}
+
+#[cfg(feature = "pure-rust")]
+#[test]
+fn test_pure_rust_backend_output() -> Result<()> {
+    encode_rgb_and_gray()
+}
+
+/// Encodes the gradient at luma quality 90 with each chroma quality, returning the JPEGs.
+fn encode_with_chroma_quality(chroma_qualities: [u8; 2]) -> Result<[Vec<u8>; 2]> {
+    let frame = gradient_frame();
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(90)?;
+    let mut encode = |chroma_quality| {
+        let config = ConversionConfig {
+            quality: Some(90),
+            chroma_quality: Some(chroma_quality),
+            ..Default::default()
+        };
+        rgb_to_jpeg_with_config(&frame, &mut compressor, &config).map(|jpeg| jpeg.data)
+    };
+    let [high, low] = chroma_qualities;
+    Ok([encode(high)?, encode(low)?])
+}
+
+#[cfg(feature = "pure-rust")]
+#[test]
+fn test_chroma_quality_keeps_luma() -> Result<()> {
+    let [high, low] = encode_with_chroma_quality([90, 20])?;
+    assert_jpeg(&low);
+    assert!(low.len() < high.len(), "{} >= {}", low.len(), high.len());
+
+    // Luma is quantized the same way, so the Y plane decodes identically
+    let luma = |jpeg: &[u8]| turbojpeg::decompress(jpeg, PixelFormat::GRAY).map(|image| image.pixels);
+    assert_eq!(luma(&high)?, luma(&low)?);
+    Ok(())
+}
+
+#[cfg(not(feature = "pure-rust"))]
+#[test]
+fn test_chroma_quality_rejected_by_turbojpeg() {
+    let err = encode_with_chroma_quality([90, 20]).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<ConversionError>(),
+        Some(ConversionError::UnsupportedFormat(message)) if message == "RGB888 with chroma_quality"
+    ));
+}
+
+#[test]
+fn test_chroma_quality_rejected_for_yuv() {
+    let frame = ImageRawAny {
+        header: None,
+        image: Some(RawImageVariant::Yuv420(ImageYuv420 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: vec![128; (TEST_WIDTH * TEST_HEIGHT * 3 / 2) as usize],
+        })),
+    };
+    let config = ConversionConfig {
+        chroma_quality: Some(20),
+        ..Default::default()
+    };
+    let err = rgb_to_jpeg_with_config(&frame, &mut Compressor::new().unwrap(), &config).unwrap_err();
+    assert!(matches!(err, ConversionError::UnsupportedFormat(ref message) if message == "YUV420 with chroma_quality"));
+}
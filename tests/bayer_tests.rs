@@ -0,0 +1,66 @@
+#![cfg(feature = "bayer")]
+
+use anyhow::Result;
+use raw_to_jpeg::bayer::{demosaic_bilinear, BayerPattern};
+use raw_to_jpeg::{bayer_to_jpeg, ConversionError};
+use turbojpeg::Compressor;
+
+const TEST_WIDTH: usize = 176;
+const TEST_HEIGHT: usize = 144;
+
+/// Samples a scene of a single `rgb` color through a `pattern` color filter array.
+fn mosaic_of(rgb: [u8; 3], width: usize, height: usize, pattern: BayerPattern) -> Vec<u8> {
+    (0..height)
+        .flat_map(|y| (0..width).map(move |x| rgb[pattern.channel_at(x, y)]))
+        .collect()
+}
+
+#[test]
+fn test_demosaic_flat_color() {
+    let rgb = [200, 120, 40];
+    for pattern in [BayerPattern::Rggb, BayerPattern::Bggr, BayerPattern::Grbg, BayerPattern::Gbrg] {
+        let mosaic = mosaic_of(rgb, 6, 4, pattern);
+        let demosaiced = demosaic_bilinear(&mosaic, 6, 4, pattern);
+        assert_eq!(demosaiced.len(), 6 * 4 * 3);
+        // A flat scene is recovered exactly, edges included
+        assert!(demosaiced.chunks_exact(3).all(|pixel| pixel == rgb), "{pattern:?}");
+    }
+}
+
+#[test]
+fn test_demosaic_interpolates_missing_channels() {
+    // 2x2 RGGB block: R=100, G=50 and 70, B=10
+    let demosaiced = demosaic_bilinear(&[100, 50, 70, 10], 2, 2, BayerPattern::Rggb);
+    assert_eq!(&demosaiced[..3], [100, 60, 10]);
+    assert_eq!(&demosaiced[9..], [100, 60, 10]);
+}
+
+#[test]
+fn test_bayer_rggb_conversion() -> Result<()> {
+    let mosaic = mosaic_of([200, 120, 40], TEST_WIDTH, TEST_HEIGHT, BayerPattern::Rggb);
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(90)?;
+
+    let jpeg = bayer_to_jpeg(&mosaic, TEST_WIDTH, TEST_HEIGHT, BayerPattern::Rggb, &mut compressor)?;
+    assert_eq!(jpeg[..2], [0xFF, 0xD8]);
+    assert_eq!(jpeg[jpeg.len() - 2..], [0xFF, 0xD9]);
+    let header = turbojpeg::read_header(&jpeg)?;
+    assert_eq!((header.width, header.height), (TEST_WIDTH, TEST_HEIGHT));
+    Ok(())
+}
+
+#[test]
+fn test_bayer_rejects_bad_input() -> Result<()> {
+    let mut compressor = Compressor::new()?;
+    // RGB-sized expectations must not leak in: one byte per pixel is enough, one less is not
+    let short = vec![0u8; TEST_WIDTH * TEST_HEIGHT - 1];
+    let err = bayer_to_jpeg(&short, TEST_WIDTH, TEST_HEIGHT, BayerPattern::Rggb, &mut compressor).unwrap_err();
+    assert!(matches!(
+        err,
+        ConversionError::BufferTooSmall { format: "BAYER", expected, .. } if expected == TEST_WIDTH * TEST_HEIGHT
+    ));
+
+    let err = bayer_to_jpeg(&[0u8; 8], 8, 1, BayerPattern::Rggb, &mut compressor).unwrap_err();
+    assert!(matches!(err, ConversionError::InvalidDimensions { format: "BAYER", .. }));
+    Ok(())
+}
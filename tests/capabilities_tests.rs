@@ -0,0 +1,107 @@
+use anyhow::Result;
+use make87_messages::image::uncompressed::image_raw_any::Image as RawImageVariant;
+use make87_messages::image::uncompressed::{
+    ImageNv12, ImageRawAny, ImageRgb888, ImageRgba8888, ImageYuv420, ImageYuv422, ImageYuv444,
+};
+use raw_to_jpeg::capabilities::{capabilities, supported_formats};
+use raw_to_jpeg::{raw_frame, rgb_to_jpeg, ConversionConfig, RawPixelFormat};
+use turbojpeg::Compressor;
+
+const WIDTH: u32 = 16;
+const HEIGHT: u32 = 16;
+
+/// One frame of every `ImageRawAny` variant. The match makes this fail to compile when a
+/// variant is added, so it cannot be left out of the check below.
+fn frame_of_every_variant() -> Vec<ImageRawAny> {
+    let pixels = (WIDTH * HEIGHT) as usize;
+    let variants = [
+        RawImageVariant::Rgb888(ImageRgb888 {
+            header: None,
+            width: WIDTH,
+            height: HEIGHT,
+            data: vec![128; pixels * 3],
+        }),
+        RawImageVariant::Rgba8888(ImageRgba8888 {
+            header: None,
+            width: WIDTH,
+            height: HEIGHT,
+            data: vec![128; pixels * 4],
+        }),
+        RawImageVariant::Yuv420(ImageYuv420 {
+            header: None,
+            width: WIDTH,
+            height: HEIGHT,
+            data: vec![128; pixels * 3 / 2],
+        }),
+        RawImageVariant::Yuv422(ImageYuv422 {
+            header: None,
+            width: WIDTH,
+            height: HEIGHT,
+            data: vec![128; pixels * 2],
+        }),
+        RawImageVariant::Yuv444(ImageYuv444 {
+            header: None,
+            width: WIDTH,
+            height: HEIGHT,
+            data: vec![128; pixels * 3],
+        }),
+        RawImageVariant::Nv12(ImageNv12 {
+            header: None,
+            width: WIDTH,
+            height: HEIGHT,
+            data: vec![128; pixels * 3 / 2],
+        }),
+    ];
+    for variant in &variants {
+        match variant {
+            RawImageVariant::Rgb888(_)
+            | RawImageVariant::Rgba8888(_)
+            | RawImageVariant::Yuv420(_)
+            | RawImageVariant::Yuv422(_)
+            | RawImageVariant::Yuv444(_)
+            | RawImageVariant::Nv12(_) => {}
+        }
+    }
+    variants
+        .into_iter()
+        .map(|image| ImageRawAny {
+            header: None,
+            image: Some(image),
+        })
+        .collect()
+}
+
+#[test]
+fn test_every_converted_variant_is_a_supported_format() -> Result<()> {
+    let mut compressor = Compressor::new()?;
+    for frame in frame_of_every_variant() {
+        let (_, _, _, format) = raw_frame(&frame).expect("frame has image data");
+        rgb_to_jpeg(&frame, &mut compressor, &ConversionConfig::default())?;
+        assert!(
+            supported_formats().contains(&format.name()),
+            "{} converts but is not in {:?}",
+            format.name(),
+            supported_formats()
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_supported_formats_are_known_names() {
+    for name in supported_formats() {
+        if *name != "BAYER" {
+            let format = RawPixelFormat::from_name(name).expect("named format");
+            assert!(format.is_enabled(), "{name} is listed but not built in");
+        }
+    }
+}
+
+#[test]
+fn test_capabilities_json() {
+    let json = capabilities().to_json();
+    assert_eq!(json["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(json["formats"].as_array().unwrap().len(), supported_formats().len());
+    assert_eq!(json["output_formats"][0], "jpeg");
+    assert_eq!(json["lossless"], true);
+}
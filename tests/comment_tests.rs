@@ -0,0 +1,65 @@
+use anyhow::Result;
+use make87_messages::image::uncompressed::image_raw_any::Image as RawImageVariant;
+use make87_messages::image::uncompressed::{ImageRawAny, ImageYuv420};
+use raw_to_jpeg::{rgb_to_jpeg_with_config, ConversionConfig, MAX_COMMENT_LEN};
+use turbojpeg::Compressor;
+
+const TEST_WIDTH: u32 = 176;
+const TEST_HEIGHT: u32 = 144;
+
+fn gray_frame() -> ImageRawAny {
+    ImageRawAny {
+        header: None,
+        image: Some(RawImageVariant::Yuv420(ImageYuv420 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: vec![128u8; (TEST_WIDTH * TEST_HEIGHT * 3 / 2) as usize],
+        })),
+    }
+}
+
+/// Walks the marker segments in front of the scan data and returns the payload of each COM.
+fn read_comments(jpeg: &[u8]) -> Vec<Vec<u8>> {
+    let mut comments = Vec::new();
+    let mut pos = 2;
+    while pos + 4 <= jpeg.len() && jpeg[pos] == 0xFF && jpeg[pos + 1] != 0xDA {
+        let len = u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]) as usize;
+        if jpeg[pos + 1] == 0xFE {
+            comments.push(jpeg[pos + 4..pos + 2 + len].to_vec());
+        }
+        pos += 2 + len;
+    }
+    comments
+}
+
+fn convert_with_comment(comment: &str) -> Result<Vec<u8>> {
+    let mut compressor = Compressor::new()?;
+    let config = ConversionConfig {
+        comment: Some(comment.to_string()),
+        ..Default::default()
+    };
+    Ok(rgb_to_jpeg_with_config(&gray_frame(), &mut compressor, &config)?.data)
+}
+
+#[test]
+fn test_jpeg_comment_embedded() -> Result<()> {
+    let jpeg = convert_with_comment("camera-07/front")?;
+    assert_eq!(read_comments(&jpeg), [b"camera-07/front".to_vec()]);
+
+    // Still a decodable JPEG
+    let decoded = turbojpeg::read_header(&jpeg)?;
+    assert_eq!((decoded.width, decoded.height), (TEST_WIDTH as usize, TEST_HEIGHT as usize));
+    Ok(())
+}
+
+#[test]
+fn test_jpeg_comment_empty_or_oversized() -> Result<()> {
+    assert!(read_comments(&convert_with_comment("")?).is_empty());
+
+    let long = "x".repeat(MAX_COMMENT_LEN + 100);
+    let comments = read_comments(&convert_with_comment(&long)?);
+    assert_eq!(comments.len(), 1);
+    assert_eq!(comments[0].len(), MAX_COMMENT_LEN);
+    Ok(())
+}
@@ -0,0 +1,145 @@
+//! Image quality metrics for checking decoded output against the raw input, so channel swaps
+//! or misread chroma fail a test instead of only producing a valid-looking JPEG.
+
+use turbojpeg::PixelFormat;
+
+/// SSIM every conversion of the test frames at `JPEG_QUALITY` must reach.
+pub const SSIM_THRESHOLD: f64 = 0.95;
+
+/// A frame as full-range (JFIF) YCbCr planes with 4:2:0 chroma, the layout metrics are
+/// computed in. Odd trailing rows/columns have no chroma sample.
+pub struct Planes {
+    pub width: usize,
+    pub height: usize,
+    pub y: Vec<u8>,
+    pub cb: Vec<u8>,
+    pub cr: Vec<u8>,
+}
+
+impl Planes {
+    /// Converts packed RGB with the BT.601 matrix JPEG uses, averaging chroma over 2x2 blocks.
+    pub fn from_rgb(rgb: &[u8], width: usize, height: usize) -> Self {
+        let pixel = |x: usize, y: usize| {
+            let p = &rgb[(y * width + x) * 3..];
+            (p[0] as f64, p[1] as f64, p[2] as f64)
+        };
+        let mut planes = Planes::empty(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let (r, g, b) = pixel(x, y);
+                planes.y.push(clamp(0.299 * r + 0.587 * g + 0.114 * b));
+            }
+        }
+        for y in 0..height / 2 {
+            for x in 0..width / 2 {
+                let (mut cb, mut cr) = (0.0, 0.0);
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let (r, g, b) = pixel(2 * x + dx, 2 * y + dy);
+                    cb += 128.0 - 0.168736 * r - 0.331264 * g + 0.5 * b;
+                    cr += 128.0 + 0.5 * r - 0.418688 * g - 0.081312 * b;
+                }
+                planes.cb.push(clamp(cb / 4.0));
+                planes.cr.push(clamp(cr / 4.0));
+            }
+        }
+        planes
+    }
+
+    /// Takes tightly packed planar YUV 4:2:0 (I420) as is.
+    pub fn from_yuv420(data: &[u8], width: usize, height: usize) -> Self {
+        let (luma, chroma) = (width * height, (width / 2) * (height / 2));
+        Planes {
+            width,
+            height,
+            y: data[..luma].to_vec(),
+            cb: data[luma..luma + chroma].to_vec(),
+            cr: data[luma + chroma..luma + 2 * chroma].to_vec(),
+        }
+    }
+
+    /// Decodes a JPEG through RGB, as a viewer would show it.
+    pub fn from_jpeg(jpeg: &[u8]) -> Self {
+        let image = turbojpeg::decompress(jpeg, PixelFormat::RGB).expect("output must decode");
+        let rgb: Vec<u8> = image
+            .pixels
+            .chunks(image.pitch)
+            .take(image.height)
+            .flat_map(|row| &row[..image.width * 3])
+            .copied()
+            .collect();
+        Planes::from_rgb(&rgb, image.width, image.height)
+    }
+
+    fn empty(width: usize, height: usize) -> Self {
+        Planes {
+            width,
+            height,
+            y: Vec::with_capacity(width * height),
+            cb: Vec::with_capacity((width / 2) * (height / 2)),
+            cr: Vec::with_capacity((width / 2) * (height / 2)),
+        }
+    }
+}
+
+fn clamp(value: f64) -> u8 {
+    value.round().clamp(0.0, 255.0) as u8
+}
+
+/// Mean SSIM of two planes over 8x8 windows every 4 pixels (clipped to planes smaller than
+/// that), with the usual constants for 8-bit samples. 1.0 means identical.
+pub fn ssim_plane(a: &[u8], b: &[u8], width: usize, height: usize) -> f64 {
+    const WINDOW: usize = 8;
+    const STEP: usize = 4;
+    let (c1, c2) = ((0.01f64 * 255.0).powi(2), (0.03f64 * 255.0).powi(2));
+    let (mut total, mut windows) = (0.0, 0);
+    for y in (0..=height.saturating_sub(WINDOW)).step_by(STEP) {
+        for x in (0..=width.saturating_sub(WINDOW)).step_by(STEP) {
+            let (mut sa, mut sb, mut saa, mut sbb, mut sab) = (0.0, 0.0, 0.0, 0.0, 0.0);
+            for row in y..(y + WINDOW).min(height) {
+                for col in x..(x + WINDOW).min(width) {
+                    let (va, vb) = (a[row * width + col] as f64, b[row * width + col] as f64);
+                    sa += va;
+                    sb += vb;
+                    saa += va * va;
+                    sbb += vb * vb;
+                    sab += va * vb;
+                }
+            }
+            let n = ((y + WINDOW).min(height) - y) as f64 * ((x + WINDOW).min(width) - x) as f64;
+            let (ma, mb) = (sa / n, sb / n);
+            let (var_a, var_b, cov) = (saa / n - ma * ma, sbb / n - mb * mb, sab / n - ma * mb);
+            total += ((2.0 * ma * mb + c1) * (2.0 * cov + c2)) / ((ma * ma + mb * mb + c1) * (var_a + var_b + c2));
+            windows += 1;
+        }
+    }
+    total / windows as f64
+}
+
+/// SSIM of two frames: the per-plane SSIMs weighted by sample count (Y 4, Cb 1, Cr 1), as
+/// ffmpeg reports it for 4:2:0. A red/blue swap drags the chroma planes well below the luma.
+pub fn ssim(reference: &Planes, actual: &Planes) -> f64 {
+    assert_eq!((reference.width, reference.height), (actual.width, actual.height), "frame size differs");
+    let (chroma_width, chroma_height) = (reference.width / 2, reference.height / 2);
+    let y = ssim_plane(&reference.y, &actual.y, reference.width, reference.height);
+    let cb = ssim_plane(&reference.cb, &actual.cb, chroma_width, chroma_height);
+    let cr = ssim_plane(&reference.cr, &actual.cr, chroma_width, chroma_height);
+    (4.0 * y + cb + cr) / 6.0
+}
+
+/// PSNR of the luma planes in dB; infinite for identical planes.
+pub fn psnr(reference: &Planes, actual: &Planes) -> f64 {
+    let mse = reference.y.iter().zip(&actual.y).map(|(a, b)| (*a as f64 - *b as f64).powi(2)).sum::<f64>()
+        / reference.y.len() as f64;
+    10.0 * (255.0 * 255.0 / mse).log10()
+}
+
+/// Decodes `jpeg` and asserts its SSIM against `reference` exceeds `threshold`.
+pub fn assert_ssim_above(jpeg: &[u8], reference: &Planes, threshold: f64) {
+    let decoded = Planes::from_jpeg(jpeg);
+    let ssim = ssim(reference, &decoded);
+    assert!(
+        ssim > threshold,
+        "SSIM {ssim:.4} not above {threshold} (luma PSNR {:.1} dB)",
+        psnr(reference, &decoded)
+    );
+}
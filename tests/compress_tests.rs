@@ -0,0 +1,140 @@
+use anyhow::Result;
+use make87_messages::core::Header;
+use make87_messages::image::uncompressed::image_raw_any::Image as RawImageVariant;
+use make87_messages::image::uncompressed::{ImageRawAny, ImageRgb888, ImageYuv420};
+use raw_to_jpeg::{rgb_to_jpeg, Compress, ConversionConfig};
+use turbojpeg::{Image, PixelFormat, Subsamp, YuvImage};
+
+const FAKE_JPEG: [u8; 4] = [0xFF, 0xD8, 0xFF, 0xD9];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Call {
+    Rgb { width: usize, height: usize, format: PixelFormat },
+    Yuv { width: usize, height: usize, subsamp: Subsamp },
+    Quality(i32),
+    Subsamp(Subsamp),
+    Optimize(bool),
+}
+
+/// Records every call and writes a fixed stand-in JPEG instead of compressing.
+#[derive(Default)]
+struct RecordingCompressor {
+    calls: Vec<Call>,
+}
+
+impl Compress for RecordingCompressor {
+    fn compress_rgb(&mut self, image: Image<&[u8]>, output: &mut Vec<u8>) -> raw_to_jpeg::Result<()> {
+        self.calls.push(Call::Rgb {
+            width: image.width,
+            height: image.height,
+            format: image.format,
+        });
+        output.clear();
+        output.extend_from_slice(&FAKE_JPEG);
+        Ok(())
+    }
+
+    fn compress_yuv(&mut self, image: YuvImage<&[u8]>, output: &mut Vec<u8>) -> raw_to_jpeg::Result<()> {
+        self.calls.push(Call::Yuv {
+            width: image.width,
+            height: image.height,
+            subsamp: image.subsamp,
+        });
+        output.clear();
+        output.extend_from_slice(&FAKE_JPEG);
+        Ok(())
+    }
+
+    fn set_quality(&mut self, quality: i32) -> raw_to_jpeg::Result<()> {
+        self.calls.push(Call::Quality(quality));
+        Ok(())
+    }
+
+    fn set_subsamp(&mut self, subsamp: Subsamp) -> raw_to_jpeg::Result<()> {
+        self.calls.push(Call::Subsamp(subsamp));
+        Ok(())
+    }
+
+    fn set_optimize(&mut self, optimize: bool) -> raw_to_jpeg::Result<()> {
+        self.calls.push(Call::Optimize(optimize));
+        Ok(())
+    }
+}
+
+#[test]
+fn test_rgb_to_jpeg_with_mock_compressor() -> Result<()> {
+    let header = Header {
+        entity_path: "cameras/1".to_string(),
+        ..Default::default()
+    };
+    let frame = ImageRawAny {
+        header: Some(header.clone()),
+        image: Some(RawImageVariant::Yuv420(ImageYuv420 {
+            header: None,
+            width: 4,
+            height: 2,
+            data: vec![128; 12],
+        })),
+    };
+    let config = ConversionConfig {
+        quality: Some(75),
+        optimize_huffman: true,
+        ..Default::default()
+    };
+
+    // Through a trait object, as a pool handing out different implementations would
+    let mut mock = RecordingCompressor::default();
+    let compressor: &mut dyn Compress = &mut mock;
+    let jpeg = rgb_to_jpeg(&frame, compressor, &config)?;
+    assert_eq!(jpeg.data, FAKE_JPEG);
+    assert_eq!(jpeg.header, Some(header));
+    assert_eq!(
+        mock.calls,
+        [
+            Call::Quality(75),
+            Call::Optimize(true),
+            Call::Yuv {
+                width: 4,
+                height: 2,
+                subsamp: Subsamp::Sub2x2
+            },
+        ]
+    );
+    Ok(())
+}
+
+// The pure-Rust build encodes packed input without the compressor
+#[cfg(not(feature = "pure-rust"))]
+#[test]
+fn test_packed_input_goes_through_compress_rgb() -> Result<()> {
+    let frame = ImageRawAny {
+        header: None,
+        image: Some(RawImageVariant::Rgb888(ImageRgb888 {
+            header: None,
+            width: 2,
+            height: 2,
+            data: vec![255; 12],
+        })),
+    };
+    let config = ConversionConfig {
+        subsamp: Some(Subsamp::Sub2x1),
+        ..Default::default()
+    };
+
+    let mut mock = RecordingCompressor::default();
+    let jpeg = rgb_to_jpeg(&frame, &mut mock, &config)?;
+    assert_eq!(jpeg.data, FAKE_JPEG);
+    assert_eq!(
+        mock.calls,
+        [
+            Call::Optimize(false),
+            Call::Subsamp(Subsamp::Sub2x1),
+            Call::Rgb {
+                width: 2,
+                height: 2,
+                format: PixelFormat::RGB
+            },
+        ]
+    );
+    Ok(())
+}
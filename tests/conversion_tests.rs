@@ -1,12 +1,31 @@
+mod common;
+
 use anyhow::Result;
+use common::{assert_ssim_above, ssim, Planes, SSIM_THRESHOLD};
 use make87_messages::core::Header;
 use make87_messages::google::protobuf::Timestamp;
 use make87_messages::image::uncompressed::image_raw_any::Image as RawImageVariant;
-use make87_messages::image::uncompressed::{ImageNv12, ImageRawAny, ImageRgb888, ImageYuv420, ImageYuv422, ImageYuv444};
-use raw_to_jpeg::rgb_to_jpeg;
+use make87_messages::image::uncompressed::{
+    ImageNv12, ImageRawAny, ImageRgb888, ImageRgba8888, ImageYuv420, ImageYuv422, ImageYuv444,
+};
+use raw_to_jpeg::pixels::{
+    AlphaMode, AspectRatio, ColorMatrix, DepthReduction, GammaLut, Gray16Map, Orientation, Plane, Roi, TenBitReduction,
+    Yuv420Planes,
+};
+use raw_to_jpeg::restart::RestartInterval;
+use raw_to_jpeg::{
+    auto_subsamp, compress_raw, compress_raw_into, compress_to_target_size, convert_batch, frame_header, gray16_to_jpeg, gray_to_jpeg, gray_to_jpeg_lossless, make_error_frame,
+    make_thumbnail, mono12_packed_to_jpeg, nv12_to_i420, output_dimensions, p010_to_jpeg, packed_to_jpeg, parse_alpha_mode,
+    parse_aspect_ratio,
+    planar_rgb_to_jpeg, promote_frame_header, rgb161616_to_jpeg,
+    rgb_to_jpeg, rgb_to_jpeg_into, rgb_to_jpeg_with_config, rgb_to_jpeg_with_stats, semi_planar_to_jpeg,
+    semi_planar_to_jpeg_strided, set_quality_clamped, thumbnail_size, upsample_chroma, verify_roundtrip, warmup, yuyv_to_jpeg,
+    ChromaOrder, ChromaUpsample, ConversionConfig, ConversionError, RawPixelFormat, DEFAULT_THUMBNAIL_WIDTH, ERROR_FRAME_COLOR, MONO12_DEFAULT_SHIFT,
+    TARGET_SIZE_QUALITY_RANGE,
+};
 use std::fs;
 use std::path::Path;
-use turbojpeg::Compressor;
+use turbojpeg::{Colorspace, Compressor, PixelFormat, Subsamp};
 
 /// Test data directory structure:
 /// tests/data/
@@ -55,6 +74,7 @@ fn save_output_jpeg(data: &[u8], filename: &str) -> Result<()> {
 #[test]
 fn test_rgb888_conversion() -> Result<()> {
     let raw_data = load_test_file("tulips_rgb444_prog_packed_qcif.yuv")?;
+    let reference = Planes::from_rgb(&raw_data, TEST_WIDTH as usize, TEST_HEIGHT as usize);
 
     let header = create_test_header();
 
@@ -73,7 +93,7 @@ fn test_rgb888_conversion() -> Result<()> {
     let mut compressor = Compressor::new()?;
     compressor.set_quality(JPEG_QUALITY)?;
 
-    let jpeg_result = rgb_to_jpeg(&image_raw, &mut compressor)?;
+    let jpeg_result = rgb_to_jpeg(&image_raw, &mut compressor, &ConversionConfig::default())?;
 
     // Verify JPEG header is present
     assert!(jpeg_result.header.is_some());
@@ -83,6 +103,12 @@ fn test_rgb888_conversion() -> Result<()> {
     assert_eq!(jpeg_result.data[0], 0xFF);
     assert_eq!(jpeg_result.data[1], 0xD8);
 
+    // The decoded output must look like the input, and a red/blue swap must not pass for it
+    assert_ssim_above(&jpeg_result.data, &reference, SSIM_THRESHOLD);
+    let decoded = turbojpeg::decompress(&jpeg_result.data, PixelFormat::BGR)?;
+    let swapped = Planes::from_rgb(&decoded.pixels, decoded.width, decoded.height);
+    assert!(ssim(&reference, &swapped) < SSIM_THRESHOLD);
+
     // Save for visual inspection
     save_output_jpeg(&jpeg_result.data, "test_frame_640x480_rgb888_output.jpg")?;
 
@@ -90,9 +116,764 @@ fn test_rgb888_conversion() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_legacy_inner_header_is_used() -> Result<()> {
+    // Legacy producers only set the header on the image variant
+    let mut image_raw = ImageRawAny {
+        header: None,
+        image: Some(RawImageVariant::Rgb888(ImageRgb888 {
+            header: Some(create_test_header()),
+            width: 16,
+            height: 8,
+            data: vec![128; 16 * 8 * 3],
+        })),
+    };
+    assert_eq!(frame_header(&image_raw), Some(&create_test_header()));
+
+    let mut compressor = Compressor::new()?;
+    let jpeg = rgb_to_jpeg(&image_raw, &mut compressor, &ConversionConfig::default())?;
+    assert_eq!(jpeg.header, Some(create_test_header()));
+
+    promote_frame_header(&mut image_raw);
+    assert_eq!(image_raw.header, Some(create_test_header()));
+    let jpeg = rgb_to_jpeg(&image_raw, &mut compressor, &ConversionConfig::default())?;
+    assert_eq!(jpeg.header, Some(create_test_header()));
+    Ok(())
+}
+
+#[test]
+fn test_bgr888_conversion() -> Result<()> {
+    let rgb_data = load_test_file("tulips_rgb444_prog_packed_qcif.yuv")?;
+    let bgr_data: Vec<u8> = rgb_data.chunks_exact(3).flat_map(|p| [p[2], p[1], p[0]]).collect();
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+
+    let width = TEST_WIDTH as usize;
+    let height = TEST_HEIGHT as usize;
+    let rgb_jpeg = packed_to_jpeg(&rgb_data, width, height, PixelFormat::RGB, &mut compressor)?;
+    let bgr_jpeg = packed_to_jpeg(&bgr_data, width, height, PixelFormat::BGR, &mut compressor)?;
+
+    // Verify JPEG data
+    assert!(bgr_jpeg.len() > 2);
+    assert_eq!(bgr_jpeg[0], 0xFF);
+    assert_eq!(bgr_jpeg[1], 0xD8);
+
+    // Same pixels in a different byte order must encode identically (no red/blue swap)
+    assert_eq!(bgr_jpeg, rgb_jpeg);
+
+    save_output_jpeg(&bgr_jpeg, "test_frame_640x480_bgr888_output.jpg")?;
+    Ok(())
+}
+
+/// Encodes a solid red frame given in `format` and checks it decodes back as red, i.e. the
+/// leading alpha byte was not taken for a color channel.
+fn assert_alpha_first_stays_red(format: PixelFormat, pixel: [u8; 4]) -> Result<()> {
+    let width = 16;
+    let height = 16;
+    let pixels: Vec<u8> = pixel.iter().copied().cycle().take(width * height * 4).collect();
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+    let jpeg = packed_to_jpeg(&pixels, width, height, format, &mut compressor)?;
+
+    let decoded = turbojpeg::decompress(&jpeg, PixelFormat::RGB)?;
+    let center = height / 2 * decoded.pitch + width / 2 * 3;
+    let [r, g, b] = [decoded.pixels[center], decoded.pixels[center + 1], decoded.pixels[center + 2]];
+    assert!(r > 200 && g < 50 && b < 50, "{format:?} decoded as ({r}, {g}, {b})");
+    Ok(())
+}
+
+#[test]
+fn test_argb8888_conversion() -> Result<()> {
+    assert_alpha_first_stays_red(PixelFormat::ARGB, [0xFF, 0xFF, 0x00, 0x00])
+}
+
+#[test]
+fn test_abgr8888_conversion() -> Result<()> {
+    assert_alpha_first_stays_red(PixelFormat::ABGR, [0xFF, 0x00, 0x00, 0xFF])
+}
+
+#[test]
+fn test_planar_rgb_conversion() -> Result<()> {
+    let rgb_data = load_test_file("tulips_rgb444_prog_packed_qcif.yuv")?;
+    let plane = |channel: usize| -> Vec<u8> { rgb_data.chunks_exact(3).map(|p| p[channel]).collect() };
+    let (r, g, b) = (plane(0), plane(1), plane(2));
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+
+    let width = TEST_WIDTH as usize;
+    let height = TEST_HEIGHT as usize;
+    let rgb_jpeg = packed_to_jpeg(&rgb_data, width, height, PixelFormat::RGB, &mut compressor)?;
+    let planar_jpeg = planar_rgb_to_jpeg([&r, &g, &b], width, height, &mut compressor)?;
+    assert_eq!(planar_jpeg, rgb_jpeg);
+
+    let err = planar_rgb_to_jpeg([&r, &g[1..], &b], width, height, &mut compressor).unwrap_err();
+    assert!(err.to_string().starts_with("Planar RGB data too small"));
+
+    save_output_jpeg(&planar_jpeg, "test_frame_640x480_planar_rgb_output.jpg")?;
+    Ok(())
+}
+
+#[test]
+fn test_bgra8888_conversion() -> Result<()> {
+    let rgb_data = load_test_file("tulips_rgb444_prog_packed_qcif.yuv")?;
+    let bgra_data: Vec<u8> = rgb_data.chunks_exact(3).flat_map(|p| [p[2], p[1], p[0], 0xFF]).collect();
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+
+    let width = TEST_WIDTH as usize;
+    let height = TEST_HEIGHT as usize;
+    let rgb_jpeg = packed_to_jpeg(&rgb_data, width, height, PixelFormat::RGB, &mut compressor)?;
+    let bgra_jpeg = packed_to_jpeg(&bgra_data, width, height, PixelFormat::BGRA, &mut compressor)?;
+
+    assert_eq!(bgra_jpeg[0], 0xFF);
+    assert_eq!(bgra_jpeg[1], 0xD8);
+    assert_eq!(bgra_jpeg, rgb_jpeg);
+    Ok(())
+}
+
+#[test]
+fn test_gray8_conversion() -> Result<()> {
+    let width = TEST_WIDTH as usize;
+    let height = TEST_HEIGHT as usize;
+    let ramp: Vec<u8> = (0..height).flat_map(|_| (0..width).map(|x| (x * 255 / (width - 1)) as u8)).collect();
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+
+    let jpeg = gray_to_jpeg(&ramp, width, height, &mut compressor)?;
+
+    assert_eq!(jpeg[0], 0xFF);
+    assert_eq!(jpeg[1], 0xD8);
+
+    // Must be a single-component JPEG, not a gray-looking YCbCr one
+    let header = turbojpeg::read_header(&jpeg)?;
+    assert_eq!(header.colorspace, Colorspace::Gray);
+    assert_eq!((header.width, header.height), (width, height));
+
+    save_output_jpeg(&jpeg, "test_frame_640x480_gray8_output.jpg")?;
+    Ok(())
+}
+
+#[test]
+fn test_gray16_conversion() -> Result<()> {
+    let width = TEST_WIDTH as usize;
+    let height = TEST_HEIGHT as usize;
+    let ramp: Vec<u8> = (0..height)
+        .flat_map(|_| (0..width).flat_map(|x| ((x * 4000 / (width - 1)) as u16 + 500).to_le_bytes()))
+        .collect();
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+
+    let jpeg = gray16_to_jpeg(&ramp, width, height, Gray16Map::MinMax, &mut compressor)?;
+    let header = turbojpeg::read_header(&jpeg)?;
+    assert_eq!(header.colorspace, Colorspace::Gray);
+    assert_eq!((header.width, header.height), (width, height));
+
+    let err = gray16_to_jpeg(&ramp[1..], width, height, Gray16Map::Shift8, &mut compressor).unwrap_err();
+    assert!(err.to_string().starts_with("GRAY16 data too small"));
+
+    save_output_jpeg(&jpeg, "test_frame_640x480_gray16_output.jpg")?;
+    Ok(())
+}
+
+#[test]
+fn test_rgb161616_conversion() -> Result<()> {
+    // Five vertical bands of 16-bit gray, 16 pixels wide, from black to full white
+    let levels: [u16; 5] = [0x0000, 0x3000, 0x8000, 0xC000, 0xFFFF];
+    let (width, height) = (levels.len() * 16, 16usize);
+    let data: Vec<u8> = (0..height)
+        .flat_map(|_| (0..width).flat_map(|x| [levels[x / 16]; 3]))
+        .flat_map(u16::to_le_bytes)
+        .collect();
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(95)?;
+    let jpeg = rgb161616_to_jpeg(&data, width, height, Gray16Map::Shift8, &mut compressor)?;
+    let decoded = turbojpeg::decompress(&jpeg, PixelFormat::RGB)?;
+    assert_eq!((decoded.width, decoded.height), (width, height));
+    let band_centers: Vec<&[u8]> = (0..levels.len())
+        .map(|band| &decoded.pixels[(height / 2) * decoded.pitch + (band * 16 + 8) * 3..][..3])
+        .collect();
+
+    // White stays white, and every channel rises with the 16-bit level
+    assert!(band_centers[4].iter().all(|&v| v >= 254), "white mapped to {:?}", band_centers[4]);
+    assert!(band_centers[0].iter().all(|&v| v <= 1), "black mapped to {:?}", band_centers[0]);
+    for pair in band_centers.windows(2) {
+        assert!((0..3).all(|c| pair[0][c] < pair[1][c]), "{:?} not below {:?}", pair[0], pair[1]);
+    }
+    // 0x8000 >> 8
+    assert!(band_centers[2].iter().all(|&v| v.abs_diff(128) <= 2));
+
+    for len in [data.len() - 1, data.len() + 6] {
+        let mut resized = data.clone();
+        resized.resize(len, 0);
+        let err = rgb161616_to_jpeg(&resized, width, height, Gray16Map::Shift8, &mut compressor).unwrap_err();
+        assert!(matches!(
+            err,
+            ConversionError::BufferSizeMismatch { format: "RGB161616", expected, got } if expected == data.len() && got == len
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_mono12_packed_conversion() -> Result<()> {
+    let width = TEST_WIDTH as usize;
+    let height = TEST_HEIGHT as usize;
+    // Horizontal 12-bit ramp, two samples per 3 bytes
+    let samples: Vec<u16> = (0..height)
+        .flat_map(|_| (0..width).map(|x| (x * 4095 / (width - 1)) as u16))
+        .collect();
+    let packed: Vec<u8> = samples
+        .chunks_exact(2)
+        .flat_map(|pair| [(pair[0] >> 4) as u8, (pair[0] & 0x0F | (pair[1] & 0x0F) << 4) as u8, (pair[1] >> 4) as u8])
+        .collect();
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+
+    let jpeg = mono12_packed_to_jpeg(&packed, width, height, MONO12_DEFAULT_SHIFT, &mut compressor)?;
+    let header = turbojpeg::read_header(&jpeg)?;
+    assert_eq!(header.colorspace, Colorspace::Gray);
+    assert_eq!((header.width, header.height), (width, height));
+
+    // The packed length must match exactly, in both directions
+    for data in [&packed[1..], &[packed.as_slice(), &[0]].concat()[..]] {
+        let err = mono12_packed_to_jpeg(data, width, height, MONO12_DEFAULT_SHIFT, &mut compressor).unwrap_err();
+        assert!(matches!(
+            err,
+            ConversionError::BufferSizeMismatch { format: "MONO12_PACKED", expected, .. } if expected == packed.len()
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_p010_conversion() -> Result<()> {
+    let width = TEST_WIDTH as usize;
+    let height = TEST_HEIGHT as usize;
+    // 10-bit luma ramp and neutral chroma (512), shifted into the high bits of each word
+    let luma = (0..height).flat_map(|_| (0..width).map(|x| (x * 1023 / (width - 1)) as u16));
+    let chroma = std::iter::repeat_n(512u16, width * height / 2);
+    let p010: Vec<u8> = luma.chain(chroma).flat_map(|sample| (sample << 6).to_le_bytes()).collect();
+    assert_eq!(p010.len(), width * height * 3);
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+
+    for reduction in [DepthReduction::Truncate, DepthReduction::Round] {
+        let jpeg = p010_to_jpeg(&p010, width, height, reduction, &mut compressor)?;
+        let header = turbojpeg::read_header(&jpeg)?;
+        assert_eq!((header.width, header.height), (width, height));
+        assert_eq!(header.subsamp, Subsamp::Sub2x2);
+    }
+
+    // The length check counts 16-bit samples, not bytes of an 8-bit NV12 frame
+    let nv12_sized = &p010[..width * height * 3 / 2];
+    let err = p010_to_jpeg(nv12_sized, width, height, DepthReduction::Truncate, &mut compressor).unwrap_err();
+    assert!(err.to_string().starts_with("P010 data too small"));
+    Ok(())
+}
+
+#[test]
+fn test_yuv420p10_conversion() -> Result<()> {
+    let width = TEST_WIDTH as usize;
+    let height = TEST_HEIGHT as usize;
+    // 10-bit luma ramp and neutral chroma (512) in three planes of little-endian words
+    let luma = (0..height).flat_map(|_| (0..width).map(|x| (x * 1023 / (width - 1)) as u16));
+    let chroma = std::iter::repeat_n(512u16, width * height / 2);
+    let planar: Vec<u8> = luma.chain(chroma).flat_map(|sample| sample.to_le_bytes()).collect();
+    assert_eq!(planar.len(), RawPixelFormat::Yuv420P10.expected_len(width, height));
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+
+    for reduction in [TenBitReduction::Shift, TenBitReduction::Scale] {
+        let config = ConversionConfig {
+            yuv420p10_reduction: reduction,
+            ..Default::default()
+        };
+        let mut jpeg = Vec::new();
+        compress_raw_into(&planar, width, height, RawPixelFormat::Yuv420P10, &mut compressor, &config, &mut jpeg)?;
+        let header = turbojpeg::read_header(&jpeg)?;
+        assert_eq!((header.width, header.height), (width, height));
+        assert_eq!(header.subsamp, Subsamp::Sub2x2);
+    }
+
+    // Each plane is checked on its own, and the error names the first one cut short
+    let mut compress = |data: &[u8]| compress_raw(data, width, height, RawPixelFormat::Yuv420P10, &mut compressor);
+    let i420_sized = &planar[..width * height * 3 / 2];
+    assert!(compress(i420_sized).unwrap_err().to_string().starts_with("YUV420P10 Y plane data too small"));
+    let u_cut = &planar[..width * height * 2 + 2];
+    assert!(compress(u_cut).unwrap_err().to_string().starts_with("YUV420P10 U plane data too small"));
+    let v_cut = &planar[..planar.len() - 2];
+    assert!(compress(v_cut).unwrap_err().to_string().starts_with("YUV420P10 V plane data too small"));
+
+    let err = compress_raw(&planar, width - 1, height, RawPixelFormat::Yuv420P10, &mut compressor).unwrap_err();
+    assert!(matches!(err, ConversionError::InvalidDimensions { .. }));
+    Ok(())
+}
+
+#[test]
+fn test_rgb888_lossless_roundtrip() -> Result<()> {
+    let raw_data = load_test_file("tulips_rgb444_prog_packed_qcif.yuv")?;
+    let image_raw = ImageRawAny {
+        header: Some(create_test_header()),
+        image: Some(RawImageVariant::Rgb888(ImageRgb888 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: raw_data.clone(),
+        })),
+    };
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+    let config = ConversionConfig {
+        lossless: true,
+        ..Default::default()
+    };
+    let jpeg = rgb_to_jpeg_with_config(&image_raw, &mut compressor, &config)?;
+    assert!(turbojpeg::read_header(&jpeg.data)?.is_lossless);
+
+    let decoded = turbojpeg::decompress(&jpeg.data, PixelFormat::RGB)?;
+    assert_eq!(decoded.pitch, TEST_WIDTH as usize * 3);
+    assert_eq!(decoded.pixels, raw_data[..(TEST_WIDTH * TEST_HEIGHT * 3) as usize]);
+
+    save_output_jpeg(&jpeg.data, "test_frame_640x480_rgb888_lossless_output.jpg")?;
+    Ok(())
+}
+
+#[test]
+fn test_gray8_lossless_roundtrip() -> Result<()> {
+    let width = TEST_WIDTH as usize;
+    let height = TEST_HEIGHT as usize;
+    let noise: Vec<u8> = (0..width * height).map(|i| (i * 7919 % 251) as u8).collect();
+
+    let jpeg = gray_to_jpeg_lossless(&noise, width, height)?;
+    let decoded = turbojpeg::decompress(&jpeg, PixelFormat::GRAY)?;
+    assert_eq!(decoded.pixels, noise);
+    Ok(())
+}
+
+#[test]
+fn test_optimize_huffman_not_larger() -> Result<()> {
+    let frames = [
+        ImageRawAny {
+            header: Some(create_test_header()),
+            image: Some(RawImageVariant::Rgb888(ImageRgb888 {
+                header: None,
+                width: TEST_WIDTH,
+                height: TEST_HEIGHT,
+                data: load_test_file("tulips_rgb444_prog_packed_qcif.yuv")?,
+            })),
+        },
+        ImageRawAny {
+            header: Some(create_test_header()),
+            image: Some(RawImageVariant::Yuv420(ImageYuv420 {
+                header: None,
+                width: TEST_WIDTH,
+                height: TEST_HEIGHT,
+                data: load_test_file("tulips_yuv420_prog_planar_qcif.yuv")?,
+            })),
+        },
+    ];
+    let optimized = ConversionConfig {
+        optimize_huffman: true,
+        ..Default::default()
+    };
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+    for frame in &frames {
+        let default_jpeg = rgb_to_jpeg(frame, &mut compressor, &ConversionConfig::default())?;
+        let optimized_jpeg = rgb_to_jpeg_with_config(frame, &mut compressor, &optimized)?;
+        assert!(optimized_jpeg.data.len() <= default_jpeg.data.len());
+        verify_roundtrip(&optimized_jpeg, TEST_WIDTH as usize, TEST_HEIGHT as usize)?;
+
+        // The flag does not stick to the compressor for the next default conversion
+        let again = rgb_to_jpeg(frame, &mut compressor, &ConversionConfig::default())?;
+        assert_eq!(again.data, default_jpeg.data);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_force_grayscale() -> Result<()> {
+    let rgb_frame = ImageRawAny {
+        header: Some(create_test_header()),
+        image: Some(RawImageVariant::Rgb888(ImageRgb888 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: load_test_file("tulips_rgb444_prog_packed_qcif.yuv")?,
+        })),
+    };
+    let yuv_frame = ImageRawAny {
+        header: Some(create_test_header()),
+        image: Some(RawImageVariant::Yuv420(ImageYuv420 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: load_test_file("tulips_yuv420_prog_planar_qcif.yuv")?,
+        })),
+    };
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+    let gray = ConversionConfig {
+        force_grayscale: true,
+        ..Default::default()
+    };
+
+    for frame in [&rgb_frame, &yuv_frame] {
+        let color_jpeg = rgb_to_jpeg(frame, &mut compressor, &ConversionConfig::default())?;
+        let gray_jpeg = rgb_to_jpeg_with_config(frame, &mut compressor, &gray)?;
+
+        let header = turbojpeg::read_header(&gray_jpeg.data)?;
+        assert_eq!(header.colorspace, Colorspace::Gray);
+        assert_eq!((header.width, header.height), (TEST_WIDTH as usize, TEST_HEIGHT as usize));
+        assert!(gray_jpeg.data.len() < color_jpeg.data.len());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_force_grayscale_color_space() -> Result<()> {
+    let (width, height) = (16u32, 16u32);
+    let blue_frame = ImageRawAny {
+        header: None,
+        image: Some(RawImageVariant::Rgb888(ImageRgb888 {
+            header: None,
+            width,
+            height,
+            data: [0, 0, 255].repeat((width * height) as usize),
+        })),
+    };
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(100)?;
+
+    let mut center_luma = |color_space| -> Result<u8> {
+        let config = ConversionConfig {
+            force_grayscale: true,
+            color_space,
+            ..Default::default()
+        };
+        let jpeg = rgb_to_jpeg_with_config(&blue_frame, &mut compressor, &config)?;
+        let decoded = turbojpeg::decompress(&jpeg.data, PixelFormat::GRAY)?;
+        Ok(decoded.pixels[(height / 2 * width + width / 2) as usize])
+    };
+
+    // Pure blue weighs 0.114 in BT.601 but only 0.0722 in BT.709
+    assert!(center_luma(ColorMatrix::Bt601)?.abs_diff(29) <= 1);
+    assert!(center_luma(ColorMatrix::Bt709)?.abs_diff(18) <= 1);
+    Ok(())
+}
+
+#[test]
+fn test_rgb888_subsamp_config() -> Result<()> {
+    let raw_data = load_test_file("tulips_rgb444_prog_packed_qcif.yuv")?;
+
+    let image_raw = ImageRawAny {
+        header: Some(create_test_header()),
+        image: Some(RawImageVariant::Rgb888(ImageRgb888 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: raw_data,
+        })),
+    };
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+
+    let sub420 = ConversionConfig {
+        subsamp: Some(Subsamp::Sub2x2),
+        ..Default::default()
+    };
+    let sub444 = ConversionConfig {
+        subsamp: Some(Subsamp::None),
+        ..Default::default()
+    };
+    let jpeg_420 = rgb_to_jpeg_with_config(&image_raw, &mut compressor, &sub420)?;
+    let jpeg_444 = rgb_to_jpeg_with_config(&image_raw, &mut compressor, &sub444)?;
+
+    assert_eq!(turbojpeg::read_header(&jpeg_420.data)?.subsamp, Subsamp::Sub2x2);
+    assert_eq!(turbojpeg::read_header(&jpeg_444.data)?.subsamp, Subsamp::None);
+    assert_ne!(jpeg_420.data.len(), jpeg_444.data.len());
+    Ok(())
+}
+
+#[test]
+fn test_reused_buffer_matches_allocating_path() -> Result<()> {
+    let rgb_frame = ImageRawAny {
+        header: Some(create_test_header()),
+        image: Some(RawImageVariant::Rgb888(ImageRgb888 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: load_test_file("tulips_rgb444_prog_packed_qcif.yuv")?,
+        })),
+    };
+    let yuv_frame = ImageRawAny {
+        header: Some(create_test_header()),
+        image: Some(RawImageVariant::Yuv420(ImageYuv420 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: load_test_file("tulips_yuv420_prog_planar_qcif.yuv")?,
+        })),
+    };
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+
+    let config = ConversionConfig::default();
+    let mut buffer = Vec::new();
+    for frame in [&rgb_frame, &yuv_frame, &rgb_frame] {
+        let expected = rgb_to_jpeg(frame, &mut compressor, &ConversionConfig::default())?;
+        rgb_to_jpeg_into(frame, &mut compressor, &config, &mut buffer)?;
+        assert_eq!(buffer, expected.data);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_progressive_output() -> Result<()> {
+    let image_raw = ImageRawAny {
+        header: Some(create_test_header()),
+        image: Some(RawImageVariant::Yuv420(ImageYuv420 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: load_test_file("tulips_yuv420_prog_planar_qcif.yuv")?,
+        })),
+    };
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+
+    let progressive_config = ConversionConfig {
+        progressive: true,
+        ..Default::default()
+    };
+    let baseline = rgb_to_jpeg(&image_raw, &mut compressor, &ConversionConfig::default())?;
+    let progressive = rgb_to_jpeg_with_config(&image_raw, &mut compressor, &progressive_config)?;
+
+    // SOF0 marks baseline, SOF2 progressive; 0xFF is always byte-stuffed in entropy data
+    let has_marker = |data: &[u8], marker: u8| data.windows(2).any(|w| w == [0xFF, marker]);
+    assert!(has_marker(&baseline.data, 0xC0));
+    assert!(!has_marker(&baseline.data, 0xC2));
+    assert!(has_marker(&progressive.data, 0xC2));
+    assert!(!has_marker(&progressive.data, 0xC0));
+    Ok(())
+}
+
+#[test]
+fn test_restart_markers() -> Result<()> {
+    let image_raw = ImageRawAny {
+        header: Some(create_test_header()),
+        image: Some(RawImageVariant::Yuv420(ImageYuv420 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: load_test_file("tulips_yuv420_prog_planar_qcif.yuv")?,
+        })),
+    };
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+
+    let rst_markers = |data: &[u8]| -> Vec<u8> {
+        data.windows(2)
+            .filter(|w| w[0] == 0xFF && (0xD0..=0xD7).contains(&w[1]))
+            .map(|w| w[1])
+            .collect()
+    };
+    let has_marker = |data: &[u8], marker: u8| data.windows(2).any(|w| w == [0xFF, marker]);
+
+    let plain = rgb_to_jpeg(&image_raw, &mut compressor, &ConversionConfig::default())?;
+    assert!(rst_markers(&plain.data).is_empty());
+
+    // 144 rows of 4:2:0 are 9 MCU rows, so a marker after each of the first 8
+    let every_row = ConversionConfig {
+        restart: Some(RestartInterval::Rows(1)),
+        ..Default::default()
+    };
+    let jpeg = rgb_to_jpeg_with_config(&image_raw, &mut compressor, &every_row)?;
+    assert!(has_marker(&jpeg.data, 0xDD)); // DRI
+    let markers = rst_markers(&jpeg.data);
+    assert_eq!(markers, [0xD0, 0xD1, 0xD2, 0xD3, 0xD4, 0xD5, 0xD6, 0xD7, 0xD0]);
+    verify_roundtrip(&jpeg, TEST_WIDTH as usize, TEST_HEIGHT as usize)?;
+
+    // Combined with progressive, the output must stay progressive
+    let progressive = ConversionConfig {
+        restart: Some(RestartInterval::Mcus(4)),
+        progressive: true,
+        ..Default::default()
+    };
+    let jpeg = rgb_to_jpeg_with_config(&image_raw, &mut compressor, &progressive)?;
+    assert!(has_marker(&jpeg.data, 0xC2));
+    assert!(!rst_markers(&jpeg.data).is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_rgb888_downscale() -> Result<()> {
+    let image_raw = ImageRawAny {
+        header: Some(create_test_header()),
+        image: Some(RawImageVariant::Rgb888(ImageRgb888 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: load_test_file("tulips_rgb444_prog_packed_qcif.yuv")?,
+        })),
+    };
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+
+    let half = ConversionConfig {
+        scale_denom: 2,
+        ..Default::default()
+    };
+    let jpeg = rgb_to_jpeg_with_config(&image_raw, &mut compressor, &half)?;
+
+    let header = turbojpeg::read_header(&jpeg.data)?;
+    assert_eq!((header.width, header.height), (88, 72));
+    Ok(())
+}
+
+#[test]
+fn test_rgb888_roi_crop() -> Result<()> {
+    let image_raw = ImageRawAny {
+        header: Some(create_test_header()),
+        image: Some(RawImageVariant::Rgb888(ImageRgb888 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: load_test_file("tulips_rgb444_prog_packed_qcif.yuv")?,
+        })),
+    };
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+
+    let crop = ConversionConfig {
+        roi: Some(Roi { x: 40, y: 20, width: 64, height: 48 }),
+        ..Default::default()
+    };
+    let jpeg = rgb_to_jpeg_with_config(&image_raw, &mut compressor, &crop)?;
+    let header = turbojpeg::read_header(&jpeg.data)?;
+    assert_eq!((header.width, header.height), (64, 48));
+
+    let out_of_bounds = ConversionConfig {
+        roi: Some(Roi { x: 150, y: 0, width: 64, height: 48 }),
+        ..Default::default()
+    };
+    let err = rgb_to_jpeg_with_config(&image_raw, &mut compressor, &out_of_bounds).unwrap_err();
+    assert_eq!(err.to_string(), "ROI 64x48+150+0 does not fit in 176x144 frame");
+    Ok(())
+}
+
+#[test]
+fn test_rgb888_aspect_crop() -> Result<()> {
+    let image_raw = ImageRawAny {
+        header: Some(create_test_header()),
+        image: Some(RawImageVariant::Rgb888(ImageRgb888 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: load_test_file("tulips_rgb444_prog_packed_qcif.yuv")?,
+        })),
+    };
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+
+    // 176x144 is 11:9, so 16:9 keeps the full width and 176 * 9 / 16 = 99 rows
+    let aspect = ConversionConfig {
+        aspect: Some(parse_aspect_ratio("16:9")?),
+        ..Default::default()
+    };
+    assert_eq!(output_dimensions(&image_raw, &aspect), Some((176, 99)));
+    let jpeg = rgb_to_jpeg_with_config(&image_raw, &mut compressor, &aspect)?;
+    let header = turbojpeg::read_header(&jpeg.data)?;
+    assert_eq!((header.width, header.height), (176, 99));
+
+    // Centered: the same as cropping the middle rows by hand
+    let centered = ConversionConfig {
+        roi: Some(Roi { x: 0, y: 22, width: 176, height: 99 }),
+        ..Default::default()
+    };
+    assert_eq!(jpeg.data, rgb_to_jpeg_with_config(&image_raw, &mut compressor, &centered)?.data);
+
+    // A taller target keeps the height and trims the sides; within a ROI it crops the ROI
+    let portrait = AspectRatio { width: 1, height: 1 };
+    assert_eq!(
+        portrait.center_crop(Roi { x: 0, y: 0, width: 176, height: 144 }),
+        Roi { x: 16, y: 0, width: 144, height: 144 }
+    );
+    let within_roi = ConversionConfig {
+        roi: Some(Roi { x: 40, y: 20, width: 64, height: 48 }),
+        aspect: Some(portrait),
+        ..Default::default()
+    };
+    assert_eq!(output_dimensions(&image_raw, &within_roi), Some((48, 48)));
+
+    assert!(matches!(parse_aspect_ratio("16x9"), Err(ConversionError::InvalidAspectRatio(_))));
+    assert!(matches!(parse_aspect_ratio("16:0"), Err(ConversionError::InvalidAspectRatio(_))));
+    Ok(())
+}
+
+#[test]
+fn test_rgb888_rotation() -> Result<()> {
+    // Red top-left corner on black, so it can be found after rotating
+    let (width, height) = (64usize, 32usize);
+    let mut data = vec![0u8; width * height * 3];
+    for y in 0..8 {
+        for x in 0..8 {
+            data[(y * width + x) * 3] = 255;
+        }
+    }
+    let image_raw = ImageRawAny {
+        header: None,
+        image: Some(RawImageVariant::Rgb888(ImageRgb888 {
+            header: None,
+            width: width as u32,
+            height: height as u32,
+            data,
+        })),
+    };
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+    let rot90 = ConversionConfig {
+        orientation: Orientation::Rot90,
+        ..Default::default()
+    };
+    let jpeg = rgb_to_jpeg_with_config(&image_raw, &mut compressor, &rot90)?;
+    verify_roundtrip(&jpeg, height, width)?;
+    assert_eq!(output_dimensions(&image_raw, &rot90), Some((height, width)));
+
+    // Clockwise: the top-left corner is now top-right
+    let decoded = turbojpeg::decompress(&jpeg.data, PixelFormat::RGB)?;
+    let red_at = |x: usize, y: usize| decoded.pixels[y * decoded.pitch + x * 3];
+    assert!(red_at(height - 4, 4) > 200);
+    assert!(red_at(4, 4) < 50);
+    Ok(())
+}
+
 #[test]
 fn test_yuv420_conversion() -> Result<()> {
     let raw_data = load_test_file("tulips_yuv420_prog_planar_qcif.yuv")?;
+    let reference = Planes::from_yuv420(&raw_data, TEST_WIDTH as usize, TEST_HEIGHT as usize);
 
     let header = create_test_header();
 
@@ -111,12 +892,13 @@ fn test_yuv420_conversion() -> Result<()> {
     let mut compressor = Compressor::new()?;
     compressor.set_quality(JPEG_QUALITY)?;
 
-    let jpeg_result = rgb_to_jpeg(&image_raw, &mut compressor)?;
+    let jpeg_result = rgb_to_jpeg(&image_raw, &mut compressor, &ConversionConfig::default())?;
 
     // Verify JPEG data
     assert!(jpeg_result.data.len() > 2);
     assert_eq!(jpeg_result.data[0], 0xFF);
     assert_eq!(jpeg_result.data[1], 0xD8);
+    assert_ssim_above(&jpeg_result.data, &reference, SSIM_THRESHOLD);
 
     save_output_jpeg(&jpeg_result.data, "test_frame_640x480_yuv420_output.jpg")?;
 
@@ -145,7 +927,7 @@ fn test_yuv422_conversion() -> Result<()> {
     let mut compressor = Compressor::new()?;
     compressor.set_quality(JPEG_QUALITY)?;
 
-    let jpeg_result = rgb_to_jpeg(&image_raw, &mut compressor)?;
+    let jpeg_result = rgb_to_jpeg(&image_raw, &mut compressor, &ConversionConfig::default())?;
 
     // Verify JPEG data
     assert!(jpeg_result.data.len() > 2);
@@ -179,7 +961,7 @@ fn test_yuv444_conversion() -> Result<()> {
     let mut compressor = Compressor::new()?;
     compressor.set_quality(JPEG_QUALITY)?;
 
-    let jpeg_result = rgb_to_jpeg(&image_raw, &mut compressor)?;
+    let jpeg_result = rgb_to_jpeg(&image_raw, &mut compressor, &ConversionConfig::default())?;
 
     // Verify JPEG data
     assert!(jpeg_result.data.len() > 2);
@@ -213,7 +995,7 @@ fn test_nv12_conversion() -> Result<()> {
     let mut compressor = Compressor::new()?;
     compressor.set_quality(JPEG_QUALITY)?;
 
-    let jpeg_result = rgb_to_jpeg(&image_raw, &mut compressor)?;
+    let jpeg_result = rgb_to_jpeg(&image_raw, &mut compressor, &ConversionConfig::default())?;
 
     // Verify JPEG data
     assert!(jpeg_result.data.len() > 2);
@@ -227,112 +1009,840 @@ fn test_nv12_conversion() -> Result<()> {
 }
 
 
-#[cfg(test)]
-mod benchmark_tests {
-    use super::*;
-    use std::time::Instant;
-
-    #[test]
-    #[ignore] // Run with `cargo test benchmark_tests -- --ignored`
-    fn benchmark_conversion_performance() -> Result<()> {
-        const NUM_RUNS: usize = 100;
-
-        // This test requires the input files to exist
-        let formats = [
-            ("rgb888", "tulips_rgb444_prog_packed_qcif.yuv"),
-            ("yuv420", "tulips_yuv420_prog_planar_qcif.yuv"),
-            ("yuv422", "tulips_yuv422_prog_planar_qcif.yuv"),
-            ("yuv444", "tulips_yuv444_prog_planar_qcif.yuv"),
-            ("nv12", "tulips_nv12_prog_qcif.yuv"),
-        ];
-
-        println!("Running {} iterations per format...\n", NUM_RUNS);
-
-        for (format_name, filename) in formats.iter() {
-            if let Ok(raw_data) = load_test_file(filename) {
-                let header = create_test_header();
-
-                // Create appropriate image variant based on format
-                let image_raw = match *format_name {
-                    "rgb888" => ImageRawAny {
-                        header: Some(header.clone()),
-                        image: Some(RawImageVariant::Rgb888(ImageRgb888 {
-                            header: Some(header.clone()),
-                            width: TEST_WIDTH,
-                            height: TEST_HEIGHT,
-                            data: raw_data,
-                        })),
-                    },
-                    "yuv420" => ImageRawAny {
-                        header: Some(header.clone()),
-                        image: Some(RawImageVariant::Yuv420(ImageYuv420 {
-                            header: Some(header.clone()),
-                            width: TEST_WIDTH,
-                            height: TEST_HEIGHT,
-                            data: raw_data,
-                        })),
-                    },
-                    "yuv422" => ImageRawAny {
-                        header: Some(header.clone()),
-                        image: Some(RawImageVariant::Yuv422(ImageYuv422 {
-                            header: Some(header.clone()),
-                            width: TEST_WIDTH,
-                            height: TEST_HEIGHT,
-                            data: raw_data,
-                        })),
-                    },
-                    "yuv444" => ImageRawAny {
-                        header: Some(header.clone()),
-                        image: Some(RawImageVariant::Yuv444(ImageYuv444 {
-                            header: Some(header.clone()),
-                            width: TEST_WIDTH,
-                            height: TEST_HEIGHT,
-                            data: raw_data,
-                        })),
-                    },
-                    "nv12" => ImageRawAny {
-                        header: Some(header.clone()),
-                        image: Some(RawImageVariant::Nv12(ImageNv12 {
-                            header: Some(header.clone()),
-                            width: TEST_WIDTH,
-                            height: TEST_HEIGHT,
-                            data: raw_data,
-                        })),
-                    },
-                    _ => continue, // Skip other formats for this benchmark
-                };
-
-                let mut total_duration = std::time::Duration::ZERO;
-                let mut min_duration = std::time::Duration::MAX;
-                let mut max_duration = std::time::Duration::ZERO;
-
-                // Run NUM_RUNS iterations
-                for _ in 0..NUM_RUNS {
-                    let mut compressor = Compressor::new()?;
-                    compressor.set_quality(JPEG_QUALITY)?;
-
-                    let start = Instant::now();
-                    let _result = rgb_to_jpeg(&image_raw, &mut compressor)?;
-                    let duration = start.elapsed();
-
-                    total_duration += duration;
-                    min_duration = min_duration.min(duration);
-                    max_duration = max_duration.max(duration);
-                }
-
-                let avg_duration = total_duration / NUM_RUNS as u32;
-
-                println!("{} format:", format_name.to_uppercase());
-                println!("  Average: {:?}", avg_duration);
-                println!("  Min:     {:?}", min_duration);
-                println!("  Max:     {:?}", max_duration);
-                println!("  Total:   {:?}", total_duration);
-                println!();
-            } else {
-                println!("Skipping {} format - file {} not found", format_name, filename);
-            }
-        }
+#[test]
+fn test_nv21_conversion() -> Result<()> {
+    let nv12_data = load_test_file("tulips_nv12_prog_qcif.yuv")?;
+    let width = TEST_WIDTH as usize;
+    let height = TEST_HEIGHT as usize;
+    let y_size = width * height;
+
+    // NV21 is NV12 with each chroma byte pair swapped
+    let mut nv21_data = nv12_data.clone();
+    for pair in nv21_data[y_size..y_size + y_size / 2].chunks_exact_mut(2) {
+        pair.swap(0, 1);
+    }
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+
+    let nv12_jpeg = semi_planar_to_jpeg(&nv12_data, width, height, ChromaOrder::Uv, &mut compressor)?;
+    let nv21_jpeg = semi_planar_to_jpeg(&nv21_data, width, height, ChromaOrder::Vu, &mut compressor)?;
+
+    assert_eq!(nv21_jpeg[0], 0xFF);
+    assert_eq!(nv21_jpeg[1], 0xD8);
+    assert_eq!(nv21_jpeg, nv12_jpeg);
+
+    let err = semi_planar_to_jpeg(&nv21_data[..y_size], width, height, ChromaOrder::Vu, &mut compressor).unwrap_err();
+    assert!(err.to_string().starts_with("NV21 data too small"));
+    Ok(())
+}
+
+#[test]
+fn test_rgb888_padded_stride() -> Result<()> {
+    let rgb_data = load_test_file("tulips_rgb444_prog_packed_qcif.yuv")?;
+    let width = TEST_WIDTH as usize;
+    let height = TEST_HEIGHT as usize;
+    let row_len = width * 3;
+    let stride = 576; // 528 aligned up to 64
+
+    // Pad every row out to the stride with garbage that must not leak into the image
+    let padded: Vec<u8> = rgb_data[..row_len * height]
+        .chunks_exact(row_len)
+        .flat_map(|row| row.iter().copied().chain(std::iter::repeat_n(0xAB, stride - row_len)))
+        .collect();
+    let frame = |data: Vec<u8>| ImageRawAny {
+        header: Some(create_test_header()),
+        image: Some(RawImageVariant::Rgb888(ImageRgb888 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data,
+        })),
+    };
+    let strided = ConversionConfig {
+        rgb_stride: Some(stride),
+        ..Default::default()
+    };
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+    let tight_jpeg = rgb_to_jpeg(&frame(rgb_data.clone()), &mut compressor, &ConversionConfig::default())?;
+    let padded_jpeg = rgb_to_jpeg_with_config(&frame(padded.clone()), &mut compressor, &strided)?;
+    assert_eq!(padded_jpeg.data, tight_jpeg.data);
+
+    // Steps that copy pixels out skip the padding as well
+    let cropped = ConversionConfig {
+        roi: Some(Roi { x: 16, y: 8, width: 64, height: 48 }),
+        ..Default::default()
+    };
+    let gray = ConversionConfig {
+        force_grayscale: true,
+        ..Default::default()
+    };
+    for config in [cropped, gray] {
+        let tight = rgb_to_jpeg_with_config(&frame(rgb_data.clone()), &mut compressor, &config)?;
+        let config = ConversionConfig {
+            rgb_stride: Some(stride),
+            ..config
+        };
+        assert_eq!(rgb_to_jpeg_with_config(&frame(padded.clone()), &mut compressor, &config)?.data, tight.data);
+    }
+
+    // The buffer has to cover every padded row
+    let short = padded[..stride * height - 1].to_vec();
+    let err = rgb_to_jpeg_with_config(&frame(short), &mut compressor, &strided).unwrap_err();
+    assert!(err.to_string().starts_with("RGB888 data too small"));
+
+    let narrow = ConversionConfig {
+        rgb_stride: Some(row_len - 1),
+        ..Default::default()
+    };
+    let err = rgb_to_jpeg_with_config(&frame(padded), &mut compressor, &narrow).unwrap_err();
+    assert_eq!(err.to_string(), format!("RGB888 stride {} is smaller than the width {}", row_len - 1, row_len));
+
+    save_output_jpeg(&padded_jpeg.data, "test_rgb888_strided_output.jpg")?;
+    Ok(())
+}
+
+#[test]
+fn test_nv12_padded_stride() -> Result<()> {
+    let nv12_data = load_test_file("tulips_nv12_prog_qcif.yuv")?;
+    let width = TEST_WIDTH as usize;
+    let height = TEST_HEIGHT as usize;
+    let stride = 192; // 176 aligned up to 64
+
+    // Pad every Y and UV row out to the stride with garbage that must not leak into the image
+    let padded: Vec<u8> = nv12_data[..width * height * 3 / 2]
+        .chunks_exact(width)
+        .flat_map(|row| row.iter().copied().chain(std::iter::repeat_n(0xAB, stride - width)))
+        .collect();
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+    let tight_jpeg = semi_planar_to_jpeg(&nv12_data, width, height, ChromaOrder::Uv, &mut compressor)?;
+    let padded_jpeg = semi_planar_to_jpeg_strided(&padded, width, height, stride, ChromaOrder::Uv, &mut compressor)?;
+    assert_eq!(padded_jpeg, tight_jpeg);
+
+    // Same through the message path, with the trailing padding of the last row cut off
+    let image_raw = ImageRawAny {
+        header: Some(create_test_header()),
+        image: Some(RawImageVariant::Nv12(ImageNv12 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: padded[..padded.len() - (stride - width)].to_vec(),
+        })),
+    };
+    let config = ConversionConfig {
+        nv12_stride: Some(stride),
+        ..Default::default()
+    };
+    let jpeg = rgb_to_jpeg_with_config(&image_raw, &mut compressor, &config)?;
+    assert_eq!(jpeg.data, tight_jpeg);
+
+    let short = &padded[..stride * height];
+    let err = semi_planar_to_jpeg_strided(short, width, height, stride, ChromaOrder::Uv, &mut compressor).unwrap_err();
+    assert!(err.to_string().starts_with("NV12 data too small"));
+
+    save_output_jpeg(&jpeg.data, "test_frame_640x480_nv12_strided_output.jpg")?;
+    Ok(())
+}
+
+#[test]
+fn test_nv12_to_i420() -> Result<()> {
+    #[rustfmt::skip]
+    let nv12 = [
+        // Y, 4x4
+        0, 1, 2, 3,
+        4, 5, 6, 7,
+        8, 9, 10, 11,
+        12, 13, 14, 15,
+        // Interleaved U V, 2x2 pairs
+        100, 200, 101, 201,
+        102, 202, 103, 203,
+    ];
+    #[rustfmt::skip]
+    let i420 = vec![
+        0, 1, 2, 3,
+        4, 5, 6, 7,
+        8, 9, 10, 11,
+        12, 13, 14, 15,
+        // U, then V
+        100, 101, 102, 103,
+        200, 201, 202, 203,
+    ];
+    assert_eq!(nv12_to_i420(&nv12, 4, 4, 4)?, i420);
+
+    // Rows padded to a stride of 6, without the padding of the last row
+    let padded: Vec<u8> = nv12
+        .chunks_exact(4)
+        .flat_map(|row| row.iter().copied().chain([0xAB, 0xAB]))
+        .collect();
+    assert_eq!(nv12_to_i420(&padded[..padded.len() - 2], 4, 4, 6)?, i420);
+
+    let err = nv12_to_i420(&nv12, 3, 4, 4).unwrap_err();
+    assert!(matches!(err, ConversionError::InvalidDimensions { format: "NV12", .. }));
+    let err = nv12_to_i420(&nv12[..20], 4, 4, 4).unwrap_err();
+    assert!(matches!(err, ConversionError::BufferTooSmall { format: "NV12", expected: 24, got: 20 }));
+    Ok(())
+}
+
+#[test]
+fn test_conversion_stats() -> Result<()> {
+    let raw_data = load_test_file("tulips_yuv420_prog_planar_qcif.yuv")?;
+    let image_raw = ImageRawAny {
+        header: Some(create_test_header()),
+        image: Some(RawImageVariant::Yuv420(ImageYuv420 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: raw_data.clone(),
+        })),
+    };
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+
+    let (jpeg, stats) = rgb_to_jpeg_with_stats(&image_raw, &mut compressor, &ConversionConfig::default())?;
+    assert_eq!(stats.input_bytes, raw_data.len());
+    assert_eq!(stats.output_bytes, jpeg.data.len());
+    assert_eq!(stats.ratio, raw_data.len() as f64 / jpeg.data.len() as f64);
+    assert!(stats.ratio > 1.0);
+    assert_eq!(stats.subsamp_used, Subsamp::Sub2x2);
+    Ok(())
+}
+
+#[test]
+fn test_convert_batch_keeps_going_after_failures() -> Result<()> {
+    let rgb_data = load_test_file("tulips_rgb444_prog_packed_qcif.yuv")?;
+    let rgb_frame = |data: Vec<u8>| ImageRawAny {
+        header: Some(create_test_header()),
+        image: Some(RawImageVariant::Rgb888(ImageRgb888 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data,
+        })),
+    };
+    let frames = vec![
+        rgb_frame(rgb_data.clone()),
+        rgb_frame(rgb_data[..100].to_vec()),
+        ImageRawAny {
+            header: Some(create_test_header()),
+            image: None,
+        },
+        rgb_frame(rgb_data.clone()),
+    ];
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+    let results = convert_batch(&frames, &mut compressor);
+
+    let pattern: Vec<bool> = results.iter().map(|result| result.is_ok()).collect();
+    assert_eq!(pattern, [true, false, false, true]);
+    assert_eq!(results[0].as_ref().unwrap().data, results[3].as_ref().unwrap().data);
+    assert_eq!(results[0].as_ref().unwrap().header, frames[0].header);
+    Ok(())
+}
+
+#[test]
+fn test_verify_roundtrip() -> Result<()> {
+    let image_raw = ImageRawAny {
+        header: Some(create_test_header()),
+        image: Some(RawImageVariant::Rgb888(ImageRgb888 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: load_test_file("tulips_rgb444_prog_packed_qcif.yuv")?,
+        })),
+    };
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+
+    let mut jpeg = rgb_to_jpeg(&image_raw, &mut compressor, &ConversionConfig::default())?;
+    verify_roundtrip(&jpeg, TEST_WIDTH as usize, TEST_HEIGHT as usize)?;
+    assert!(verify_roundtrip(&jpeg, TEST_WIDTH as usize / 2, TEST_HEIGHT as usize).is_err());
+
+    // Wipe the SOI and frame header so the JPEG no longer parses
+    jpeg.data[..64].fill(0);
+    assert!(verify_roundtrip(&jpeg, TEST_WIDTH as usize, TEST_HEIGHT as usize).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_set_quality_clamped() -> Result<()> {
+    let mut compressor = Compressor::new()?;
+    assert_eq!(set_quality_clamped(&mut compressor, 75)?, 75);
+    assert_eq!(set_quality_clamped(&mut compressor, -5)?, 1);
+    assert_eq!(set_quality_clamped(&mut compressor, 0)?, 1);
+    assert_eq!(set_quality_clamped(&mut compressor, 250)?, 100);
+
+    // Applied quality must actually change the output
+    let image_raw = ImageRawAny {
+        header: Some(create_test_header()),
+        image: Some(RawImageVariant::Yuv420(ImageYuv420 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: load_test_file("tulips_yuv420_prog_planar_qcif.yuv")?,
+        })),
+    };
+    set_quality_clamped(&mut compressor, 20)?;
+    let low = rgb_to_jpeg(&image_raw, &mut compressor, &ConversionConfig::default())?;
+    set_quality_clamped(&mut compressor, 1000)?;
+    let high = rgb_to_jpeg(&image_raw, &mut compressor, &ConversionConfig::default())?;
+    assert!(low.data.len() < high.data.len());
+    Ok(())
+}
+
+#[test]
+fn test_compress_to_target_size() -> Result<()> {
+    let image_raw = ImageRawAny {
+        header: Some(create_test_header()),
+        image: Some(RawImageVariant::Rgb888(ImageRgb888 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: load_test_file("tulips_rgb444_prog_packed_qcif.yuv")?,
+        })),
+    };
+    let (min_quality, max_quality) = TARGET_SIZE_QUALITY_RANGE;
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(min_quality as i32)?;
+    let smallest = rgb_to_jpeg(&image_raw, &mut compressor, &ConversionConfig::default())?.data.len();
+    compressor.set_quality(max_quality as i32)?;
+    let largest = rgb_to_jpeg(&image_raw, &mut compressor, &ConversionConfig::default())?.data.len();
+    assert!(smallest < largest);
+
+    // A budget between the extremes lands on an intermediate quality that fits
+    let budget = (smallest + largest) / 2;
+    let (quality, jpeg) = compress_to_target_size(&image_raw, budget, &mut compressor)?;
+    assert!(jpeg.data.len() <= budget, "{} > {}", jpeg.data.len(), budget);
+    assert!((min_quality..max_quality).contains(&quality));
+    assert_eq!(jpeg.header, image_raw.header);
+    verify_roundtrip(&jpeg, TEST_WIDTH as usize, TEST_HEIGHT as usize)?;
+
+    // A generous budget allows the top of the range
+    let (quality, _) = compress_to_target_size(&image_raw, largest, &mut compressor)?;
+    assert!(quality > 80);
+
+    // Nothing fits: falls back to the lowest quality
+    let (quality, jpeg) = compress_to_target_size(&image_raw, 100, &mut compressor)?;
+    assert_eq!(quality, min_quality);
+    assert_eq!(jpeg.data.len(), smallest);
+    Ok(())
+}
+
+#[test]
+fn test_make_thumbnail() -> Result<()> {
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+    let header = create_test_header();
+
+    // 176x144 QCIF: 160 wide keeps the 11:9 aspect ratio, 131 rows rounded
+    let rgb = ImageRawAny {
+        header: Some(header.clone()),
+        image: Some(RawImageVariant::Rgb888(ImageRgb888 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: load_test_file("tulips_rgb444_prog_packed_qcif.yuv")?,
+        })),
+    };
+    let yuv = ImageRawAny {
+        header: Some(header.clone()),
+        image: Some(RawImageVariant::Yuv420(ImageYuv420 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: load_test_file("tulips_yuv420_prog_planar_qcif.yuv")?,
+        })),
+    };
+    for frame in [&rgb, &yuv] {
+        let thumbnail = make_thumbnail(frame, DEFAULT_THUMBNAIL_WIDTH, &mut compressor, &ConversionConfig::default())?;
+        assert_eq!(thumbnail.header, Some(header.clone()));
+        let decoded = turbojpeg::read_header(&thumbnail.data)?;
+        assert_eq!((decoded.width, decoded.height), (160, 131));
+        assert_eq!(thumbnail_size(176, 144, 160), (160, 131));
+    }
+
+    // Sized from the cropped and rotated frame for packed input
+    let config = ConversionConfig {
+        roi: Some(Roi { x: 0, y: 0, width: 160, height: 80 }),
+        orientation: Orientation::Rot90,
+        ..Default::default()
+    };
+    let thumbnail = make_thumbnail(&rgb, 40, &mut compressor, &config)?;
+    let decoded = turbojpeg::read_header(&thumbnail.data)?;
+    assert_eq!((decoded.width, decoded.height), (40, 80));
+
+    // Never enlarged
+    assert_eq!(thumbnail_size(100, 50, 160), (100, 50));
+    assert_eq!(thumbnail_size(4000, 10, 160), (160, 1));
+    Ok(())
+}
 
-        Ok(())
+#[test]
+fn test_make_error_frame() -> Result<()> {
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+    let jpeg = make_error_frame(320, 240, &mut compressor)?;
+
+    assert_eq!(jpeg[..2], [0xFF, 0xD8]);
+    let decoded = turbojpeg::decompress(&jpeg, PixelFormat::RGB)?;
+    assert_eq!((decoded.width, decoded.height), (320, 240));
+    let center = &decoded.pixels[120 * decoded.pitch + 160 * 3..][..3];
+    for (channel, expected) in center.iter().zip(ERROR_FRAME_COLOR) {
+        assert!(channel.abs_diff(expected) <= 4, "{center:?}");
     }
+    Ok(())
+}
+
+#[test]
+fn test_warmup() -> Result<()> {
+    let mut compressor = Compressor::new()?;
+    warmup(&mut compressor)?;
+
+    // Extreme settings must not trip it up either
+    compressor.set_quality(1)?;
+    compressor.set_subsamp(Subsamp::Gray)?;
+    warmup(&mut compressor)?;
+
+    // The compressor's settings survive, so its output matches an unwarmed one
+    let mut fresh = Compressor::new()?;
+    fresh.set_quality(1)?;
+    fresh.set_subsamp(Subsamp::Gray)?;
+    let frame = vec![0u8; 16 * 16 * 3];
+    assert_eq!(
+        compress_raw(&frame, 16, 16, RawPixelFormat::Rgb888, &mut compressor)?,
+        compress_raw(&frame, 16, 16, RawPixelFormat::Rgb888, &mut fresh)?
+    );
+    Ok(())
+}
+
+#[test]
+fn test_yuyv_conversion() -> Result<()> {
+    let width = TEST_WIDTH as usize;
+    let height = TEST_HEIGHT as usize;
+
+    // Horizontal luma ramp with constant, slightly blue chroma
+    let yuyv: Vec<u8> = (0..height)
+        .flat_map(|_| (0..width / 2).flat_map(|x| [(x * 2) as u8, 160, (x * 2 + 1) as u8, 110]))
+        .collect();
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+
+    let jpeg = yuyv_to_jpeg(&yuyv, width, height, &mut compressor)?;
+    assert_eq!(jpeg[0], 0xFF);
+    assert_eq!(jpeg[1], 0xD8);
+
+    let header = turbojpeg::read_header(&jpeg)?;
+    assert_eq!((header.width, header.height), (width, height));
+    assert_eq!(header.subsamp, Subsamp::Sub2x1);
+
+    let err = yuyv_to_jpeg(&yuyv[1..], width, height, &mut compressor).unwrap_err();
+    assert!(err.to_string().starts_with("YUYV data too small"));
+    Ok(())
+}
+
+#[test]
+fn test_truncated_buffers_rejected() -> Result<()> {
+    let pixels = (TEST_WIDTH * TEST_HEIGHT) as usize;
+    let truncated = |len: usize| vec![0u8; len - 1];
+
+    let cases = [
+        ("RGB888", pixels * 3, RawImageVariant::Rgb888(ImageRgb888 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: truncated(pixels * 3),
+        })),
+        ("RGBA8888", pixels * 4, RawImageVariant::Rgba8888(ImageRgba8888 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: truncated(pixels * 4),
+        })),
+        ("YUV420", pixels * 3 / 2, RawImageVariant::Yuv420(ImageYuv420 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: truncated(pixels * 3 / 2),
+        })),
+        ("YUV422", pixels * 2, RawImageVariant::Yuv422(ImageYuv422 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: truncated(pixels * 2),
+        })),
+        ("YUV444", pixels * 3, RawImageVariant::Yuv444(ImageYuv444 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: truncated(pixels * 3),
+        })),
+    ];
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+
+    for (name, expected_len, image) in cases {
+        let image_raw = ImageRawAny {
+            header: Some(create_test_header()),
+            image: Some(image),
+        };
+        let err = rgb_to_jpeg(&image_raw, &mut compressor, &ConversionConfig::default()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("{name} data too small: expected {expected_len}, got {}", expected_len - 1)
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_odd_dimensions_rejected() -> Result<()> {
+    let odd_width = TEST_WIDTH - 1;
+    let mut compressor = Compressor::new()?;
+
+    let yuv420 = ImageRawAny {
+        header: Some(create_test_header()),
+        image: Some(RawImageVariant::Yuv420(ImageYuv420 {
+            header: None,
+            width: odd_width,
+            height: TEST_HEIGHT,
+            data: vec![0u8; (TEST_WIDTH * TEST_HEIGHT * 3 / 2) as usize],
+        })),
+    };
+    let err = rgb_to_jpeg(&yuv420, &mut compressor, &ConversionConfig::default()).unwrap_err();
+    assert_eq!(err.to_string(), "YUV420 requires even width and height, got 175x144");
+
+    let yuv422 = ImageRawAny {
+        header: Some(create_test_header()),
+        image: Some(RawImageVariant::Yuv422(ImageYuv422 {
+            header: None,
+            width: odd_width,
+            height: TEST_HEIGHT,
+            data: vec![0u8; (TEST_WIDTH * TEST_HEIGHT * 2) as usize],
+        })),
+    };
+    let err = rgb_to_jpeg(&yuv422, &mut compressor, &ConversionConfig::default()).unwrap_err();
+    assert_eq!(err.to_string(), "YUV422 requires an even width, got 175x144");
+
+    // 4:2:2 only subsamples horizontally, so an odd height is fine
+    let yuv422_odd_height = ImageRawAny {
+        header: Some(create_test_header()),
+        image: Some(RawImageVariant::Yuv422(ImageYuv422 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT - 1,
+            data: vec![0u8; (TEST_WIDTH * TEST_HEIGHT * 2) as usize],
+        })),
+    };
+    assert!(rgb_to_jpeg(&yuv422_odd_height, &mut compressor, &ConversionConfig::default()).is_ok());
+    Ok(())
+}
+
+#[test]
+fn test_rgba_composite_over_background() -> Result<()> {
+    let (width, height) = (32usize, 32usize);
+    let half_red = ImageRawAny {
+        header: None,
+        image: Some(RawImageVariant::Rgba8888(ImageRgba8888 {
+            header: None,
+            width: width as u32,
+            height: height as u32,
+            data: [255, 0, 0, 128].repeat(width * height),
+        })),
+    };
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+    let center = |jpeg: &[u8]| -> Result<Vec<u8>> {
+        let decoded = turbojpeg::decompress(jpeg, PixelFormat::RGB)?;
+        let offset = (height / 2) * decoded.pitch + (width / 2) * 3;
+        Ok(decoded.pixels[offset..offset + 3].to_vec())
+    };
+
+    // Ignoring alpha keeps the pure red
+    let ignored = rgb_to_jpeg(&half_red, &mut compressor, &ConversionConfig::default())?;
+    let [r, g, b] = center(&ignored.data)?[..] else { unreachable!() };
+    assert!(r > 240 && g < 15 && b < 15);
+
+    // Over white, 50% red is pink
+    let config = ConversionConfig {
+        alpha_mode: parse_alpha_mode("composite_over", [255, 255, 255], false)?,
+        ..Default::default()
+    };
+    let composited = rgb_to_jpeg_with_config(&half_red, &mut compressor, &config)?;
+    let [r, g, b] = center(&composited.data)?[..] else { unreachable!() };
+    assert!(r > 240, "red {r}");
+    assert!((120..=135).contains(&g) && (120..=135).contains(&b), "green {g}, blue {b}");
+
+    assert_eq!(parse_alpha_mode("ignore", [0, 0, 0], false)?, AlphaMode::Ignore);
+    let err = parse_alpha_mode("blend", [0, 0, 0], false).unwrap_err();
+    assert!(matches!(err, ConversionError::UnsupportedAlphaMode(ref name) if name == "blend"));
+    Ok(())
+}
+
+#[test]
+fn test_passthrough_jpeg() -> Result<()> {
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+    let jpeg = packed_to_jpeg(&[90u8; 16 * 16 * 3], 16, 16, PixelFormat::RGB, &mut compressor)?;
+
+    // A JPEG mislabelled as a raw RGB frame
+    let mislabelled = ImageRawAny {
+        header: Some(create_test_header()),
+        image: Some(RawImageVariant::Rgb888(ImageRgb888 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: jpeg.clone(),
+        })),
+    };
+    let err = rgb_to_jpeg(&mislabelled, &mut compressor, &ConversionConfig::default()).unwrap_err();
+    assert!(matches!(err, ConversionError::BufferTooSmall { .. }));
+
+    let passthrough = ConversionConfig {
+        passthrough_jpeg: true,
+        comment: Some("not applied".to_string()),
+        ..Default::default()
+    };
+    let output = rgb_to_jpeg_with_config(&mislabelled, &mut compressor, &passthrough)?;
+    assert_eq!(output.data, jpeg);
+    assert_eq!(output.header, Some(create_test_header()));
+
+    // Raw frames that fit their layout are still converted, even if they happen to start with SOI
+    let mut raw = vec![128u8; (TEST_WIDTH * TEST_HEIGHT * 3) as usize];
+    raw[..2].copy_from_slice(&[0xFF, 0xD8]);
+    let raw_frame = ImageRawAny {
+        header: None,
+        image: Some(RawImageVariant::Rgb888(ImageRgb888 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: raw.clone(),
+        })),
+    };
+    let converted = rgb_to_jpeg_with_config(&raw_frame, &mut compressor, &passthrough)?;
+    assert_ne!(converted.data, raw);
+    verify_roundtrip(&converted, TEST_WIDTH as usize, TEST_HEIGHT as usize)?;
+    Ok(())
+}
+
+#[test]
+fn test_yuv420_planes_at_offsets() -> Result<()> {
+    let (width, height) = (64usize, 48usize);
+    let (chroma_width, chroma_height) = (width / 2, height / 2);
+    let y: Vec<u8> = (0..width * height).map(|i| (i % 251) as u8).collect();
+    let u: Vec<u8> = (0..chroma_width * chroma_height).map(|i| (64 + i % 64) as u8).collect();
+    let v: Vec<u8> = (0..chroma_width * chroma_height).map(|i| (192 - i % 64) as u8).collect();
+    let tight = [y.as_slice(), &u, &v].concat();
+
+    // Rows padded to 16 extra bytes, and 100 bytes of padding before each chroma plane
+    let (y_stride, chroma_stride) = (width + 16, chroma_width + 16);
+    let mut padded = Vec::new();
+    let mut push_plane = |plane: &[u8], row_len: usize, stride: usize| {
+        padded.extend_from_slice(&[0xAA; 100]);
+        let offset = padded.len();
+        for row in plane.chunks_exact(row_len) {
+            padded.extend_from_slice(row);
+            padded.extend(std::iter::repeat_n(0xAA, stride - row_len));
+        }
+        Plane { offset, stride }
+    };
+    let planes = Yuv420Planes {
+        y: push_plane(&y, width, y_stride),
+        u: push_plane(&u, chroma_width, chroma_stride),
+        v: push_plane(&v, chroma_width, chroma_stride),
+    };
+
+    let frame = |data: Vec<u8>| ImageRawAny {
+        header: None,
+        image: Some(RawImageVariant::Yuv420(ImageYuv420 {
+            header: None,
+            width: width as u32,
+            height: height as u32,
+            data,
+        })),
+    };
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+    let expected = rgb_to_jpeg(&frame(tight), &mut compressor, &ConversionConfig::default())?;
+    let config = ConversionConfig {
+        yuv420_planes: Some(planes),
+        ..Default::default()
+    };
+    let repacked = rgb_to_jpeg_with_config(&frame(padded.clone()), &mut compressor, &config)?;
+    assert_eq!(repacked.data, expected.data);
+
+    // A V plane running past the end of the buffer
+    let short = ConversionConfig {
+        yuv420_planes: Some(Yuv420Planes {
+            v: Plane {
+                offset: padded.len() - 10,
+                stride: chroma_stride,
+            },
+            ..planes
+        }),
+        ..Default::default()
+    };
+    let err = rgb_to_jpeg_with_config(&frame(padded), &mut compressor, &short).unwrap_err();
+    assert!(matches!(err, ConversionError::BufferTooSmall { format: "YUV420 V plane", .. }));
+    Ok(())
+}
+
+#[test]
+fn test_auto_subsamp_thresholds() {
+    assert_eq!(auto_subsamp(0), Subsamp::Sub2x2);
+    assert_eq!(auto_subsamp(50), Subsamp::Sub2x2);
+    assert_eq!(auto_subsamp(74), Subsamp::Sub2x2);
+    assert_eq!(auto_subsamp(75), Subsamp::Sub2x1);
+    assert_eq!(auto_subsamp(89), Subsamp::Sub2x1);
+    assert_eq!(auto_subsamp(90), Subsamp::None);
+    assert_eq!(auto_subsamp(100), Subsamp::None);
+}
+
+#[test]
+fn test_gamma_brightens_mid_gray() -> Result<()> {
+    let (width, height) = (32usize, 32usize);
+    let mid_gray = ImageRawAny {
+        header: None,
+        image: Some(RawImageVariant::Rgb888(ImageRgb888 {
+            header: None,
+            width: width as u32,
+            height: height as u32,
+            data: vec![128; width * height * 3],
+        })),
+    };
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+    let mut center_with_gamma = |gamma: f64| -> Result<u8> {
+        let config = ConversionConfig {
+            gamma: Some(GammaLut::new(gamma)),
+            ..Default::default()
+        };
+        let jpeg = rgb_to_jpeg_with_config(&mid_gray, &mut compressor, &config)?;
+        let decoded = turbojpeg::decompress(&jpeg.data, PixelFormat::GRAY)?;
+        Ok(decoded.pixels[(height / 2) * decoded.pitch + width / 2])
+    };
+
+    let linear = center_with_gamma(1.0)?;
+    let corrected = center_with_gamma(2.2)?;
+    assert!(linear.abs_diff(128) <= 2, "gamma 1.0 gave {linear}");
+    // 255 * (128 / 255)^(1 / 2.2) = 186
+    assert!(corrected.abs_diff(186) <= 2, "gamma 2.2 gave {corrected}");
+    Ok(())
+}
+
+/// Variance of the differences between horizontal neighbours: low for smooth gradients, high
+/// where flat runs alternate with steps.
+fn neighbour_difference_variance(plane: &[u8], width: usize) -> f64 {
+    let diffs: Vec<f64> = plane
+        .chunks_exact(width)
+        .flat_map(|row| row.windows(2).map(|pair| pair[1] as f64 - pair[0] as f64))
+        .collect();
+    let mean = diffs.iter().sum::<f64>() / diffs.len() as f64;
+    diffs.iter().map(|d| (d - mean).powi(2)).sum::<f64>() / diffs.len() as f64
+}
+
+#[test]
+fn test_chroma_upsample_bilinear_is_smoother() -> Result<()> {
+    // 8x4 chroma plane ramping left to right, as 4:2:0 chroma of a 16x8 frame
+    let (width, height) = (8usize, 4usize);
+    let chroma: Vec<u8> = (0..height).flat_map(|_| (0..width).map(|x| (x * 32) as u8)).collect();
+
+    let nearest = upsample_chroma(&chroma, width, height, width * 2, height * 2, ChromaUpsample::Nearest);
+    let bilinear = upsample_chroma(&chroma, width, height, width * 2, height * 2, ChromaUpsample::Bilinear);
+    assert_eq!(nearest.len(), width * height * 4);
+    assert_eq!(bilinear.len(), width * height * 4);
+    // Nearest repeats every sample twice; bilinear stays on the original samples' range
+    assert_eq!(&nearest[..4], &[0, 0, 32, 32]);
+    assert_eq!(bilinear.iter().max(), chroma.iter().max());
+
+    let nearest_variance = neighbour_difference_variance(&nearest, width * 2);
+    let bilinear_variance = neighbour_difference_variance(&bilinear, width * 2);
+    assert!(
+        bilinear_variance < nearest_variance / 4.0,
+        "bilinear {bilinear_variance} vs nearest {nearest_variance}"
+    );
+
+    // Subsampled YUV input encodes as 4:4:4 when an upsampler is set
+    let yuv420 = ImageRawAny {
+        header: None,
+        image: Some(RawImageVariant::Yuv420(ImageYuv420 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: load_test_file("tulips_yuv420_prog_planar_qcif.yuv")?,
+        })),
+    };
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+    let native = rgb_to_jpeg_with_config(&yuv420, &mut compressor, &ConversionConfig::default())?;
+    assert_eq!(turbojpeg::read_header(&native.data)?.subsamp, Subsamp::Sub2x2);
+    let config = ConversionConfig {
+        chroma_upsample: Some(ChromaUpsample::Bilinear),
+        ..Default::default()
+    };
+    let upsampled = rgb_to_jpeg_with_config(&yuv420, &mut compressor, &config)?;
+    let header = turbojpeg::read_header(&upsampled.data)?;
+    assert_eq!(header.subsamp, Subsamp::None);
+    assert_eq!((header.width, header.height), (TEST_WIDTH as usize, TEST_HEIGHT as usize));
+    assert_ssim_above(&upsampled.data, &Planes::from_jpeg(&native.data), SSIM_THRESHOLD);
+    Ok(())
+}
+
+#[test]
+fn test_config_builder() -> Result<()> {
+    // The builder's defaults are ConversionConfig's defaults, which leave the compressor alone
+    let defaults = ConversionConfig::builder().build();
+    assert_eq!(defaults.quality, None);
+    assert_eq!(defaults.subsamp, None);
+    assert!(!defaults.progressive && !defaults.optimize_huffman && !defaults.force_grayscale);
+    assert_eq!(defaults.orientation, Orientation::None);
+
+    let config = ConversionConfig::builder()
+        .quality(60)
+        .subsamp(Subsamp::None)
+        .progressive(true)
+        .optimize_huffman(true)
+        .transform(Orientation::Rot90)
+        .force_grayscale(false)
+        .build();
+    assert_eq!(config.quality, Some(60));
+    assert_eq!(config.subsamp, Some(Subsamp::None));
+    assert!(config.progressive && config.optimize_huffman && !config.force_grayscale);
+    assert_eq!(ConversionConfig::builder().quality(150).build().quality, Some(100));
+    assert_eq!(ConversionConfig::builder().quality(0).build().quality, Some(1));
+
+    let image_raw = ImageRawAny {
+        header: Some(create_test_header()),
+        image: Some(RawImageVariant::Rgb888(ImageRgb888 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: load_test_file("tulips_rgb444_prog_packed_qcif.yuv")?,
+        })),
+    };
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+    let jpeg = rgb_to_jpeg(&image_raw, &mut compressor, &config)?;
+    let header = turbojpeg::read_header(&jpeg.data)?;
+    assert_eq!((header.width, header.height), (TEST_HEIGHT as usize, TEST_WIDTH as usize));
+    assert_eq!(header.subsamp, Subsamp::None);
+
+    // rgb_to_jpeg sets the compressor to the configured quality
+    let mut reference = Compressor::new()?;
+    reference.set_quality(60)?;
+    assert_eq!(jpeg.data, rgb_to_jpeg_with_config(&image_raw, &mut reference, &config)?.data);
+    // and without one keeps the compressor's own
+    let mut low = Compressor::new()?;
+    low.set_quality(10)?;
+    let unset = rgb_to_jpeg(&image_raw, &mut low, &ConversionConfig::default())?;
+    assert!(unset.data.len() < jpeg.data.len());
+    Ok(())
 }
@@ -0,0 +1,69 @@
+use make87_messages::core::Header;
+use make87_messages::image::compressed::ImageJpeg;
+use make87_messages::image::uncompressed::image_raw_any::Image as RawImageVariant;
+use make87_messages::image::uncompressed::{ImageRawAny, ImageYuv420};
+use raw_to_jpeg::debug::{debug_frame, fnv1a_64, SourceReference};
+use raw_to_jpeg::RawPixelFormat;
+
+fn yuv420_frame(data: Vec<u8>) -> ImageRawAny {
+    ImageRawAny {
+        header: None,
+        image: Some(RawImageVariant::Yuv420(ImageYuv420 {
+            header: None,
+            width: 4,
+            height: 2,
+            data,
+        })),
+    }
+}
+
+#[test]
+fn test_fnv1a_reference_values() {
+    assert_eq!(fnv1a_64(b""), 0xcbf2_9ce4_8422_2325);
+    assert_eq!(fnv1a_64(b"a"), 0xaf63_dc4c_8601_ec8c);
+    assert_eq!(fnv1a_64(b"foobar"), 0x8594_4171_f739_67e8);
+}
+
+#[test]
+fn test_source_reference() {
+    let data: Vec<u8> = (0..12).collect();
+    let source = SourceReference::of(&yuv420_frame(data.clone())).unwrap();
+    assert_eq!(
+        source,
+        SourceReference {
+            format: RawPixelFormat::Yuv420,
+            width: 4,
+            height: 2,
+            len: 12,
+            hash: fnv1a_64(&data),
+        }
+    );
+    assert_eq!(source.file_name(), format!("yuv420_4x2_{:016x}.jpg", fnv1a_64(&data)));
+
+    // A single changed byte gives another reference
+    let mut changed = data;
+    changed[11] ^= 1;
+    assert_ne!(SourceReference::of(&yuv420_frame(changed)).unwrap().hash, source.hash);
+
+    let empty = ImageRawAny {
+        header: None,
+        image: None,
+    };
+    assert_eq!(SourceReference::of(&empty), None);
+}
+
+#[test]
+fn test_debug_frame_bundles_jpeg_and_reference() {
+    let source = SourceReference::of(&yuv420_frame(vec![0; 12])).unwrap();
+    let jpeg = ImageJpeg {
+        header: Some(Header {
+            entity_path: "cameras/1".to_string(),
+            ..Default::default()
+        }),
+        data: vec![0xFF, 0xD8, 0xFF, 0xD9],
+    };
+    let frame = debug_frame(&jpeg, &source);
+    assert_eq!(frame.header, jpeg.header);
+    assert_eq!(frame.data, jpeg.data);
+    assert_eq!(frame.path, source.file_name());
+}
@@ -0,0 +1,88 @@
+use raw_to_jpeg::{
+    parse_dedup_mode, sample_difference, sample_frame, ConversionError, DedupMode, FrameDedup, SceneChangeDetector,
+    DEDUP_SAMPLE_STEP,
+};
+
+fn frame(fill: u8) -> Vec<u8> {
+    vec![fill; 176 * 144 * 3]
+}
+
+#[test]
+fn test_dedup_off_converts_everything() {
+    let mut dedup = FrameDedup::default();
+    assert!(dedup.should_convert(&frame(10)));
+    assert!(dedup.should_convert(&frame(10)));
+}
+
+#[test]
+fn test_dedup_exact() {
+    let mut dedup = FrameDedup::new(DedupMode::Exact);
+    assert!(dedup.should_convert(&frame(10)));
+    assert!(!dedup.should_convert(&frame(10)));
+    assert!(!dedup.should_convert(&frame(10)));
+
+    // A single changed byte is a new frame
+    let mut changed = frame(10);
+    changed[1234] = 11;
+    assert!(dedup.should_convert(&changed));
+    assert!(dedup.should_convert(&frame(10)));
+}
+
+#[test]
+fn test_dedup_sampled_threshold() {
+    let mut dedup = FrameDedup::new(DedupMode::Sampled { threshold: 2 });
+    assert!(dedup.should_convert(&frame(100)));
+    // Within the threshold: noise on a static scene
+    assert!(!dedup.should_convert(&frame(102)));
+    // Compared against the last converted frame, not the skipped one
+    assert!(dedup.should_convert(&frame(103)));
+    assert!(dedup.should_convert(&frame(200)));
+
+    // Bytes between samples are not looked at
+    let mut between_samples = frame(200);
+    between_samples[DEDUP_SAMPLE_STEP + 1] = 0;
+    assert!(!dedup.should_convert(&between_samples));
+
+    // A different frame size never counts as a repeat
+    assert!(dedup.should_convert(&vec![200; 64 * 48 * 3]));
+}
+
+#[test]
+fn test_parse_dedup_mode() {
+    assert_eq!(parse_dedup_mode("off", 5).unwrap(), DedupMode::Off);
+    assert_eq!(parse_dedup_mode("exact", 5).unwrap(), DedupMode::Exact);
+    assert_eq!(parse_dedup_mode("sampled", 5).unwrap(), DedupMode::Sampled { threshold: 5 });
+
+    let err = parse_dedup_mode("fuzzy", 5).unwrap_err();
+    assert!(matches!(err, ConversionError::UnsupportedDedupMode(ref name) if name == "fuzzy"));
+    assert_eq!(err.to_string(), "Unknown dedup mode 'fuzzy': expected off, exact or sampled");
+}
+
+#[test]
+fn test_sample_difference() {
+    assert_eq!(sample_difference(&sample_frame(&frame(10)), &sample_frame(&frame(10))), Some(0.0));
+    assert_eq!(sample_difference(&sample_frame(&frame(10)), &sample_frame(&frame(250))), Some(240.0));
+    assert_eq!(sample_difference(&[0, 0, 0, 0], &[4, 0, 2, 0]), Some(1.5));
+    // Frames of different sizes, or empty ones, are not comparable
+    assert_eq!(sample_difference(&[0, 0], &[0, 0, 0]), None);
+    assert_eq!(sample_difference(&[], &[]), None);
+}
+
+#[test]
+fn test_scene_change_boost() {
+    let mut detector = SceneChangeDetector::new(30, 15);
+    // Nothing to compare the first frame with
+    assert_eq!(detector.boost(&frame(40)), 0);
+    // A static scene with sensor noise is not boosted
+    assert_eq!(detector.boost(&frame(40)), 0);
+    assert_eq!(detector.boost(&frame(45)), 0);
+    // A cut is boosted once, then the new scene is static again
+    assert_eq!(detector.boost(&frame(200)), 15);
+    assert_eq!(detector.boost(&frame(200)), 0);
+    // Compared against the previous frame, so a slow fade never triggers
+    for fill in (200..=250).step_by(10) {
+        assert_eq!(detector.boost(&frame(fill)), 0);
+    }
+    // A change of frame size starts a new scene
+    assert!(detector.is_scene_change(&vec![250; 64 * 48 * 3]));
+}
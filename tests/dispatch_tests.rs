@@ -0,0 +1,72 @@
+use make87_messages::core::Header;
+use make87_messages::google::protobuf::Timestamp;
+use raw_to_jpeg::dispatch::{FrameSequence, ReorderBuffer, StaleFrameFilter};
+use std::time::{Duration, SystemTime};
+
+#[test]
+fn test_in_order_results_pass_through() {
+    let mut reorder = ReorderBuffer::new();
+    assert_eq!(reorder.push(0, "a"), vec!["a"]);
+    assert_eq!(reorder.push(1, "b"), vec!["b"]);
+    assert_eq!(reorder.pending(), 0);
+}
+
+#[test]
+fn test_out_of_order_results_are_held_back() {
+    let mut reorder = ReorderBuffer::new();
+    assert!(reorder.push(2, 'c').is_empty());
+    assert!(reorder.push(1, 'b').is_empty());
+    assert_eq!(reorder.pending(), 2);
+
+    assert_eq!(reorder.push(0, 'a'), vec!['a', 'b', 'c']);
+    assert_eq!(reorder.pending(), 0);
+
+    assert!(reorder.push(4, 'e').is_empty());
+    assert_eq!(reorder.push(3, 'd'), vec!['d', 'e']);
+}
+
+#[test]
+fn test_frame_sequence() {
+    let mut sequence = FrameSequence::new();
+    assert_eq!(sequence.assign(None), 0);
+    assert_eq!(sequence.assign(None), 1);
+
+    // Header ids take precedence; the counter keeps advancing underneath
+    let header = Header {
+        reference_id: 4711,
+        ..Default::default()
+    };
+    assert_eq!(sequence.assign(Some(&header)), 4711);
+    assert_eq!(sequence.assign(Some(&Header::default())), 3);
+    assert_eq!(sequence.assign(None), 4);
+}
+
+fn stamped(seconds: i64, nanos: i32) -> Header {
+    Header {
+        timestamp: Some(Timestamp { seconds, nanos }),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_stale_frame_filter() {
+    let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+    let mut filter = StaleFrameFilter::new(Duration::from_millis(200));
+
+    // Fresh: within the limit, exactly at it, or stamped in the future
+    assert_eq!(filter.check(Some(&stamped(999, 900_000_000)), now), None);
+    assert_eq!(filter.check(Some(&stamped(999, 800_000_000)), now), None);
+    assert_eq!(filter.check(Some(&stamped(1_005, 0)), now), None);
+    assert_eq!(filter.dropped(), 0);
+
+    // Stale
+    assert_eq!(filter.check(Some(&stamped(999, 500_000_000)), now), Some(Duration::from_millis(500)));
+    assert_eq!(filter.check(Some(&stamped(990, 0)), now), Some(Duration::from_secs(10)));
+    assert_eq!(filter.dropped(), 2);
+
+    // Without a usable timestamp the age is unknown, so the frame is kept
+    assert_eq!(filter.check(None, now), None);
+    assert_eq!(filter.check(Some(&Header::default()), now), None);
+    assert_eq!(filter.check(Some(&stamped(-5, 0)), now), None);
+    assert_eq!(filter.dropped(), 2);
+}
@@ -0,0 +1,335 @@
+use anyhow::Result;
+use make87_messages::core::Header;
+use make87_messages::google::protobuf::Timestamp;
+use make87_messages::image::uncompressed::image_raw_any::Image as RawImageVariant;
+use make87_messages::image::uncompressed::{ImageRawAny, ImageRgb888, ImageYuv420};
+use raw_to_jpeg::pixels::Roi;
+use raw_to_jpeg::{
+    compress_with_retry, packed_to_jpeg, parse_color_space, parse_orientation, parse_output_format, parse_subsamp,
+    rgb_to_jpeg, rgb_to_jpeg_with_config, validate_frame, validate_frame_with_config, validate_raw, verify_roundtrip,
+    ConversionConfig, ConversionError, OutputFormat, RawPixelFormat, DEFAULT_MAX_PIXELS,
+};
+use turbojpeg::{Compressor, PixelFormat};
+
+const TEST_WIDTH: u32 = 176;
+const TEST_HEIGHT: u32 = 144;
+
+fn rgb_frame(data_len: usize) -> ImageRawAny {
+    ImageRawAny {
+        header: None,
+        image: Some(RawImageVariant::Rgb888(ImageRgb888 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: vec![128u8; data_len],
+        })),
+    }
+}
+
+#[test]
+fn test_no_image_data() -> Result<()> {
+    let mut compressor = Compressor::new()?;
+    let empty = ImageRawAny {
+        header: None,
+        image: None,
+    };
+    let err = rgb_to_jpeg(&empty, &mut compressor, &ConversionConfig::default()).unwrap_err();
+    assert!(matches!(err, ConversionError::NoImageData { header: None }));
+    Ok(())
+}
+
+#[test]
+fn test_no_image_data_names_frame() -> Result<()> {
+    let mut compressor = Compressor::new()?;
+    let empty = ImageRawAny {
+        header: Some(Header {
+            timestamp: Some(Timestamp {
+                seconds: 1234567890,
+                nanos: 5_000,
+            }),
+            reference_id: 42,
+            entity_path: "/camera/front".to_string(),
+        }),
+        image: None,
+    };
+    let message = rgb_to_jpeg(&empty, &mut compressor, &ConversionConfig::default()).unwrap_err().to_string();
+    assert!(message.contains("1234567890.000005000"), "{}", message);
+    assert!(message.contains("/camera/front"), "{}", message);
+    assert!(message.contains("42"), "{}", message);
+    Ok(())
+}
+
+#[test]
+fn test_buffer_too_small() -> Result<()> {
+    let mut compressor = Compressor::new()?;
+    let expected_len = (TEST_WIDTH * TEST_HEIGHT * 3) as usize;
+    let err = rgb_to_jpeg(&rgb_frame(expected_len - 1), &mut compressor, &ConversionConfig::default()).unwrap_err();
+    assert!(matches!(
+        err,
+        ConversionError::BufferTooSmall { format: "RGB888", expected, got }
+            if expected == expected_len && got == expected_len - 1
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_zero_dimension() -> Result<()> {
+    let mut compressor = Compressor::new()?;
+    let frame = ImageRawAny {
+        header: None,
+        image: Some(RawImageVariant::Rgb888(ImageRgb888 {
+            header: None,
+            width: 0,
+            height: TEST_HEIGHT,
+            data: Vec::new(),
+        })),
+    };
+    let err = rgb_to_jpeg(&frame, &mut compressor, &ConversionConfig::default()).unwrap_err();
+    assert!(matches!(err, ConversionError::ZeroDimension { width: 0, height: 144 }));
+    assert_eq!(err.to_string(), "image has zero dimension (0x144)");
+    Ok(())
+}
+
+#[test]
+fn test_too_many_pixels() -> Result<()> {
+    let mut compressor = Compressor::new()?;
+    // A corrupt header claiming 10 gigapixels over an empty buffer: the guard has to fire
+    // before the buffer size is even looked at, let alone anything allocated for the frame
+    let frame = ImageRawAny {
+        header: None,
+        image: Some(RawImageVariant::Rgb888(ImageRgb888 {
+            header: None,
+            width: 100_000,
+            height: 100_000,
+            data: Vec::new(),
+        })),
+    };
+    let err = rgb_to_jpeg(&frame, &mut compressor, &ConversionConfig::default()).unwrap_err();
+    assert!(matches!(
+        err,
+        ConversionError::TooManyPixels {
+            width: 100_000,
+            height: 100_000,
+            max_pixels: DEFAULT_MAX_PIXELS
+        }
+    ));
+    assert_eq!(err.to_string(), "image of 100000x100000 exceeds the limit of 100000000 pixels");
+
+    // The limit is configurable and inclusive
+    let pixels = (TEST_WIDTH * TEST_HEIGHT) as usize;
+    let frame = rgb_frame(pixels * 3);
+    let at_limit = ConversionConfig {
+        max_pixels: Some(pixels),
+        ..Default::default()
+    };
+    assert!(validate_frame_with_config(&frame, &at_limit).is_ok());
+    let below = ConversionConfig {
+        max_pixels: Some(pixels - 1),
+        ..Default::default()
+    };
+    let err = validate_frame_with_config(&frame, &below).unwrap_err();
+    assert!(matches!(err, ConversionError::TooManyPixels { .. }));
+    Ok(())
+}
+
+#[test]
+fn test_dimension_overflow() {
+    // The pixel count fits, four bytes per pixel do not
+    let (width, height) = (usize::MAX / 8, 3);
+    assert!(width.checked_mul(height).is_some());
+    assert_eq!(RawPixelFormat::Rgba8888.checked_len(width, height), None);
+    assert_eq!(RawPixelFormat::Rgba8888.expected_len(width, height), usize::MAX);
+    assert_eq!(RawPixelFormat::Gray8.checked_len(width, height), Some(width * height));
+
+    let unlimited = ConversionConfig {
+        max_pixels: Some(usize::MAX),
+        ..Default::default()
+    };
+    let err = validate_raw(&[0; 64], width, height, RawPixelFormat::Rgba8888, &unlimited).unwrap_err();
+    assert!(matches!(err, ConversionError::DimensionOverflow { format: "RGBA8888", .. }));
+
+    // A huge row stride overflows on its own
+    let strided = ConversionConfig {
+        nv12_stride: Some(usize::MAX / 2),
+        ..unlimited
+    };
+    let err = validate_raw(&[0; 64], 4, 4, RawPixelFormat::Nv12, &strided).unwrap_err();
+    assert!(matches!(err, ConversionError::DimensionOverflow { format: "NV12", .. }));
+}
+
+/// 40000x30000 RGBA is 4.8 GB, past what a 32-bit size can hold.
+#[cfg(target_pointer_width = "32")]
+#[test]
+fn test_dimension_overflow_32bit() {
+    assert_eq!(RawPixelFormat::Rgba8888.checked_len(40_000, 30_000), None);
+    let unlimited = ConversionConfig {
+        max_pixels: Some(usize::MAX),
+        ..Default::default()
+    };
+    let err = validate_raw(&[0; 64], 40_000, 30_000, RawPixelFormat::Rgba8888, &unlimited).unwrap_err();
+    assert!(matches!(err, ConversionError::DimensionOverflow { .. }));
+}
+
+#[test]
+fn test_validate_frame() {
+    let expected_len = (TEST_WIDTH * TEST_HEIGHT * 3) as usize;
+    assert!(validate_frame(&rgb_frame(expected_len)).is_ok());
+
+    let err = validate_frame(&rgb_frame(expected_len - 1)).unwrap_err();
+    assert!(matches!(
+        err,
+        ConversionError::BufferTooSmall { format: "RGB888", expected, got }
+            if expected == expected_len && got == expected_len - 1
+    ));
+
+    // Preprocessing from the config is checked too
+    let config = ConversionConfig {
+        scale_denom: TEST_HEIGHT as usize + 1,
+        ..Default::default()
+    };
+    let err = validate_frame_with_config(&rgb_frame(expected_len), &config).unwrap_err();
+    assert!(matches!(err, ConversionError::InvalidScale { .. }));
+}
+
+#[test]
+fn test_invalid_dimensions() -> Result<()> {
+    let mut compressor = Compressor::new()?;
+    let odd = ImageRawAny {
+        header: None,
+        image: Some(RawImageVariant::Yuv420(ImageYuv420 {
+            header: None,
+            width: TEST_WIDTH - 1,
+            height: TEST_HEIGHT,
+            data: vec![0u8; (TEST_WIDTH * TEST_HEIGHT * 3 / 2) as usize],
+        })),
+    };
+    let err = rgb_to_jpeg(&odd, &mut compressor, &ConversionConfig::default()).unwrap_err();
+    assert!(matches!(
+        err,
+        ConversionError::InvalidDimensions { format: "YUV420", width: 175, height: 144, .. }
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_roi_and_scale_errors() -> Result<()> {
+    let mut compressor = Compressor::new()?;
+    let frame = rgb_frame((TEST_WIDTH * TEST_HEIGHT * 3) as usize);
+
+    let roi_config = ConversionConfig {
+        roi: Some(Roi { x: 150, y: 0, width: 64, height: 48 }),
+        ..Default::default()
+    };
+    let err = rgb_to_jpeg_with_config(&frame, &mut compressor, &roi_config).unwrap_err();
+    assert!(matches!(err, ConversionError::RoiOutOfBounds { width: 176, height: 144, .. }));
+
+    let scale_config = ConversionConfig {
+        scale_denom: 200,
+        ..Default::default()
+    };
+    let err = rgb_to_jpeg_with_config(&frame, &mut compressor, &scale_config).unwrap_err();
+    assert!(matches!(err, ConversionError::InvalidScale { factor: 200, .. }));
+    Ok(())
+}
+
+#[test]
+fn test_dimension_mismatch() -> Result<()> {
+    let mut compressor = Compressor::new()?;
+    let jpeg = rgb_to_jpeg(&rgb_frame((TEST_WIDTH * TEST_HEIGHT * 3) as usize), &mut compressor, &ConversionConfig::default())?;
+    let err = verify_roundtrip(&jpeg, 88, 72).unwrap_err();
+    assert!(matches!(
+        err,
+        ConversionError::DimensionMismatch { expected: (88, 72), got: (176, 144) }
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_unsupported_subsampling() {
+    let err = parse_subsamp("411").unwrap_err();
+    assert!(matches!(err, ConversionError::UnsupportedSubsampling(ref name) if name == "411"));
+}
+
+#[test]
+fn test_unsupported_transform() {
+    let err = parse_orientation("rot45").unwrap_err();
+    assert!(matches!(err, ConversionError::UnsupportedTransform(ref name) if name == "rot45"));
+}
+
+#[test]
+fn test_unsupported_color_space() {
+    let err = parse_color_space("srgb").unwrap_err();
+    assert!(matches!(err, ConversionError::UnsupportedColorSpace(ref name) if name == "srgb"));
+}
+
+#[test]
+fn test_unsupported_output_format() {
+    assert_eq!(parse_output_format("jpeg").unwrap(), OutputFormat::Jpeg);
+    let err = parse_output_format("avif").unwrap_err();
+    assert!(matches!(err, ConversionError::UnsupportedOutputFormat(ref name) if name == "avif"));
+    // WebP only parses when it can actually be encoded
+    assert_eq!(parse_output_format("webp").is_ok(), cfg!(feature = "webp"));
+}
+
+#[test]
+fn test_turbojpeg_error_is_wrapped() -> Result<()> {
+    let mut compressor = Compressor::new()?;
+    let err = packed_to_jpeg(&[], 0, 0, PixelFormat::RGB, &mut compressor).unwrap_err();
+    assert!(matches!(err, ConversionError::TurboJpeg(_)));
+    assert!(std::error::Error::source(&err).is_some());
+    Ok(())
+}
+
+fn turbojpeg_failure() -> ConversionError {
+    ConversionError::TurboJpeg(turbojpeg::Error::TurboJpegError("out of memory".to_string()))
+}
+
+#[test]
+fn test_compress_with_retry_counts_attempts() -> Result<()> {
+    let mut compressor = Compressor::new()?;
+
+    // Fails once, then recovers on the retry
+    let mut attempts = 0;
+    let result = compress_with_retry(&mut compressor, 80, 1, |_| {
+        attempts += 1;
+        if attempts == 1 { Err(turbojpeg_failure()) } else { Ok(attempts) }
+    });
+    assert_eq!(result?, 2);
+
+    // Keeps failing: one first attempt plus `retries` retries, then the last error
+    let mut attempts = 0;
+    let result: Result<(), _> = compress_with_retry(&mut compressor, 80, 3, |_| {
+        attempts += 1;
+        Err(turbojpeg_failure())
+    });
+    assert!(matches!(result, Err(ConversionError::TurboJpeg(_))));
+    assert_eq!(attempts, 4);
+
+    // No retries configured
+    let mut attempts = 0;
+    let result: Result<(), _> = compress_with_retry(&mut compressor, 80, 0, |_| {
+        attempts += 1;
+        Err(turbojpeg_failure())
+    });
+    assert!(result.is_err());
+    assert_eq!(attempts, 1);
+    Ok(())
+}
+
+#[test]
+fn test_compress_with_retry_skips_input_errors() -> Result<()> {
+    let mut compressor = Compressor::new()?;
+    let mut attempts = 0;
+    let result: Result<(), _> = compress_with_retry(&mut compressor, 80, 3, |_| {
+        attempts += 1;
+        Err(ConversionError::BufferTooSmall {
+            format: "RGB888",
+            expected: 12,
+            got: 4,
+        })
+    });
+    // A fresh compressor cannot fix bad input
+    assert!(matches!(result, Err(ConversionError::BufferTooSmall { .. })));
+    assert_eq!(attempts, 1);
+    Ok(())
+}
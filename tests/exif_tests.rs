@@ -0,0 +1,80 @@
+use anyhow::{Result, anyhow};
+use make87_messages::core::Header;
+use make87_messages::google::protobuf::Timestamp;
+use make87_messages::image::uncompressed::image_raw_any::Image as RawImageVariant;
+use make87_messages::image::uncompressed::{ImageRawAny, ImageYuv420};
+use raw_to_jpeg::{rgb_to_jpeg_with_config, ConversionConfig};
+use std::collections::HashMap;
+use turbojpeg::Compressor;
+
+const TEST_WIDTH: u32 = 176;
+const TEST_HEIGHT: u32 = 144;
+
+/// Minimal reader for the big-endian TIFF structure written by `exif::timestamp_app1`:
+/// follows IFD0 to the Exif IFD and returns its ASCII values by tag.
+fn read_exif_strings(jpeg: &[u8]) -> Result<HashMap<u16, String>> {
+    if jpeg[2..4] != [0xFF, 0xE1] || &jpeg[6..12] != b"Exif\0\0" {
+        return Err(anyhow!("no EXIF APP1 segment after SOI"));
+    }
+    let tiff = &jpeg[12..];
+    if &tiff[0..4] != b"MM\0\x2A" {
+        return Err(anyhow!("unexpected TIFF header"));
+    }
+    let u16_at = |offset: usize| u16::from_be_bytes([tiff[offset], tiff[offset + 1]]);
+    let u32_at = |offset: usize| u32::from_be_bytes(tiff[offset..offset + 4].try_into().unwrap()) as usize;
+
+    let ifd0 = u32_at(4);
+    let exif_ifd = (0..u16_at(ifd0) as usize)
+        .map(|i| ifd0 + 2 + i * 12)
+        .find(|&entry| u16_at(entry) == 0x8769)
+        .map(|entry| u32_at(entry + 8))
+        .ok_or_else(|| anyhow!("no Exif IFD pointer"))?;
+
+    let mut values = HashMap::new();
+    for i in 0..u16_at(exif_ifd) as usize {
+        let entry = exif_ifd + 2 + i * 12;
+        let count = u32_at(entry + 4);
+        let offset = u32_at(entry + 8);
+        let text = std::str::from_utf8(&tiff[offset..offset + count - 1])?;
+        values.insert(u16_at(entry), text.to_string());
+    }
+    Ok(values)
+}
+
+#[test]
+fn test_exif_timestamp_embedded() -> Result<()> {
+    let header = Header {
+        timestamp: Some(Timestamp {
+            seconds: 1234567890,
+            nanos: 250_000_000,
+        }),
+        ..Default::default()
+    };
+    let image_raw = ImageRawAny {
+        header: Some(header),
+        image: Some(RawImageVariant::Yuv420(ImageYuv420 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: vec![128u8; (TEST_WIDTH * TEST_HEIGHT * 3 / 2) as usize],
+        })),
+    };
+
+    let mut compressor = Compressor::new()?;
+    let config = ConversionConfig {
+        exif_timestamp: true,
+        ..Default::default()
+    };
+    let jpeg = rgb_to_jpeg_with_config(&image_raw, &mut compressor, &config)?;
+
+    assert_eq!(jpeg.data[0..2], [0xFF, 0xD8]);
+    let values = read_exif_strings(&jpeg.data)?;
+    assert_eq!(values[&0x9003], "2009:02:13 23:31:30");
+    assert_eq!(values[&0x9011], "+00:00");
+    assert_eq!(values[&0x9291], "250000000");
+
+    // Still a decodable JPEG
+    let decoded = turbojpeg::read_header(&jpeg.data)?;
+    assert_eq!((decoded.width, decoded.height), (TEST_WIDTH as usize, TEST_HEIGHT as usize));
+    Ok(())
+}
@@ -0,0 +1,92 @@
+//! Checks of the per-format cargo features. With the default features every layout is enabled;
+//! `cargo test --no-default-features --features yuv420 --test feature_tests` covers a build
+//! that only converts YUV420.
+
+use anyhow::Result;
+use make87_messages::image::uncompressed::image_raw_any::Image as RawImageVariant;
+use make87_messages::image::uncompressed::{ImageNv12, ImageRawAny};
+use raw_to_jpeg::{compress_raw, rgb_to_jpeg, ConversionConfig, ConversionError, RawPixelFormat};
+use turbojpeg::Compressor;
+
+const TEST_WIDTH: usize = 32;
+const TEST_HEIGHT: usize = 16;
+
+const ALL_FORMATS: [&str; 18] = [
+    "rgb888",
+    "rgba8888",
+    "bgr888",
+    "bgra8888",
+    "gray8",
+    "yuv420",
+    "yv12",
+    "yuv422",
+    "yuv411",
+    "yuv444",
+    "yuv444_packed",
+    "nv12",
+    "nv21",
+    "yuyv",
+    "p010",
+    "yuv420p10",
+    "rgb161616",
+    "cmyk",
+];
+
+#[test]
+fn test_enabled_formats_match_features() {
+    for name in ALL_FORMATS {
+        let format = RawPixelFormat::from_name(name).unwrap();
+        let expected = match format.feature() {
+            "rgb" => cfg!(feature = "rgb"),
+            "gray" => cfg!(feature = "gray"),
+            "yuv420" => cfg!(feature = "yuv420"),
+            "yuv422" => cfg!(feature = "yuv422"),
+            "yuv411" => cfg!(feature = "yuv411"),
+            "yuv444" => cfg!(feature = "yuv444"),
+            "nv12" => cfg!(feature = "nv12"),
+            "p010" => cfg!(feature = "p010"),
+            "cmyk" => cfg!(feature = "cmyk"),
+            other => panic!("unexpected feature {other} for {name}"),
+        };
+        assert_eq!(format.is_enabled(), expected, "{name}");
+    }
+}
+
+#[test]
+fn test_disabled_formats_are_unsupported() -> Result<()> {
+    let mut compressor = Compressor::new()?;
+    for name in ALL_FORMATS {
+        let format = RawPixelFormat::from_name(name).unwrap();
+        let data = vec![128; format.expected_len(TEST_WIDTH, TEST_HEIGHT)];
+        let result = compress_raw(&data, TEST_WIDTH, TEST_HEIGHT, format, &mut compressor);
+        if format.is_enabled() {
+            assert!(result.is_ok(), "{name}: {:?}", result.err());
+        } else {
+            let err = result.unwrap_err();
+            assert!(matches!(err, ConversionError::UnsupportedFormat(_)), "{name}: {err}");
+            assert!(err.to_string().contains(format.feature()), "{err}");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "nv12"))]
+#[test]
+fn test_rgb_to_jpeg_rejects_disabled_nv12() -> Result<()> {
+    let image_raw = ImageRawAny {
+        header: None,
+        image: Some(RawImageVariant::Nv12(ImageNv12 {
+            header: None,
+            width: TEST_WIDTH as u32,
+            height: TEST_HEIGHT as u32,
+            data: vec![128; TEST_WIDTH * TEST_HEIGHT * 3 / 2],
+        })),
+    };
+    let mut compressor = Compressor::new()?;
+    let err = rgb_to_jpeg(&image_raw, &mut compressor, &ConversionConfig::default()).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Unsupported format: NV12 (built without the `nv12` feature)"
+    );
+    Ok(())
+}
@@ -0,0 +1,104 @@
+#![cfg(feature = "ffi")]
+
+use raw_to_jpeg::ffi::{
+    raw_to_jpeg_ffi, raw_to_jpeg_free, FORMAT_CODES, RAW_TO_JPEG_ERR_CONVERSION, RAW_TO_JPEG_ERR_FORMAT,
+    RAW_TO_JPEG_ERR_NULL, RAW_TO_JPEG_ERR_QUALITY, RAW_TO_JPEG_OK,
+};
+use raw_to_jpeg::RawPixelFormat;
+use std::ptr;
+
+const WIDTH: u32 = 32;
+const HEIGHT: u32 = 16;
+
+fn format_code(format: RawPixelFormat) -> u32 {
+    FORMAT_CODES.iter().position(|code| *code == format).unwrap() as u32
+}
+
+/// Calls the FFI function like a C host would, returning the code and the output pointer/length.
+fn convert(data: &[u8], format: RawPixelFormat, quality: i32) -> (i32, *mut u8, usize) {
+    let mut out_ptr = ptr::null_mut();
+    let mut out_len = 0;
+    let code = unsafe {
+        raw_to_jpeg_ffi(
+            data.as_ptr(),
+            data.len(),
+            WIDTH,
+            HEIGHT,
+            format_code(format),
+            quality,
+            &mut out_ptr,
+            &mut out_len,
+        )
+    };
+    (code, out_ptr, out_len)
+}
+
+#[test]
+fn test_ffi_converts_and_frees() {
+    let format = RawPixelFormat::Yuv420;
+    let data = vec![128; format.expected_len(WIDTH as usize, HEIGHT as usize)];
+    let (code, out_ptr, out_len) = convert(&data, format, 85);
+    assert_eq!(code, RAW_TO_JPEG_OK);
+    assert!(!out_ptr.is_null());
+
+    let jpeg = unsafe { std::slice::from_raw_parts(out_ptr, out_len) };
+    let header = turbojpeg::read_header(jpeg).unwrap();
+    assert_eq!((header.width, header.height), (WIDTH as usize, HEIGHT as usize));
+    unsafe { raw_to_jpeg_free(out_ptr, out_len) };
+    // Freeing null is a no-op
+    unsafe { raw_to_jpeg_free(ptr::null_mut(), 0) };
+}
+
+#[test]
+fn test_ffi_error_codes() {
+    let format = RawPixelFormat::Rgb888;
+    let data = vec![0; format.expected_len(WIDTH as usize, HEIGHT as usize)];
+
+    // Too short for the dimensions; nothing is returned to free
+    let (code, out_ptr, out_len) = convert(&data[..10], format, 85);
+    assert_eq!(code, RAW_TO_JPEG_ERR_CONVERSION);
+    assert!(out_ptr.is_null());
+    assert_eq!(out_len, 0);
+
+    assert_eq!(convert(&data, format, 0).0, RAW_TO_JPEG_ERR_QUALITY);
+    assert_eq!(convert(&data, format, 101).0, RAW_TO_JPEG_ERR_QUALITY);
+
+    let mut out_ptr = ptr::null_mut();
+    let mut out_len = 0;
+    let code = unsafe {
+        raw_to_jpeg_ffi(
+            data.as_ptr(),
+            data.len(),
+            WIDTH,
+            HEIGHT,
+            999,
+            85,
+            &mut out_ptr,
+            &mut out_len,
+        )
+    };
+    assert_eq!(code, RAW_TO_JPEG_ERR_FORMAT);
+    let code = unsafe { raw_to_jpeg_ffi(ptr::null(), 0, WIDTH, HEIGHT, 0, 85, &mut out_ptr, &mut out_len) };
+    assert_eq!(code, RAW_TO_JPEG_ERR_NULL);
+    let code = unsafe {
+        raw_to_jpeg_ffi(
+            data.as_ptr(),
+            data.len(),
+            WIDTH,
+            HEIGHT,
+            0,
+            85,
+            ptr::null_mut(),
+            &mut out_len,
+        )
+    };
+    assert_eq!(code, RAW_TO_JPEG_ERR_NULL);
+}
+
+#[test]
+fn test_ffi_format_codes_are_unique() {
+    for (code, format) in FORMAT_CODES.iter().enumerate() {
+        assert_eq!(format_code(*format), code as u32, "{}", format.name());
+        assert_eq!(RawPixelFormat::from_name(format.name()), Some(*format));
+    }
+}
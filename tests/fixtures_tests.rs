@@ -0,0 +1,34 @@
+use anyhow::Result;
+use raw_to_jpeg::RawPixelFormat;
+use std::path::PathBuf;
+use std::process::Command;
+
+const WIDTH: usize = 176;
+const HEIGHT: usize = 144;
+
+/// Runs the `gen_fixtures` binary into a scratch directory and returns that directory.
+fn generate_fixtures() -> Result<PathBuf> {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("fixtures");
+    let status = Command::new(env!("CARGO_BIN_EXE_gen_fixtures")).arg(&dir).status()?;
+    assert!(status.success(), "gen_fixtures failed: {status}");
+    Ok(dir)
+}
+
+#[test]
+fn test_generated_fixtures() -> Result<()> {
+    let dir = generate_fixtures()?;
+
+    let rgb888 = std::fs::read(dir.join("synthetic_rgb888_qcif.yuv"))?;
+    assert_eq!(rgb888.len(), WIDTH * HEIGHT * 3);
+
+    for format in RawPixelFormat::NAMED {
+        let name = format.name().to_ascii_lowercase();
+        let data = std::fs::read(dir.join(format!("synthetic_{name}_qcif.yuv")))?;
+        assert_eq!(data.len(), format.expected_len(WIDTH, HEIGHT), "{name}");
+    }
+
+    // Deterministic: a second run writes the same bytes
+    let again = std::fs::read(generate_fixtures()?.join("synthetic_rgb888_qcif.yuv"))?;
+    assert_eq!(again, rgb888);
+    Ok(())
+}
@@ -0,0 +1,90 @@
+use anyhow::Result;
+use make87_messages::core::Header;
+use make87_messages::google::protobuf::Timestamp;
+use make87_messages::image::uncompressed::image_raw_any::Image as RawImageVariant;
+use make87_messages::image::uncompressed::{ImageRawAny, ImageRgb888};
+use raw_to_jpeg::jfif::Density;
+use raw_to_jpeg::{parse_header_style, rgb_to_jpeg_with_config, ConversionConfig, ConversionError, HeaderStyle};
+use turbojpeg::Compressor;
+
+const TEST_WIDTH: u32 = 176;
+const TEST_HEIGHT: u32 = 144;
+
+fn timestamped_rgb_frame() -> ImageRawAny {
+    ImageRawAny {
+        header: Some(Header {
+            timestamp: Some(Timestamp {
+                seconds: 1234567890,
+                nanos: 0,
+            }),
+            ..Default::default()
+        }),
+        image: Some(RawImageVariant::Rgb888(ImageRgb888 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: vec![128; (TEST_WIDTH * TEST_HEIGHT * 3) as usize],
+        })),
+    }
+}
+
+/// Marker and identifier of each APPn segment directly following SOI.
+fn app_segments(jpeg: &[u8]) -> Vec<(u8, Vec<u8>)> {
+    let mut segments = Vec::new();
+    let mut pos = 2;
+    while jpeg[pos] == 0xFF && (0xE0..=0xEF).contains(&jpeg[pos + 1]) {
+        let len = u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]) as usize;
+        let identifier = jpeg[pos + 4..pos + 2 + len].iter().take_while(|&&b| b != 0).copied().collect();
+        segments.push((jpeg[pos + 1], identifier));
+        pos += 2 + len;
+    }
+    segments
+}
+
+fn convert(header_style: Option<HeaderStyle>, exif_timestamp: bool) -> Result<Vec<u8>> {
+    let mut compressor = Compressor::new()?;
+    let config = ConversionConfig {
+        exif_timestamp,
+        density: Some(Density { x: 300, y: 300 }),
+        header_style,
+        ..Default::default()
+    };
+    let jpeg = rgb_to_jpeg_with_config(&timestamped_rgb_frame(), &mut compressor, &config)?;
+    // Still decodes at full size whatever was stripped
+    let header = turbojpeg::read_header(&jpeg.data)?;
+    assert_eq!((header.width, header.height), (TEST_WIDTH as usize, TEST_HEIGHT as usize));
+    Ok(jpeg.data)
+}
+
+#[test]
+fn test_header_styles_write_expected_app_segments() -> Result<()> {
+    let jfif = (0xE0, b"JFIF".to_vec());
+    let exif = (0xE1, b"Exif".to_vec());
+
+    assert_eq!(app_segments(&convert(None, true)?), [exif.clone(), jfif.clone()]);
+    assert_eq!(app_segments(&convert(Some(HeaderStyle::Jfif), true)?), [jfif]);
+    assert_eq!(app_segments(&convert(Some(HeaderStyle::Exif), true)?), [exif]);
+    assert!(app_segments(&convert(Some(HeaderStyle::Minimal), true)?).is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_exif_style_without_timestamp_writes_empty_exif() -> Result<()> {
+    let jpeg = convert(Some(HeaderStyle::Exif), false)?;
+    assert_eq!(app_segments(&jpeg), [(0xE1, b"Exif".to_vec())]);
+    // TIFF header and an IFD0 without entries
+    assert_eq!(&jpeg[12..26], b"MM\0\x2A\0\0\0\x08\0\0\0\0\0\0");
+
+    let minimal = convert(Some(HeaderStyle::Minimal), false)?;
+    assert!(minimal.len() < jpeg.len());
+    Ok(())
+}
+
+#[test]
+fn test_parse_header_style() {
+    assert_eq!(parse_header_style("jfif").unwrap(), HeaderStyle::Jfif);
+    assert_eq!(parse_header_style("exif").unwrap(), HeaderStyle::Exif);
+    assert_eq!(parse_header_style("minimal").unwrap(), HeaderStyle::Minimal);
+    let err = parse_header_style("xmp").unwrap_err();
+    assert!(matches!(err, ConversionError::UnsupportedHeaderStyle(ref name) if name == "xmp"));
+}
@@ -0,0 +1,63 @@
+use anyhow::Result;
+use make87_messages::image::uncompressed::image_raw_any::Image as RawImageVariant;
+use make87_messages::image::uncompressed::{ImageRawAny, ImageRgb888};
+use raw_to_jpeg::jfif::{read_density, set_density, Density, UNITS_DPI};
+use raw_to_jpeg::{rgb_to_jpeg_with_config, ConversionConfig};
+use turbojpeg::Compressor;
+
+const TEST_WIDTH: u32 = 176;
+const TEST_HEIGHT: u32 = 144;
+
+fn rgb_frame() -> ImageRawAny {
+    ImageRawAny {
+        header: None,
+        image: Some(RawImageVariant::Rgb888(ImageRgb888 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: vec![128; (TEST_WIDTH * TEST_HEIGHT * 3) as usize],
+        })),
+    }
+}
+
+/// Units and x/y density bytes of the APP0 segment that must directly follow SOI.
+fn app0_density(jpeg: &[u8]) -> (u8, u16, u16) {
+    assert_eq!(jpeg[2..4], [0xFF, 0xE0], "no APP0 segment after SOI");
+    assert_eq!(&jpeg[6..11], b"JFIF\0");
+    (
+        jpeg[13],
+        u16::from_be_bytes([jpeg[14], jpeg[15]]),
+        u16::from_be_bytes([jpeg[16], jpeg[17]]),
+    )
+}
+
+#[test]
+fn test_density_written_to_jfif_header() -> Result<()> {
+    let mut compressor = Compressor::new()?;
+    let config = ConversionConfig {
+        density: Some(Density { x: 300, y: 150 }),
+        ..Default::default()
+    };
+    let jpeg = rgb_to_jpeg_with_config(&rgb_frame(), &mut compressor, &config)?;
+    assert_eq!(app0_density(&jpeg.data), (UNITS_DPI, 300, 150));
+    assert_eq!(read_density(&jpeg.data), Some((UNITS_DPI, 300, 150)));
+    // Still a valid JPEG of the same size
+    let header = turbojpeg::read_header(&jpeg.data)?;
+    assert_eq!((header.width, header.height), (TEST_WIDTH as usize, TEST_HEIGHT as usize));
+    Ok(())
+}
+
+#[test]
+fn test_density_inserts_missing_jfif_header() {
+    // SOI followed directly by EOI: nothing to patch
+    let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xD9];
+    set_density(&mut jpeg, Density { x: 72, y: 96 });
+    assert_eq!(jpeg.len(), 4 + 18);
+    assert_eq!(app0_density(&jpeg), (UNITS_DPI, 72, 96));
+    assert_eq!(jpeg[jpeg.len() - 2..], [0xFF, 0xD9]);
+
+    // Patching again rewrites the existing segment instead of adding another
+    set_density(&mut jpeg, Density { x: 600, y: 600 });
+    assert_eq!(jpeg.len(), 4 + 18);
+    assert_eq!(read_density(&jpeg), Some((UNITS_DPI, 600, 600)));
+}
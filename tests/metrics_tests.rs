@@ -0,0 +1,106 @@
+use raw_to_jpeg::metrics::{DecodeFailures, DecodeStatus, FormatTracker, Heartbeat, HeartbeatStatus, Metrics};
+use raw_to_jpeg::RawPixelFormat;
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_byte_accounting() {
+    let mut metrics = Metrics::default();
+    metrics.record(1000, 100, Duration::from_millis(1));
+    metrics.record(2000, 150, Duration::from_millis(1));
+
+    let snapshot = metrics.snapshot();
+    assert_eq!(snapshot.frames_converted, 2);
+    assert_eq!(snapshot.total_input_bytes, 3000);
+    assert_eq!(snapshot.total_output_bytes, 250);
+}
+
+#[test]
+fn test_rolling_average_latency() {
+    let mut metrics = Metrics::new(3);
+    assert_eq!(metrics.snapshot().average_latency, Duration::ZERO);
+
+    for ms in [10, 20, 30] {
+        metrics.record(0, 0, Duration::from_millis(ms));
+    }
+    assert_eq!(metrics.snapshot().average_latency, Duration::from_millis(20));
+
+    // Oldest sample (10ms) drops out of the window
+    metrics.record(0, 0, Duration::from_millis(40));
+    let snapshot = metrics.snapshot();
+    assert_eq!(snapshot.average_latency, Duration::from_millis(30));
+    assert_eq!(snapshot.frames_converted, 4);
+}
+
+#[test]
+fn test_decode_failures_counting_and_throttling() {
+    let start = Instant::now();
+    let mut failures = DecodeFailures::new(Duration::from_secs(10));
+    assert_eq!(failures.poll(start), None);
+
+    // The first failure is reported immediately
+    assert_eq!(failures.record(start), Some(DecodeStatus { total: 1, since_last: 1 }));
+
+    // Further failures within the interval are held back
+    assert_eq!(failures.record(start + Duration::from_secs(1)), None);
+    assert_eq!(failures.record(start + Duration::from_secs(2)), None);
+    assert_eq!(failures.poll(start + Duration::from_secs(9)), None);
+    assert_eq!(failures.total(), 3);
+
+    // ... and reported together once it has elapsed
+    let status = failures.poll(start + Duration::from_secs(10)).unwrap();
+    assert_eq!(status, DecodeStatus { total: 3, since_last: 2 });
+    assert_eq!(status.to_string(), "decode_failures=3 since_last=2");
+
+    // Nothing new, nothing to report
+    assert_eq!(failures.poll(start + Duration::from_secs(30)), None);
+    assert_eq!(failures.record(start + Duration::from_secs(31)), Some(DecodeStatus { total: 4, since_last: 1 }));
+}
+
+#[test]
+fn test_heartbeat_interval() {
+    let start = Instant::now();
+    let at = |secs: u64| start + Duration::from_secs(secs);
+    let mut heartbeat = Heartbeat::new(Duration::from_secs(5), start);
+    assert_eq!(heartbeat.next_due(), at(5));
+    assert_eq!(heartbeat.poll(at(4)), None);
+
+    // No frames: one heartbeat per interval
+    let status = heartbeat.poll(at(5)).unwrap();
+    assert_eq!(status, HeartbeatStatus { seq: 1, frames_converted: 0, idle: Duration::from_secs(5) });
+    assert_eq!(status.to_string(), "alive seq=1 frames_converted=0 idle_ms=5000");
+    assert_eq!(heartbeat.next_due(), at(10));
+    assert_eq!(heartbeat.poll(at(9)), None);
+    assert_eq!(heartbeat.poll(at(10)).map(|status| status.seq), Some(2));
+
+    // Each converted frame pushes the next heartbeat back
+    heartbeat.frame_converted(at(12));
+    heartbeat.frame_converted(at(14));
+    assert_eq!(heartbeat.next_due(), at(19));
+    assert_eq!(heartbeat.poll(at(15)), None);
+    let status = heartbeat.poll(at(19)).unwrap();
+    assert_eq!(status, HeartbeatStatus { seq: 3, frames_converted: 2, idle: Duration::from_secs(5) });
+
+    // A late poll reports the whole stall and restarts the interval from then
+    let status = heartbeat.poll(at(40)).unwrap();
+    assert_eq!(status.idle, Duration::from_secs(26));
+    assert_eq!(heartbeat.next_due(), at(45));
+}
+
+#[test]
+fn test_format_tracker_rgb_to_yuv_switch() {
+    let mut formats = FormatTracker::new();
+    assert_eq!(formats.observe(RawPixelFormat::Rgb888), None);
+    assert_eq!(formats.observe(RawPixelFormat::Rgb888), None);
+    assert_eq!(formats.observe(RawPixelFormat::Yuv420), Some(RawPixelFormat::Rgb888));
+    assert_eq!(formats.observe(RawPixelFormat::Yuv420), None);
+    assert_eq!(formats.last_format(), Some(RawPixelFormat::Yuv420));
+
+    assert_eq!(formats.count(RawPixelFormat::Rgb888), 2);
+    assert_eq!(formats.count(RawPixelFormat::Yuv420), 2);
+    assert_eq!(formats.count(RawPixelFormat::Nv12), 0);
+
+    // Switching back is a change again and keeps counting the earlier format
+    assert_eq!(formats.observe(RawPixelFormat::Rgb888), Some(RawPixelFormat::Yuv420));
+    assert_eq!(formats.counts(), &[(RawPixelFormat::Rgb888, 3), (RawPixelFormat::Yuv420, 2)]);
+    assert_eq!(formats.to_string(), "RGB888=3 YUV420=2");
+}
@@ -0,0 +1,34 @@
+use raw_to_jpeg::mjpeg::{content_type, multipart_frame, MjpegStream, BOUNDARY};
+use std::io::Read;
+
+#[test]
+fn test_multipart_frame() {
+    let jpeg = [0xFF, 0xD8, 0x01, 0x02, 0xFF, 0xD9];
+    let part = multipart_frame(&jpeg);
+
+    let head = format!("--{BOUNDARY}\r\nContent-Type: image/jpeg\r\nContent-Length: 6\r\n\r\n");
+    assert!(part.starts_with(head.as_bytes()));
+    assert_eq!(&part[head.len()..head.len() + jpeg.len()], &jpeg);
+    assert!(part.ends_with(b"\r\n"));
+    assert_eq!(part.len(), head.len() + jpeg.len() + 2);
+
+    assert_eq!(content_type(), "multipart/x-mixed-replace; boundary=mjpegframe");
+}
+
+#[test]
+fn test_stream_reader_sends_latest_frame() {
+    let stream = MjpegStream::new();
+    stream.publish(b"old");
+    stream.publish(b"new");
+    let mut reader = stream.reader();
+
+    // A client joining late starts at the newest frame
+    let expected = multipart_frame(b"new");
+    let mut part = vec![0; expected.len()];
+    reader.read_exact(&mut part).unwrap();
+    assert_eq!(part, expected);
+
+    // Nothing is left after close
+    stream.close();
+    assert_eq!(reader.read(&mut [0; 16]).unwrap(), 0);
+}
@@ -0,0 +1,61 @@
+use raw_to_jpeg::phash::{hamming_distance, phash, phash_jpeg, phash_luma};
+use turbojpeg::{Compressor, Image, PixelFormat};
+
+/// Diagonal gradient with a bright square, so the hash has some structure to pick up.
+fn scene(width: usize, height: usize) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        for x in 0..width {
+            let base = ((x + y) * 160 / (width + height)) as u8;
+            let square = (width / 4..width / 2).contains(&x) && (height / 3..height * 2 / 3).contains(&y);
+            let value = if square { base + 80 } else { base };
+            pixels.extend_from_slice(&[value, value / 2, 255 - value]);
+        }
+    }
+    pixels
+}
+
+#[test]
+fn test_brightened_frame_hashes_close() {
+    let (width, height) = (96, 64);
+    let frame = scene(width, height);
+    let brightened: Vec<u8> = frame.iter().map(|v| v.saturating_add(12)).collect();
+
+    let distance = hamming_distance(phash(&frame, width, height), phash(&brightened, width, height));
+    assert!(distance <= 4, "distance {distance}");
+}
+
+#[test]
+fn test_different_frames_hash_apart() {
+    let (width, height) = (96, 64);
+    let frame = scene(width, height);
+    let inverted: Vec<u8> = frame.iter().map(|v| 255 - v).collect();
+
+    let distance = hamming_distance(phash(&frame, width, height), phash(&inverted, width, height));
+    assert!(distance >= 24, "distance {distance}");
+}
+
+#[test]
+fn test_phash_jpeg_matches_raw_frame() -> Result<(), Box<dyn std::error::Error>> {
+    let (width, height) = (96, 64);
+    let frame = scene(width, height);
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(90)?;
+    let jpeg = compressor.compress_to_vec(Image {
+        pixels: frame.as_slice(),
+        width,
+        pitch: width * 3,
+        height,
+        format: PixelFormat::RGB,
+    })?;
+
+    let distance = hamming_distance(phash(&frame, width, height), phash_jpeg(&jpeg)?);
+    assert!(distance <= 4, "distance {distance}");
+    Ok(())
+}
+
+#[test]
+fn test_phash_empty_frame() {
+    assert_eq!(phash_luma(&[], 0, 0), 0);
+    assert_eq!(hamming_distance(0, u64::MAX), 64);
+}
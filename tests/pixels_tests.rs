@@ -0,0 +1,228 @@
+use anyhow::Result;
+use raw_to_jpeg::pixels::{
+    box_resize, composite_over, composite_pixel, luma, luma_bt601, reduce_lsb10, reduce_p010, tone_map_gray16,
+    unpack_mono12_packed, ycbcr_to_rgb, ColorMatrix, DepthReduction, GammaLut, Gray16Map, Orientation, PackedFrame,
+    Roi, TenBitReduction,
+};
+
+#[test]
+fn test_downscale_averages_blocks() -> Result<()> {
+    // 4x2 RGB: left 2x2 block black/white mix, right 2x2 block solid red
+    #[rustfmt::skip]
+    let pixels = [
+        0, 0, 0,        255, 255, 255,  255, 0, 0,  255, 0, 0,
+        255, 255, 255,  0, 0, 0,        255, 0, 0,  255, 0, 0,
+    ];
+    let frame = PackedFrame::borrowed(&pixels, 4, 2, 3);
+
+    let scaled = frame.downscale(2)?;
+    assert_eq!((scaled.width, scaled.height), (2, 1));
+    assert_eq!(scaled.pixels.as_ref(), &[128, 128, 128, 255, 0, 0]);
+
+    assert!(frame.downscale(4).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_strided_frame() -> Result<()> {
+    // 2x2 RGB with two bytes of padding (99) after each row
+    #[rustfmt::skip]
+    let pixels = [
+        1, 1, 1,  2, 2, 2,  99, 99,
+        3, 3, 3,  4, 4, 4,  99, 99,
+    ];
+    let frame = PackedFrame::strided(&pixels, 2, 2, 3, 8);
+    assert_eq!(frame.pitch(), 8);
+
+    let cropped = frame.crop(Roi { x: 1, y: 0, width: 1, height: 2 })?;
+    assert_eq!(cropped.pixels.as_ref(), &[2, 2, 2, 4, 4, 4]);
+    assert_eq!(frame.downscale(2)?.pixels.as_ref(), &[3, 3, 3]);
+
+    let tight = frame.into_tight();
+    assert_eq!(tight.pitch(), 6);
+    assert_eq!(tight.pixels.as_ref(), &[1, 1, 1, 2, 2, 2, 3, 3, 3, 4, 4, 4]);
+    Ok(())
+}
+
+#[test]
+fn test_orient_moves_corners() {
+    // 3x2 gray frame, one distinct value per pixel
+    #[rustfmt::skip]
+    let pixels = [
+        1, 2, 3,
+        4, 5, 6,
+    ];
+    let orient = |orientation| {
+        let frame = PackedFrame::borrowed(&pixels, 3, 2, 1).orient(orientation);
+        (frame.width, frame.height, frame.pixels.into_owned())
+    };
+
+    // Clockwise: the top-left corner ends up top-right, the bottom-left one top-left
+    assert_eq!(orient(Orientation::Rot90), (2, 3, vec![4, 1, 5, 2, 6, 3]));
+    assert_eq!(orient(Orientation::Rot270), (2, 3, vec![3, 6, 2, 5, 1, 4]));
+    assert_eq!(orient(Orientation::Rot180), (3, 2, vec![6, 5, 4, 3, 2, 1]));
+    assert_eq!(orient(Orientation::FlipH), (3, 2, vec![3, 2, 1, 6, 5, 4]));
+    assert_eq!(orient(Orientation::FlipV), (3, 2, vec![4, 5, 6, 1, 2, 3]));
+    assert_eq!(orient(Orientation::None), (3, 2, pixels.to_vec()));
+}
+
+#[test]
+fn test_orient_keeps_pixels_whole() {
+    // 2x1 RGB: red, blue
+    let pixels = [255, 0, 0, 0, 0, 255];
+    let rotated = PackedFrame::borrowed(&pixels, 2, 1, 3).orient(Orientation::Rot90);
+    assert_eq!((rotated.width, rotated.height), (1, 2));
+    assert_eq!(rotated.pixels.as_ref(), &pixels);
+}
+
+#[test]
+fn test_gray16_ramp_maps_monotonically() {
+    let ramp: Vec<u8> = (0..256u32)
+        .flat_map(|i| ((1000 + i * 40) as u16).to_le_bytes())
+        .collect();
+
+    for map in [Gray16Map::Shift8, Gray16Map::MinMax] {
+        let mapped = tone_map_gray16(&ramp, map);
+        assert_eq!(mapped.len(), 256);
+        assert!(mapped.windows(2).all(|pair| pair[0] <= pair[1]), "{map:?} is not monotonic");
+    }
+
+    // minmax stretches the used range to the full 8 bits
+    let stretched = tone_map_gray16(&ramp, Gray16Map::MinMax);
+    assert_eq!((stretched[0], stretched[255]), (0, 255));
+
+    // shift8 keeps the high byte
+    let shifted = tone_map_gray16(&ramp, Gray16Map::Shift8);
+    assert_eq!((shifted[0], shifted[255]), ((1000u16 >> 8) as u8, (11200u16 >> 8) as u8));
+}
+
+#[test]
+fn test_luma_bt601() {
+    let rgb = [255, 255, 255, 0, 0, 0, 255, 0, 0, 0, 255, 0, 0, 0, 255];
+    assert_eq!(luma_bt601(&rgb, 3, 0, 2), [255, 0, 76, 150, 29]);
+
+    // Same pixels as BGRA give the same luma
+    let bgra: Vec<u8> = rgb.chunks_exact(3).flat_map(|p| [p[2], p[1], p[0], 0xFF]).collect();
+    assert_eq!(luma_bt601(&bgra, 4, 2, 0), [255, 0, 76, 150, 29]);
+}
+
+#[test]
+fn test_luma_matrices_differ_on_blue() {
+    let white_and_blue = [255, 255, 255, 0, 0, 255];
+    assert_eq!(luma(&white_and_blue, 3, 0, 2, ColorMatrix::Bt601), [255, 29]);
+    assert_eq!(luma(&white_and_blue, 3, 0, 2, ColorMatrix::Bt709), [255, 18]);
+}
+
+#[test]
+fn test_reduce_p010() {
+    // 10-bit samples 0, 1, 2, 513 and 1023 in the high bits of little-endian words
+    let p010: Vec<u8> = [0u16, 1, 2, 513, 1023].iter().flat_map(|v| (v << 6).to_le_bytes()).collect();
+    assert_eq!(reduce_p010(&p010, DepthReduction::Truncate), [0, 0, 0, 128, 255]);
+    assert_eq!(reduce_p010(&p010, DepthReduction::Round), [0, 0, 1, 128, 255]);
+}
+
+#[test]
+fn test_reduce_lsb10() {
+    // 10-bit samples in the low bits of little-endian words; 4095 is out of range
+    let planar: Vec<u8> = [0u16, 3, 513, 1020, 1023, 4095].iter().flat_map(|v| v.to_le_bytes()).collect();
+    assert_eq!(reduce_lsb10(&planar, TenBitReduction::Shift), [0, 0, 128, 255, 255, 255]);
+    assert_eq!(reduce_lsb10(&planar, TenBitReduction::Scale), [0, 1, 128, 254, 255, 255]);
+}
+
+#[test]
+fn test_box_resize() {
+    // 4x2 gray ramp halved in both directions, then squeezed to a single pixel
+    let ramp = [0u8, 10, 20, 30, 40, 50, 60, 70];
+    let sample = |x: usize, y: usize| [ramp[y * 4 + x]; 3];
+    assert_eq!(box_resize(4, 2, 2, 1, sample), [25, 25, 25, 45, 45, 45]);
+    assert_eq!(box_resize(4, 2, 1, 1, sample), [35, 35, 35]);
+}
+
+#[test]
+fn test_ycbcr_to_rgb() {
+    assert_eq!(ycbcr_to_rgb([128, 128, 128]), [128, 128, 128]);
+    assert_eq!(ycbcr_to_rgb([255, 128, 128]), [255, 255, 255]);
+    // Pure red as libjpeg encodes it
+    assert_eq!(ycbcr_to_rgb([76, 85, 255]), [254, 0, 0]);
+}
+
+/// Packs 12-bit samples as Mono12Packed, the layout GigE Vision cameras send.
+fn pack_mono12(samples: &[u16]) -> Vec<u8> {
+    samples
+        .chunks(2)
+        .flat_map(|pair| match *pair {
+            [first, second] => vec![
+                (first >> 4) as u8,
+                (first & 0x0F) as u8 | ((second & 0x0F) << 4) as u8,
+                (second >> 4) as u8,
+            ],
+            [last] => vec![(last >> 4) as u8, (last & 0x0F) as u8],
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+#[test]
+fn test_unpack_mono12_packed_ramp() {
+    // Full 12-bit ramp over an odd pixel count, so the last sample is a 2-byte group
+    let ramp: Vec<u16> = (0..1025u32).map(|i| (i * 4095 / 1024) as u16).collect();
+    let packed = pack_mono12(&ramp);
+    assert_eq!(packed.len(), (ramp.len() * 3).div_ceil(2));
+
+    let unpacked = unpack_mono12_packed(&packed, ramp.len(), 4);
+    assert_eq!(unpacked.len(), ramp.len());
+    assert!(unpacked.windows(2).all(|pair| pair[0] <= pair[1]), "ramp is not monotonic");
+    assert_eq!((unpacked[0], unpacked[1024]), (0, 255));
+    assert!(unpacked.iter().zip(&ramp).all(|(&value, &sample)| value == (sample >> 4) as u8));
+
+    // A smaller shift brightens and saturates instead of wrapping
+    let brightened = unpack_mono12_packed(&packed, ramp.len(), 2);
+    assert!(brightened.windows(2).all(|pair| pair[0] <= pair[1]));
+    assert_eq!(brightened[1024], 255);
+    assert_eq!(brightened[100], (ramp[100] >> 2) as u8);
+}
+
+#[test]
+fn test_composite_keys_out_transparent_pixels() {
+    const BACKGROUND: [u8; 3] = [0, 128, 0];
+    for premultiplied in [false, true] {
+        // Fully transparent is the background, whatever color the source left under it
+        assert_eq!(composite_pixel([200, 40, 90], 0, BACKGROUND, premultiplied), BACKGROUND);
+        // Fully opaque is the color, untouched by the background
+        assert_eq!(composite_pixel([200, 40, 90], 255, BACKGROUND, premultiplied), [200, 40, 90]);
+    }
+    // Half transparent blends linearly: (200 * 128 + 0 * 127) / 255, (40 * 128 + 128 * 127) / 255, ...
+    assert_eq!(composite_pixel([200, 40, 90], 128, BACKGROUND, false), [100, 84, 45]);
+    assert_eq!(composite_pixel([100, 20, 45], 128, BACKGROUND, true), [100, 84, 45]);
+
+    let rgba = [200, 40, 90, 0, 200, 40, 90, 128, 200, 40, 90, 255];
+    assert_eq!(composite_over(&rgba, BACKGROUND, false), vec![0, 128, 0, 100, 84, 45, 200, 40, 90]);
+}
+
+#[test]
+fn test_composite_over_white() {
+    const WHITE: [u8; 3] = [255, 255, 255];
+    // 50% red over white is pink, whether stored straight or premultiplied
+    assert_eq!(composite_pixel([255, 0, 0], 128, WHITE, false), [255, 127, 127]);
+    assert_eq!(composite_pixel([128, 0, 0], 128, WHITE, true), [255, 127, 127]);
+
+    // Opaque pixels keep their color, transparent ones become the background
+    assert_eq!(composite_pixel([10, 20, 30], 255, WHITE, false), [10, 20, 30]);
+    assert_eq!(composite_pixel([10, 20, 30], 0, WHITE, false), WHITE);
+
+    let rgba = [255, 0, 0, 128, 0, 0, 255, 255];
+    assert_eq!(composite_over(&rgba, WHITE, false), vec![255, 127, 127, 0, 0, 255]);
+}
+
+#[test]
+fn test_gamma_lut() {
+    let lut = GammaLut::new(2.2);
+    assert_eq!((lut.map(0), lut.map(128), lut.map(255)), (0, 186, 255));
+    let identity = GammaLut::new(1.0);
+    assert!((0..=255).all(|value| identity.map(value) == value));
+
+    // Alpha is left alone
+    let mut rgba = [128, 64, 0, 128];
+    lut.apply(&mut rgba, 4);
+    assert_eq!(rgba, [186, 136, 0, 128]);
+}
@@ -0,0 +1,30 @@
+use raw_to_jpeg::{resolve_quality, ConversionError, QualitySource, DEFAULT_JPEG_QUALITY, JPEG_QUALITY_ENV};
+
+// One test for the whole precedence order: the environment is shared by every test thread
+#[test]
+fn test_resolve_quality_precedence() {
+    std::env::remove_var(JPEG_QUALITY_ENV);
+    assert_eq!(resolve_quality(None).unwrap(), (DEFAULT_JPEG_QUALITY, QualitySource::Default));
+    assert_eq!(resolve_quality(Some("75")).unwrap(), (75, QualitySource::Config));
+
+    std::env::set_var(JPEG_QUALITY_ENV, "40");
+    assert_eq!(resolve_quality(Some("75")).unwrap(), (40, QualitySource::Env));
+    assert_eq!(resolve_quality(None).unwrap(), (40, QualitySource::Env));
+
+    // An empty variable counts as unset
+    std::env::set_var(JPEG_QUALITY_ENV, "");
+    assert_eq!(resolve_quality(Some("75")).unwrap(), (75, QualitySource::Config));
+
+    // An invalid override is an error, not a fallback to the config
+    std::env::set_var(JPEG_QUALITY_ENV, "101");
+    let err = resolve_quality(Some("75")).unwrap_err();
+    assert!(matches!(err, ConversionError::InvalidQuality { setting: "JPEG_QUALITY", .. }));
+    assert_eq!(err.to_string(), "JPEG_QUALITY must be an integer between 1 and 100, got '101'");
+
+    std::env::remove_var(JPEG_QUALITY_ENV);
+    let err = resolve_quality(Some("high")).unwrap_err();
+    assert!(matches!(err, ConversionError::InvalidQuality { setting: "jpeg_quality", ref value } if value == "high"));
+    assert!(resolve_quality(Some("-1")).is_err());
+    // libjpeg-turbo rejects quality 0
+    assert!(resolve_quality(Some("0")).is_err());
+}
@@ -0,0 +1,46 @@
+use raw_to_jpeg::{ConversionStats, RatioMonitor, DEFAULT_RATIO_BAND};
+
+#[test]
+fn test_ratio_inside_band() {
+    let mut monitor = RatioMonitor::new(2.0, 100.0);
+    assert_eq!(monitor.check(10.0), None);
+    assert_eq!(monitor.check(2.0), None);
+    assert_eq!(monitor.check(100.0), None);
+}
+
+#[test]
+fn test_ratio_outside_band() {
+    let mut monitor = RatioMonitor::new(2.0, 100.0);
+    assert_eq!(monitor.check(1.25), Some(1.25));
+    assert_eq!(RatioMonitor::new(2.0, 100.0).check(200.0), Some(200.0));
+}
+
+#[test]
+fn test_ratio_of() {
+    assert_eq!(ConversionStats::ratio_of(100_000, 80_000), 1.25);
+    assert_eq!(ConversionStats::ratio_of(100_000, 500), 200.0);
+    // No output at all counts as ratio 0, which is below any band
+    assert_eq!(ConversionStats::ratio_of(100_000, 0), 0.0);
+    assert_eq!(RatioMonitor::new(2.0, 100.0).check(0.0), Some(0.0));
+}
+
+#[test]
+fn test_ratio_warns_once_per_run() {
+    let mut monitor = RatioMonitor::default();
+    assert_eq!(monitor.check(1.25), Some(1.25));
+    // Still out of band, possibly on the other side: same run
+    assert_eq!(monitor.check(1.1), None);
+    assert_eq!(monitor.check(1000.0), None);
+
+    // Back in band ends the run, so the next bad frame is reported again
+    assert_eq!(monitor.check(10.0), None);
+    assert_eq!(monitor.check(1.25), Some(1.25));
+}
+
+#[test]
+fn test_default_ratio_band() {
+    assert_eq!(DEFAULT_RATIO_BAND, (2.0, 100.0));
+    let mut monitor = RatioMonitor::default();
+    assert_eq!(monitor.check(2.0), None);
+    assert_eq!(monitor.check(100.0), None);
+}
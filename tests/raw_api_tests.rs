@@ -0,0 +1,316 @@
+use anyhow::Result;
+use make87_messages::core::Header;
+use make87_messages::image::uncompressed::image_raw_any::Image as RawImageVariant;
+use make87_messages::image::uncompressed::{ImageRawAny, ImageRgb888, ImageYuv420, ImageYuv444};
+use raw_to_jpeg::{
+    compress_raw, rgb_to_jpeg, rgb_to_jpeg_with_config, rgb_to_jpeg_with_header, ConversionConfig, ConversionError,
+    FormatQuality, RawPixelFormat,
+};
+use std::fs;
+use std::path::Path;
+use turbojpeg::{Colorspace, Compressor, Subsamp};
+
+const TEST_WIDTH: u32 = 176;
+const TEST_HEIGHT: u32 = 144;
+const JPEG_QUALITY: i32 = 90;
+
+fn load_test_file(filename: &str) -> Result<Vec<u8>> {
+    let path = Path::new("tests/data/input").join(filename);
+    Ok(fs::read(path)?)
+}
+
+#[test]
+fn test_compress_raw_rgb888() -> Result<()> {
+    let raw_data = load_test_file("tulips_rgb444_prog_packed_qcif.yuv")?;
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+
+    let jpeg = compress_raw(
+        &raw_data,
+        TEST_WIDTH as usize,
+        TEST_HEIGHT as usize,
+        RawPixelFormat::Rgb888,
+        &mut compressor,
+    )?;
+    let header = turbojpeg::read_header(&jpeg)?;
+    assert_eq!((header.width, header.height), (TEST_WIDTH as usize, TEST_HEIGHT as usize));
+
+    // The message path goes through the same code, so the bytes must match
+    let image_raw = ImageRawAny {
+        header: None,
+        image: Some(RawImageVariant::Rgb888(ImageRgb888 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: raw_data,
+        })),
+    };
+    assert_eq!(rgb_to_jpeg(&image_raw, &mut compressor, &ConversionConfig::default())?.data, jpeg);
+    Ok(())
+}
+
+#[test]
+fn test_compress_raw_yuv420() -> Result<()> {
+    let raw_data = load_test_file("tulips_yuv420_prog_planar_qcif.yuv")?;
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+
+    let jpeg = compress_raw(
+        &raw_data,
+        TEST_WIDTH as usize,
+        TEST_HEIGHT as usize,
+        RawPixelFormat::Yuv420,
+        &mut compressor,
+    )?;
+    let header = turbojpeg::read_header(&jpeg)?;
+    assert_eq!(header.subsamp, Subsamp::Sub2x2);
+    assert_eq!((header.width, header.height), (TEST_WIDTH as usize, TEST_HEIGHT as usize));
+
+    let image_raw = ImageRawAny {
+        header: None,
+        image: Some(RawImageVariant::Yuv420(ImageYuv420 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: raw_data.clone(),
+        })),
+    };
+    assert_eq!(rgb_to_jpeg(&image_raw, &mut compressor, &ConversionConfig::default())?.data, jpeg);
+
+    let err = compress_raw(
+        &raw_data[..100],
+        TEST_WIDTH as usize,
+        TEST_HEIGHT as usize,
+        RawPixelFormat::Yuv420,
+        &mut compressor,
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!("YUV420 data too small: expected {}, got 100", TEST_WIDTH * TEST_HEIGHT * 3 / 2)
+    );
+    Ok(())
+}
+
+#[test]
+fn test_compress_raw_yv12_matches_i420() -> Result<()> {
+    let i420 = load_test_file("tulips_yuv420_prog_planar_qcif.yuv")?;
+    let (width, height) = (TEST_WIDTH as usize, TEST_HEIGHT as usize);
+    let y_size = width * height;
+    let chroma_size = y_size / 4;
+    let (y_plane, chroma) = i420.split_at(y_size);
+    let (u_plane, v_plane) = chroma[..2 * chroma_size].split_at(chroma_size);
+    let yv12 = [y_plane, v_plane, u_plane].concat();
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+
+    let from_i420 = compress_raw(&i420, width, height, RawPixelFormat::Yuv420, &mut compressor)?;
+    let from_yv12 = compress_raw(&yv12, width, height, RawPixelFormat::Yv12, &mut compressor)?;
+    assert_eq!(from_yv12, from_i420);
+
+    // Read as I420, the swapped planes would tint the image
+    let swapped = compress_raw(&yv12, width, height, RawPixelFormat::Yuv420, &mut compressor)?;
+    assert_ne!(swapped, from_i420);
+    Ok(())
+}
+
+#[test]
+fn test_compress_raw_yuv444_packed_matches_planar() -> Result<()> {
+    let planar = load_test_file("tulips_yuv444_prog_planar_qcif.yuv")?;
+    let (width, height) = (TEST_WIDTH as usize, TEST_HEIGHT as usize);
+    let pixels = width * height;
+    let packed: Vec<u8> = (0..pixels)
+        .flat_map(|i| [planar[i], planar[pixels + i], planar[2 * pixels + i]])
+        .collect();
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+
+    let from_planar = compress_raw(&planar, width, height, RawPixelFormat::Yuv444, &mut compressor)?;
+    let from_packed = compress_raw(&packed, width, height, RawPixelFormat::Yuv444Packed, &mut compressor)?;
+    assert_eq!(from_packed, from_planar);
+
+    // The same bytes in an ImageYuv444 message, flagged as interleaved
+    let frame = ImageRawAny {
+        header: None,
+        image: Some(RawImageVariant::Yuv444(ImageYuv444 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: packed.clone(),
+        })),
+    };
+    let config = ConversionConfig {
+        yuv444_packed: true,
+        ..Default::default()
+    };
+    assert_eq!(rgb_to_jpeg_with_config(&frame, &mut compressor, &config)?.data, from_planar);
+
+    // Read as planar, the interleaved bytes scramble the image
+    let scrambled = compress_raw(&packed, width, height, RawPixelFormat::Yuv444, &mut compressor)?;
+    assert_ne!(scrambled, from_planar);
+    Ok(())
+}
+
+#[test]
+fn test_compress_raw_yuv411() -> Result<()> {
+    let planar = load_test_file("tulips_yuv444_prog_planar_qcif.yuv")?;
+    let (width, height) = (TEST_WIDTH as usize, TEST_HEIGHT as usize);
+    let pixels = width * height;
+    // Keep every fourth chroma sample of each row: quarter width, full height
+    let quarter_width = |plane: &[u8]| -> Vec<u8> {
+        plane.chunks(width).flat_map(|row| row.iter().step_by(4).copied()).collect()
+    };
+    let yuv411 = [
+        &planar[..pixels],
+        &quarter_width(&planar[pixels..2 * pixels])[..],
+        &quarter_width(&planar[2 * pixels..3 * pixels])[..],
+    ]
+    .concat();
+    assert_eq!(yuv411.len(), RawPixelFormat::Yuv411.expected_len(width, height));
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+    let jpeg = compress_raw(&yuv411, width, height, RawPixelFormat::Yuv411, &mut compressor)?;
+    let header = turbojpeg::read_header(&jpeg)?;
+    assert_eq!((header.width, header.height), (width, height));
+    assert_eq!(header.subsamp, Subsamp::Sub4x1);
+    turbojpeg::decompress(&jpeg, turbojpeg::PixelFormat::RGB)?;
+
+    let err = compress_raw(&yuv411, width - 2, height, RawPixelFormat::Yuv411, &mut compressor).unwrap_err();
+    assert!(matches!(err, ConversionError::InvalidDimensions { format: "YUV411", .. }));
+    let err = compress_raw(&yuv411[..pixels], width, height, RawPixelFormat::Yuv411, &mut compressor).unwrap_err();
+    assert!(matches!(err, ConversionError::BufferTooSmall { format: "YUV411", .. }));
+    Ok(())
+}
+
+#[test]
+fn test_rgb_to_jpeg_with_header_moves_header() -> Result<()> {
+    let mut frame = ImageRawAny {
+        header: Some(Header {
+            reference_id: 42,
+            // Stands in for bulky calibration metadata
+            entity_path: "calibration/".repeat(100_000),
+            ..Default::default()
+        }),
+        image: Some(RawImageVariant::Rgb888(ImageRgb888 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: load_test_file("tulips_rgb444_prog_packed_qcif.yuv")?,
+        })),
+    };
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+
+    let header = frame.header.take();
+    let metadata = header.as_ref().map(|header| header.entity_path.as_ptr());
+    let jpeg = rgb_to_jpeg_with_header(&frame, header, &mut compressor, &ConversionConfig::default())?;
+
+    // Same allocation: the header was moved into the output, not cloned
+    let output_header = jpeg.header.as_ref().expect("header");
+    assert_eq!(Some(output_header.entity_path.as_ptr()), metadata);
+    assert_eq!(output_header.reference_id, 42);
+    assert_eq!(jpeg.data, rgb_to_jpeg(&frame, &mut compressor, &ConversionConfig::default())?.data);
+    Ok(())
+}
+
+/// Number of components declared by the first SOF (start of frame) marker of `jpeg`.
+fn sof_components(jpeg: &[u8]) -> Option<u8> {
+    // Marker segments from after SOI up to the frame header: FF xx, then a big-endian length
+    let mut pos = 2;
+    while pos + 4 <= jpeg.len() && jpeg[pos] == 0xFF {
+        let marker = jpeg[pos + 1];
+        if matches!(marker, 0xC0..=0xC3) {
+            // Length (2), precision (1), height (2), width (2), then the component count
+            return jpeg.get(pos + 9).copied();
+        }
+        pos += 2 + u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]) as usize;
+    }
+    None
+}
+
+#[test]
+fn test_compress_raw_cmyk() -> Result<()> {
+    let (width, height) = (32usize, 16usize);
+    // Cyan to black gradient across, magenta rising down
+    let cmyk: Vec<u8> = (0..height)
+        .flat_map(|y| (0..width).flat_map(move |x| [255 - (x * 8) as u8, (y * 16) as u8, 40, (x * 8) as u8]))
+        .collect();
+
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(JPEG_QUALITY)?;
+    let jpeg = compress_raw(&cmyk, width, height, RawPixelFormat::Cmyk, &mut compressor)?;
+    assert_eq!(&jpeg[..2], &[0xFF, 0xD8]);
+    assert_eq!(&jpeg[jpeg.len() - 2..], &[0xFF, 0xD9]);
+    assert_eq!(sof_components(&jpeg), Some(4));
+    let header = turbojpeg::read_header(&jpeg)?;
+    assert_eq!((header.width, header.height), (width, height));
+    assert_eq!(header.colorspace, Colorspace::YCCK);
+
+    // CMYK decodes back close to the input
+    let decoded = turbojpeg::decompress(&jpeg, turbojpeg::PixelFormat::CMYK)?;
+    let center = (height / 2) * decoded.pitch + (width / 2) * 4;
+    let expected = &cmyk[(height / 2) * width * 4 + (width / 2) * 4..][..4];
+    for (got, want) in decoded.pixels[center..center + 4].iter().zip(expected) {
+        assert!(got.abs_diff(*want) <= 8, "{:?} vs {:?}", &decoded.pixels[center..center + 4], expected);
+    }
+
+    let err = compress_raw(&cmyk[..cmyk.len() - 1], width, height, RawPixelFormat::Cmyk, &mut compressor).unwrap_err();
+    assert!(matches!(err, ConversionError::BufferTooSmall { format: "CMYK", expected, .. } if expected == cmyk.len()));
+    Ok(())
+}
+
+#[test]
+fn test_expected_len() {
+    assert_eq!(RawPixelFormat::Gray8.expected_len(4, 2), 8);
+    assert_eq!(RawPixelFormat::Nv21.expected_len(4, 2), 12);
+    assert_eq!(RawPixelFormat::Yv12.expected_len(4, 2), 12);
+    assert_eq!(RawPixelFormat::Yuv444Packed.expected_len(4, 2), 24);
+    assert_eq!(RawPixelFormat::Yuyv.expected_len(4, 2), 16);
+    assert_eq!(RawPixelFormat::Bgr888.expected_len(4, 2), 24);
+    assert_eq!(RawPixelFormat::Rgba8888.expected_len(4, 2), 32);
+    assert_eq!(RawPixelFormat::Cmyk.expected_len(4, 2), 32);
+}
+
+#[test]
+fn test_format_quality_resolution() -> Result<()> {
+    let format_quality = FormatQuality::parse([("yuv420", 80), ("RGB888", 92), ("default", 90)])?;
+    assert_eq!(format_quality.resolve(RawPixelFormat::Yuv420, 75), 80);
+    assert_eq!(format_quality.resolve(RawPixelFormat::Rgb888, 75), 92);
+    // Unlisted formats fall back to the default entry
+    assert_eq!(format_quality.resolve(RawPixelFormat::Nv12, 75), 90);
+
+    // Without a default entry, the caller's quality applies
+    let format_quality = FormatQuality::parse([("nv12", 60)])?;
+    assert_eq!(format_quality.resolve(RawPixelFormat::Nv12, 75), 60);
+    assert_eq!(format_quality.resolve(RawPixelFormat::Yuv444, 75), 75);
+    assert_eq!(FormatQuality::default().resolve(RawPixelFormat::Rgb888, 75), 75);
+    Ok(())
+}
+
+#[test]
+fn test_format_quality_accepts_every_named_format() -> Result<()> {
+    // The app manifest documents the accepted keys; keep it in step with the named formats
+    let manifest = fs::read_to_string("MAKE87.yml")?;
+    for format in RawPixelFormat::NAMED {
+        let name = format.name().to_ascii_lowercase();
+        let format_quality = FormatQuality::parse([(name.as_str(), 80)])?;
+        assert_eq!(format_quality.resolve(format, 75), 80);
+        assert!(manifest.contains(&format!("{name},")) || manifest.contains(&format!("{name})")), "{name}");
+    }
+    Ok(())
+}
+
+#[test]
+fn test_format_quality_rejects_bad_entries() {
+    let err = FormatQuality::parse([("yuv999", 80)]).unwrap_err();
+    assert!(matches!(err, ConversionError::InvalidFormatQuality(_)));
+    assert_eq!(err.to_string(), "Invalid format quality: unknown format 'yuv999'");
+
+    let err = FormatQuality::parse([("rgb888", 101)]).unwrap_err();
+    assert_eq!(err.to_string(), "Invalid format quality: 'rgb888': quality 101 is not within 1-100");
+}
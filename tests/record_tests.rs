@@ -0,0 +1,38 @@
+use make87_messages::google::protobuf::Timestamp;
+use raw_to_jpeg::record::{record_file_name, FrameRecorder};
+use std::path::PathBuf;
+
+fn at(seconds: i64, nanos: i32) -> Timestamp {
+    Timestamp { seconds, nanos }
+}
+
+#[test]
+fn test_record_file_name() {
+    // 2009-02-13 23:31:30 UTC
+    assert_eq!(record_file_name(&at(1_234_567_890, 5), 0), "20090213-233130.000000005.jpg");
+    assert_eq!(record_file_name(&at(1_234_567_890, 5), 2), "20090213-233130.000000005-2.jpg");
+    assert_eq!(record_file_name(&at(0, 0), 0), "19700101-000000.000000000.jpg");
+}
+
+#[test]
+fn test_recorder_every_nth_frame() {
+    let mut recorder = FrameRecorder::new("rec", 3);
+    let recorded: Vec<bool> = (0..7).map(|i| recorder.next_path(&at(100 + i, 0)).is_some()).collect();
+    assert_eq!(recorded, [true, false, false, true, false, false, true]);
+}
+
+#[test]
+fn test_recorder_names_do_not_collide() {
+    let mut recorder = FrameRecorder::new("rec", 1);
+    let stamp = at(1_234_567_890, 0);
+    let first = recorder.next_path(&stamp).unwrap();
+    let second = recorder.next_path(&stamp).unwrap();
+    let third = recorder.next_path(&stamp).unwrap();
+    assert_eq!(first, PathBuf::from("rec/20090213-233130.000000000.jpg"));
+    assert_eq!(second, PathBuf::from("rec/20090213-233130.000000000-1.jpg"));
+    assert_eq!(third, PathBuf::from("rec/20090213-233130.000000000-2.jpg"));
+
+    // A new timestamp starts without a suffix again
+    let next = recorder.next_path(&at(1_234_567_891, 0)).unwrap();
+    assert_eq!(next, PathBuf::from("rec/20090213-233131.000000000.jpg"));
+}
@@ -0,0 +1,65 @@
+use anyhow::Result;
+use raw_to_jpeg::reload::{parse_config_file, reload_config, LiveConfig};
+use raw_to_jpeg::{ConversionConfig, ConversionError};
+use std::sync::Arc;
+use turbojpeg::Subsamp;
+
+fn base_config() -> ConversionConfig {
+    ConversionConfig {
+        subsamp: Some(Subsamp::Sub2x2),
+        progressive: true,
+        ..ConversionConfig::default()
+    }
+}
+
+#[test]
+fn test_config_file_overrides_base() -> Result<()> {
+    let config = parse_config_file(r#"{"jpeg_quality": 60, "jpeg_subsamp": "444"}"#, &base_config())?;
+    assert_eq!(config.quality, Some(60));
+    assert_eq!(config.subsamp, Some(Subsamp::None));
+    assert!(config.progressive);
+
+    // Keys left out fall back to the base settings
+    let config = parse_config_file("{}", &base_config())?;
+    assert_eq!(config.quality, None);
+    assert_eq!(config.subsamp, Some(Subsamp::Sub2x2));
+    Ok(())
+}
+
+#[test]
+fn test_invalid_config_file_is_rejected() {
+    for contents in [
+        "not json",
+        "[80]",
+        r#"{"jpeg_quality": 101}"#,
+        r#"{"jpeg_quality": "80"}"#,
+        r#"{"jpeg_subsamp": 420}"#,
+        r#"{"jpeg_qualty": 80}"#,
+    ] {
+        let err = parse_config_file(contents, &base_config()).unwrap_err();
+        assert!(
+            matches!(err, ConversionError::InvalidConfigFile(_)),
+            "{contents}: {err}"
+        );
+    }
+    let err = parse_config_file(r#"{"jpeg_subsamp": "411"}"#, &base_config()).unwrap_err();
+    assert!(matches!(err, ConversionError::UnsupportedSubsampling(_)));
+}
+
+#[test]
+fn test_reload_swaps_live_config() -> Result<()> {
+    let live = LiveConfig::new(base_config());
+    let before = live.load();
+
+    let reloaded = reload_config(&live, &base_config(), r#"{"jpeg_quality": 40, "jpeg_subsamp": "gray"}"#)?;
+    let after = live.load();
+    assert!(!Arc::ptr_eq(&before, &after));
+    assert!(Arc::ptr_eq(&reloaded, &after));
+    assert_eq!(after.quality, Some(40));
+    assert_eq!(after.subsamp, Some(Subsamp::Gray));
+
+    // A broken file keeps the settings in effect
+    assert!(reload_config(&live, &base_config(), "{").is_err());
+    assert!(Arc::ptr_eq(&live.load(), &after));
+    Ok(())
+}
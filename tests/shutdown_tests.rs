@@ -0,0 +1,47 @@
+use raw_to_jpeg::shutdown::{next_step, on_conversion_error, parse_error_policy, ErrorPolicy, LoopStep, StopReason};
+use raw_to_jpeg::ConversionError;
+
+#[test]
+fn test_shutdown_signal_stops_loop() {
+    let step = next_step::<u32, String>(None);
+    assert_eq!(step, LoopStep::Stop(StopReason::ShutdownRequested));
+}
+
+#[test]
+fn test_received_sample_is_processed() {
+    let step = next_step::<u32, String>(Some(Ok(7)));
+    assert_eq!(step, LoopStep::Process(7));
+}
+
+#[test]
+fn test_subscriber_error_stops_loop() {
+    let step = next_step::<u32, String>(Some(Err("closed".to_string())));
+    assert_eq!(step, LoopStep::Stop(StopReason::SubscriberClosed));
+}
+
+#[test]
+fn test_error_policy_continue_keeps_loop_running() {
+    let error = ConversionError::ZeroDimension { width: 0, height: 480 };
+    assert!(on_conversion_error(ErrorPolicy::Continue, error).is_ok());
+}
+
+#[test]
+fn test_error_policy_stop_returns_error() {
+    let error = ConversionError::ZeroDimension { width: 0, height: 480 };
+    let returned = on_conversion_error(ErrorPolicy::Stop, error).unwrap_err();
+    assert!(matches!(
+        returned,
+        ConversionError::ZeroDimension { width: 0, height: 480 }
+    ));
+}
+
+#[test]
+fn test_parse_error_policy() {
+    assert_eq!(parse_error_policy("continue").unwrap(), ErrorPolicy::Continue);
+    assert_eq!(parse_error_policy("stop").unwrap(), ErrorPolicy::Stop);
+    assert_eq!(ErrorPolicy::default(), ErrorPolicy::Continue);
+    assert!(matches!(
+        parse_error_policy("abort"),
+        Err(ConversionError::UnsupportedErrorPolicy(name)) if name == "abort"
+    ));
+}
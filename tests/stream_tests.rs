@@ -0,0 +1,95 @@
+use anyhow::Result;
+use raw_to_jpeg::stream::StreamingEncoder;
+use raw_to_jpeg::ConversionError;
+use turbojpeg::{Compressor, Image, PixelFormat, Subsamp};
+
+/// Deterministic RGB888 test pattern: gradients with some high-frequency detail.
+fn test_pattern(width: usize, height: usize) -> Vec<u8> {
+    let mut seed = 0x2545_F491_u32;
+    let mut data = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        for x in 0..width {
+            for channel in 0..3 {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                let noise = (seed % 24) as usize;
+                data.push(((x * 3 + y * 2 + channel * 80 + noise) % 256) as u8);
+            }
+        }
+    }
+    data
+}
+
+fn one_shot(data: &[u8], width: usize, height: usize, quality: i32, subsamp: Subsamp) -> Result<Vec<u8>> {
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(quality)?;
+    compressor.set_subsamp(subsamp)?;
+    let image = Image {
+        pixels: data,
+        width,
+        pitch: width * 3,
+        height,
+        format: PixelFormat::RGB,
+    };
+    Ok(compressor.compress_to_vec(image)?)
+}
+
+fn streamed(data: &[u8], width: usize, height: usize, quality: u8, subsamp: Subsamp, batch: usize) -> Result<Vec<u8>> {
+    let mut encoder = StreamingEncoder::new(Vec::new(), width, height, quality, subsamp)?;
+    for chunk in data.chunks(batch) {
+        encoder.write_rows(chunk)?;
+    }
+    Ok(encoder.finish()?)
+}
+
+#[test]
+fn test_streamed_output_matches_one_shot() -> Result<()> {
+    // MCU-aligned, and sizes with partial MCUs and odd dimensions on both edges
+    for (width, height) in [(176, 144), (33, 17), (1, 1), (15, 31)] {
+        let data = test_pattern(width, height);
+        for subsamp in [Subsamp::None, Subsamp::Sub2x1, Subsamp::Sub2x2, Subsamp::Gray] {
+            for quality in [10, 75, 100] {
+                let expected = one_shot(&data, width, height, quality as i32, subsamp)?;
+                // Whole rows at a time, and batches that split rows
+                for batch in [width * 3 * 5, 7] {
+                    let actual = streamed(&data, width, height, quality, subsamp, batch)?;
+                    assert!(
+                        actual == expected,
+                        "{}x{} {:?} q{} batch {}: streamed {} bytes, one-shot {} bytes",
+                        width,
+                        height,
+                        subsamp,
+                        quality,
+                        batch,
+                        actual.len(),
+                        expected.len()
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_streaming_row_count() -> Result<()> {
+    let data = test_pattern(16, 8);
+
+    let mut encoder = StreamingEncoder::new(Vec::new(), 16, 8, 90, Subsamp::Sub2x2)?;
+    encoder.write_rows(&data[..16 * 3 * 5])?;
+    assert_eq!(encoder.rows_written(), 5);
+    let err = encoder.finish().unwrap_err();
+    assert!(matches!(err, ConversionError::RowCountMismatch { expected: 8, got: 5 }));
+
+    let mut encoder = StreamingEncoder::new(Vec::new(), 16, 8, 90, Subsamp::Sub2x2)?;
+    encoder.write_rows(&data)?;
+    let err = encoder.write_rows(&data[..3]).unwrap_err();
+    assert!(matches!(err, ConversionError::RowCountMismatch { expected: 8, got: 9 }));
+
+    let err = StreamingEncoder::new(Vec::new(), 16, 8, 90, Subsamp::Sub1x2)
+        .err()
+        .unwrap();
+    assert!(matches!(err, ConversionError::UnsupportedSubsampling(_)));
+    Ok(())
+}
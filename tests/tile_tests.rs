@@ -0,0 +1,151 @@
+use anyhow::Result;
+use make87_messages::core::Header;
+use make87_messages::image::uncompressed::image_raw_any::Image as RawImageVariant;
+use make87_messages::image::uncompressed::{ImageRawAny, ImageRgb888, ImageYuv420};
+use raw_to_jpeg::pixels::Roi;
+use raw_to_jpeg::tile::{rgb_to_jpeg_tiles, rgb_to_jpeg_tiles_padded, tile_entity_path, TileSize};
+use raw_to_jpeg::{ConversionConfig, ConversionError};
+use std::fs;
+use turbojpeg::{Compressor, Decompressor, PixelFormat};
+
+const TEST_WIDTH: usize = 176;
+const TEST_HEIGHT: usize = 144;
+
+fn rgb_frame() -> Result<ImageRawAny> {
+    Ok(ImageRawAny {
+        header: Some(Header {
+            reference_id: 7,
+            entity_path: "cameras/pano".to_string(),
+            ..Default::default()
+        }),
+        image: Some(RawImageVariant::Rgb888(ImageRgb888 {
+            header: None,
+            width: TEST_WIDTH as u32,
+            height: TEST_HEIGHT as u32,
+            data: fs::read("tests/data/input/tulips_rgb444_prog_packed_qcif.yuv")?,
+        })),
+    })
+}
+
+#[test]
+fn test_tiles_2x2() -> Result<()> {
+    let frame = rgb_frame()?;
+    let tile_size = TileSize {
+        width: TEST_WIDTH / 2,
+        height: TEST_HEIGHT / 2,
+    };
+    let mut compressor = Compressor::new()?;
+    let tiles = rgb_to_jpeg_tiles(&frame, tile_size, &mut compressor, &ConversionConfig::default())?;
+
+    assert_eq!(tiles.len(), 4);
+    let mut decompressor = Decompressor::new()?;
+    for (index, (tile, jpeg)) in tiles.iter().enumerate() {
+        assert_eq!((tile.row, tile.col), (index / 2, index % 2));
+        assert_eq!((tile.roi.x, tile.roi.y), (tile.col * 88, tile.row * 72));
+
+        let header = decompressor.read_header(&jpeg.data)?;
+        assert_eq!((header.width, header.height), (88, 72));
+
+        let jpeg_header = jpeg.header.as_ref().unwrap();
+        assert_eq!(jpeg_header.reference_id, 7);
+        assert_eq!(jpeg_header.entity_path, format!("cameras/pano/tile/{}/{}", tile.row, tile.col));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_tiles_cut_short_at_edges() -> Result<()> {
+    let frame = rgb_frame()?;
+    let config = ConversionConfig {
+        roi: Some(Roi {
+            x: 16,
+            y: 8,
+            width: 100,
+            height: 64,
+        }),
+        ..Default::default()
+    };
+    let mut compressor = Compressor::new()?;
+    let tiles = rgb_to_jpeg_tiles(&frame, TileSize { width: 64, height: 48 }, &mut compressor, &config)?;
+
+    let regions: Vec<(usize, usize, usize, usize)> =
+        tiles.iter().map(|(tile, _)| (tile.roi.x, tile.roi.y, tile.roi.width, tile.roi.height)).collect();
+    assert_eq!(regions, [(16, 8, 64, 48), (80, 8, 36, 48), (16, 56, 64, 16), (80, 56, 36, 16)]);
+    Ok(())
+}
+
+#[test]
+fn test_padded_tiles_have_equal_size() -> Result<()> {
+    let frame = rgb_frame()?;
+    let pad = [0, 0, 255];
+    let mut compressor = Compressor::new()?;
+    let tiles = rgb_to_jpeg_tiles_padded(
+        &frame,
+        TileSize { width: 100, height: 100 },
+        pad,
+        &mut compressor,
+        &ConversionConfig::default(),
+    )?;
+
+    assert_eq!(tiles.len(), 4);
+    let regions: Vec<(usize, usize, usize, usize)> =
+        tiles.iter().map(|(tile, _)| (tile.roi.x, tile.roi.y, tile.roi.width, tile.roi.height)).collect();
+    assert_eq!(regions, [(0, 0, 100, 100), (100, 0, 76, 100), (0, 100, 100, 44), (100, 100, 76, 44)]);
+    for (_, jpeg) in &tiles {
+        let header = turbojpeg::read_header(&jpeg.data)?;
+        assert_eq!((header.width, header.height), (100, 100));
+    }
+
+    // The bottom-right corner of the last tile lies outside the frame
+    let image = turbojpeg::decompress(&tiles[3].1.data, PixelFormat::RGB)?;
+    let corner = &image.pixels[(99 * 100 + 99) * 3..][..3];
+    for (got, want) in corner.iter().zip(pad) {
+        assert!(got.abs_diff(want) <= 8, "padding {corner:?} is not {pad:?}");
+    }
+    Ok(())
+}
+
+#[test]
+fn test_padded_tile_size_is_limited_by_max_pixels() -> Result<()> {
+    let frame = rgb_frame()?;
+    let config = ConversionConfig {
+        max_pixels: Some(TEST_WIDTH * TEST_HEIGHT),
+        ..Default::default()
+    };
+    let mut compressor = Compressor::new()?;
+    let huge = TileSize {
+        width: 100_000,
+        height: 100_000,
+    };
+    let err = rgb_to_jpeg_tiles_padded(&frame, huge, [0, 0, 0], &mut compressor, &config).unwrap_err();
+    assert!(matches!(err, ConversionError::TooManyPixels { width: 100_000, height: 100_000, .. }));
+
+    // Without padding the tile is just the whole frame
+    let tiles = rgb_to_jpeg_tiles(&frame, huge, &mut compressor, &config)?;
+    assert_eq!(tiles.len(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_tiles_need_packed_input() -> Result<()> {
+    let frame = ImageRawAny {
+        header: None,
+        image: Some(RawImageVariant::Yuv420(ImageYuv420 {
+            header: None,
+            width: 16,
+            height: 16,
+            data: vec![128; 16 * 16 * 3 / 2],
+        })),
+    };
+    let mut compressor = Compressor::new()?;
+    let err = rgb_to_jpeg_tiles(&frame, TileSize { width: 8, height: 8 }, &mut compressor, &ConversionConfig::default())
+        .unwrap_err();
+    assert!(matches!(err, ConversionError::UnsupportedFormat(_)));
+    Ok(())
+}
+
+#[test]
+fn test_tile_entity_path() {
+    assert_eq!(tile_entity_path("", 0, 1), "tile/0/1");
+    assert_eq!(tile_entity_path("cameras/pano/", 2, 3), "cameras/pano/tile/2/3");
+}
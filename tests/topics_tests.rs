@@ -0,0 +1,97 @@
+use raw_to_jpeg::topics::{
+    parse_queue_discipline, resolve_output_profiles, OutputProfile, QueueDiscipline, SubscriberQueue, Topics,
+    DEFAULT_INPUT_TOPIC, DEFAULT_OUTPUT_TOPIC,
+};
+use raw_to_jpeg::ConversionError;
+use serde_json::json;
+use turbojpeg::Subsamp;
+
+#[test]
+fn test_missing_keys_use_defaults() {
+    let topics = Topics::resolve(None, None);
+    assert_eq!(topics.input, DEFAULT_INPUT_TOPIC);
+    assert_eq!(topics.output, DEFAULT_OUTPUT_TOPIC);
+    assert_eq!((topics.input.as_str(), topics.output.as_str()), ("raw_frame", "jpeg_frame"));
+}
+
+#[test]
+fn test_configured_topics_override_defaults() {
+    let topics = Topics::resolve(Some("camera_left/raw"), None);
+    assert_eq!(topics.input, "camera_left/raw");
+    assert_eq!(topics.output, DEFAULT_OUTPUT_TOPIC);
+
+    let topics = Topics::resolve(None, Some("camera_left/jpeg"));
+    assert_eq!(topics.input, DEFAULT_INPUT_TOPIC);
+    assert_eq!(topics.output, "camera_left/jpeg");
+}
+
+#[test]
+fn test_output_profile_parse() {
+    let profile = OutputProfile::parse("camera/preview", Some(40), Some("420")).unwrap();
+    assert_eq!(profile.topic, "camera/preview");
+    assert_eq!(profile.quality, Some(40));
+    assert_eq!(profile.subsamp, Some(Subsamp::Sub2x2));
+
+    let profile = OutputProfile::parse("camera/archive", None, None).unwrap();
+    assert_eq!((profile.quality, profile.subsamp), (None, None));
+
+    assert!(matches!(
+        OutputProfile::parse("camera/preview", Some(101), None),
+        Err(ConversionError::InvalidOutputProfile(_))
+    ));
+    assert!(matches!(OutputProfile::parse("", None, None), Err(ConversionError::InvalidOutputProfile(_))));
+    assert!(matches!(
+        OutputProfile::parse("camera/preview", None, Some("411")),
+        Err(ConversionError::UnsupportedSubsampling(_))
+    ));
+}
+
+#[test]
+fn test_resolve_output_profiles() {
+    let profiles = resolve_output_profiles(Vec::new(), DEFAULT_OUTPUT_TOPIC).unwrap();
+    assert_eq!(profiles.len(), 1);
+    assert_eq!(profiles[0].topic, DEFAULT_OUTPUT_TOPIC);
+    assert_eq!((profiles[0].quality, profiles[0].subsamp), (None, None));
+
+    let archive = OutputProfile::parse("archive", Some(95), None).unwrap();
+    let preview = OutputProfile::parse("preview", Some(40), Some("420")).unwrap();
+    let profiles = resolve_output_profiles(vec![archive.clone(), preview.clone()], DEFAULT_OUTPUT_TOPIC).unwrap();
+    assert_eq!(profiles, [archive.clone(), preview]);
+
+    let duplicate = OutputProfile::parse("archive", Some(50), None).unwrap();
+    assert!(matches!(
+        resolve_output_profiles(vec![archive, duplicate], DEFAULT_OUTPUT_TOPIC),
+        Err(ConversionError::InvalidOutputProfile(_))
+    ));
+}
+
+#[test]
+fn test_subscriber_queue_preferences() {
+    let bound = SubscriberQueue::from_handler(&json!({ "handler_type": "RING", "capacity": 5 }));
+    assert_eq!(
+        bound,
+        Some(SubscriberQueue {
+            discipline: QueueDiscipline::Ring,
+            depth: 5
+        })
+    );
+
+    // Each preference replaces only its own part of the bound queue
+    let fifo = SubscriberQueue::with_preferences(bound, Some(QueueDiscipline::Fifo), None).unwrap();
+    assert_eq!(fifo.to_handler(), json!({ "handler_type": "FIFO", "capacity": 5 }));
+    let deeper = SubscriberQueue::with_preferences(bound, None, Some(64)).unwrap();
+    assert_eq!(deeper.to_handler(), json!({ "handler_type": "RING", "capacity": 64 }));
+    assert_eq!(SubscriberQueue::with_preferences(bound, None, None), bound);
+
+    // Without a bound queue only a complete preference can be applied
+    assert_eq!(SubscriberQueue::with_preferences(None, Some(QueueDiscipline::Ring), None), None);
+    let both = SubscriberQueue::with_preferences(None, Some(QueueDiscipline::Ring), Some(3)).unwrap();
+    assert_eq!(both.to_handler(), json!({ "handler_type": "RING", "capacity": 3 }));
+
+    assert_eq!(SubscriberQueue::from_handler(&json!({ "handler_type": "LIFO", "capacity": 5 })), None);
+    assert_eq!(parse_queue_discipline("fifo").unwrap(), QueueDiscipline::Fifo);
+    assert!(matches!(
+        parse_queue_discipline("lifo"),
+        Err(ConversionError::UnsupportedQueueDiscipline(name)) if name == "lifo"
+    ));
+}
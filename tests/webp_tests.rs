@@ -0,0 +1,63 @@
+#![cfg(feature = "webp")]
+
+use anyhow::Result;
+use make87_messages::core::Header;
+use make87_messages::image::uncompressed::image_raw_any::Image as RawImageVariant;
+use make87_messages::image::uncompressed::{ImageRawAny, ImageRgb888, ImageYuv420};
+use raw_to_jpeg::{encode_image, ConversionConfig, ConversionError, OutputFormat};
+use turbojpeg::Compressor;
+
+const TEST_WIDTH: u32 = 176;
+const TEST_HEIGHT: u32 = 144;
+
+fn gradient_frame() -> ImageRawAny {
+    let data = (0..TEST_HEIGHT)
+        .flat_map(|y| (0..TEST_WIDTH).flat_map(move |x| [(x % 256) as u8, (y % 256) as u8, 128]))
+        .collect();
+    ImageRawAny {
+        header: Some(Header {
+            reference_id: 7,
+            ..Default::default()
+        }),
+        image: Some(RawImageVariant::Rgb888(ImageRgb888 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data,
+        })),
+    }
+}
+
+#[test]
+fn test_webp_output() -> Result<()> {
+    let frame = gradient_frame();
+    let mut compressor = Compressor::new()?;
+    let config = ConversionConfig {
+        quality: Some(75),
+        ..Default::default()
+    };
+
+    let image = encode_image(&frame, OutputFormat::Webp, &mut compressor, &config)?;
+    assert_eq!(image.format, OutputFormat::Webp);
+    assert_eq!(image.header, frame.header);
+    assert_eq!(&image.data[..4], b"RIFF");
+    assert_eq!(&image.data[8..12], b"WEBP");
+    Ok(())
+}
+
+#[test]
+fn test_webp_rejects_yuv_input() -> Result<()> {
+    let frame = ImageRawAny {
+        header: None,
+        image: Some(RawImageVariant::Yuv420(ImageYuv420 {
+            header: None,
+            width: TEST_WIDTH,
+            height: TEST_HEIGHT,
+            data: vec![128; (TEST_WIDTH * TEST_HEIGHT * 3 / 2) as usize],
+        })),
+    };
+    let mut compressor = Compressor::new()?;
+    let err = encode_image(&frame, OutputFormat::Webp, &mut compressor, &ConversionConfig::default()).unwrap_err();
+    assert!(matches!(err, ConversionError::UnsupportedFormat(_)));
+    Ok(())
+}